@@ -1,5 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use flowerbloom::BloomBuilder;
+use flowerbloom::{BloomBuilder, Pipeline};
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("insert=1, capacity=100", |b| {
@@ -24,7 +24,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         let mut bf = BloomBuilder::<&str>::new(num_items, fp_rate).build();
         b.iter(|| {
             for item in items.iter() {
-                bf.insert(black_box(item))
+                bf.insert(black_box(item));
             }
         })
     });
@@ -38,6 +38,74 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         let bf = BloomBuilder::<&str>::new(num_items, fp_rate).build();
         b.iter(|| bf.has("5"))
     });
+    c.bench_function("has_batch=10_000, capacity=100_000", |b| {
+        let items: Vec<String> = (0..10_000).map(|i| format!("{}", i)).collect();
+        let items: Vec<&str> = items.iter().map(|item| item.as_str()).collect();
+        let mut bf = BloomBuilder::<&str>::new(100_000, 0.03).build();
+        for item in items.iter().step_by(2) {
+            bf.insert(item);
+        }
+        b.iter(|| bf.has_batch(black_box(&items)))
+    });
+    c.bench_function("has=1_000_000, capacity=50_000_000", |b| {
+        let items: Vec<String> = (0..1_000_000).map(|i| format!("{}", i)).collect();
+        let items: Vec<&str> = items.iter().map(|item| item.as_str()).collect();
+        let mut bf = BloomBuilder::<&str>::new(50_000_000, 0.01).build();
+        for item in items.iter() {
+            bf.insert(item);
+        }
+        b.iter(|| items.iter().filter(|item| bf.has(black_box(item))).count())
+    });
+    c.bench_function("pipeline query=1_000_000, capacity=50_000_000", |b| {
+        let items: Vec<String> = (0..1_000_000).map(|i| format!("{}", i)).collect();
+        let items: Vec<&str> = items.iter().map(|item| item.as_str()).collect();
+        let mut bf = BloomBuilder::<&str>::new(50_000_000, 0.01).build();
+        for item in items.iter() {
+            bf.insert(item);
+        }
+        let mut pipeline = Pipeline::new(&bf);
+        b.iter(|| pipeline.query(black_box(&items)))
+    });
+    c.bench_function("insert_hash=1_000_000, capacity=50_000_000", |b| {
+        let hashes: Vec<u64> = (0..1_000_000u64)
+            .map(|i| i.wrapping_mul(0x9e3779b97f4a7c15))
+            .collect();
+        let mut bf = BloomBuilder::<&str>::new(50_000_000, 0.01).build();
+        b.iter(|| {
+            for hash in hashes.iter() {
+                bf.insert_hash(black_box(*hash)).unwrap();
+            }
+        })
+    });
+    c.bench_function("bulk_insert_hashes=1_000_000, capacity=50_000_000", |b| {
+        let mut hashes: Vec<u64> = (0..1_000_000u64)
+            .map(|i| i.wrapping_mul(0x9e3779b97f4a7c15))
+            .collect();
+        hashes.sort_unstable();
+        let mut bf = BloomBuilder::<&str>::new(50_000_000, 0.01).build();
+        b.iter(|| bf.bulk_insert_hashes(black_box(&hashes)).unwrap())
+    });
+    #[cfg(feature = "xxhash")]
+    c.bench_function("insert=1, capacity=10,000, hasher=xxhash", |b| {
+        let num_items = 10_000;
+        let fp_rate = 0.03;
+        let mut bf = BloomBuilder::<&str>::new(num_items, fp_rate)
+            .hasher::<flowerbloom::XxHasher>()
+            .build();
+        b.iter(|| bf.insert(black_box("hi")))
+    });
+    #[cfg(feature = "xxhash")]
+    c.bench_function("split block has_hash=10_000, ndv=100_000", |b| {
+        let mut bf = flowerbloom::SplitBlockBloomFilter::new(100_000, 0.01);
+        for i in (0..10_000u64).step_by(2) {
+            bf.insert_hash(i.wrapping_mul(0x9e3779b97f4a7c15));
+        }
+        b.iter(|| {
+            (0..10_000u64)
+                .filter(|i| bf.has_hash(black_box(i.wrapping_mul(0x9e3779b97f4a7c15))))
+                .count()
+        })
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -8,7 +8,7 @@ fn bench_fibs(c: &mut Criterion) {
 
     let mut bloom = Bloom::new_for_fp_rate(capacity, fp_rate);
 
-    let mut bf: BloomFilter<&str> = BloomBuilder::new(capacity as u32, fp_rate as f32).build();
+    let mut bf: BloomFilter<&str> = BloomBuilder::new(capacity as u64, fp_rate as f32).build();
 
     let mut group = c.benchmark_group("crate comparisons");
     group.bench_function("bloomy crate", |b| {
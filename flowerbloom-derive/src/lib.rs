@@ -0,0 +1,90 @@
+//! Derive macro for flowerbloom's `BloomEncode` trait. Use it through the `derive`
+//! feature of the flowerbloom crate rather than depending on this crate directly.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+/// Derives a canonical byte encoding for a struct or enum by encoding each of its
+/// fields in declaration order with their own `BloomEncode` implementations. Enum
+/// values are prefixed with the index of their variant as a big-endian u32.
+#[proc_macro_derive(BloomEncode)]
+pub fn derive_bloom_encode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::flowerbloom::BloomEncode));
+    }
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let fields = data.fields.iter().enumerate().map(|(i, field)| {
+                let member = match &field.ident {
+                    Some(ident) => quote!(#ident),
+                    None => {
+                        let index = Index::from(i);
+                        quote!(#index)
+                    }
+                };
+                quote! {
+                    ::flowerbloom::BloomEncode::encode_into(&self.#member, buf);
+                }
+            });
+            quote! { #(#fields)* }
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(i, variant)| {
+                let ident = &variant.ident;
+                let tag = i as u32;
+                let (pattern, encodes) = destructure(&variant.fields);
+                quote! {
+                    Self::#ident #pattern => {
+                        ::flowerbloom::BloomEncode::encode_into(&#tag, buf);
+                        #encodes
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "BloomEncode cannot be derived for unions",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::flowerbloom::BloomEncode for #ident #ty_generics #where_clause {
+            fn encode_into(&self, buf: &mut ::std::vec::Vec<u8>) {
+                #body
+            }
+        }
+    };
+    expanded.into()
+}
+
+/// Builds a pattern binding every field of an enum variant, along with the
+/// statements encoding each binding in order.
+fn destructure(fields: &Fields) -> (TokenStream, TokenStream) {
+    let bindings: Vec<_> = (0..fields.len())
+        .map(|i| format_ident!("field_{}", i))
+        .collect();
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!({ #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!((#(#bindings),*)),
+        Fields::Unit => quote!(),
+    };
+    let encodes = quote! {
+        #(::flowerbloom::BloomEncode::encode_into(#bindings, buf);)*
+    };
+    (pattern, encodes)
+}
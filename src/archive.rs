@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use rkyv::munge::munge;
+use rkyv::rancor::{Fallible, Source};
+use rkyv::ser::{Allocator, Writer};
+use rkyv::vec::ArchivedVec;
+use rkyv::{Archive, Deserialize, Place, Serialize};
+
+use crate::hasher::builtin_hasher;
+use crate::{has_probes, BloomFilter, Error, FilterParams, HasherInstance};
+
+/// The layout of an archived bloom filter, from which `ArchivedBloomFilter` is derived.
+/// Filters are archived from their own fields, so this is only constructed by tests.
+#[derive(Archive, Serialize, Deserialize)]
+#[rkyv(archived = ArchivedBloomFilter)]
+#[rkyv(attr(
+    doc = "A bloom filter archived with rkyv, which can be queried directly from \
+    the buffer holding it, without deserializing its bits. See `ArchivedBloomFilter::view`."
+))]
+pub struct BloomFilterLayout {
+    capacity: u64,
+    num_hash_fns: u32,
+    tier_hash_fns: Vec<u32>,
+    hasher_id: u64,
+    seed: Option<u64>,
+    max_fp_rate: Option<f64>,
+    num_inserted: u64,
+    bits: Vec<u8>,
+}
+
+/// Archives a bloom filter with rkyv, so it can be embedded in a larger archived snapshot
+/// and queried straight from the snapshot's buffer. Only the filter's parameters and bits
+/// are archived, and its hasher is restored from its id and seed when the archived filter
+/// is viewed or deserialized, so only filters built with the package's hashers can be
+/// queried. Requires the `rkyv` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{ArchivedBloomFilter, BloomBuilder, BloomFilter};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+/// bf.insert("hello");
+///
+/// let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&bf).unwrap();
+/// let archived = rkyv::access::<ArchivedBloomFilter, rkyv::rancor::Error>(&bytes).unwrap();
+/// assert!(archived.view::<&str>().unwrap().has("hello"));
+/// ```
+impl<T: AsRef<[u8]>> Archive for BloomFilter<T> {
+    type Archived = ArchivedBloomFilter;
+    type Resolver = BloomFilterLayoutResolver;
+
+    fn resolve(&self, resolver: Self::Resolver, out: Place<Self::Archived>) {
+        let params = self.params();
+        munge!(let ArchivedBloomFilter {
+            capacity,
+            num_hash_fns,
+            tier_hash_fns,
+            hasher_id,
+            seed,
+            max_fp_rate,
+            num_inserted,
+            bits,
+        } = out);
+        params.capacity.resolve((), capacity);
+        params.num_hash_fns.resolve((), num_hash_fns);
+        params
+            .tier_hash_fns
+            .resolve(resolver.tier_hash_fns, tier_hash_fns);
+        params.hasher_id.resolve((), hasher_id);
+        params.seed.resolve(resolver.seed, seed);
+        self.max_fp_rate.resolve(resolver.max_fp_rate, max_fp_rate);
+        (self.num_inserted as u64).resolve((), num_inserted);
+        ArchivedVec::resolve_from_len(self.byte_len(), resolver.bits, bits);
+    }
+}
+
+impl<T: AsRef<[u8]>, S: Fallible + Allocator + Writer + ?Sized> Serialize<S> for BloomFilter<T> {
+    fn serialize(&self, serializer: &mut S) -> Result<Self::Resolver, S::Error> {
+        let params = self.params();
+        Ok(BloomFilterLayoutResolver {
+            capacity: (),
+            num_hash_fns: (),
+            tier_hash_fns: params.tier_hash_fns.serialize(serializer)?,
+            hasher_id: (),
+            seed: params.seed.serialize(serializer)?,
+            max_fp_rate: self.max_fp_rate.serialize(serializer)?,
+            num_inserted: (),
+            bits: ArchivedVec::serialize_from_slice(&self.bits_to_bytes(), serializer)?,
+        })
+    }
+}
+
+/// Deserializes an archived bloom filter, failing with `Error::UnknownHasher` if it was
+/// not built with one of the package's hashers, and with `Error::InvalidEncoding` if its
+/// bits are empty, as in `BloomFilter::from_parts`.
+impl<T: AsRef<[u8]>, D: Fallible + ?Sized> Deserialize<BloomFilter<T>, D> for ArchivedBloomFilter
+where
+    D::Error: Source,
+{
+    fn deserialize(&self, _: &mut D) -> Result<BloomFilter<T>, D::Error> {
+        let mut bf =
+            BloomFilter::from_byte_parts(self.params(), self.bits()).map_err(D::Error::new)?;
+        bf.num_inserted = self.len();
+        bf.max_fp_rate = self.max_fp_rate.as_ref().map(|rate| rate.to_native());
+        Ok(bf)
+    }
+}
+
+impl ArchivedBloomFilter {
+    /// Restores the archived filter's hasher for querying items of a given type. Fails
+    /// with `Error::UnknownHasher` if the filter was not built with one of the package's
+    /// hashers, and with `Error::InvalidEncoding` if its bits are empty.
+    pub fn view<T: AsRef<[u8]>>(&self) -> Result<ArchivedFilterView<'_, T>, Error> {
+        if self.bits.is_empty() {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(ArchivedFilterView {
+            filter: self,
+            hasher: self.hasher()?,
+        })
+    }
+    /// Returns the structural parameters of the archived bloom filter.
+    pub fn params(&self) -> FilterParams {
+        FilterParams {
+            capacity: self.capacity.to_native(),
+            bit_len: self.bits.len() as u64 * 8,
+            num_hash_fns: self.num_hash_fns.to_native(),
+            tier_hash_fns: self.tier_hash_fns.iter().map(|n| n.to_native()).collect(),
+            hasher_id: self.hasher_id.to_native(),
+            seed: self.seed.as_ref().map(|seed| seed.to_native()),
+        }
+    }
+    /// Returns the number of elements inserted into the bloom filter when it was archived.
+    pub fn len(&self) -> usize {
+        self.num_inserted.to_native() as usize
+    }
+    /// Returns true if no elements were inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the bits of the archived bloom filter, as found in the archive's buffer.
+    pub fn bits(&self) -> &[u8] {
+        self.bits.as_slice()
+    }
+    fn hasher<T: AsRef<[u8]>>(&self) -> Result<Arc<dyn HasherInstance<T>>, Error> {
+        let hasher_id = self.hasher_id.to_native();
+        let seed = self.seed.as_ref().map(|seed| seed.to_native());
+        builtin_hasher(hasher_id, seed).ok_or(Error::UnknownHasher(hasher_id))
+    }
+}
+
+/// An archived bloom filter paired with its restored hasher, so that items can be
+/// checked against the archive's buffer directly. See `ArchivedBloomFilter::view`.
+pub struct ArchivedFilterView<'a, T: AsRef<[u8]>> {
+    filter: &'a ArchivedBloomFilter,
+    hasher: Arc<dyn HasherInstance<T>>,
+}
+
+impl<T: AsRef<[u8]>> ArchivedFilterView<'_, T> {
+    /// Checks if the archived bloom filter contains a specified element. Like any bloom
+    /// filter, it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        has_probes(
+            self.filter.bits(),
+            self.hasher.hash_pair(&elem),
+            self.filter.num_hash_fns.to_native(),
+        )
+    }
+    /// Checks if the archived bloom filter contains a specified element of a class
+    /// configured via `BloomBuilder::tier`, see `BloomFilter::has_with_class`.
+    /// Fails with `Error::UnknownClass` if the class was not configured on the builder.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = match self.filter.tier_hash_fns.get(class) {
+            Some(n) => n.to_native(),
+            None => return Err(Error::UnknownClass(class)),
+        };
+        Ok(has_probes(
+            self.filter.bits(),
+            self.hasher.hash_pair(&elem),
+            num_hash_fns,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomBuilder;
+    use rkyv::rancor::Error as RancorError;
+
+    #[derive(Archive, Serialize, Deserialize)]
+    struct Snapshot {
+        name: String,
+        seen: BloomFilter<String>,
+    }
+
+    #[test]
+    fn embedded_in_snapshot() {
+        let mut seen: BloomFilter<String> = BloomBuilder::new(1_000, 0.01).tier(0.0001).build();
+        seen.insert("foo".to_string());
+        seen.insert_with_class("bar".to_string(), 0).unwrap();
+        let snapshot = Snapshot {
+            name: "nyan".to_string(),
+            seen,
+        };
+        let bytes = rkyv::to_bytes::<RancorError>(&snapshot).unwrap();
+
+        let archived = rkyv::access::<ArchivedSnapshot, RancorError>(&bytes).unwrap();
+        assert_eq!("nyan", archived.name.as_str());
+        assert_eq!(snapshot.seen.params(), archived.seen.params());
+        assert_eq!(snapshot.seen.bits_to_bytes(), archived.seen.bits());
+        assert_eq!(2, archived.seen.len());
+        let view = archived.seen.view::<String>().unwrap();
+        assert!(view.has("foo".to_string()));
+        assert!(view.has_with_class("bar".to_string(), 0).unwrap());
+        assert_eq!(
+            Err(Error::UnknownClass(1)),
+            view.has_with_class("bar".to_string(), 1)
+        );
+        assert!(!view.has("baz".to_string()));
+
+        let restored: Snapshot = rkyv::deserialize::<_, RancorError>(archived).unwrap();
+        assert_eq!(snapshot.seen.bits, restored.seen.bits);
+        assert!(restored.seen.union(&snapshot.seen).is_ok());
+    }
+
+    #[test]
+    fn unknown_hasher() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hash_fn(|item: &&str| item.len() as u64)
+            .build();
+        let bytes = rkyv::to_bytes::<RancorError>(&bf).unwrap();
+        let archived = rkyv::access::<ArchivedBloomFilter, RancorError>(&bytes).unwrap();
+        assert!(archived.view::<&str>().is_err());
+        assert!(rkyv::deserialize::<BloomFilter<&str>, RancorError>(archived).is_err());
+    }
+
+    #[test]
+    fn empty_bits() {
+        let layout = BloomFilterLayout {
+            capacity: 100,
+            num_hash_fns: 7,
+            tier_hash_fns: vec![],
+            hasher_id: BloomBuilder::<&str>::new(100, 0.01)
+                .seed(1)
+                .build()
+                .params()
+                .hasher_id,
+            seed: Some(1),
+            max_fp_rate: None,
+            num_inserted: 0,
+            bits: vec![],
+        };
+        let bytes = rkyv::to_bytes::<RancorError>(&layout).unwrap();
+        let archived = rkyv::access::<ArchivedBloomFilter, RancorError>(&bytes).unwrap();
+        assert_eq!(Some(Error::InvalidEncoding), archived.view::<&str>().err());
+        assert!(rkyv::deserialize::<BloomFilter<&str>, RancorError>(archived).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::format::{crc32_update, invalid, Header, CHECKSUM_VERSION, MAGIC, MAX_TIERS};
+use crate::{words, BloomFilter, Compression, Error};
+
+/// The length of the part of the header before the number of hash functions of each
+/// tier, ending in the number of tiers.
+const FIXED_HEADER_LEN: usize = 50;
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Streams the bloom filter to an asynchronous writer in the format of `to_bytes`,
+    /// as `write_to` does, so that services can checkpoint filters without blocking
+    /// their runtime. The writer is not buffered or flushed. Requires the `tokio`
+    /// feature.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let mut file = vec![];
+    /// bf.write_to_async(&mut file).await.unwrap();
+    /// let restored: BloomFilter<&str> = BloomFilter::read_from_async(&file[..]).await.unwrap();
+    /// assert!(restored.has("hello"));
+    /// # });
+    /// ```
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, mut w: W) -> io::Result<()> {
+        let mut header = vec![];
+        self.header().write_to(&mut header)?;
+        let mut crc = crc32_update(0, &header);
+        w.write_all(&header).await?;
+        for batch in words::byte_batches(&self.bits, self.byte_len()) {
+            crc = crc32_update(crc, &batch);
+            w.write_all(&batch).await?;
+        }
+        w.write_all(&crc.to_be_bytes()).await
+    }
+    /// Streams a bloom filter encoded with `to_bytes` or `write_to` from an asynchronous
+    /// reader, as `read_from` does, including filters written with compressed bits,
+    /// reading exactly as many bytes as the filter occupies and reporting errors the
+    /// same way. Requires the `tokio` feature.
+    pub async fn read_from_async<R: AsyncRead + Unpin>(mut r: R) -> io::Result<BloomFilter<T>> {
+        let mut encoded = vec![0; FIXED_HEADER_LEN];
+        r.read_exact(&mut encoded).await?;
+        // Checked before the rest of the header is read, as its length comes from it.
+        if encoded[..4] != MAGIC {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let num_tiers = u32::from_be_bytes(encoded[46..50].try_into().unwrap());
+        if num_tiers > MAX_TIERS {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        (&mut r)
+            .take(4 * num_tiers as u64 + 8)
+            .read_to_end(&mut encoded)
+            .await?;
+        let header = Header::read_from(&mut &encoded[..])?;
+        let hasher = header.hasher()?;
+        let payload_len = match header.compression {
+            Compression::None => header.byte_len(),
+            _ => {
+                let mut len = [0; 8];
+                r.read_exact(&mut len).await?;
+                encoded.extend_from_slice(&len);
+                u64::from_be_bytes(len)
+            }
+        };
+        let mut payload = vec![];
+        (&mut r).take(payload_len).read_to_end(&mut payload).await?;
+        if payload.len() as u64 != payload_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if header.version >= CHECKSUM_VERSION {
+            let crc = crc32_update(crc32_update(0, &encoded), &payload);
+            let mut expected = [0; 4];
+            r.read_exact(&mut expected).await?;
+            if u32::from_be_bytes(expected) != crc {
+                return Err(invalid(Error::Corrupted));
+            }
+        }
+        let bits = header.compression.decompress(payload, header.byte_len())?;
+        Ok(header.into_filter(hasher, bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomBuilder;
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.01)
+            .tier(0.001)
+            .max_fp_rate(0.05)
+            .build();
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        let mut encoded = vec![];
+        block_on(bf.write_to_async(&mut encoded)).unwrap();
+        assert_eq!(bf.to_bytes(), encoded);
+
+        // Trailing bytes are left unread.
+        encoded.extend_from_slice(b"rest");
+        let mut input = &encoded[..];
+        let restored: BloomFilter<String> =
+            block_on(BloomFilter::read_from_async(&mut input)).unwrap();
+        assert_eq!(b"rest", input);
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+
+        let mut compressed = vec![];
+        bf.write_to_compressed(&mut compressed, Compression::Rle)
+            .unwrap();
+        let restored: BloomFilter<String> =
+            block_on(BloomFilter::read_from_async(&compressed[..])).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).build();
+        let encoded = bf.to_bytes();
+        let read = |bytes: &[u8]| {
+            block_on(BloomFilter::<&str>::read_from_async(bytes))
+                .err()
+                .unwrap()
+        };
+        let inner = |err: io::Error| err.into_inner().unwrap().downcast::<Error>().unwrap();
+
+        for len in [0, 10, FIXED_HEADER_LEN + 4, encoded.len() - 1] {
+            assert_eq!(io::ErrorKind::UnexpectedEof, read(&encoded[..len]).kind());
+        }
+        let mut bad_magic = encoded.clone();
+        bad_magic[0] ^= 1;
+        assert_eq!(Error::InvalidEncoding, *inner(read(&bad_magic)));
+        let mut corrupted = encoded.clone();
+        corrupted[FIXED_HEADER_LEN + 10] ^= 1;
+        assert_eq!(Error::Corrupted, *inner(read(&corrupted)));
+        let mut future = encoded.clone();
+        future[4] = 3;
+        assert_eq!(Error::UnsupportedVersion(3), *inner(read(&future)));
+    }
+}
@@ -0,0 +1,536 @@
+use std::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::words::AlignedWords;
+use crate::{
+    class_num_hash_fns, filter_params, probes, tier_num_hash_fns, BloomBuilder, BloomFilter, Error,
+    FilterParams, HasherInstance,
+};
+
+impl<T: AsRef<[u8]>> BloomBuilder<T> {
+    /// Builds a bloom filter that can be shared between threads and inserted into
+    /// without locking, see `AtomicBloomFilter`. The filter is sized and hashes items
+    /// exactly as the filter returned by `build` would, but no maximum false positive
+    /// rate is enforced.
+    pub fn build_atomic(self) -> AtomicBloomFilter<T> {
+        let (num_hash_fns, size) = self.checked_sizing();
+        AtomicBloomFilter {
+            words: AlignedWords::zeroed(size.div_ceil(8)),
+            bit_len: size as u64 * 8,
+            capacity: self.capacity,
+            num_hash_fns,
+            hasher: self.hasher,
+            class_hash_fns: self.tiers.iter().map(|p| tier_num_hash_fns(*p)).collect(),
+            num_inserted: AtomicUsize::new(0),
+            probe_hashers: self.probe_hashers,
+            seqlock: None,
+        }
+    }
+}
+
+/// A bloom filter whose bits are atomic words, so that many threads can insert into and
+/// query a single filter shared through an `Arc`, without serializing on a `Mutex`.
+/// Inserts set bits with an atomic `fetch_or`, and queries load them without locking.
+/// Built with `BloomBuilder::build_atomic`.
+///
+/// An element is visible to queries on any thread once its insert returns. A query
+/// racing with the insert of the same element may not see it yet, but will never see a
+/// bit cleared by another insert. Queries of many elements can be made to see the
+/// filter as of a single point in time with `with_consistent_reads`.
+///
+/// ## Example
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// use flowerbloom::{AtomicBloomFilter, BloomBuilder};
+///
+/// let bf: Arc<AtomicBloomFilter<String>> = Arc::new(BloomBuilder::new(1000, 0.01).build_atomic());
+/// let handles: Vec<_> = (0..4)
+///     .map(|i| {
+///         let bf = bf.clone();
+///         thread::spawn(move || bf.insert(format!("{}", i)))
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+/// assert!((0..4).all(|i| bf.has(format!("{}", i))));
+/// ```
+pub struct AtomicBloomFilter<T: AsRef<[u8]>> {
+    /// Laid out like the words of a `BloomFilter`'s bits, on cache lines of their own.
+    words: AlignedWords<AtomicU64>,
+    bit_len: u64,
+    capacity: u64,
+    num_hash_fns: u32,
+    hasher: Arc<dyn HasherInstance<T>>,
+    class_hash_fns: Vec<u32>,
+    num_inserted: AtomicUsize,
+    probe_hashers: Vec<Arc<dyn HasherInstance<T>>>,
+    seqlock: Option<SeqLock>,
+}
+
+impl<T: AsRef<[u8]>> AtomicBloomFilter<T> {
+    /// Makes batch queries and copies of the filter consistent with concurrent writes,
+    /// so that `has_batch` and `to_filter` see every insert and clear either entirely or
+    /// not at all, as if the filter were locked while they read it. Writers never wait,
+    /// but count their writes in two shared counters, and readers retry whenever a write
+    /// raced with them, so readers may spin for as long as writes keep overlapping them.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{AtomicBloomFilter, BloomBuilder};
+    ///
+    /// let bf: AtomicBloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+    ///     .build_atomic()
+    ///     .with_consistent_reads();
+    /// bf.insert("foo");
+    /// assert_eq!(vec![true, false], bf.has_batch(&["foo", "bar"]));
+    /// ```
+    pub fn with_consistent_reads(mut self) -> AtomicBloomFilter<T> {
+        self.seqlock = Some(SeqLock::default());
+        self
+    }
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, as in `BloomFilter::insert`. The result is
+    /// only best-effort when threads race to insert the same element: each of them may
+    /// set some of its bits first, so more than one of them can see it as new.
+    pub fn insert(&self, elem: T) -> bool {
+        self.insert_with_probes(&elem, self.num_hash_fns)
+    }
+    /// Insert an element into the bloom filter with the number of hash functions of a
+    /// tier, as in `BloomFilter::insert_with_class`. Fails with `Error::UnknownClass` if the class
+    /// was not configured via `BloomBuilder::tier`.
+    pub fn insert_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        Ok(self.insert_with_probes(&elem, class_num_hash_fns(&self.class_hash_fns, class)?))
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.probes(&elem, self.num_hash_fns)
+            .all(|idx| self.get_bit(idx))
+    }
+    /// Checks if the bloom filter contains an element inserted with a class, as in
+    /// `BloomFilter::has_with_class`.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self
+            .probes(&elem, num_hash_fns)
+            .all(|idx| self.get_bit(idx)))
+    }
+    /// Checks if the bloom filter contains each of a batch of elements, in order, as
+    /// `has` would for each of them. The whole batch is hashed upfront, and its bits are
+    /// then read in one pass, which sees a single state of the filter if it was made
+    /// consistent with `with_consistent_reads`.
+    pub fn has_batch(&self, elems: &[T]) -> Vec<bool> {
+        let k = self.num_hash_fns as usize;
+        let indices: Vec<u64> = elems
+            .iter()
+            .flat_map(|elem| self.probes(elem, self.num_hash_fns))
+            .collect();
+        self.read(|| {
+            (0..elems.len())
+                .map(|i| {
+                    indices[i * k..(i + 1) * k]
+                        .iter()
+                        .all(|idx| self.get_bit(*idx))
+                })
+                .collect()
+        })
+    }
+    /// Clear all set bits of the bloom filter, setting them back to zero. Elements
+    /// inserted concurrently with the clear may be partially cleared.
+    pub fn clear(&self) {
+        self.write(|| {
+            for word in self.words.iter() {
+                word.store(0, Ordering::Release);
+            }
+        });
+        self.num_inserted.store(0, Ordering::Relaxed);
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.num_inserted.load(Ordering::Relaxed)
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the number of bits set in the bloom filter.
+    pub fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.load(Ordering::Acquire).count_ones() as usize)
+            .sum()
+    }
+    /// Returns the number of bits in the bloom filter.
+    pub fn bit_len(&self) -> usize {
+        self.bit_len as usize
+    }
+    /// Returns the structural parameters of the bloom filter, identical to those of a
+    /// `BloomFilter` built with the same builder.
+    pub fn params(&self) -> FilterParams {
+        filter_params(
+            self.capacity,
+            self.bit_len,
+            self.num_hash_fns,
+            &self.class_hash_fns,
+            &self.hasher,
+            &self.probe_hashers,
+        )
+    }
+    /// Returns a copy of the bloom filter as a `BloomFilter`, such as to persist it.
+    /// Inserts made concurrently with the copy may be partially included.
+    pub fn to_filter(&self) -> BloomFilter<T> {
+        BloomFilter {
+            bits: self.read(|| {
+                self.words
+                    .iter()
+                    .map(|word| word.load(Ordering::Acquire))
+                    .collect()
+            }),
+            byte_len: (self.bit_len / 8) as usize,
+            capacity: self.capacity,
+            num_hash_fns: self.num_hash_fns,
+            hasher: self.hasher.clone(),
+            class_hash_fns: self.class_hash_fns.clone(),
+            num_inserted: self.len(),
+            max_fp_rate: None,
+            probe_hashers: self.probe_hashers.clone(),
+            dirty: vec![],
+        }
+    }
+    /// Returns a handle that buffers inserts and merges them into the filter in bulk,
+    /// for threads inserting at a high rate, see `AtomicBloomWriter`. The handle flushes
+    /// its buffer once it holds a given number of bit indices, and when dropped.
+    ///
+    /// ## Example
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// use flowerbloom::{AtomicBloomFilter, BloomBuilder};
+    ///
+    /// let bf: Arc<AtomicBloomFilter<String>> =
+    ///     Arc::new(BloomBuilder::new(100_000, 0.01).build_atomic());
+    /// let handles: Vec<_> = (0..4)
+    ///     .map(|t| {
+    ///         let bf = bf.clone();
+    ///         thread::spawn(move || {
+    ///             let mut writer = bf.writer(4096);
+    ///             for i in 0..1000 {
+    ///                 writer.insert(format!("{}-{}", t, i));
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for handle in handles {
+    ///     handle.join().unwrap();
+    /// }
+    /// assert!(bf.has("3-999".to_string()));
+    /// ```
+    pub fn writer(&self, buffer_len: usize) -> AtomicBloomWriter<'_, T> {
+        AtomicBloomWriter {
+            filter: self,
+            indices: Vec::with_capacity(buffer_len),
+            buffer_len,
+            num_inserted: 0,
+        }
+    }
+    fn insert_with_probes(&self, elem: &T, num_hash_fns: u32) -> bool {
+        self.num_inserted.fetch_add(1, Ordering::Relaxed);
+        // Hashes the element before the write starts, unless probed by independent
+        // hashers, so that readers racing with it retry less often.
+        let probes = self.probes(elem, num_hash_fns);
+        self.write(|| {
+            let mut changed = false;
+            for idx in probes {
+                let mask = 1 << (idx % 64);
+                let prev = self.words[(idx / 64) as usize].fetch_or(mask, Ordering::Release);
+                changed |= prev & mask == 0;
+            }
+            changed
+        })
+    }
+    fn read<R>(&self, mut f: impl FnMut() -> R) -> R {
+        match &self.seqlock {
+            Some(seqlock) => seqlock.read(f),
+            None => f(),
+        }
+    }
+    fn write<R>(&self, f: impl FnOnce() -> R) -> R {
+        match &self.seqlock {
+            Some(seqlock) => seqlock.write(f),
+            None => f(),
+        }
+    }
+    fn get_bit(&self, idx: u64) -> bool {
+        self.words[(idx / 64) as usize].load(Ordering::Acquire) & (1 << (idx % 64)) != 0
+    }
+    fn probes<'a>(&'a self, elem: &'a T, num_hash_fns: u32) -> impl Iterator<Item = u64> + 'a {
+        probes(
+            &*self.hasher,
+            &self.probe_hashers,
+            elem,
+            num_hash_fns,
+            self.bit_len,
+        )
+    }
+}
+
+/// A handle buffering inserts into an `AtomicBloomFilter`, returned by
+/// `AtomicBloomFilter::writer`. Each handle is meant to be owned by a single thread.
+/// Inserts only compute the bit indices of their elements, and flushing sorts the
+/// buffered indices and sets the bits of each word with a single `fetch_or`, so that
+/// many random atomic writes to the shared filter become fewer, sequential ones.
+///
+/// Buffered elements are not visible to queries until they are flushed, either once the
+/// buffer fills up, by calling `flush`, or when the handle is dropped.
+pub struct AtomicBloomWriter<'a, T: AsRef<[u8]>> {
+    filter: &'a AtomicBloomFilter<T>,
+    indices: Vec<u64>,
+    buffer_len: usize,
+    num_inserted: usize,
+}
+
+impl<T: AsRef<[u8]>> AtomicBloomWriter<'_, T> {
+    /// Buffers the insert of an element, flushing the buffer if it is full.
+    pub fn insert(&mut self, elem: T) {
+        self.buffer(&elem, self.filter.num_hash_fns);
+    }
+    /// Buffers the insert of an element with the number of hash functions of a tier, as
+    /// in `BloomFilter::insert_with_class`. Fails with `Error::UnknownClass` if the class was not configured
+    /// via `BloomBuilder::tier`.
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> Result<(), Error> {
+        self.buffer(
+            &elem,
+            class_num_hash_fns(&self.filter.class_hash_fns, class)?,
+        );
+        Ok(())
+    }
+    /// Merges every buffered insert into the filter.
+    pub fn flush(&mut self) {
+        self.indices.sort_unstable();
+        let mut indices = self.indices.iter().peekable();
+        let words = &self.filter.words;
+        self.filter.write(|| {
+            while let Some(idx) = indices.next() {
+                let word = idx / 64;
+                let mut mask = 1 << (idx % 64);
+                while let Some(idx) = indices.next_if(|idx| *idx / 64 == word) {
+                    mask |= 1 << (idx % 64);
+                }
+                words[word as usize].fetch_or(mask, Ordering::Release);
+            }
+        });
+        self.indices.clear();
+        self.filter
+            .num_inserted
+            .fetch_add(self.num_inserted, Ordering::Relaxed);
+        self.num_inserted = 0;
+    }
+    /// Returns the number of bit indices waiting to be flushed.
+    pub fn buffered_len(&self) -> usize {
+        self.indices.len()
+    }
+    fn buffer(&mut self, elem: &T, num_hash_fns: u32) {
+        self.indices.extend(self.filter.probes(elem, num_hash_fns));
+        self.num_inserted += 1;
+        if self.indices.len() >= self.buffer_len {
+            self.flush();
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Drop for AtomicBloomWriter<'_, T> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Counts the writes to an `AtomicBloomFilter` that started and finished, so that
+/// readers can tell whether any write raced with them, as a sequence lock does, but with
+/// any number of concurrent writers.
+#[derive(Default)]
+struct SeqLock {
+    started: AtomicU64,
+    finished: AtomicU64,
+}
+
+impl SeqLock {
+    fn write<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.started.fetch_add(1, Ordering::Relaxed);
+        // Orders the count before the writes, for readers which see any of them.
+        fence(Ordering::Release);
+        let result = f();
+        self.finished.fetch_add(1, Ordering::Release);
+        result
+    }
+    fn read<R>(&self, mut f: impl FnMut() -> R) -> R {
+        loop {
+            // Every write counted as started had finished if the counts are equal,
+            // as writes finish after they start.
+            let finished = self.finished.load(Ordering::Acquire);
+            let started = self.started.load(Ordering::Acquire);
+            if started == finished {
+                let result = f();
+                // Any write seen by the read was counted as started before it.
+                fence(Ordering::Acquire);
+                if self.started.load(Ordering::Relaxed) == started {
+                    return result;
+                }
+            }
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Converts a bloom filter into one that can be shared between threads, with the same
+/// parameters, hashers and set bits.
+impl<T: AsRef<[u8]>> From<BloomFilter<T>> for AtomicBloomFilter<T> {
+    fn from(bf: BloomFilter<T>) -> AtomicBloomFilter<T> {
+        AtomicBloomFilter {
+            words: AlignedWords::from_fn(bf.bits.len(), |i| AtomicU64::new(bf.bits[i])),
+            bit_len: bf.bit_len() as u64,
+            capacity: bf.capacity,
+            num_hash_fns: bf.num_hash_fns,
+            hasher: bf.hasher,
+            class_hash_fns: bf.class_hash_fns,
+            num_inserted: AtomicUsize::new(bf.num_inserted),
+            probe_hashers: bf.probe_hashers,
+            seqlock: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testing::{assert_matches_dense, Backend};
+
+    impl Backend for AtomicBloomFilter<String> {
+        fn insert(&mut self, elem: String) -> bool {
+            Self::insert(self, elem)
+        }
+        fn insert_with_class(&mut self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::insert_with_class(self, elem, class)
+        }
+        fn has(&self, elem: String) -> bool {
+            Self::has(self, elem)
+        }
+        fn has_with_class(&self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::has_with_class(self, elem, class)
+        }
+        fn to_filter(&self) -> BloomFilter<String> {
+            Self::to_filter(self)
+        }
+    }
+
+    #[test]
+    fn matches_dense_filter() {
+        // An odd number of bytes leaves the last word partially used.
+        let builder = || BloomBuilder::new(1_000, 0.01).seed(7).tier(0.001);
+        assert_ne!(0, builder().build().byte_len() % 8);
+        assert_matches_dense(builder().build(), builder().build_atomic(), 200);
+    }
+
+    #[test]
+    fn conversion_and_clear() {
+        let atomic: AtomicBloomFilter<String> = BloomBuilder::new(1_000, 0.01).build_atomic();
+        assert_eq!(0, atomic.words.as_ptr() as usize % 64);
+        for i in 0..200 {
+            atomic.insert(format!("{}", i));
+        }
+        let back = AtomicBloomFilter::from(atomic.to_filter());
+        assert_eq!(atomic.params(), back.params());
+        assert!((0..200).all(|i| back.has(format!("{}", i))));
+        back.clear();
+        assert!(back.is_empty());
+        assert_eq!(0, back.count_ones());
+    }
+
+    #[test]
+    fn buffered_writer() {
+        let builder = || BloomBuilder::new(10_000, 0.01).seed(7).tier(0.001);
+        let direct: AtomicBloomFilter<String> = builder().build_atomic();
+        let buffered: AtomicBloomFilter<String> = builder().build_atomic();
+        let mut writer = buffered.writer(100);
+        for i in 0..1_000 {
+            direct.insert(format!("{}", i));
+            writer.insert(format!("{}", i));
+            assert!(writer.buffered_len() < 100);
+        }
+        direct.insert_with_class("foo".to_string(), 0).unwrap();
+        writer.insert_with_class("foo".to_string(), 0).unwrap();
+        assert!(writer.buffered_len() > 0);
+        drop(writer);
+        assert_eq!(direct.to_filter().bits, buffered.to_filter().bits);
+        assert_eq!(1_001, buffered.len());
+
+        // Buffered elements are only visible once flushed.
+        let mut writer = buffered.writer(1_000);
+        writer.insert("bar".to_string());
+        assert!(!buffered.has("bar".to_string()));
+        writer.flush();
+        assert_eq!(0, writer.buffered_len());
+        assert!(buffered.has("bar".to_string()));
+    }
+
+    #[test]
+    fn threads() {
+        let bf: Arc<AtomicBloomFilter<String>> =
+            Arc::new(BloomBuilder::new(10_000, 0.01).build_atomic());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let bf = bf.clone();
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        bf.insert(format!("{}-{}", t, i));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(4_000, bf.len());
+        assert!((0..4).all(|t| (0..1_000).all(|i| bf.has(format!("{}-{}", t, i)))));
+    }
+
+    #[test]
+    fn consistent_reads() {
+        let builder = || BloomBuilder::new(2_000, 0.000_001).seed(7);
+        let elems: Vec<String> = (0..2_000).map(|i| format!("{}", i)).collect();
+        // No element is a false positive before it is inserted, so every consistent
+        // read of elements inserted in order finds a prefix of them.
+        let mut dense: BloomFilter<String> = builder().build();
+        for elem in elems.iter() {
+            assert!(!dense.has(elem.clone()));
+            dense.insert(elem.clone());
+        }
+
+        let bf: Arc<AtomicBloomFilter<String>> =
+            Arc::new(builder().build_atomic().with_consistent_reads());
+        let writer = {
+            let (bf, elems) = (bf.clone(), elems.clone());
+            thread::spawn(move || {
+                for elem in elems {
+                    bf.insert(elem);
+                }
+            })
+        };
+        loop {
+            let found = bf.has_batch(&elems);
+            let prefix = found.iter().take_while(|found| **found).count();
+            assert!(found[prefix..].iter().all(|found| !found));
+            if prefix == elems.len() {
+                break;
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(dense.bits, bf.to_filter().bits);
+        assert!(bf.has_batch(&[]).is_empty());
+    }
+}
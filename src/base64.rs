@@ -0,0 +1,87 @@
+/// The alphabet of standard base64.
+pub(crate) const BASE64_STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// The alphabet of URL and filename safe base64.
+#[cfg_attr(not(all(feature = "murmur3", feature = "serde")), allow(dead_code))]
+pub(crate) const BASE64_URL: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encodes bytes as base64 with a given alphabet, padded with `=`.
+pub(crate) fn base64_encode(bytes: &[u8], alphabet: &[u8; 64]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut buf = [0u8; 3];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, buf[0], buf[1], buf[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(alphabet[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes padded base64 with a given alphabet, returning None if the input is not
+/// valid base64.
+pub(crate) fn base64_decode(s: &str, alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let last = i == s.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for c in &chunk[..4 - padding] {
+            let value = alphabet.iter().position(|a| a == c)?;
+            n = n << 6 | value as u32;
+        }
+        n <<= 6 * padding as u32;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64() {
+        // The test vectors of RFC 4648.
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (decoded, encoded) in vectors {
+            assert_eq!(encoded, base64_encode(decoded.as_bytes(), BASE64_STANDARD));
+            assert_eq!(
+                decoded.as_bytes(),
+                base64_decode(encoded, BASE64_STANDARD).unwrap()
+            );
+        }
+        assert_eq!(
+            vec![0xff, 0x00, 0xfe],
+            base64_decode("/wD+", BASE64_STANDARD).unwrap()
+        );
+        assert_eq!(
+            vec![0xff, 0x00, 0xfe],
+            base64_decode("_wD-", BASE64_URL).unwrap()
+        );
+        for invalid in ["Zg=", "Zg==Zg==", "Z===", "Zm9v-A==", "Zm 9"] {
+            assert_eq!(None, base64_decode(invalid, BASE64_STANDARD), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{probe_index, BloomFilter};
+
+/// The number of elements whose bits are tested together, one per 64-bit lane of a
+/// 256-bit vector.
+const LANES: usize = 4;
+
+/// Tests one bit of the filter's words for every lane, returning a mask with bit `lane`
+/// set if the bit at that lane's index is set.
+type TestBits = fn(&[u64], &[u64; LANES]) -> u32;
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Checks if the bloom filter contains each of a batch of elements, in order, as
+    /// `has` would for each of them. The whole batch is hashed upfront, and the bits of
+    /// several elements are then gathered and tested at once, which keeps many memory
+    /// loads in flight and is considerably faster than checking elements one by one
+    /// when scanning large numbers of keys. With the `simd` feature, the bits are tested
+    /// with AVX2 gathers on x86_64 processors supporting them.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo");
+    /// bf.insert("bar");
+    ///
+    /// assert_eq!(vec![true, false, true], bf.has_batch(&["foo", "nyan", "bar"]));
+    /// ```
+    pub fn has_batch(&self, elems: &[T]) -> Vec<bool> {
+        // Independent hashers derive no probes from a single hash, so there is nothing
+        // to gather ahead of hashing each probe.
+        if !self.probe_hashers.is_empty() {
+            return elems
+                .iter()
+                .map(|elem| self.has_with_probes(elem, self.num_hash_fns))
+                .collect();
+        }
+        let test_bits = test_bits_fn();
+        let hashes: Vec<(u64, u64)> = elems.iter().map(|elem| self.hash(elem)).collect();
+        let mut found = Vec::with_capacity(elems.len());
+        let mut lanes = hashes.chunks_exact(LANES);
+        for lanes in lanes.by_ref() {
+            let mask = self.probe_lanes(lanes, test_bits);
+            found.extend((0..LANES).map(|lane| mask & (1 << lane) != 0));
+        }
+        found.extend(
+            lanes
+                .remainder()
+                .iter()
+                .map(|hashes| self.has_hashed(*hashes, self.num_hash_fns)),
+        );
+        found
+    }
+    /// Probes the bits of a full set of lanes, returning the mask of lanes whose bits
+    /// are all set.
+    fn probe_lanes(&self, hashes: &[(u64, u64)], test_bits: TestBits) -> u32 {
+        let m = self.bit_len() as u64;
+        let mut mask = (1 << LANES) - 1;
+        for i in 0..self.num_hash_fns {
+            let idx = std::array::from_fn(|lane| probe_index(hashes[lane], i, m));
+            mask &= test_bits(&self.bits, &idx);
+            // If every lane has a bit at 0, none of the elements are in the filter.
+            if mask == 0 {
+                break;
+            }
+        }
+        mask
+    }
+}
+
+/// Picks the fastest way to test bits supported by the running processor.
+fn test_bits_fn() -> TestBits {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        return avx2::test_bits;
+    }
+    test_bits
+}
+
+fn test_bits(words: &[u64], idx: &[u64; LANES]) -> u32 {
+    idx.iter().enumerate().fold(0, |mask, (lane, idx)| {
+        let bit = (words[(idx / 64) as usize] >> (idx % 64)) & 1;
+        mask | (bit as u32) << lane
+    })
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::LANES;
+
+    /// Only picked by `test_bits_fn` once AVX2 support has been detected. Probes reduced
+    /// modulo the bit length of a filter always fall within its words, but the gather
+    /// cannot be bounds checked, so any other index is left to the scalar version.
+    pub(super) fn test_bits(words: &[u64], idx: &[u64; LANES]) -> u32 {
+        if idx.iter().any(|idx| ((idx / 64) as usize) >= words.len()) {
+            return super::test_bits(words, idx);
+        }
+        // SAFETY: AVX2 is supported by the processor, and every gathered word was
+        // checked to be within the filter's words.
+        unsafe { gather_test_bits(words, idx) }
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn gather_test_bits(words: &[u64], idx: &[u64; LANES]) -> u32 {
+        let idx = _mm256_loadu_si256(idx.as_ptr() as *const __m256i);
+        let pos = _mm256_srli_epi64::<6>(idx);
+        let shift = _mm256_and_si256(idx, _mm256_set1_epi64x(63));
+        let gathered = _mm256_i64gather_epi64::<8>(words.as_ptr() as *const i64, pos);
+        let one = _mm256_set1_epi64x(1);
+        let bits = _mm256_and_si256(_mm256_srlv_epi64(gathered, shift), one);
+        let set = _mm256_cmpeq_epi64(bits, one);
+        _mm256_movemask_pd(_mm256_castsi256_pd(set)) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BloomBuilder, HasherInstance, KeyedHasher};
+
+    use super::*;
+
+    #[test]
+    fn matches_has() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.05).seed(3).build();
+        for i in 0..1_000 {
+            bf.insert(format!("{}", i));
+        }
+        // Enough absent elements for some lanes to be false positives, and a batch
+        // that does not fill its last set of lanes.
+        let elems: Vec<String> = (500..3_003).map(|i| format!("{}", i)).collect();
+        let expected: Vec<bool> = elems.iter().map(|elem| bf.has(elem.clone())).collect();
+        assert_eq!(expected, bf.has_batch(&elems));
+        assert!(expected[..500].iter().all(|found| *found));
+        assert!(expected[500..].iter().any(|found| *found));
+        assert!(bf.has_batch(&[]).is_empty());
+
+        let hashers: Vec<Box<dyn HasherInstance<String>>> = (0..4)
+            .map(|seed| Box::new(KeyedHasher::new(seed)) as Box<dyn HasherInstance<String>>)
+            .collect();
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.05).hashers(hashers).build();
+        bf.insert("foo".to_string());
+        assert_eq!(
+            vec![true, false],
+            bf.has_batch(&["foo".to_string(), "bar".to_string()])
+        );
+    }
+
+    #[test]
+    fn scalar_and_picked_bits_agree() {
+        let words = [0x8000_0000_0000_0001, 0x10, u64::MAX];
+        for idx in [[0, 63, 68, 129], [1, 62, 64, 130], [68, 68, 0, 191]] {
+            assert_eq!(test_bits(&words, &idx), test_bits_fn()(&words, &idx));
+        }
+        assert_eq!(0b1111, test_bits(&words, &[0, 63, 68, 129]));
+        assert_eq!(0b1000, test_bits(&words, &[1, 62, 64, 130]));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{probe_index, split_hash, BloomFilter, Error, DIRTY_CHUNK_SIZE};
+
+/// The largest number of regions the bit indices of a bulk load are first split into,
+/// as a power of two, few enough for the processor to write to all of them as
+/// sequential streams.
+const REGION_SHIFT: u32 = 6;
+/// The number of parts each region is then split into, as a power of two, once its
+/// indices are in cache, so that the bits set for each part stay in the processor's
+/// L1 or L2 cache.
+const PART_SHIFT: u32 = 8;
+/// The number of hashes whose bit indices are bucketed at once, bounding the memory
+/// used by a bulk load to a little over a hundred MiB.
+const CHUNK_LEN: usize = 1 << 20;
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Inserts many hashes computed ahead of time at once, as `insert_hash` would for
+    /// each of them, for ETL jobs loading large numbers of pre-hashed keys. Rather than
+    /// setting the bits of one hash after another across the whole filter, the bit
+    /// indices of a large chunk of hashes are bucketed by the part of the filter they
+    /// fall in and set part by part, in ascending order, so that the filter is written
+    /// nearly sequentially. This pays off for filters much larger than the processor's
+    /// caches when random memory accesses are bound by latency; where they are bound
+    /// by memory bandwidth instead, it is about as fast as inserting hashes one by one.
+    ///
+    /// The bits set do not depend on the order of the hashes, so hashes can be passed
+    /// as they come, such as sorted by an earlier stage of a job. Fails with
+    /// `Error::IndependentHashers` if the filter was built with independent hashers via
+    /// `BloomBuilder::hashers`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, DefaultHasher, Hasher};
+    ///
+    /// let mut hashes: Vec<u64> = ["foo", "bar"].iter().map(DefaultHasher::hash).collect();
+    /// hashes.sort_unstable();
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).deterministic().build();
+    /// bf.bulk_insert_hashes(&hashes).unwrap();
+    /// assert!(bf.has("foo") && bf.has("bar"));
+    /// assert_eq!(2, bf.len());
+    /// ```
+    pub fn bulk_insert_hashes(&mut self, hashes: &[u64]) -> Result<(), Error> {
+        self.check_single_hasher()?;
+        let m = self.bit_len() as u64;
+        // Regions and parts cover a power of two number of bits each, so that the
+        // bucket of an index is found with a shift.
+        let region_shift = (64 - (m - 1).leading_zeros()).saturating_sub(REGION_SHIFT);
+        let part_shift = region_shift.saturating_sub(PART_SHIFT);
+        let mut indices = vec![];
+        let mut regions = vec![];
+        let mut parts = vec![];
+        let mut region_offsets = [0; (1 << REGION_SHIFT) + 1];
+        let mut part_offsets = [0; (1 << PART_SHIFT) + 1];
+        let mut dirty = vec![];
+        for chunk in hashes.chunks(CHUNK_LEN) {
+            indices.clear();
+            for hash in chunk {
+                let hashes = split_hash(*hash);
+                indices.extend((0..self.num_hash_fns).map(|i| probe_index(hashes, i, m)));
+            }
+            bucket(&indices, &mut regions, 0, region_shift, &mut region_offsets);
+            for (region, bounds) in region_offsets.windows(2).enumerate() {
+                let start = (region as u64) << region_shift;
+                let region = &regions[bounds[0]..bounds[1]];
+                bucket(region, &mut parts, start, part_shift, &mut part_offsets);
+                for (part, bounds) in part_offsets.windows(2).enumerate() {
+                    let start = start + ((part as u64) << part_shift);
+                    self.set_bits(&parts[bounds[0]..bounds[1]], start, &mut dirty);
+                }
+            }
+        }
+        self.num_inserted += hashes.len();
+        Ok(())
+    }
+    /// Sets the bits at indices from a starting index, which fall close to each other,
+    /// marking the chunks they change as dirty.
+    fn set_bits(&mut self, indices: &[u64], start: u64, dirty: &mut Vec<bool>) {
+        let Some(last) = indices.iter().max() else {
+            return;
+        };
+        // Changes are tracked per chunk without branching, which would be mispredicted
+        // as often as bits turn out to be set already.
+        let first_chunk = (start / 8) as usize / DIRTY_CHUNK_SIZE;
+        dirty.clear();
+        dirty.resize(
+            (*last / 8) as usize / DIRTY_CHUNK_SIZE - first_chunk + 1,
+            false,
+        );
+        for idx in indices {
+            let word = &mut self.bits[(idx / 64) as usize];
+            let bit = 1 << (idx % 64);
+            dirty[(idx / 8) as usize / DIRTY_CHUNK_SIZE - first_chunk] |= *word & bit == 0;
+            *word |= bit;
+        }
+        for (chunk, changed) in dirty.iter().enumerate() {
+            if *changed {
+                let byte = (first_chunk + chunk) * DIRTY_CHUNK_SIZE;
+                self.mark_dirty(byte, byte + 1);
+            }
+        }
+    }
+}
+
+/// Counting sorts bit indices from a starting index into buckets of `1 << shift` bits,
+/// leaving the offset of the start of each bucket in `offsets`, followed by the number
+/// of indices. Unlike a full sort, this takes linear time, and leaves the indices of
+/// each bucket in any order.
+fn bucket(indices: &[u64], out: &mut Vec<u64>, start: u64, shift: u32, offsets: &mut [usize]) {
+    offsets.iter_mut().for_each(|offset| *offset = 0);
+    for idx in indices {
+        offsets[((idx - start) >> shift) as usize + 1] += 1;
+    }
+    for bucket in 1..offsets.len() {
+        offsets[bucket] += offsets[bucket - 1];
+    }
+    out.resize(indices.len(), 0);
+    let mut next = offsets.to_vec();
+    for idx in indices {
+        let next = &mut next[((idx - start) >> shift) as usize];
+        out[*next] = *idx;
+        *next += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BloomBuilder, DefaultHasher, Hasher};
+
+    use super::*;
+
+    #[test]
+    fn matches_insert_hash() {
+        let builder = || BloomBuilder::<String>::new(500_000, 0.01).seed(9);
+        let mut bulk = builder().build();
+        let mut single = builder().build();
+
+        let mut hashes: Vec<u64> = (0..20_000)
+            .map(|i| DefaultHasher::hash(&format!("{}", i)))
+            .collect();
+        hashes.sort_unstable();
+        bulk.bulk_insert_hashes(&hashes).unwrap();
+        // Hashes in any order set the same bits.
+        hashes.reverse();
+        bulk.bulk_insert_hashes(&hashes[..100]).unwrap();
+        bulk.bulk_insert_hashes(&[]).unwrap();
+        for hash in hashes.iter().chain(&hashes[..100]) {
+            single.insert_hash(*hash).unwrap();
+        }
+        assert_eq!(single.bits, bulk.bits);
+        assert_eq!(20_100, bulk.len());
+        assert_eq!(single.dirty_len(), bulk.dirty_len());
+
+        // Filters with fewer bits than buckets.
+        let mut tiny: BloomFilter<&str> = BloomBuilder::new(1, 0.5).deterministic().build();
+        tiny.bulk_insert_hashes(&[DefaultHasher::hash(&"foo")])
+            .unwrap();
+        assert!(tiny.has("foo"));
+    }
+
+    #[test]
+    fn buckets() {
+        let mut out = vec![];
+        let mut offsets = [0; 4];
+        bucket(&[25, 10, 12, 27, 11], &mut out, 10, 3, &mut offsets);
+        assert_eq!([0, 3, 4, 5], offsets);
+        assert_eq!(vec![10, 12, 11, 25, 27], out);
+    }
+}
@@ -0,0 +1,371 @@
+use std::sync::Arc;
+
+use crate::crdt::Chunk;
+use crate::words::AlignedWords;
+use crate::{
+    base_hashes, class_num_hash_fns, filter_params, nth_probe, probes, tier_num_hash_fns, words,
+    BloomBuilder, BloomFilter, Error, FilterParams, HasherInstance, SnapshotDelta,
+};
+
+impl<T: AsRef<[u8]>> BloomBuilder<T> {
+    /// Builds a bloom filter holding its bits in separately allocated chunks of a
+    /// number of bytes, see `ChunkedBloomFilter`. The filter is sized and hashes items
+    /// exactly as the filter returned by `build` would, but no maximum false positive
+    /// rate is enforced. Panics if the chunk length is not a positive multiple of 8.
+    pub fn build_chunked(self, chunk_len: usize) -> ChunkedBloomFilter<T> {
+        assert!(
+            chunk_len > 0 && chunk_len.is_multiple_of(8),
+            "chunk length must be a positive multiple of 8 bytes"
+        );
+        let (num_hash_fns, size) = self.checked_sizing();
+        let num_chunks = size.div_ceil(chunk_len);
+        ChunkedBloomFilter {
+            chunks: (0..num_chunks).map(|_| None).collect(),
+            chunk_words: chunk_len / 8,
+            byte_len: size,
+            capacity: self.capacity,
+            num_hash_fns,
+            hasher: self.hasher,
+            class_hash_fns: self.tiers.iter().map(|p| tier_num_hash_fns(*p)).collect(),
+            num_inserted: 0,
+            probe_hashers: self.probe_hashers,
+            dirty: vec![false; num_chunks],
+        }
+    }
+}
+
+/// A bloom filter holding its bits in a list of fixed-size chunks rather than in a
+/// single contiguous allocation, for filters of many gigabytes, which may not find a
+/// contiguous region of memory that large. Built with `BloomBuilder::build_chunked`.
+///
+/// Chunks are only allocated once a bit in them is first set, so memory grows as the
+/// filter fills up, and clearing the filter frees them. Changed chunks are tracked so
+/// that they can be persisted one by one with `snapshot_delta`, in the format applied
+/// to a `BloomFilter` snapshot by `BloomFilter::apply_snapshot_delta`.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, ChunkedBloomFilter};
+///
+/// let mut bf: ChunkedBloomFilter<&str> = BloomBuilder::new(100_000_000, 0.01).build_chunked(1 << 20);
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// assert!(bf.allocated_len() <= 7 << 20);
+/// ```
+pub struct ChunkedBloomFilter<T: AsRef<[u8]>> {
+    /// The chunks of words, laid out like the words of a `BloomFilter`'s bits when put
+    /// end to end, or `None` for chunks with no bit set yet. Every chunk has
+    /// `chunk_words` words, except for the last one, which may be shorter.
+    chunks: Vec<Option<Box<[u64]>>>,
+    chunk_words: usize,
+    byte_len: usize,
+    capacity: u64,
+    num_hash_fns: u32,
+    hasher: Arc<dyn HasherInstance<T>>,
+    class_hash_fns: Vec<u32>,
+    num_inserted: usize,
+    probe_hashers: Vec<Arc<dyn HasherInstance<T>>>,
+    // The chunks changed since the last call to `snapshot_delta`.
+    dirty: Vec<bool>,
+}
+
+impl<T: AsRef<[u8]>> ChunkedBloomFilter<T> {
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, as in `BloomFilter::insert`.
+    pub fn insert(&mut self, elem: T) -> bool {
+        let num_hash_fns = self.num_hash_fns;
+        self.insert_with_probes(&elem, num_hash_fns)
+    }
+    /// Insert an element into the bloom filter with the number of hash functions of a
+    /// tier, as in `BloomFilter::insert_with_class`. Fails with `Error::UnknownClass` if the class
+    /// was not configured via `BloomBuilder::tier`.
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self.insert_with_probes(&elem, num_hash_fns))
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.probes(&elem, self.num_hash_fns)
+            .all(|idx| self.get_bit(idx))
+    }
+    /// Checks if the bloom filter contains an element inserted with a class, as in
+    /// `BloomFilter::has_with_class`.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self
+            .probes(&elem, num_hash_fns)
+            .all(|idx| self.get_bit(idx)))
+    }
+    /// Clear all set bits of the bloom filter, freeing every chunk.
+    pub fn clear(&mut self) {
+        for (chunk, dirty) in self.chunks.iter_mut().zip(self.dirty.iter_mut()) {
+            *dirty |= chunk.take().is_some();
+        }
+        self.num_inserted = 0;
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.num_inserted
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the number of bits set in the bloom filter.
+    pub fn count_ones(&self) -> usize {
+        self.chunks
+            .iter()
+            .flatten()
+            .flat_map(|chunk| chunk.iter())
+            .map(|w| w.count_ones() as usize)
+            .sum()
+    }
+    /// Returns the number of bits in the bloom filter.
+    pub fn bit_len(&self) -> usize {
+        self.byte_len * 8
+    }
+    /// Returns the number of bytes of the chunks allocated so far.
+    pub fn allocated_len(&self) -> usize {
+        self.chunks
+            .iter()
+            .flatten()
+            .map(|chunk| chunk.len() * 8)
+            .sum()
+    }
+    /// Returns the structural parameters of the bloom filter, identical to those of a
+    /// `BloomFilter` built with the same builder.
+    pub fn params(&self) -> FilterParams {
+        filter_params(
+            self.capacity,
+            self.bit_len() as u64,
+            self.num_hash_fns,
+            &self.class_hash_fns,
+            &self.hasher,
+            &self.probe_hashers,
+        )
+    }
+    /// Returns the chunks of the bloom filter that changed since its last snapshot, and
+    /// marks the filter as clean, as in `BloomFilter::snapshot_delta`. Each chunk of the
+    /// delta is one chunk of the filter, so that a filter can be flushed chunk by chunk.
+    /// Filters start out clean, so the first snapshot of a filter must be a full one,
+    /// such as with `to_filter`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, ChunkedBloomFilter};
+    ///
+    /// let builder = || BloomBuilder::new(1_000_000, 0.01).seed(42);
+    /// let mut bf: ChunkedBloomFilter<&str> = builder().build_chunked(4096);
+    /// let mut snapshot: BloomFilter<&str> = builder().build();
+    ///
+    /// bf.insert("hello");
+    /// let delta = bf.snapshot_delta();
+    /// assert!(delta.chunks.len() <= 7);
+    ///
+    /// snapshot.apply_snapshot_delta(&delta).unwrap();
+    /// assert!(snapshot.has("hello"));
+    /// ```
+    pub fn snapshot_delta(&mut self) -> SnapshotDelta {
+        let chunks = (0..self.chunks.len())
+            .filter(|i| self.dirty[*i])
+            .map(|i| {
+                let offset = i * self.chunk_words * 8;
+                let byte_len = (self.chunk_words * 8).min(self.byte_len - offset);
+                let bytes = match &self.chunks[i] {
+                    Some(chunk) => words::to_bytes(chunk, byte_len),
+                    None => vec![0; byte_len],
+                };
+                Chunk { offset, bytes }
+            })
+            .collect();
+        self.dirty.iter_mut().for_each(|dirty| *dirty = false);
+        SnapshotDelta {
+            num_inserted: self.num_inserted as u64,
+            chunks,
+        }
+    }
+    /// Converts a bloom filter into one holding its bits in chunks of a number of bytes,
+    /// with the same parameters, hashers and set bits. Chunks with no bit set are not
+    /// allocated. Fails with `Error::InvalidParams` if the chunk length is not a
+    /// positive multiple of 8.
+    pub fn from_filter(
+        bf: BloomFilter<T>,
+        chunk_len: usize,
+    ) -> Result<ChunkedBloomFilter<T>, Error> {
+        if chunk_len == 0 || !chunk_len.is_multiple_of(8) {
+            return Err(Error::InvalidParams);
+        }
+        let chunks: Vec<Option<Box<[u64]>>> = bf
+            .bits
+            .chunks(chunk_len / 8)
+            .map(|chunk| match chunk.iter().any(|w| *w != 0) {
+                true => Some(chunk.into()),
+                false => None,
+            })
+            .collect();
+        Ok(ChunkedBloomFilter {
+            dirty: vec![false; chunks.len()],
+            chunks,
+            chunk_words: chunk_len / 8,
+            byte_len: bf.byte_len,
+            capacity: bf.capacity,
+            num_hash_fns: bf.num_hash_fns,
+            hasher: bf.hasher,
+            class_hash_fns: bf.class_hash_fns,
+            num_inserted: bf.num_inserted,
+            probe_hashers: bf.probe_hashers,
+        })
+    }
+    /// Returns a copy of the bloom filter as a `BloomFilter`, which holds its bits in a
+    /// single contiguous allocation.
+    pub fn to_filter(&self) -> BloomFilter<T> {
+        let mut bits = AlignedWords::zeroed(words::word_len(self.byte_len));
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if let Some(chunk) = chunk {
+                bits[i * self.chunk_words..][..chunk.len()].copy_from_slice(chunk);
+            }
+        }
+        BloomFilter {
+            bits,
+            byte_len: self.byte_len,
+            capacity: self.capacity,
+            num_hash_fns: self.num_hash_fns,
+            hasher: self.hasher.clone(),
+            class_hash_fns: self.class_hash_fns.clone(),
+            num_inserted: self.num_inserted,
+            max_fp_rate: None,
+            probe_hashers: self.probe_hashers.clone(),
+            dirty: vec![],
+        }
+    }
+    fn insert_with_probes(&mut self, elem: &T, num_hash_fns: u32) -> bool {
+        self.num_inserted += 1;
+        let hashes = base_hashes(&*self.hasher, &self.probe_hashers, elem);
+        let m = self.bit_len() as u64;
+        let mut changed = false;
+        for i in 0..num_hash_fns {
+            let idx = nth_probe(hashes, &self.probe_hashers, elem, i, m);
+            changed |= self.set_bit(idx);
+        }
+        changed
+    }
+    /// Sets the bit at an index, allocating its chunk if needed, and returns true if it
+    /// was not set before.
+    fn set_bit(&mut self, idx: u64) -> bool {
+        let pos = (idx / 64) as usize;
+        let (i, pos) = (pos / self.chunk_words, pos % self.chunk_words);
+        let num_words = words::word_len(self.byte_len);
+        let chunk_words = self.chunk_words.min(num_words - i * self.chunk_words);
+        let chunk = self.chunks[i].get_or_insert_with(|| vec![0; chunk_words].into());
+        let mask = 1 << (idx % 64);
+        let changed = chunk[pos] & mask == 0;
+        chunk[pos] |= mask;
+        self.dirty[i] |= changed;
+        changed
+    }
+    fn get_bit(&self, idx: u64) -> bool {
+        let pos = (idx / 64) as usize;
+        match &self.chunks[pos / self.chunk_words] {
+            Some(chunk) => chunk[pos % self.chunk_words] & (1 << (idx % 64)) != 0,
+            None => false,
+        }
+    }
+    fn probes<'a>(&'a self, elem: &'a T, num_hash_fns: u32) -> impl Iterator<Item = u64> + 'a {
+        probes(
+            &*self.hasher,
+            &self.probe_hashers,
+            elem,
+            num_hash_fns,
+            self.bit_len() as u64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_matches_dense, Backend};
+
+    impl Backend for ChunkedBloomFilter<String> {
+        fn insert(&mut self, elem: String) -> bool {
+            Self::insert(self, elem)
+        }
+        fn insert_with_class(&mut self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::insert_with_class(self, elem, class)
+        }
+        fn has(&self, elem: String) -> bool {
+            Self::has(self, elem)
+        }
+        fn has_with_class(&self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::has_with_class(self, elem, class)
+        }
+        fn to_filter(&self) -> BloomFilter<String> {
+            Self::to_filter(self)
+        }
+    }
+
+    #[test]
+    fn matches_dense_filter() {
+        // An odd number of bytes leaves the last chunk partially used.
+        let builder = || BloomBuilder::new(10_000, 0.01).seed(7).tier(0.001);
+        let dense: BloomFilter<String> = builder().build();
+        assert_ne!(0, dense.byte_len() % 1024);
+        let chunked: ChunkedBloomFilter<String> = builder().build_chunked(1024);
+        assert_eq!(0, chunked.allocated_len());
+        assert_matches_dense(builder().build(), chunked, 1_000);
+    }
+
+    #[test]
+    fn allocates_set_chunks() {
+        let builder = || BloomBuilder::new(10_000, 0.01).seed(7);
+        let mut dense: BloomFilter<String> = builder().build();
+        let mut chunked: ChunkedBloomFilter<String> = builder().build_chunked(1024);
+        for i in 0..1_000 {
+            dense.insert(format!("{}", i));
+            chunked.insert(format!("{}", i));
+        }
+        assert_eq!(dense.bits.len() * 8, chunked.allocated_len());
+
+        // Converting a filter only allocates the chunks with a bit set.
+        let mut sparse: BloomFilter<String> = builder().build();
+        sparse.insert("foo".to_string());
+        let (params, bits) = (sparse.params(), sparse.bits.clone());
+        assert_eq!(
+            Err(Error::InvalidParams),
+            ChunkedBloomFilter::from_filter(sparse.clone(), 0).map(|_| ())
+        );
+        assert_eq!(
+            Err(Error::InvalidParams),
+            ChunkedBloomFilter::from_filter(sparse.clone(), 12).map(|_| ())
+        );
+        let converted = ChunkedBloomFilter::from_filter(sparse, 64).unwrap();
+        assert_eq!(params, converted.params());
+        assert_eq!(bits, converted.to_filter().bits);
+        assert!(converted.allocated_len() <= converted.params().num_hash_fns as usize * 64);
+    }
+
+    #[test]
+    fn grows_and_flushes_per_chunk() {
+        let builder = || BloomBuilder::new(100_000, 0.01).seed(3);
+        let mut chunked: ChunkedBloomFilter<&str> = builder().build_chunked(4096);
+        let mut snapshot: BloomFilter<&str> = builder().build();
+        chunked.insert("foo");
+        assert!(chunked.allocated_len() <= 7 * 4096);
+
+        let delta = chunked.snapshot_delta();
+        assert_eq!(chunked.allocated_len(), 4096 * delta.chunks.len());
+        snapshot.apply_snapshot_delta(&delta).unwrap();
+        assert!(snapshot.has("foo"));
+        assert!(chunked.snapshot_delta().chunks.is_empty());
+
+        // Clearing frees every chunk, and zeroes the chunks it frees in the snapshot.
+        chunked.clear();
+        assert_eq!(0, chunked.allocated_len());
+        assert!(!chunked.has("foo"));
+        snapshot
+            .apply_snapshot_delta(&chunked.snapshot_delta())
+            .unwrap();
+        assert_eq!(0, snapshot.count_ones());
+    }
+}
@@ -0,0 +1,275 @@
+use std::io::{self, Write};
+
+use crate::delta::{read_varint, write_varint};
+use crate::format::invalid;
+use crate::{BloomFilter, Error};
+
+/// The largest number of bytes the bits of a compressed filter may decompress to,
+/// 1 GiB. Decoding compressed filters with larger bit arrays fails, so that a small
+/// malicious input cannot expand into an unbounded allocation. Uncompressed filters
+/// are not limited, as their bits are read from the input itself.
+pub const MAX_DECOMPRESSED_LEN: u64 = 1 << 30;
+
+/// How the bits of an encoded filter are compressed, see
+/// `BloomFilter::write_to_compressed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// The bits are written as is.
+    None,
+    /// Runs of zero bytes are replaced by their length, which shrinks mostly empty
+    /// filters, such as freshly built ones, at almost no cost.
+    Rle,
+    /// The bits are compressed with zstd at a given level, from 1 to 22, which also
+    /// shrinks filters with scattered bits set, at a higher cost. Requires the `zstd`
+    /// feature.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Identifies the compression in the flags of an encoded filter.
+    pub(crate) fn codec(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Rle => 1,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => 2,
+        }
+    }
+    /// Returns the compression identified by a codec, with the level of zstd being
+    /// irrelevant to decompression. Fails with `Error::InvalidEncoding` for unknown
+    /// codecs, and for zstd unless the `zstd` feature is enabled.
+    pub(crate) fn from_codec(codec: u8) -> Result<Compression, Error> {
+        match codec {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Rle),
+            #[cfg(feature = "zstd")]
+            2 => Ok(Compression::Zstd(0)),
+            _ => Err(Error::InvalidEncoding),
+        }
+    }
+    /// Compresses bits, failing with an error of kind `InvalidInput` if they are larger
+    /// than `MAX_DECOMPRESSED_LEN`, as they could not be decoded.
+    pub(crate) fn compress(self, bits: &[u8]) -> io::Result<Vec<u8>> {
+        if self != Compression::None && bits.len() as u64 > MAX_DECOMPRESSED_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "filter is too large to be compressed",
+            ));
+        }
+        match self {
+            Compression::None => Ok(bits.to_vec()),
+            Compression::Rle => Ok(rle_encode(bits)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => zstd::bulk::compress(bits, level),
+        }
+    }
+    /// Decompresses bits, failing with `Error::InvalidEncoding` unless they decompress
+    /// to exactly the given number of bytes, which must not exceed
+    /// `MAX_DECOMPRESSED_LEN` for compressed bits.
+    pub(crate) fn decompress(self, payload: Vec<u8>, byte_len: u64) -> io::Result<Vec<u8>> {
+        if self != Compression::None && byte_len > MAX_DECOMPRESSED_LEN {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let bits = match self {
+            Compression::None => payload,
+            Compression::Rle => rle_decode(&payload, byte_len).map_err(invalid)?,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => {
+                // Decompression stops one byte past the expected length, so a malicious
+                // payload cannot expand without bound.
+                let mut bits = vec![];
+                let decoder = zstd::stream::read::Decoder::new(&payload[..])?;
+                io::Read::read_to_end(&mut io::Read::take(decoder, byte_len + 1), &mut bits)
+                    .map_err(|_| invalid(Error::InvalidEncoding))?;
+                bits
+            }
+        };
+        if bits.len() as u64 != byte_len {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        Ok(bits)
+    }
+}
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Streams the bloom filter to a writer in the format of `to_bytes`, with its bits
+    /// compressed, so that mostly empty filters take a fraction of their size when
+    /// replicated or persisted. Compressed filters are decoded by `from_bytes` and
+    /// `read_from` like any other, but cannot be memory-mapped. Filters with more than
+    /// `MAX_DECOMPRESSED_LEN` bytes of bits cannot be compressed.
+    ///
+    /// The compression is identified in bits 2 and 3 of the flags of the encoding, and
+    /// the compressed bits are preceded by their length as a u64. Run-length encoded
+    /// bits alternate between the length of a run of zero bytes and the length of a run
+    /// of other bytes, followed by those bytes, both as LEB128 variable-length integers.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, Compression};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(100_000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let mut compressed = vec![];
+    /// bf.write_to_compressed(&mut compressed, Compression::Rle).unwrap();
+    /// assert!(compressed.len() < 200);
+    ///
+    /// let restored: BloomFilter<&str> = BloomFilter::from_bytes(&compressed).unwrap();
+    /// assert!(restored.has("hello"));
+    /// ```
+    pub fn write_to_compressed<W: Write>(&self, w: W, compression: Compression) -> io::Result<()> {
+        self.write_encoded(w, compression)
+    }
+}
+
+fn rle_encode(bits: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut rest = bits;
+    while !rest.is_empty() {
+        let zeros = rest.iter().take_while(|b| **b == 0).count();
+        rest = &rest[zeros..];
+        let literals = rest.iter().take_while(|b| **b != 0).count();
+        write_varint(&mut out, zeros as u64);
+        write_varint(&mut out, literals as u64);
+        out.extend_from_slice(&rest[..literals]);
+        rest = &rest[literals..];
+    }
+    out
+}
+
+fn rle_decode(mut input: &[u8], byte_len: u64) -> Result<Vec<u8>, Error> {
+    let mut bits = vec![];
+    while !input.is_empty() {
+        let varint = |input: &mut &[u8]| read_varint(input).map_err(|_| Error::InvalidEncoding);
+        let zeros = varint(&mut input)?;
+        let literals = varint(&mut input)?;
+        let len = (bits.len() as u64)
+            .checked_add(zeros)
+            .and_then(|len| len.checked_add(literals));
+        if len.is_none_or(|len| len > byte_len) || literals > input.len() as u64 {
+            return Err(Error::InvalidEncoding);
+        }
+        bits.resize(bits.len() + zeros as usize, 0);
+        let (bytes, rest) = input.split_at(literals as usize);
+        bits.extend_from_slice(bytes);
+        input = rest;
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{crc32_update, Header};
+    use crate::{BloomBuilder, FilterParams};
+
+    #[test]
+    fn rle() {
+        for bits in [
+            vec![0; 100],
+            vec![1, 2, 3],
+            vec![0, 0, 5, 0, 6, 7, 0, 0, 0],
+            vec![],
+        ] {
+            let encoded = rle_encode(&bits);
+            assert_eq!(bits, rle_decode(&encoded, bits.len() as u64).unwrap());
+        }
+        assert_eq!(vec![100, 0], rle_encode(&[0; 100]));
+        assert_eq!(
+            vec![2, 1, 5, 1, 2, 6, 7, 3, 0],
+            rle_encode(&[0, 0, 5, 0, 6, 7, 0, 0, 0])
+        );
+
+        // Runs past the expected length, or with missing bytes, are rejected.
+        assert_eq!(Err(Error::InvalidEncoding), rle_decode(&[101, 0], 100));
+        assert_eq!(Err(Error::InvalidEncoding), rle_decode(&[0, 2, 1], 100));
+        assert_eq!(Err(Error::InvalidEncoding), rle_decode(&[0x80], 100));
+    }
+
+    fn roundtrip(compression: Compression) {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).build();
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        let mut compressed = vec![];
+        bf.write_to_compressed(&mut compressed, compression)
+            .unwrap();
+        assert!(compressed.len() < bf.byte_len() / 4, "{}", compressed.len());
+
+        let restored: BloomFilter<String> = BloomFilter::from_bytes(&compressed).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+
+        let decode = |bytes: &[u8]| BloomFilter::<String>::from_bytes(bytes).err();
+        let mut corrupted = compressed.clone();
+        corrupted[70] ^= 1;
+        assert_eq!(Some(Error::Corrupted), decode(&corrupted));
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            decode(&compressed[..compressed.len() - 1])
+        );
+    }
+
+    #[test]
+    fn rle_roundtrip() {
+        roundtrip(Compression::Rle);
+        let bf: BloomFilter<&str> = BloomBuilder::new(10_000, 0.01).build();
+        let mut uncompressed = vec![];
+        bf.write_to_compressed(&mut uncompressed, Compression::None)
+            .unwrap();
+        assert_eq!(bf.to_bytes(), uncompressed);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_roundtrip() {
+        roundtrip(Compression::Zstd(3));
+    }
+
+    #[test]
+    fn decompression_bomb() {
+        // A single run of zeros claiming to expand to 1 TiB.
+        let byte_len = 1 << 40;
+        let header = BloomFilter::<&str>::new(100, 0.01).header();
+        let header = Header {
+            params: FilterParams {
+                bit_len: byte_len * 8,
+                ..header.params
+            },
+            compression: Compression::Rle,
+            ..header
+        };
+        let mut payload = vec![];
+        write_varint(&mut payload, byte_len);
+        write_varint(&mut payload, 0);
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).unwrap();
+        bytes.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes.extend_from_slice(&crc32_update(0, &bytes).to_be_bytes());
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            BloomFilter::<&str>::from_bytes(&bytes).err()
+        );
+
+        let empty = rle_encode(&[0; 64]);
+        assert!(Compression::Rle
+            .decompress(empty.clone(), MAX_DECOMPRESSED_LEN + 1)
+            .is_err());
+        assert_eq!(vec![0; 64], Compression::Rle.decompress(empty, 64).unwrap());
+    }
+
+    #[test]
+    fn unknown_codec() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).build();
+        let mut bytes = bf.to_bytes();
+        bytes[5] |= 3 << 2;
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            BloomFilter::<&str>::from_bytes(&bytes).err()
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::format::{crc32_update, invalid, read_array, MAX_TIERS};
+use crate::hasher::builtin_hasher;
+use crate::{BloomFilter, Error, FilterParams};
+
+/// The bytes every archive starts with.
+const ARCHIVE_MAGIC: [u8; 4] = *b"FBAR";
+/// The version of the archive format. Version 1 is the same layout with the capacity
+/// of each filter in the index encoded as a u32, and can still be opened.
+const ARCHIVE_VERSION: u8 = 2;
+/// The size of the archive header: the magic bytes and version, followed by the
+/// offset, length and CRC-32 checksum of the index.
+const HEADER_LEN: u64 = 25;
+
+/// Many named bloom filters persisted together in a single file, so that applications
+/// maintaining hundreds of small filters, such as one per tenant or per shard, keep
+/// them in one file rather than one file each. Only the index of the archive is held in
+/// memory, and filters are read from the file as they are requested.
+///
+/// The archive starts with the magic bytes `FBAR` and the version of the format as a
+/// single byte, followed by the offset and length of the index as u64s and its CRC-32
+/// checksum as a u32. The rest of the file holds each filter encoded as by
+/// `BloomFilter::write_to`, along with the index, which lists the name, offset, length
+/// and parameters of every filter. All numbers are big-endian.
+///
+/// Every insert and removal appends to the file and then points the header to a new
+/// index, so a crash leaves the archive as it was before the interrupted change.
+/// Replaced filters and old indexes stay in the file until it is compacted, which
+/// happens once they take more space than the live filters, or with `compact`.
+///
+/// Only filters built with one of the package's hashers can be archived, as they are
+/// restored from their encoding.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter, FilterArchive};
+///
+/// let path = std::env::temp_dir().join("flowerbloom-archive-doc.fbar");
+/// let mut archive = FilterArchive::open(&path).unwrap();
+///
+/// let mut bf: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+/// bf.insert("hello");
+/// archive.insert("greetings", &bf).unwrap();
+/// drop(archive);
+///
+/// let archive = FilterArchive::open(&path).unwrap();
+/// let restored: BloomFilter<&str> = archive.get("greetings").unwrap().unwrap();
+/// assert!(restored.has("hello"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct FilterArchive {
+    file: File,
+    path: PathBuf,
+    index: BTreeMap<String, Entry>,
+    index_offset: u64,
+    /// Where the next filter is appended, right past the current index.
+    end: u64,
+    /// The number of bytes of the file taken by replaced filters and old indexes.
+    garbage_len: u64,
+}
+
+/// Where a filter is held in an archive, and its parameters.
+struct Entry {
+    offset: u64,
+    len: u64,
+    params: FilterParams,
+}
+
+impl FilterArchive {
+    /// Opens the archive at a path, creating an empty one if the file does not exist.
+    /// Fails with an error of kind `InvalidData` if the file is not an archive, or if
+    /// its index does not match its checksum.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FilterArchive> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.metadata()?.len() == 0 {
+            write_index(&mut file, &BTreeMap::new(), HEADER_LEN)?;
+            sync_parent(&path)?;
+        }
+        let file_len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(0))?;
+        if read_array(&mut file)? != ARCHIVE_MAGIC {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let [version] = read_array(&mut file)?;
+        if version == 0 || version > ARCHIVE_VERSION {
+            return Err(invalid(Error::UnsupportedVersion(version)));
+        }
+        let index_offset = u64::from_be_bytes(read_array(&mut file)?);
+        let index_len = u64::from_be_bytes(read_array(&mut file)?);
+        let index_crc = u32::from_be_bytes(read_array(&mut file)?);
+        let end = index_offset
+            .checked_add(index_len)
+            .filter(|end| index_offset >= HEADER_LEN && *end <= file_len)
+            .ok_or_else(|| invalid(Error::InvalidEncoding))?;
+
+        let mut bytes = vec![0; index_len as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut bytes)?;
+        if crc32_update(0, &bytes) != index_crc {
+            return Err(invalid(Error::Corrupted));
+        }
+        let index = decode_index(&bytes, index_offset, version).map_err(invalid)?;
+        let live_len: u64 = index.values().map(|entry| entry.len).sum();
+        Ok(FilterArchive {
+            file,
+            path,
+            index,
+            index_offset,
+            end,
+            garbage_len: (end - HEADER_LEN - index_len).saturating_sub(live_len),
+        })
+    }
+    /// Reads the filter with a given name from the archive, returning `None` if there is
+    /// no such filter. Errors are reported as by `BloomFilter::read_from`.
+    pub fn get<T: AsRef<[u8]>>(&self, name: &str) -> io::Result<Option<BloomFilter<T>>> {
+        let Some(entry) = self.index.get(name) else {
+            return Ok(None);
+        };
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut bytes = vec![0; entry.len as usize];
+        file.read_exact(&mut bytes)?;
+        BloomFilter::from_bytes(&bytes).map(Some).map_err(invalid)
+    }
+    /// Adds a filter to the archive under a name, replacing any filter previously held
+    /// under that name, and syncs the archive to disk. Fails with `Error::UnknownHasher`
+    /// if the filter uses a custom hasher or independent hashers, as it could not be
+    /// restored.
+    pub fn insert<T: AsRef<[u8]>>(
+        &mut self,
+        name: &str,
+        filter: &BloomFilter<T>,
+    ) -> io::Result<()> {
+        let params = filter.params();
+        if builtin_hasher::<T>(params.hasher_id, params.seed).is_none() {
+            return Err(invalid(Error::UnknownHasher(params.hasher_id)));
+        }
+        let offset = self.end;
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut w = BufWriter::new(&self.file);
+        filter.write_to(&mut w)?;
+        w.flush()?;
+        drop(w);
+        let len = self.file.stream_position()? - offset;
+
+        let old = self.index.insert(
+            name.to_string(),
+            Entry {
+                offset,
+                len,
+                params,
+            },
+        );
+        self.commit(old, offset + len)
+    }
+    /// Removes the filter with a given name from the archive, and syncs the archive to
+    /// disk. Returns false if there was no such filter.
+    pub fn remove(&mut self, name: &str) -> io::Result<bool> {
+        let Some(old) = self.index.remove(name) else {
+            return Ok(false);
+        };
+        self.commit(Some(old), self.end)?;
+        Ok(true)
+    }
+    /// Returns the parameters of the filter with a given name, without reading it.
+    pub fn params(&self, name: &str) -> Option<&FilterParams> {
+        self.index.get(name).map(|entry| &entry.params)
+    }
+    /// Returns true if the archive holds a filter with a given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+    /// Returns the names of the filters in the archive, in lexicographic order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+    /// Returns the number of filters in the archive.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+    /// Returns true if the archive holds no filters.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+    /// Returns the number of bytes of the file taken by replaced or removed filters and
+    /// old indexes, which are reclaimed by `compact`.
+    pub fn garbage_len(&self) -> u64 {
+        self.garbage_len
+    }
+    /// Rewrites the archive with only its live filters, reclaiming the space taken by
+    /// replaced or removed ones. The new archive is written to a temporary file which
+    /// then replaces the archive, so a crash leaves either the old or the new one.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let tmp = tmp_path(&self.path);
+        let mut out = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        let mut w = BufWriter::new(&mut out);
+        w.write_all(&[0; HEADER_LEN as usize])?;
+        let mut index = BTreeMap::new();
+        let mut offset = HEADER_LEN;
+        for (name, entry) in self.index.iter() {
+            let mut file = &self.file;
+            file.seek(SeekFrom::Start(entry.offset))?;
+            io::copy(&mut file.take(entry.len), &mut w)?;
+            index.insert(
+                name.clone(),
+                Entry {
+                    offset,
+                    len: entry.len,
+                    params: entry.params.clone(),
+                },
+            );
+            offset += entry.len;
+        }
+        w.flush()?;
+        drop(w);
+        let index_len = write_index(&mut out, &index, offset)?;
+        fs::rename(&tmp, &self.path)?;
+        sync_parent(&self.path)?;
+        self.file = out;
+        self.index = index;
+        self.index_offset = offset;
+        self.end = offset + index_len;
+        self.garbage_len = 0;
+        Ok(())
+    }
+    /// Appends a new index past the archive's filters and points the header to it,
+    /// accounting for a replaced or removed entry, and compacts the archive if it holds
+    /// more garbage than live filters.
+    fn commit(&mut self, old: Option<Entry>, index_offset: u64) -> io::Result<()> {
+        let index_len = write_index(&mut self.file, &self.index, index_offset)?;
+        self.garbage_len += self.end - self.index_offset + old.map_or(0, |entry| entry.len);
+        self.index_offset = index_offset;
+        self.end = index_offset + index_len;
+        let live_len: u64 = self.index.values().map(|entry| entry.len).sum();
+        if self.garbage_len > live_len {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes an index at an offset of an archive, truncates the file right past it, and
+/// once it is synced, points the header to it. Returns the length of the index.
+fn write_index(file: &mut File, index: &BTreeMap<String, Entry>, offset: u64) -> io::Result<u64> {
+    let bytes = encode_index(index);
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&bytes)?;
+    file.set_len(offset + bytes.len() as u64)?;
+    file.sync_all()?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    header.extend_from_slice(&ARCHIVE_MAGIC);
+    header.push(ARCHIVE_VERSION);
+    header.extend_from_slice(&offset.to_be_bytes());
+    header.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+    header.extend_from_slice(&crc32_update(0, &bytes).to_be_bytes());
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.sync_data()?;
+    Ok(bytes.len() as u64)
+}
+
+/// Encodes an index as the number of filters as a u32, followed by the name of each
+/// filter as its length as a u32 and its UTF-8 bytes, its offset, length and number of
+/// bits and its capacity as u64s, its number of hash functions as a u32, its hasher id
+/// as a u64, a byte set to 1 if it is seeded followed by its seed as a u64, and the
+/// number of tiers as a u32 followed by the number of hash functions of each tier as
+/// u32s.
+fn encode_index(index: &BTreeMap<String, Entry>) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(&(index.len() as u32).to_be_bytes());
+    for (name, entry) in index.iter() {
+        let params = &entry.params;
+        out.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&entry.offset.to_be_bytes());
+        out.extend_from_slice(&entry.len.to_be_bytes());
+        out.extend_from_slice(&params.bit_len.to_be_bytes());
+        out.extend_from_slice(&params.capacity.to_be_bytes());
+        out.extend_from_slice(&params.num_hash_fns.to_be_bytes());
+        out.extend_from_slice(&params.hasher_id.to_be_bytes());
+        out.push(params.seed.is_some() as u8);
+        out.extend_from_slice(&params.seed.unwrap_or(0).to_be_bytes());
+        out.extend_from_slice(&(params.tier_hash_fns.len() as u32).to_be_bytes());
+        for num_hash_fns in &params.tier_hash_fns {
+            out.extend_from_slice(&num_hash_fns.to_be_bytes());
+        }
+    }
+    out
+}
+
+/// Decodes an index written by a version of the archive format, checking that every
+/// filter lies before the index itself.
+fn decode_index(
+    mut input: &[u8],
+    index_offset: u64,
+    version: u8,
+) -> Result<BTreeMap<String, Entry>, Error> {
+    let r = &mut input;
+    let mut index = BTreeMap::new();
+    let num_entries = u32::from_be_bytes(read_array(r).map_err(|_| Error::InvalidEncoding)?);
+    for _ in 0..num_entries {
+        let u32_at = |r: &mut &[u8]| {
+            read_array(r)
+                .map(u32::from_be_bytes)
+                .map_err(|_| Error::InvalidEncoding)
+        };
+        let name_len = u32_at(r)? as usize;
+        if name_len > r.len() {
+            return Err(Error::InvalidEncoding);
+        }
+        let (name, rest) = r.split_at(name_len);
+        let name = String::from_utf8(name.to_vec()).map_err(|_| Error::InvalidEncoding)?;
+        *r = rest;
+        let u64_at = |r: &mut &[u8]| {
+            read_array(r)
+                .map(u64::from_be_bytes)
+                .map_err(|_| Error::InvalidEncoding)
+        };
+        let offset = u64_at(r)?;
+        let len = u64_at(r)?;
+        let bit_len = u64_at(r)?;
+        let capacity = match version {
+            1 => u32_at(r)? as u64,
+            _ => u64_at(r)?,
+        };
+        let num_hash_fns = u32_at(r)?;
+        let hasher_id = u64_at(r)?;
+        let [seeded] = read_array(r).map_err(|_| Error::InvalidEncoding)?;
+        let seed = u64_at(r)?;
+        let num_tiers = u32_at(r)?;
+        if num_tiers > MAX_TIERS {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut tier_hash_fns = vec![];
+        for _ in 0..num_tiers {
+            tier_hash_fns.push(u32_at(r)?);
+        }
+        let fits = offset
+            .checked_add(len)
+            .is_some_and(|end| offset >= HEADER_LEN && end <= index_offset);
+        if !fits || seeded > 1 {
+            return Err(Error::InvalidEncoding);
+        }
+        let params = FilterParams {
+            capacity,
+            bit_len,
+            num_hash_fns,
+            tier_hash_fns,
+            hasher_id,
+            seed: (seeded == 1).then_some(seed),
+        };
+        index.insert(
+            name,
+            Entry {
+                offset,
+                len,
+                params,
+            },
+        );
+    }
+    if !input.is_empty() {
+        return Err(Error::InvalidEncoding);
+    }
+    Ok(index)
+}
+
+/// Returns the path of the temporary file a file is written to before being renamed
+/// into place, next to it and named after its full file name, so that files differing
+/// only by their extension do not share one.
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomBuilder;
+
+    fn archive_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("flowerbloom-archive-{}", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn filter(items: &[&str]) -> BloomFilter<String> {
+        let mut bf = BloomBuilder::new(1_000, 0.01).seed(7).tier(0.001).build();
+        for item in items {
+            bf.insert(item.to_string());
+        }
+        bf
+    }
+
+    #[test]
+    fn roundtrip() {
+        let path = archive_path("roundtrip");
+        let mut archive = FilterArchive::open(&path).unwrap();
+        assert!(archive.is_empty());
+        for i in 0..100 {
+            let name = format!("tenant-{:03}", i);
+            archive.insert(&name, &filter(&[&name])).unwrap();
+        }
+        assert!(archive.remove("tenant-050").unwrap());
+        assert!(!archive.remove("tenant-050").unwrap());
+        drop(archive);
+
+        let archive = FilterArchive::open(&path).unwrap();
+        assert_eq!(99, archive.len());
+        assert_eq!(Some("tenant-000"), archive.names().next());
+        assert!(!archive.contains("tenant-050"));
+        assert!(archive.get::<String>("tenant-050").unwrap().is_none());
+        assert_eq!(Some(&filter(&[]).params()), archive.params("tenant-007"));
+        let bf: BloomFilter<String> = archive.get("tenant-007").unwrap().unwrap();
+        assert_eq!(filter(&["tenant-007"]).bits, bf.bits);
+        assert_eq!(1, bf.len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn version_1_index() {
+        let params = filter(&[]).params();
+        let entry = Entry {
+            offset: HEADER_LEN,
+            len: 100,
+            params: params.clone(),
+        };
+        let index = BTreeMap::from([("foo".to_string(), entry)]);
+        let mut bytes = encode_index(&index);
+        // The index of version 1 held the capacity as a u32, following the name, offset,
+        // length and number of bits.
+        let capacity = 4 + 4 + 3 + 8 * 3;
+        bytes.drain(capacity..capacity + 4);
+        let decoded = decode_index(&bytes, 1 << 20, 1).unwrap();
+        assert_eq!(params, decoded["foo"].params);
+        assert!(decode_index(&bytes, 1 << 20, ARCHIVE_VERSION).is_err());
+    }
+
+    #[test]
+    fn replace_and_compact() {
+        let path = archive_path("compact");
+        let mut archive = FilterArchive::open(&path).unwrap();
+        archive.insert("a", &filter(&["foo"])).unwrap();
+        archive.insert("b", &filter(&["bar"])).unwrap();
+        archive.insert("a", &filter(&["baz"])).unwrap();
+        assert!(archive.garbage_len() > 0);
+        let a: BloomFilter<String> = archive.get("a").unwrap().unwrap();
+        assert!(a.has("baz".to_string()));
+
+        archive.compact().unwrap();
+        assert_eq!(0, archive.garbage_len());
+        let live_len = 2 * filter(&[]).to_bytes().len() as u64;
+        assert!(fs::metadata(&path).unwrap().len() < live_len + 200);
+        let b: BloomFilter<String> = archive.get("b").unwrap().unwrap();
+        assert!(b.has("bar".to_string()));
+
+        // Garbage beyond the size of the live filters triggers a compaction.
+        for i in 0..10 {
+            archive.insert("a", &filter(&[&i.to_string()])).unwrap();
+        }
+        assert!(fs::metadata(&path).unwrap().len() < 3 * live_len);
+        drop(archive);
+        let archive = FilterArchive::open(&path).unwrap();
+        let a: BloomFilter<String> = archive.get("a").unwrap().unwrap();
+        assert!(a.has("9".to_string()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compact_keeps_sibling_files() {
+        let path = archive_path("siblings.a");
+        let other = path.with_extension("tmp");
+        fs::write(&other, b"unrelated").unwrap();
+        let mut archive = FilterArchive::open(&path).unwrap();
+        archive.insert("a", &filter(&["foo"])).unwrap();
+        archive.compact().unwrap();
+        assert_eq!(b"unrelated", &fs::read(&other).unwrap()[..]);
+        assert!(!tmp_path(&path).exists());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+    }
+
+    #[test]
+    fn interrupted_insert() {
+        let path = archive_path("interrupted");
+        let mut archive = FilterArchive::open(&path).unwrap();
+        archive.insert("a", &filter(&["foo"])).unwrap();
+        drop(archive);
+
+        // Bytes appended without updating the header are ignored, and overwritten.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0xff; 100]).unwrap();
+        drop(file);
+        let mut archive = FilterArchive::open(&path).unwrap();
+        assert_eq!(vec!["a"], archive.names().collect::<Vec<_>>());
+        archive.insert("b", &filter(&["bar"])).unwrap();
+        drop(archive);
+        let archive = FilterArchive::open(&path).unwrap();
+        assert_eq!(2, archive.len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_archives() {
+        let path = archive_path("invalid");
+        let mut archive = FilterArchive::open(&path).unwrap();
+        archive.insert("a", &filter(&["foo"])).unwrap();
+        drop(archive);
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let err = FilterArchive::open(&path).err().unwrap();
+        assert_eq!(
+            Error::Corrupted,
+            *err.into_inner().unwrap().downcast::<Error>().unwrap()
+        );
+
+        fs::write(&path, b"not an archive at all, really not").unwrap();
+        let err = FilterArchive::open(&path).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+        let custom: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hash_fn(|item: &&str| item.len() as u64)
+            .build();
+        fs::remove_file(&path).unwrap();
+        let mut archive = FilterArchive::open(&path).unwrap();
+        let err = archive.insert("custom", &custom).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(archive.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
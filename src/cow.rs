@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use crate::words::AlignedWords;
+use crate::{BloomFilter, Error};
+
+/// A bloom filter that hands out cheap, immutable snapshots of its state, so that
+/// long-running readers, such as a scan over many keys on another thread, query a
+/// consistent filter while the writer keeps inserting, without either holding a lock.
+///
+/// Taking a snapshot only clones a reference to the filter. The first write after a
+/// snapshot is taken, while the snapshot is still alive, copies the filter's bits, and
+/// the writer then keeps inserting into its own copy until the next snapshot. Writes
+/// made while no snapshot is alive are made in place.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter, CowBloomFilter};
+///
+/// let mut bf: CowBloomFilter<&str> = CowBloomFilter::new(BloomFilter::new(1000, 0.01));
+/// bf.insert("hello");
+///
+/// let snapshot = bf.snapshot();
+/// bf.insert("world");
+///
+/// assert!(snapshot.has("hello"));
+/// assert!(!snapshot.has("world"));
+/// assert!(bf.has("world"));
+/// ```
+pub struct CowBloomFilter<T: AsRef<[u8]>> {
+    inner: Arc<BloomFilter<T>>,
+}
+
+impl<T: AsRef<[u8]>> CowBloomFilter<T> {
+    /// Wraps a bloom filter so that snapshots of it can be taken.
+    pub fn new(filter: BloomFilter<T>) -> CowBloomFilter<T> {
+        CowBloomFilter {
+            inner: Arc::new(filter),
+        }
+    }
+    /// Returns an immutable view of the bloom filter as it is now, unaffected by
+    /// subsequent writes.
+    pub fn snapshot(&self) -> BloomSnapshot<T> {
+        BloomSnapshot {
+            inner: self.inner.clone(),
+        }
+    }
+    /// Insert an element into the bloom filter, copying its bits first if a snapshot
+    /// of it is alive. Returns true if the element was probably not in the filter
+    /// before, as in `BloomFilter::insert`.
+    pub fn insert(&mut self, elem: T) -> bool {
+        self.filter_mut().insert(elem)
+    }
+    /// Insert an element into the bloom filter with the number of hash functions of a
+    /// tier, as in `BloomFilter::insert_with_class`.
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> Result<bool, Error> {
+        self.filter_mut().insert_with_class(elem, class)
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.inner.has(elem)
+    }
+    /// Clear all set bits of the bloom filter, leaving snapshots of it untouched.
+    pub fn clear(&mut self) {
+        match Arc::get_mut(&mut self.inner) {
+            Some(filter) => filter.clear(),
+            None => {
+                let mut filter = self
+                    .inner
+                    .with_bits(AlignedWords::zeroed(self.inner.bits.len()), 0);
+                filter.dirty = self.inner.dirty.clone();
+                filter.mark_all_dirty();
+                self.inner = Arc::new(filter);
+            }
+        }
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+    /// Returns the bloom filter, to query it in any other way.
+    pub fn filter(&self) -> &BloomFilter<T> {
+        &self.inner
+    }
+    /// Returns the bloom filter for writing, copying it first if a snapshot of it is
+    /// alive, so that it can be written to in any other way.
+    pub fn filter_mut(&mut self) -> &mut BloomFilter<T> {
+        Arc::make_mut(&mut self.inner)
+    }
+    /// Returns the bloom filter, copying it if a snapshot of it is alive.
+    pub fn into_inner(self) -> BloomFilter<T> {
+        Arc::try_unwrap(self.inner).unwrap_or_else(|filter| (*filter).clone())
+    }
+}
+
+impl<T: AsRef<[u8]>> From<BloomFilter<T>> for CowBloomFilter<T> {
+    fn from(filter: BloomFilter<T>) -> CowBloomFilter<T> {
+        CowBloomFilter::new(filter)
+    }
+}
+
+/// An immutable view of a `CowBloomFilter` at the time it was taken, returned by
+/// `CowBloomFilter::snapshot`. Snapshots can be cloned cheaply and sent to other
+/// threads, and are never affected by writes to the filter they were taken from.
+pub struct BloomSnapshot<T: AsRef<[u8]>> {
+    inner: Arc<BloomFilter<T>>,
+}
+
+impl<T: AsRef<[u8]>> BloomSnapshot<T> {
+    /// Checks if the snapshot contains a specified element. Like any bloom filter, it
+    /// can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.inner.has(elem)
+    }
+    /// Checks if the snapshot contains an element inserted with a class, as in
+    /// `BloomFilter::has_with_class`.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        self.inner.has_with_class(elem, class)
+    }
+    /// Returns the number of times an element had been inserted into the bloom filter
+    /// when the snapshot was taken.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    /// Returns true if no elements had been inserted into the bloom filter when the
+    /// snapshot was taken.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+    /// Returns the bloom filter as it was when the snapshot was taken, to query it in
+    /// any other way.
+    pub fn filter(&self) -> &BloomFilter<T> {
+        &self.inner
+    }
+}
+
+impl<T: AsRef<[u8]>> Clone for BloomSnapshot<T> {
+    fn clone(&self) -> Self {
+        BloomSnapshot {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::BloomBuilder;
+
+    #[test]
+    fn snapshots_are_isolated() {
+        let mut bf: CowBloomFilter<String> = BloomBuilder::new(10_000, 0.01).build().into();
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        let snapshot = bf.snapshot();
+        let bits = snapshot.filter().bits.as_ptr();
+
+        let reader = {
+            let snapshot = snapshot.clone();
+            thread::spawn(move || {
+                (0..100).all(|i| snapshot.has(format!("{}", i))) && snapshot.len() == 100
+            })
+        };
+        for i in 100..200 {
+            bf.insert(format!("{}", i));
+        }
+        assert!(reader.join().unwrap());
+        assert_eq!(100, snapshot.len());
+        assert!((100..200).all(|i| bf.has(format!("{}", i))));
+        assert_eq!(bits, snapshot.filter().bits.as_ptr());
+        assert_ne!(bits, bf.filter().bits.as_ptr());
+
+        bf.clear();
+        assert!(bf.is_empty());
+        assert!(snapshot.has("0".to_string()));
+    }
+
+    #[test]
+    fn writes_in_place_without_snapshots() {
+        let mut bf: CowBloomFilter<&str> = BloomBuilder::new(1_000, 0.01).build().into();
+        let bits = bf.filter().bits.as_ptr();
+        bf.insert("foo");
+        drop(bf.snapshot());
+        bf.insert("bar");
+        assert_eq!(bits, bf.filter().bits.as_ptr());
+
+        // Copies keep tracking the chunks changed since the last delta.
+        let dirty_len = bf.filter().dirty_len();
+        assert!(dirty_len > 0);
+        let snapshot = bf.snapshot();
+        let mut filter = bf.into_inner();
+        assert_eq!(dirty_len, filter.dirty_len());
+        filter.insert("baz");
+        assert!(filter.has("foo") && filter.has("bar"));
+        assert!(!snapshot.has("baz"));
+    }
+}
@@ -0,0 +1,263 @@
+//! Bloom filters form a join-semilattice under OR: merging filters is commutative,
+//! associative, and idempotent, so they can be used as state-based CRDTs. Nodes in a
+//! cluster can insert into their own replica of a filter and gossip with one another
+//! in any order, and all replicas converge to the same filter without a coordinator.
+//!
+//! To avoid sending entire filters on every round of gossip, nodes exchange a cheap
+//! `Digest` of their filter first. Comparing digests reveals which chunks of the bit
+//! array differ, and only those chunks need to be sent and merged.
+//!
+//! ## Example
+//! ```
+//! use flowerbloom::{crdt, BloomBuilder, BloomFilter};
+//!
+//! let mut node_a = BloomBuilder::new(1000, 0.01).seed(42).build();
+//! let mut node_b = BloomBuilder::new(1000, 0.01).seed(42).build();
+//! node_a.insert("foo");
+//! node_b.insert("bar");
+//!
+//! // Node b sends its digest to node a, which replies with the chunks that differ.
+//! let chunk_size = 64;
+//! let digest_b = crdt::digest(&node_b, chunk_size).unwrap();
+//! let digest_a = crdt::digest(&node_a, chunk_size).unwrap();
+//! let differing = crdt::diff(&digest_a, &digest_b).unwrap();
+//! let chunks = crdt::chunks(&node_a, chunk_size, &differing).unwrap();
+//!
+//! // Node b merges them into its own filter.
+//! crdt::merge_chunks(&mut node_b, &chunks).unwrap();
+//! assert!(node_b.has("foo") && node_b.has("bar"));
+//! ```
+use crate::{fnv1a, or_into, words, BloomFilter, Error, FilterParams};
+
+/// Merges another filter into a filter by OR-ing their bits together. Unlike
+/// `BloomFilter::union`, the number of inserts of the merged filter is the larger of
+/// both filters rather than their sum, so merging is idempotent: merging a filter that
+/// has already been merged leaves the result unchanged. Both filters must have been
+/// built with identical parameters and hashers, otherwise an error is returned.
+pub fn merge<T: AsRef<[u8]>>(
+    into: &mut BloomFilter<T>,
+    other: &BloomFilter<T>,
+) -> Result<(), Error> {
+    into.check_compatible(other)?;
+    or_into(&mut into.bits, &other.bits);
+    into.num_inserted = into.num_inserted.max(other.num_inserted);
+    into.mark_all_dirty();
+    Ok(())
+}
+
+/// A digest of a filter's state, made up of its parameters and a hash
+/// of each fixed-size chunk of its bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digest {
+    /// The parameters of the filter.
+    pub params: FilterParams,
+    /// The number of bytes in each chunk. The last chunk may be shorter.
+    pub chunk_size: usize,
+    /// The FNV-1a hash of each chunk of the filter's bits.
+    pub chunk_hashes: Vec<u64>,
+}
+
+/// A chunk of a filter's bits sent to another node during anti-entropy sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The offset of the chunk's first byte within the filter's bits.
+    pub offset: usize,
+    /// The chunk's bytes.
+    pub bytes: Vec<u8>,
+}
+
+/// Computes a digest of a filter, hashing its bits in chunks of chunk_size bytes.
+/// Fails with `Error::InvalidChunk` if the chunk size is zero.
+pub fn digest<T: AsRef<[u8]>>(filter: &BloomFilter<T>, chunk_size: usize) -> Result<Digest, Error> {
+    if chunk_size == 0 {
+        return Err(Error::InvalidChunk);
+    }
+    Ok(Digest {
+        params: filter.params(),
+        chunk_size,
+        chunk_hashes: filter
+            .bits_to_bytes()
+            .chunks(chunk_size)
+            .map(fnv1a)
+            .collect(),
+    })
+}
+
+/// Compares two digests, returning the indices of the chunks that differ. The digests
+/// must come from filters built with identical parameters, otherwise an error is returned.
+/// Fails with `Error::InvalidChunk` if the digests were not computed with the same chunk
+/// size, or if either does not hold one hash per chunk of the filter's bits.
+pub fn diff(local: &Digest, remote: &Digest) -> Result<Vec<usize>, Error> {
+    local.params.check_compatible(&remote.params)?;
+    let byte_len = (local.params.bit_len / 8) as usize;
+    if local.chunk_size != remote.chunk_size
+        || local.chunk_size == 0
+        || local.chunk_hashes.len() != byte_len.div_ceil(local.chunk_size)
+        || remote.chunk_hashes.len() != local.chunk_hashes.len()
+    {
+        return Err(Error::InvalidChunk);
+    }
+    Ok(local
+        .chunk_hashes
+        .iter()
+        .zip(remote.chunk_hashes.iter())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// Extracts the chunks at the given indices from a filter's bits, skipping indices
+/// past the end of the filter. Fails with `Error::InvalidChunk` if the chunk size is
+/// zero, or if an index is so large that its offset overflows.
+pub fn chunks<T: AsRef<[u8]>>(
+    filter: &BloomFilter<T>,
+    chunk_size: usize,
+    indices: &[usize],
+) -> Result<Vec<Chunk>, Error> {
+    if chunk_size == 0 {
+        return Err(Error::InvalidChunk);
+    }
+    let mut chunks = vec![];
+    for i in indices {
+        let offset = i.checked_mul(chunk_size).ok_or(Error::InvalidChunk)?;
+        if offset > filter.byte_len() {
+            continue;
+        }
+        let end = offset.saturating_add(chunk_size).min(filter.byte_len());
+        chunks.push(Chunk {
+            offset,
+            bytes: words::bytes_in(&filter.bits, offset, end),
+        });
+    }
+    Ok(chunks)
+}
+
+/// Merges chunks received from another node into a filter by OR-ing them into its bits.
+/// An error is returned, leaving the filter untouched, if any chunk does not fit
+/// within the filter. The number of inserts of the filter is then re-estimated from its
+/// bits, as in `BloomFilter::estimate_count`, unless it already counts more, so that
+/// merging stays idempotent.
+pub fn merge_chunks<T: AsRef<[u8]>>(
+    filter: &mut BloomFilter<T>,
+    chunks: &[Chunk],
+) -> Result<(), Error> {
+    for chunk in chunks {
+        let end = chunk
+            .offset
+            .checked_add(chunk.bytes.len())
+            .ok_or(Error::InvalidChunk)?;
+        if end > filter.byte_len() {
+            return Err(Error::InvalidChunk);
+        }
+    }
+    for chunk in chunks {
+        let end = chunk.offset + chunk.bytes.len();
+        let mut bytes = words::bytes_in(&filter.bits, chunk.offset, end);
+        bytes
+            .iter_mut()
+            .zip(&chunk.bytes)
+            .for_each(|(b, c)| *b |= c);
+        words::copy_from_bytes(&mut filter.bits, chunk.offset, &bytes);
+        filter.mark_dirty(chunk.offset, end);
+    }
+    // Saturated filters have an infinite estimate.
+    let estimate = filter.estimate_count().round().min(filter.bit_len() as f64) as usize;
+    filter.num_inserted = filter.num_inserted.max(estimate);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomBuilder;
+
+    fn filter_with(items: &[&str]) -> BloomFilter<String> {
+        // Replicas share a seed so that items map to the same bits on every node.
+        let mut bf = BloomBuilder::new(1_000, 0.01).seed(7).build();
+        for item in items {
+            bf.insert(item.to_string());
+        }
+        bf
+    }
+
+    #[test]
+    fn merge_is_a_semilattice() {
+        let a = filter_with(&["foo"]);
+        let b = filter_with(&["bar"]);
+        let c = filter_with(&["baz", "foo"]);
+
+        // Commutative.
+        let mut ab = filter_with(&["foo"]);
+        merge(&mut ab, &b).unwrap();
+        let mut ba = filter_with(&["bar"]);
+        merge(&mut ba, &a).unwrap();
+        assert_eq!(ab.bits, ba.bits);
+        assert_eq!(ab.len(), ba.len());
+
+        // Associative.
+        let mut ab_c = ab.clone();
+        merge(&mut ab_c, &c).unwrap();
+        let mut bc = filter_with(&["bar"]);
+        merge(&mut bc, &c).unwrap();
+        let mut a_bc = filter_with(&["foo"]);
+        merge(&mut a_bc, &bc).unwrap();
+        assert_eq!(ab_c.bits, a_bc.bits);
+        assert_eq!(filter_with(&["foo", "bar", "baz"]).bits, a_bc.bits);
+
+        // Idempotent.
+        let before = ab_c.clone();
+        merge(&mut ab_c, &before).unwrap();
+        assert_eq!(before.bits, ab_c.bits);
+        assert_eq!(before.len(), ab_c.len());
+    }
+
+    #[test]
+    fn anti_entropy_sync() {
+        let a = filter_with(&["foo", "bar"]);
+        let mut b = filter_with(&["baz"]);
+        let differing = diff(&digest(&a, 16).unwrap(), &digest(&b, 16).unwrap()).unwrap();
+        assert!(!differing.is_empty());
+        merge_chunks(&mut b, &chunks(&a, 16, &differing).unwrap()).unwrap();
+        for item in ["foo", "bar", "baz"] {
+            assert!(b.has(item.to_string()));
+        }
+        assert_eq!(3, b.len());
+        // Once b has everything a has, a's chunks no longer add anything new.
+        let differing = diff(&digest(&a, 16).unwrap(), &digest(&b, 16).unwrap()).unwrap();
+        let before = b.clone();
+        merge_chunks(&mut b, &chunks(&a, 16, &differing).unwrap()).unwrap();
+        assert_eq!(before.bits, b.bits);
+    }
+
+    #[test]
+    fn invalid_chunks() {
+        let mut a = filter_with(&[]);
+        let chunk = Chunk {
+            offset: a.byte_len(),
+            bytes: vec![1],
+        };
+        assert_eq!(Err(Error::InvalidChunk), merge_chunks(&mut a, &[chunk]));
+
+        let other: BloomFilter<String> = BloomBuilder::new(10, 0.01).build();
+        assert!(diff(&digest(&a, 16).unwrap(), &digest(&other, 16).unwrap()).is_err());
+
+        let local = digest(&a, 16).unwrap();
+        assert_eq!(
+            Err(Error::InvalidChunk),
+            diff(&local, &digest(&a, 32).unwrap())
+        );
+        let mut truncated = digest(&a, 16).unwrap();
+        truncated.chunk_hashes.pop();
+        assert_eq!(Err(Error::InvalidChunk), diff(&local, &truncated));
+        assert_eq!(Err(Error::InvalidChunk), diff(&truncated, &truncated));
+
+        assert_eq!(Some(Error::InvalidChunk), digest(&a, 0).err());
+        assert_eq!(Some(Error::InvalidChunk), chunks(&a, 0, &[0]).err());
+        assert_eq!(
+            Some(Error::InvalidChunk),
+            chunks(&a, 16, &[usize::MAX]).err()
+        );
+        assert_eq!(Ok(vec![]), chunks(&a, 16, &[a.byte_len()]));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{BloomFilter, Error};
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Computes a compact delta that turns a previous version of this bloom filter into
+    /// this one, so replicas can be kept in sync by sending only the bytes that changed
+    /// instead of the entire filter. The delta is the XOR of both filters' bits, sparsely
+    /// encoded as the positions and values of its non-zero bytes. Both filters must have
+    /// been built with identical parameters and hashers, otherwise an error is returned.
+    ///
+    /// The layout of a delta is a header of the digest of the filter's parameters, see
+    /// `params_digest`, as a big-endian u64, followed by the number of elements inserted
+    /// into it, then a pair per changed byte of the distance from the previous changed
+    /// byte followed by the XOR-ed byte itself. All other numbers are encoded as LEB128
+    /// variable-length integers.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut primary = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let mut replica = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// primary.insert("foo");
+    /// replica.insert("foo");
+    ///
+    /// let previous = primary.union(&replica).unwrap();
+    /// primary.insert("bar");
+    ///
+    /// let delta = primary.delta(&previous).unwrap();
+    /// replica.apply_delta(&delta).unwrap();
+    /// assert!(replica.has("bar"));
+    /// ```
+    pub fn delta(&self, previous: &Self) -> Result<Vec<u8>, Error> {
+        self.check_compatible(previous)?;
+        let mut out = self.params_digest().to_be_bytes().to_vec();
+        write_varint(&mut out, self.num_inserted as u64);
+        let mut last = 0;
+        for (w, (a, b)) in self.bits.iter().zip(previous.bits.iter()).enumerate() {
+            let xor = a ^ b;
+            if xor == 0 {
+                continue;
+            }
+            // Deltas are encoded per byte, so skip the unchanged bytes of the word.
+            for (j, byte) in xor.to_le_bytes().into_iter().enumerate() {
+                if byte == 0 {
+                    continue;
+                }
+                let i = w * 8 + j;
+                write_varint(&mut out, (i - last) as u64);
+                out.push(byte);
+                last = i;
+            }
+        }
+        Ok(out)
+    }
+    /// Applies a delta computed by `BloomFilter::delta` to this bloom filter, which must
+    /// be identical to the previous filter the delta was computed against. An error is
+    /// returned, leaving the filter untouched, if the delta is malformed or was computed
+    /// for a filter with different parameters or hashers. The number of inserted elements
+    /// is taken from the delta, capped at the number of bits of the filter.
+    pub fn apply_delta(&mut self, delta: &[u8]) -> Result<(), Error> {
+        let (digest, mut input) = delta.split_first_chunk::<8>().ok_or(Error::InvalidDelta)?;
+        if u64::from_be_bytes(*digest) != self.params_digest() {
+            return Err(Error::InvalidDelta);
+        }
+        let byte_len = self.byte_len() as u64;
+        let num_inserted = read_varint(&mut input)?.min(self.bit_len() as u64);
+        // Changes are decoded in full before being applied, so a malformed
+        // delta never leaves the filter partially updated.
+        let mut changes = vec![];
+        let mut pos = 0u64;
+        while !input.is_empty() {
+            pos = pos
+                .checked_add(read_varint(&mut input)?)
+                .ok_or(Error::InvalidDelta)?;
+            let (xor, rest) = input.split_first().ok_or(Error::InvalidDelta)?;
+            if pos >= byte_len {
+                return Err(Error::InvalidDelta);
+            }
+            changes.push((pos as usize, *xor));
+            input = rest;
+        }
+        for (pos, xor) in changes {
+            self.bits[pos / 8] ^= (xor as u64) << (8 * (pos % 8));
+            self.mark_dirty(pos, pos + 1);
+        }
+        self.num_inserted = num_inserted as usize;
+        Ok(())
+    }
+}
+
+/// Writes an unsigned LEB128 variable-length integer.
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Reads an unsigned LEB128 variable-length integer, advancing the input past it.
+pub(crate) fn read_varint(input: &mut &[u8]) -> Result<u64, Error> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (b, rest) = input.split_first().ok_or(Error::InvalidDelta)?;
+        *input = rest;
+        n |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(Error::InvalidDelta)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BloomBuilder;
+
+    use super::*;
+
+    #[test]
+    fn delta_roundtrip() {
+        let mut primary: BloomFilter<String> =
+            BloomBuilder::new(10_000, 0.01).deterministic().build();
+        let mut replica: BloomFilter<String> =
+            BloomBuilder::new(10_000, 0.01).deterministic().build();
+        for i in 0..100 {
+            primary.insert(format!("{}", i));
+            replica.insert(format!("{}", i));
+        }
+        let previous = primary.clone();
+        for i in 100..110 {
+            primary.insert(format!("{}", i));
+        }
+        let delta = primary.delta(&previous).unwrap();
+        assert!(delta.len() < primary.byte_len() / 10);
+
+        replica.apply_delta(&delta).unwrap();
+        assert_eq!(primary.bits, replica.bits);
+        assert_eq!(primary.len(), replica.len());
+    }
+
+    #[test]
+    fn invalid_delta() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.01).build();
+        let b: BloomFilter<&str> = BloomBuilder::new(200, 0.01).build();
+        assert!(a.delta(&b).is_err());
+
+        let delta = b.delta(&b).unwrap();
+        assert_eq!(Err(Error::InvalidDelta), a.apply_delta(&delta));
+        assert_eq!(Err(Error::InvalidDelta), a.apply_delta(&[]));
+
+        // A delta for a filter of the same size with another seed is rejected.
+        let c: BloomFilter<&str> = BloomBuilder::new(100, 0.01).build();
+        assert_eq!(a.byte_len(), c.byte_len());
+        assert_eq!(
+            Err(Error::InvalidDelta),
+            a.apply_delta(&c.delta(&c).unwrap())
+        );
+
+        // A change past the end of the filter is rejected without modifying it.
+        let mut out = a.params_digest().to_be_bytes().to_vec();
+        write_varint(&mut out, 1);
+        write_varint(&mut out, 0);
+        out.push(1);
+        write_varint(&mut out, a.byte_len() as u64);
+        out.push(1);
+        assert_eq!(Err(Error::InvalidDelta), a.apply_delta(&out));
+        assert_eq!(0, a.count_ones());
+
+        // The number of inserted elements is capped at the number of bits.
+        let mut out = a.params_digest().to_be_bytes().to_vec();
+        write_varint(&mut out, u64::MAX);
+        a.apply_delta(&out).unwrap();
+        assert_eq!(a.bit_len(), a.len());
+    }
+}
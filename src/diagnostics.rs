@@ -0,0 +1,145 @@
+//! The false positive rates promised by a bloom filter assume that its hasher spreads
+//! items uniformly over the bit array. A custom hasher that clusters items, or whose
+//! two base hashes are correlated, silently produces far more false positives than the
+//! filter's parameters suggest.
+//!
+//! This module feeds a sample of keys through a hasher exactly as a filter would, counts
+//! how many probes land in each of a number of buckets, and reports how far those counts
+//! stray from a uniform distribution, so hashers can be validated before being trusted.
+//!
+//! ## Example
+//! ```
+//! use flowerbloom::{diagnostics, DefaultHasher, StatelessHasher};
+//!
+//! let keys: Vec<String> = (0..10_000).map(|i| format!("key-{}", i)).collect();
+//! let hasher = StatelessHasher::<DefaultHasher>::new();
+//! let report = diagnostics::uniformity(&hasher, keys, 256, 7);
+//! assert!(report.is_uniform());
+//!
+//! // A hasher that only looks at the length of a key is anything but uniform.
+//! let keys: Vec<String> = (0..10_000).map(|i| format!("key-{}", i)).collect();
+//! let by_len = |key: &String| key.len() as u64;
+//! assert!(!diagnostics::uniformity(&by_len, keys, 256, 7).is_uniform());
+//! ```
+use crate::{probe_index, HasherInstance};
+
+/// How uniformly a hasher spread a sample of keys over a number of buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniformityReport {
+    /// The number of keys in the sample.
+    pub num_keys: usize,
+    /// The number of buckets probes were counted in.
+    pub num_buckets: usize,
+    /// The total number of probes counted, which is the number of keys
+    /// multiplied by the number of hash functions.
+    pub num_probes: usize,
+    /// Pearson's chi-square statistic of the bucket counts against a uniform distribution.
+    pub chi_square: f64,
+    /// The largest relative deviation of any bucket's count from the expected count.
+    pub max_skew: f64,
+}
+
+impl UniformityReport {
+    /// Returns the degrees of freedom of the chi-square statistic.
+    pub fn degrees_of_freedom(&self) -> usize {
+        self.num_buckets.saturating_sub(1)
+    }
+    /// Returns how many standard deviations the chi-square statistic lies above its
+    /// expected value for a uniform hasher, using the normal approximation of the
+    /// chi-square distribution. Values near zero or below are what a uniform hasher
+    /// produces, while large values indicate the hasher clusters keys.
+    pub fn z_score(&self) -> f64 {
+        let df = self.degrees_of_freedom() as f64;
+        (self.chi_square - df) / (2.0 * df).sqrt()
+    }
+    /// Returns true if the sample is consistent with a uniform hasher, meaning the
+    /// chi-square statistic lies within 4 standard deviations of its expected value.
+    /// A uniform hasher fails this check for fewer than 1 in 10,000 samples.
+    pub fn is_uniform(&self) -> bool {
+        self.z_score() < 4.0
+    }
+}
+
+/// Hashes a sample of keys and counts the first num_hash_fns probes of every key into
+/// num_buckets buckets, deriving probes exactly as a bloom filter with num_buckets bits
+/// would. The sample should hold many more probes than there are buckets for the
+/// statistics to be meaningful, at least 5 per bucket.
+pub fn uniformity<T, H, I>(
+    hasher: &H,
+    keys: I,
+    num_buckets: usize,
+    num_hash_fns: u32,
+) -> UniformityReport
+where
+    T: AsRef<[u8]>,
+    H: HasherInstance<T> + ?Sized,
+    I: IntoIterator<Item = T>,
+{
+    assert!(num_buckets > 1, "at least 2 buckets are needed");
+    let mut counts = vec![0usize; num_buckets];
+    let mut num_keys = 0;
+    for key in keys {
+        let hashes = hasher.hash_pair(&key);
+        for i in 0..num_hash_fns {
+            counts[probe_index(hashes, i, num_buckets as u64) as usize] += 1;
+        }
+        num_keys += 1;
+    }
+    let num_probes = num_keys * num_hash_fns as usize;
+    let expected = num_probes as f64 / num_buckets as f64;
+    let (chi_square, max_skew) = if expected > 0.0 {
+        counts
+            .iter()
+            .fold((0.0, 0.0), |(chi_square, max_skew), count| {
+                let deviation = *count as f64 - expected;
+                (
+                    chi_square + deviation * deviation / expected,
+                    f64::max(max_skew, deviation.abs() / expected),
+                )
+            })
+    } else {
+        (0.0, 0.0)
+    };
+    UniformityReport {
+        num_keys,
+        num_buckets,
+        num_probes,
+        chi_square,
+        max_skew,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DefaultHasher, Hasher, StatelessHasher};
+
+    #[test]
+    fn default_hasher_is_uniform() {
+        let keys = (0..20_000).map(|i| format!("{}", i));
+        let hasher = StatelessHasher::<DefaultHasher>::new();
+        let report = uniformity(&hasher, keys, 512, 5);
+        assert_eq!(20_000, report.num_keys);
+        assert_eq!(100_000, report.num_probes);
+        assert_eq!(511, report.degrees_of_freedom());
+        assert!(report.is_uniform(), "{:?}", report);
+        assert!(report.max_skew < 0.5);
+    }
+
+    #[test]
+    fn clustered_hasher() {
+        // Only 16 distinct hashes, so most buckets stay empty.
+        let keys = (0..20_000).map(|i| format!("{}", i));
+        let clustered = |key: &String| DefaultHasher::hash(key) & 0xf;
+        let report = uniformity(&clustered, keys, 512, 1);
+        assert!(!report.is_uniform());
+        assert!(report.max_skew > 10.0);
+    }
+
+    #[test]
+    fn empty_sample() {
+        let hasher = StatelessHasher::<DefaultHasher>::new();
+        let report = uniformity(&hasher, Vec::<String>::new(), 16, 3);
+        assert_eq!(0.0, report.chi_square);
+    }
+}
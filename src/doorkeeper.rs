@@ -0,0 +1,108 @@
+use crate::{BloomBuilder, BloomFilter};
+
+/// A doorkeeper is the admission gate used in front of TinyLFU caches. It is made up of
+/// a pair of bloom filters: an active filter that records new keys and a previous
+/// filter that remembers the keys seen during the last window. Once the number of
+/// recorded keys reaches a threshold, the filters are rotated: the active filter
+/// becomes the previous one, and a fresh, empty filter becomes active. This ages out
+/// keys that have not been seen in a while without ever clearing everything at once.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::Doorkeeper;
+///
+/// let reset_threshold = 1000;
+/// let fp_rate = 0.01;
+/// let mut doorkeeper: Doorkeeper<&str> = Doorkeeper::new(reset_threshold, fp_rate);
+///
+/// // The first time a key is seen it is recorded but not admitted.
+/// assert!(!doorkeeper.allow(&"hello"));
+/// // Subsequent sightings within the window are admitted.
+/// assert!(doorkeeper.allow(&"hello"));
+/// ```
+pub struct Doorkeeper<T: AsRef<[u8]>> {
+    active: BloomFilter<T>,
+    previous: BloomFilter<T>,
+    reset_threshold: u64,
+    count: u64,
+    resets: u64,
+}
+
+impl<T: AsRef<[u8]> + Clone> Doorkeeper<T> {
+    /// Creates a new doorkeeper that rotates its filters after recording
+    /// reset_threshold keys. Each filter is sized to hold that many keys at
+    /// the desired false positive rate.
+    pub fn new(reset_threshold: u64, desired_fp_rate: f32) -> Doorkeeper<T> {
+        assert!(reset_threshold > 0);
+        Doorkeeper {
+            active: BloomBuilder::new(reset_threshold, desired_fp_rate).build(),
+            previous: BloomBuilder::new(reset_threshold, desired_fp_rate).build(),
+            reset_threshold,
+            count: 0,
+            resets: 0,
+        }
+    }
+    /// Returns true if the key has probably been seen within the current or previous
+    /// window, in which case the caller should admit it. Otherwise, the key is
+    /// recorded so that its next sighting is admitted, and false is returned.
+    pub fn allow(&mut self, key: &T) -> bool {
+        if self.active.has(key.clone()) || self.previous.has(key.clone()) {
+            return true;
+        }
+        self.active.insert(key.clone());
+        self.count += 1;
+        if self.count >= self.reset_threshold {
+            self.rotate();
+        }
+        false
+    }
+    /// Checks if the key has probably been seen within the current
+    /// or previous window without recording it.
+    pub fn contains(&self, key: &T) -> bool {
+        self.active.has(key.clone()) || self.previous.has(key.clone())
+    }
+    /// Rotates the filters, making the active filter the previous one
+    /// and starting a fresh, empty active filter.
+    pub fn rotate(&mut self) {
+        std::mem::swap(&mut self.active, &mut self.previous);
+        self.active.clear();
+        self.count = 0;
+        self.resets += 1;
+    }
+    /// Returns the number of keys recorded since the last rotation.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Returns the number of times the filters have been rotated.
+    pub fn resets(&self) -> u64 {
+        self.resets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admits_on_second_sighting() {
+        let mut doorkeeper: Doorkeeper<String> = Doorkeeper::new(100, 0.01);
+        assert!(!doorkeeper.allow(&"hello".to_string()));
+        assert!(doorkeeper.allow(&"hello".to_string()));
+        assert!(doorkeeper.contains(&"hello".to_string()));
+        assert_eq!(1, doorkeeper.count());
+    }
+
+    #[test]
+    fn rotates_on_threshold() {
+        let mut doorkeeper: Doorkeeper<String> = Doorkeeper::new(1, 0.01);
+        assert!(!doorkeeper.allow(&"old".to_string()));
+        assert_eq!(1, doorkeeper.resets());
+        assert_eq!(0, doorkeeper.count());
+        // Keys from the previous window are still remembered.
+        assert!(doorkeeper.contains(&"old".to_string()));
+
+        doorkeeper.rotate();
+        assert_eq!(2, doorkeeper.resets());
+        assert!(!doorkeeper.contains(&"old".to_string()));
+    }
+}
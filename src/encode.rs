@@ -0,0 +1,305 @@
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::delta::write_varint;
+use crate::{BloomBuilder, BloomFilter};
+
+/// BloomEncode defines a type that can be written out as bytes to be inserted into a
+/// bloom filter. Two values must produce the same bytes if and only if they are
+/// equal, so variable-length values such as strings are prefixed with their length,
+/// keeping tuples like `("ab", "c")` and `("a", "bc")` apart. Fixed-size values such
+/// as integers and byte arrays are written as-is, with integers in big-endian order.
+///
+/// With the `derive` feature enabled, `#[derive(BloomEncode)]` implements the trait
+/// for structs and enums by encoding each of their fields in declaration order.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomEncode, EncodedBloomFilter};
+///
+/// struct User {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// impl BloomEncode for User {
+///     fn encode_into(&self, buf: &mut Vec<u8>) {
+///         (self.id, &self.name).encode_into(buf);
+///     }
+/// }
+///
+/// let mut bf = EncodedBloomFilter::new(1000, 0.01);
+/// bf.insert(&User { id: 1, name: "nyan".into() });
+/// assert!(bf.has(&User { id: 1, name: "nyan".into() }));
+/// ```
+pub trait BloomEncode {
+    fn encode_into(&self, buf: &mut Vec<u8>);
+    /// Encodes the value into a newly allocated vector of bytes.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode_into(&mut buf);
+        buf
+    }
+}
+
+macro_rules! encode_int {
+    ($($ty:ty),*) => {
+        $(
+            impl BloomEncode for $ty {
+                fn encode_into(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+        )*
+    };
+}
+
+encode_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// Pointer-sized integers are widened so that encodings match across platforms.
+impl BloomEncode for usize {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode_into(buf);
+    }
+}
+
+impl BloomEncode for isize {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        (*self as i64).encode_into(buf);
+    }
+}
+
+impl BloomEncode for bool {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl BloomEncode for char {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode_into(buf);
+    }
+}
+
+impl BloomEncode for [u8] {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        write_varint(buf, self.len() as u64);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl BloomEncode for Vec<u8> {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode_into(buf);
+    }
+}
+
+impl BloomEncode for str {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode_into(buf);
+    }
+}
+
+impl BloomEncode for String {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode_into(buf);
+    }
+}
+
+/// Fixed-size byte arrays, such as UUIDs or hashes, are written without a length.
+impl<const N: usize> BloomEncode for [u8; N] {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self);
+    }
+}
+
+impl BloomEncode for Ipv4Addr {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.octets().encode_into(buf);
+    }
+}
+
+impl BloomEncode for Ipv6Addr {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.octets().encode_into(buf);
+    }
+}
+
+impl BloomEncode for IpAddr {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        match self {
+            IpAddr::V4(addr) => {
+                buf.push(4);
+                addr.encode_into(buf);
+            }
+            IpAddr::V6(addr) => {
+                buf.push(6);
+                addr.encode_into(buf);
+            }
+        }
+    }
+}
+
+impl<T: BloomEncode> BloomEncode for Option<T> {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                buf.push(1);
+                value.encode_into(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+}
+
+impl<T: BloomEncode + ?Sized> BloomEncode for &T {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        (**self).encode_into(buf);
+    }
+}
+
+macro_rules! encode_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: BloomEncode),+> BloomEncode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_into(&self, buf: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_into(buf);)+
+            }
+        }
+    };
+}
+
+encode_tuple!(A);
+encode_tuple!(A, B);
+encode_tuple!(A, B, C);
+encode_tuple!(A, B, C, D);
+encode_tuple!(A, B, C, D, E);
+encode_tuple!(A, B, C, D, E, F);
+
+/// A bloom filter over any type implementing `BloomEncode`. Items are encoded as bytes
+/// with `BloomEncode::encode_into` and then inserted into an underlying bloom filter,
+/// so users do not have to hand-roll byte conversions for their own types.
+pub struct EncodedBloomFilter<T: ?Sized> {
+    inner: BloomFilter<Vec<u8>>,
+    buf: Vec<u8>,
+    _item: PhantomData<fn(&T)>,
+}
+
+impl<T: BloomEncode + ?Sized> EncodedBloomFilter<T> {
+    /// Creates a new bloom filter with a specified capacity and desired false positive
+    /// rate, using the package's default hasher over the encoded items.
+    pub fn new(capacity: u64, desired_fp_rate: f32) -> EncodedBloomFilter<T> {
+        EncodedBloomFilter::with_filter(BloomBuilder::new(capacity, desired_fp_rate).build())
+    }
+    /// Wraps a bloom filter over encoded items, such as one configured with a custom
+    /// hasher via `BloomBuilder`.
+    pub fn with_filter(filter: BloomFilter<Vec<u8>>) -> EncodedBloomFilter<T> {
+        EncodedBloomFilter {
+            inner: filter,
+            buf: vec![],
+            _item: PhantomData,
+        }
+    }
+    /// Insert an element into the bloom filter. Returns true if the
+    /// element was probably not in the filter before.
+    pub fn insert(&mut self, elem: &T) -> bool {
+        // The encoding buffer is reused across inserts to avoid an allocation per item.
+        self.buf.clear();
+        elem.encode_into(&mut self.buf);
+        self.inner
+            .insert_with_probes(&self.buf, self.inner.num_hash_fns)
+    }
+    /// Checks if the bloom filter contains a specified element. The bloom filter can
+    /// produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: &T) -> bool {
+        self.inner.has(elem.encode())
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+    /// Clear all set bits of the bloom filter, setting them back to zero.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+    /// Returns the underlying bloom filter over the encoded elements.
+    pub fn as_bloom_filter(&self) -> &BloomFilter<Vec<u8>> {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        assert_eq!(vec![0, 0, 1, 0], 256u32.encode());
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 7], 7usize.encode());
+        assert_eq!(vec![2, b'h', b'i'], "hi".encode());
+        assert_eq!(vec![1, 2, 3], [1u8, 2, 3].encode());
+        assert_eq!(vec![4, 127, 0, 0, 1], IpAddr::from([127, 0, 0, 1]).encode());
+        assert_eq!(vec![1, 1], Some(true).encode());
+        // Length prefixes keep tuples of strings from colliding.
+        assert_ne!(("ab", "c").encode(), ("a", "bc").encode());
+    }
+
+    #[test]
+    fn encoded_filter() {
+        let mut bf: EncodedBloomFilter<(u32, &str)> = EncodedBloomFilter::new(1_000, 0.01);
+        for i in 0..100 {
+            assert!(bf.insert(&(i, "nyan")));
+        }
+        for i in 0..100 {
+            assert!(bf.has(&(i, "nyan")));
+        }
+        assert_eq!(100, bf.len());
+        bf.clear();
+        assert!(bf.is_empty());
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived() {
+        #[derive(crate::BloomEncode)]
+        struct User {
+            id: u32,
+            name: String,
+        }
+
+        #[derive(crate::BloomEncode)]
+        struct Pair<A>(A, A);
+
+        #[derive(crate::BloomEncode)]
+        enum Key {
+            Id(u32),
+            Name { name: String },
+            Anonymous,
+        }
+
+        let user = User {
+            id: 7,
+            name: "nyan".into(),
+        };
+        assert_eq!((7u32, "nyan").encode(), user.encode());
+        assert_eq!((1u8, 2u8).encode(), Pair(1u8, 2u8).encode());
+        assert_eq!((0u32, 7u32).encode(), Key::Id(7).encode());
+        assert_eq!(
+            (1u32, "nyan").encode(),
+            Key::Name {
+                name: "nyan".into()
+            }
+            .encode()
+        );
+        assert_eq!(2u32.encode(), Key::Anonymous.encode());
+
+        let mut bf = EncodedBloomFilter::new(100, 0.01);
+        bf.insert(&user);
+        assert!(bf.has(&user));
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::FilterParams;
+
+/// Errors that can occur when operating on bloom filters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Two filters cannot be combined as they were not built with
+    /// identical parameters, such as their capacity or number of hash functions.
+    IncompatibleParams {
+        expected: Box<FilterParams>,
+        found: Box<FilterParams>,
+    },
+    /// An operation over many filters was given none.
+    NoFilters,
+    /// Two sharded filters cannot be combined as they do not have the same number
+    /// of shards.
+    IncompatibleShards { expected: usize, found: usize },
+    /// A filter cannot be folded as its bits cannot be split into two equal halves.
+    NotFoldable,
+    /// A delta could not be applied to a filter as it is malformed
+    /// or was computed for a filter of a different size.
+    InvalidDelta,
+    /// A chunk received during anti-entropy sync does not fit within the filter.
+    InvalidChunk,
+    /// A filter already holds as many elements as it was sized for, or has
+    /// become too full to meet its maximum false positive rate.
+    CapacityExceeded,
+    /// Encoded bytes could not be decoded into a filter as they are truncated,
+    /// do not start with the expected magic bytes, or do not match their own header.
+    InvalidEncoding,
+    /// Encoded bytes were written in a newer version of the format than this
+    /// version of the package can decode.
+    UnsupportedVersion(u8),
+    /// An encoded filter was built with a hasher that cannot be restored from its
+    /// id and seed, such as a custom hasher or one keyed by a secret.
+    UnknownHasher(u64),
+    /// Encoded bytes do not match their checksum, meaning they were corrupted
+    /// after being encoded, such as by a bit flip on disk or in transit.
+    Corrupted,
+    /// An element was inserted or checked as a member of a class that was not
+    /// configured as a tier of the filter.
+    UnknownClass(usize),
+    /// A precomputed hash was given to a filter built with independent hashers,
+    /// which have no single hash to derive probes from.
+    IndependentHashers,
+    /// An element was checked by a borrowed form, but the filter's hasher needs the
+    /// element itself rather than its bytes, such as a closure.
+    UnhashableBytes,
+    /// A filter cannot be created with the given parameters, as they are out of range,
+    /// contradict each other, or size a filter too large to allocate.
+    InvalidParams,
+    /// The number of elements in filters cannot be estimated, as every bit of them, or
+    /// of their union, is set, which any number of elements could have caused.
+    Saturated,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IncompatibleParams { expected, found } => {
+                write!(
+                    f,
+                    "filters were not built with identical parameters: expected {:?}, found {:?}",
+                    expected, found
+                )
+            }
+            Error::NoFilters => write!(f, "no filters were provided"),
+            Error::IncompatibleShards { expected, found } => {
+                write!(f, "expected {} shards, found {}", expected, found)
+            }
+            Error::NotFoldable => write!(f, "filter bits cannot be split into equal halves"),
+            Error::InvalidDelta => write!(f, "delta is malformed or does not match the filter"),
+            Error::InvalidChunk => write!(f, "chunk does not fit within the filter"),
+            Error::CapacityExceeded => write!(f, "filter has exceeded its capacity"),
+            Error::InvalidEncoding => write!(f, "encoded filter is malformed"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "encoded filter has unsupported version {}", version)
+            }
+            Error::UnknownHasher(id) => write!(f, "filter hasher with id {} is unknown", id),
+            Error::Corrupted => write!(f, "encoded filter does not match its checksum"),
+            Error::UnknownClass(class) => {
+                write!(f, "class {} was not configured as a tier", class)
+            }
+            Error::IndependentHashers => write!(
+                f,
+                "filters with independent hashers cannot be used with precomputed hashes"
+            ),
+            Error::UnhashableBytes => write!(f, "filter hasher cannot hash borrowed elements"),
+            Error::InvalidParams => write!(f, "filter parameters are invalid"),
+            Error::Saturated => write!(f, "every bit of the filters is set"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
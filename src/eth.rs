@@ -0,0 +1,131 @@
+use sha3::{Digest, Keccak256};
+
+/// The number of bytes in an Ethereum logs bloom.
+const LOGS_BLOOM_BYTES: usize = 256;
+
+/// A bloom filter reproducing the logs bloom found in Ethereum block headers and
+/// transaction receipts, which records the addresses and topics of emitted logs.
+///
+/// The construction is fixed by the protocol: the filter is 2048 bits wide, and every
+/// item sets 3 bits. Each bit index is taken from the low 11 bits of one of the first
+/// three big-endian byte pairs of the item's Keccak-256 hash, and bits are numbered
+/// from the end of the 256-byte array, so that the filter reads as a single 2048-bit
+/// big-endian number.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::EthLogsBloom;
+///
+/// let address = [0x11; 20];
+/// let mut bloom = EthLogsBloom::new();
+/// bloom.insert(address);
+/// assert!(bloom.has(address));
+///
+/// // The bloom round trips through the 256-byte block header field.
+/// let encoded = bloom.to_bytes();
+/// assert_eq!(Some(bloom), EthLogsBloom::from_bytes(&encoded));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthLogsBloom {
+    bits: [u8; LOGS_BLOOM_BYTES],
+}
+
+impl EthLogsBloom {
+    /// Creates a new, empty logs bloom.
+    pub fn new() -> EthLogsBloom {
+        EthLogsBloom {
+            bits: [0; LOGS_BLOOM_BYTES],
+        }
+    }
+    /// Insert an address or topic into the logs bloom. Returns true if any bit
+    /// of the bloom changed, meaning the item was definitely not in it before.
+    pub fn insert<T: AsRef<[u8]>>(&mut self, item: T) -> bool {
+        let mut changed = false;
+        for (pos, mask) in logs_bloom_bits(item.as_ref()) {
+            changed |= self.bits[pos] & mask == 0;
+            self.bits[pos] |= mask;
+        }
+        changed
+    }
+    /// Checks if the logs bloom contains an address or topic. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has<T: AsRef<[u8]>>(&self, item: T) -> bool {
+        logs_bloom_bits(item.as_ref())
+            .iter()
+            .all(|(pos, mask)| self.bits[*pos] & mask != 0)
+    }
+    /// Adds all items of another logs bloom into this one, such as when
+    /// combining the blooms of a block's receipts into the header's bloom.
+    pub fn union(&mut self, other: &EthLogsBloom) {
+        for (dst, src) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *dst |= *src;
+        }
+    }
+    /// Returns true if no bits of the logs bloom are set.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|b| *b == 0)
+    }
+    /// Encodes the logs bloom as the 256 bytes found in a block header.
+    pub fn to_bytes(&self) -> [u8; LOGS_BLOOM_BYTES] {
+        self.bits
+    }
+    /// Decodes a logs bloom from the 256 bytes found in a block header.
+    /// Returns None if the input is not exactly 256 bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Option<EthLogsBloom> {
+        Some(EthLogsBloom {
+            bits: bytes.try_into().ok()?,
+        })
+    }
+}
+
+impl Default for EthLogsBloom {
+    fn default() -> Self {
+        EthLogsBloom::new()
+    }
+}
+
+/// Returns the byte positions and masks of the 3 bits an item sets in a logs bloom.
+fn logs_bloom_bits(item: &[u8]) -> [(usize, u8); 3] {
+    let hash = Keccak256::digest(item);
+    let mut bits = [(0, 0); 3];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        let idx = u16::from_be_bytes([hash[2 * i], hash[2 * i + 1]]) as usize & 2047;
+        *bit = (LOGS_BLOOM_BYTES - 1 - idx / 8, 1 << (idx % 8));
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_positions() {
+        // keccak256("") starts with c5d2 4601 86f7, giving bit indices 0x5d2, 0x601
+        // and 0x6f7 counted from the end of the bloom.
+        let mut bloom = EthLogsBloom::new();
+        assert!(bloom.insert([]));
+        assert!(!bloom.insert([]));
+        let mut expected = [0u8; 256];
+        for idx in [0x5d2, 0x601, 0x6f7] {
+            expected[255 - idx / 8] |= 1 << (idx % 8);
+        }
+        assert_eq!(expected, bloom.to_bytes());
+    }
+
+    #[test]
+    fn union_and_roundtrip() {
+        let mut a = EthLogsBloom::new();
+        a.insert("topic-a");
+        let mut b = EthLogsBloom::new();
+        b.insert("topic-b");
+        a.union(&b);
+        assert!(a.has("topic-a"));
+        assert!(a.has("topic-b"));
+
+        let decoded = EthLogsBloom::from_bytes(&a.to_bytes()).unwrap();
+        assert_eq!(a, decoded);
+        assert!(EthLogsBloom::from_bytes(&[0; 255]).is_none());
+        assert!(EthLogsBloom::default().is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+use std::hash::Hasher as _;
+
+use crate::hasher::{FXHASH_HASHER_ID, WYHASH_HASHER_ID};
+use crate::Hasher;
+
+/// A hasher using the non-cryptographic wyhash function, which is among the fastest
+/// hash functions for short keys while still passing the SMHasher quality tests. It
+/// should only be used for trusted inputs, as colliding keys are easy to construct.
+/// Requires the `fast-hash` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, WyHasher};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher::<WyHasher>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+pub struct WyHasher {}
+
+impl<T: AsRef<[u8]>> Hasher<T> for WyHasher {
+    fn hash(item: &T) -> u64 {
+        wyhash(item.as_ref(), 0)
+    }
+    fn id() -> u64 {
+        WYHASH_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<WyHasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+/// A hasher using FxHash, the simple multiplicative hash function used inside the Rust
+/// compiler. It is the cheapest hasher offered by the package, but is also the weakest,
+/// so it should only be used for trusted, in-memory workloads. Its output differs
+/// between 32-bit and 64-bit platforms, so filters built with it should not be
+/// persisted. Requires the `fast-hash` feature.
+pub struct FxHasher {}
+
+impl<T: AsRef<[u8]>> Hasher<T> for FxHasher {
+    fn hash(item: &T) -> u64 {
+        let mut hasher = rustc_hash::FxHasher::default();
+        hasher.write(item.as_ref());
+        hasher.finish()
+    }
+    fn id() -> u64 {
+        FXHASH_HASHER_ID
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<FxHasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+/// The default secret of the final version of wyhash.
+const WYP: [u64; 4] = [
+    0x2d358dccaa6c78a5,
+    0x8bb84b93962eacc9,
+    0x4b33a62ed433d4a3,
+    0x4d5a2da51de1aa47,
+];
+
+/// Computes the final version of wyhash over some bytes with the default secret.
+fn wyhash(bytes: &[u8], seed: u64) -> u64 {
+    let len = bytes.len();
+    let mut seed = seed ^ wymix(seed ^ WYP[0], WYP[1]);
+    let (a, b) = if len <= 16 {
+        if len >= 4 {
+            let quarter = (len >> 3) << 2;
+            (
+                (wyr4(bytes) << 32) | wyr4(&bytes[quarter..]),
+                (wyr4(&bytes[len - 4..]) << 32) | wyr4(&bytes[len - 4 - quarter..]),
+            )
+        } else if len > 0 {
+            (wyr3(bytes), 0)
+        } else {
+            (0, 0)
+        }
+    } else {
+        let mut p = bytes;
+        if p.len() > 48 {
+            let mut see1 = seed;
+            let mut see2 = seed;
+            while p.len() > 48 {
+                seed = wymix(wyr8(p) ^ WYP[1], wyr8(&p[8..]) ^ seed);
+                see1 = wymix(wyr8(&p[16..]) ^ WYP[2], wyr8(&p[24..]) ^ see1);
+                see2 = wymix(wyr8(&p[32..]) ^ WYP[3], wyr8(&p[40..]) ^ see2);
+                p = &p[48..];
+            }
+            seed ^= see1 ^ see2;
+        }
+        while p.len() > 16 {
+            seed = wymix(wyr8(p) ^ WYP[1], wyr8(&p[8..]) ^ seed);
+            p = &p[16..];
+        }
+        // The last 16 bytes are always read, overlapping with bytes already mixed.
+        (wyr8(&bytes[len - 16..]), wyr8(&bytes[len - 8..]))
+    };
+    let (a, b) = wymum(a ^ WYP[1], b ^ seed);
+    wymix(a ^ WYP[0] ^ len as u64, b ^ WYP[1])
+}
+
+/// Multiplies two u64s into a u128, returning its low and high halves.
+fn wymum(a: u64, b: u64) -> (u64, u64) {
+    let r = a as u128 * b as u128;
+    (r as u64, (r >> 64) as u64)
+}
+
+fn wymix(a: u64, b: u64) -> u64 {
+    let (lo, hi) = wymum(a, b);
+    lo ^ hi
+}
+
+fn wyr8(p: &[u8]) -> u64 {
+    u64::from_le_bytes(p[..8].try_into().unwrap())
+}
+
+fn wyr4(p: &[u8]) -> u64 {
+    u32::from_le_bytes(p[..4].try_into().unwrap()) as u64
+}
+
+/// Reads 1 to 3 bytes into a u64, reading the first, middle, and last bytes.
+fn wyr3(p: &[u8]) -> u64 {
+    let k = p.len();
+    ((p[0] as u64) << 16) | ((p[k >> 1] as u64) << 8) | p[k - 1] as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wyhash_vectors() {
+        // Test vectors from the reference implementation, hashing each input
+        // with a seed equal to its position in the list.
+        let vectors: [(&str, u64); 7] = [
+            ("", 0x93228a4de0eec5a2),
+            ("a", 0xc5bac3db178713c4),
+            ("abc", 0xa97f2f7b1d9b3314),
+            ("message digest", 0x786d1f1df3801df4),
+            ("abcdefghijklmnopqrstuvwxyz", 0xdca5a8138ad37c87),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                0xb9e734f117cfaf70,
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                0x6cc5eab49a92d617,
+            ),
+        ];
+        for (seed, (input, expected)) in vectors.iter().enumerate() {
+            assert_eq!(*expected, wyhash(input.as_bytes(), seed as u64));
+        }
+    }
+
+    #[test]
+    fn fxhash_hasher() {
+        let a = <FxHasher as Hasher<&str>>::hash(&"nyan");
+        assert_eq!(a, <FxHasher as Hasher<&str>>::hash(&"nyan"));
+        assert_ne!(a, <FxHasher as Hasher<&str>>::hash(&"cat"));
+    }
+}
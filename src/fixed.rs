@@ -0,0 +1,263 @@
+use std::f64::consts::LN_2;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::words::AlignedWords;
+use crate::{probe_index, random_seed, BloomFilter, HasherInstance, KeyedHasher};
+
+/// Computes the number of bytes a bloom filter needs to store a number of items with an
+/// expected false positive rate in the range (0, 1.0), as `optimal_bits_needed` would,
+/// rounded up to whole bytes. Unlike `optimal_bits_needed`, this is a `const fn`, so
+/// that the size of a `FixedBloomFilter` can be computed at compile time.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::fixed_bytes_needed;
+///
+/// const BYTES: usize = fixed_bytes_needed(1_000, 0.01);
+/// assert_eq!(1_199, BYTES);
+/// ```
+pub const fn fixed_bytes_needed(capacity: u64, fp_rate: f64) -> usize {
+    assert!(
+        fp_rate > 0.0 && fp_rate < 1.0,
+        "fp_rate must be in (0, 1.0)"
+    );
+    let bits = ceil(-(capacity as f64) * ln(fp_rate) / (LN_2 * LN_2));
+    let bytes = bits.div_ceil(8) as usize;
+    if bytes == 0 {
+        1
+    } else {
+        bytes
+    }
+}
+
+/// Computes the natural logarithm of a positive, normal number in a const context,
+/// where `f64::ln` cannot be called. The number is split into its binary exponent and
+/// a mantissa m in [1, 2), whose logarithm is 2 * atanh((m - 1) / (m + 1)), a series
+/// converging to full precision within a few dozen terms.
+const fn ln(x: f64) -> f64 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mantissa = f64::from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let mut sum = 0.0;
+    let mut term = s;
+    let mut i = 1;
+    while i < 64 {
+        sum += term / i as f64;
+        term *= s * s;
+        i += 2;
+    }
+    exponent as f64 * LN_2 + 2.0 * sum
+}
+
+/// Rounds a non-negative number up to the nearest integer in a const context.
+const fn ceil(x: f64) -> u64 {
+    let truncated = x as u64;
+    if (truncated as f64) < x {
+        truncated + 1
+    } else {
+        truncated
+    }
+}
+
+/// A bloom filter holding its bits in an inline array of `BYTES` bytes, for embedded
+/// and latency-critical code that cannot allocate or afford an indirection on every
+/// query. The filter never allocates, hashes items with a `KeyedHasher` stored inline
+/// rather than behind a trait object, and can be built in a const context, such as for
+/// a `static`. Size filters at compile time with `fixed_bytes_needed`.
+///
+/// Items map to the same bits as in a `BloomFilter` with the same number of bits,
+/// number of hash functions and seed, which `to_filter` converts the filter into.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{fixed_bytes_needed, FixedBloomFilter};
+///
+/// const BYTES: usize = fixed_bytes_needed(100, 0.01);
+///
+/// let mut bf: FixedBloomFilter<&str, BYTES> = FixedBloomFilter::new(100);
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// assert_eq!(BYTES, std::mem::size_of_val(bf.as_bytes()));
+/// ```
+pub struct FixedBloomFilter<T: AsRef<[u8]>, const BYTES: usize> {
+    /// The bits of the filter, bit `i` being bit `i % 8` of byte `i / 8`.
+    bits: [u8; BYTES],
+    capacity: u64,
+    num_hash_fns: u32,
+    seed: u64,
+    hasher: KeyedHasher,
+    num_inserted: usize,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: AsRef<[u8]>, const BYTES: usize> FixedBloomFilter<T, BYTES> {
+    const BIT_LEN: u64 = BYTES as u64 * 8;
+
+    /// Creates an empty bloom filter sized to hold a number of items, using the optimal
+    /// number of hash functions for its number of bits, and a randomly seeded hasher.
+    pub fn new(capacity: u64) -> FixedBloomFilter<T, BYTES> {
+        FixedBloomFilter::with_seed(capacity, random_seed())
+    }
+    /// Creates an empty bloom filter sized to hold a number of items, as in `new`, with
+    /// a hasher keyed by a seed. Filters built with the same seed and parameters set the
+    /// same bits for the same items.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::FixedBloomFilter;
+    ///
+    /// static EMPTY: FixedBloomFilter<&str, 128> = FixedBloomFilter::with_seed(100, 42);
+    /// assert!(!EMPTY.has("hello"));
+    /// ```
+    pub const fn with_seed(capacity: u64, seed: u64) -> FixedBloomFilter<T, BYTES> {
+        const { assert!(BYTES > 0, "fixed filters must hold at least one byte") };
+        // The optimal number of hash functions, (m / n) * ln(2), for the filter's bits.
+        let per_item = (BYTES as f64 * 8.0) / (if capacity == 0 { 1 } else { capacity }) as f64;
+        let num_hash_fns = ceil(per_item * LN_2) as u32;
+        FixedBloomFilter {
+            bits: [0; BYTES],
+            capacity,
+            num_hash_fns: if num_hash_fns == 0 { 1 } else { num_hash_fns },
+            seed,
+            hasher: KeyedHasher::new(seed),
+            num_inserted: 0,
+            _marker: PhantomData,
+        }
+    }
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, as in `BloomFilter::insert`.
+    pub fn insert(&mut self, elem: T) -> bool {
+        self.num_inserted += 1;
+        let hashes = self.hasher.hash_pair(&elem);
+        let m = Self::BIT_LEN;
+        let mut changed = false;
+        for i in 0..self.num_hash_fns {
+            let idx = probe_index(hashes, i, m);
+            let (byte, mask) = ((idx / 8) as usize, 1 << (idx % 8));
+            changed |= self.bits[byte] & mask == 0;
+            self.bits[byte] |= mask;
+        }
+        changed
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        let hashes = self.hasher.hash_pair(&elem);
+        (0..self.num_hash_fns).all(|i| {
+            let idx = probe_index(hashes, i, Self::BIT_LEN);
+            self.bits[(idx / 8) as usize] & (1 << (idx % 8)) != 0
+        })
+    }
+    /// Clear all set bits of the bloom filter, setting them back to zero.
+    pub fn clear(&mut self) {
+        self.bits = [0; BYTES];
+        self.num_inserted = 0;
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.num_inserted
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the number of bits set in the bloom filter.
+    pub fn count_ones(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+    /// Returns the number of bits in the bloom filter.
+    pub const fn bit_len(&self) -> usize {
+        BYTES * 8
+    }
+    /// Returns the number of hash functions used for each item.
+    pub const fn num_hash_fns(&self) -> u32 {
+        self.num_hash_fns
+    }
+    /// Returns the bits of the bloom filter, bit `i` being bit `i % 8` of byte `i / 8`.
+    pub const fn as_bytes(&self) -> &[u8; BYTES] {
+        &self.bits
+    }
+    /// Returns a copy of the bloom filter as a heap-allocated `BloomFilter`, such as to
+    /// persist it or to combine it with other filters.
+    pub fn to_filter(&self) -> BloomFilter<T> {
+        let hasher: Arc<dyn HasherInstance<T>> = Arc::new(KeyedHasher::new(self.seed));
+        BloomFilter {
+            bits: AlignedWords::from_bytes(&self.bits),
+            byte_len: BYTES,
+            capacity: self.capacity,
+            num_hash_fns: self.num_hash_fns,
+            hasher,
+            class_hash_fns: vec![],
+            num_inserted: self.num_inserted,
+            max_fp_rate: None,
+            probe_hashers: vec![],
+            dirty: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{assert_matches_dense, Backend};
+    use crate::{optimal_bits_needed, BloomBuilder, Error};
+
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        for (capacity, fp_rate) in [(1, 0.5), (100, 0.01), (1_000, 0.03), (123_456, 0.001)] {
+            let bits = optimal_bits_needed(capacity, fp_rate as f32);
+            assert_eq!(
+                bits.div_ceil(8) as usize,
+                fixed_bytes_needed(capacity, fp_rate)
+            );
+        }
+        assert_eq!(1, fixed_bytes_needed(0, 0.01));
+        for x in [0.001, 0.01, 0.5, 0.999, 1.0, 3.0, 1e10] {
+            assert!((ln(x) - f64::ln(x)).abs() < 1e-12);
+        }
+    }
+
+    impl<const BYTES: usize> Backend for FixedBloomFilter<String, BYTES> {
+        fn insert(&mut self, elem: String) -> bool {
+            Self::insert(self, elem)
+        }
+        fn insert_with_class(&mut self, _: String, class: usize) -> Result<bool, Error> {
+            Err(Error::UnknownClass(class))
+        }
+        fn has(&self, elem: String) -> bool {
+            Self::has(self, elem)
+        }
+        fn has_with_class(&self, _: String, class: usize) -> Result<bool, Error> {
+            Err(Error::UnknownClass(class))
+        }
+        fn to_filter(&self) -> BloomFilter<String> {
+            Self::to_filter(self)
+        }
+    }
+
+    #[test]
+    fn matches_dense_filter() {
+        const BYTES: usize = fixed_bytes_needed(200, 0.01);
+        let bf: FixedBloomFilter<String, BYTES> = FixedBloomFilter::with_seed(200, 7);
+        let dense: BloomFilter<String> = BloomBuilder::new(200, 0.01).seed(7).build();
+        assert_eq!(dense.bit_len(), bf.bit_len());
+        assert_eq!(dense.num_hash_fns(), bf.num_hash_fns());
+        assert_matches_dense(dense, bf, 200);
+    }
+
+    #[test]
+    fn clear() {
+        const BYTES: usize = fixed_bytes_needed(200, 0.01);
+        let mut bf: FixedBloomFilter<String, BYTES> = FixedBloomFilter::with_seed(200, 7);
+        for i in 0..200 {
+            bf.insert(format!("{}", i));
+        }
+        assert_eq!(200, bf.len());
+        bf.clear();
+        assert!(bf.is_empty());
+        assert_eq!(0, bf.count_ones());
+    }
+}
@@ -0,0 +1,612 @@
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use crate::hasher::builtin_hasher;
+use crate::words::AlignedWords;
+use crate::{words, BloomFilter, Compression, Error, FilterParams, HasherInstance};
+
+/// The bytes every encoded filter starts with.
+pub(crate) const MAGIC: [u8; 4] = *b"FBLM";
+/// The version of the format written by `BloomFilter::to_bytes`. Version 1 is the
+/// same layout without the trailing checksum, and can still be decoded.
+const VERSION: u8 = 2;
+/// The first version of the format ending in a checksum.
+pub(crate) const CHECKSUM_VERSION: u8 = 2;
+
+/// Set in the flags byte if the hasher is seeded.
+const FLAG_SEED: u8 = 1;
+/// Set in the flags byte if a maximum false positive rate is configured.
+const FLAG_MAX_FP_RATE: u8 = 1 << 1;
+/// The bits of the flags byte identifying the compression of the bits, see
+/// `Compression::codec`.
+const COMPRESSION_MASK: u8 = 0b11 << COMPRESSION_SHIFT;
+const COMPRESSION_SHIFT: u8 = 2;
+/// Set in the flags byte if the capacity does not fit in a u32, and is encoded as a u64.
+const FLAG_WIDE_CAPACITY: u8 = 1 << 4;
+/// The most tiers a decoded filter can have, far more than any filter needs, so that
+/// the number of tiers read from the input cannot drive an unbounded loop of reads.
+pub(crate) const MAX_TIERS: u32 = 1024;
+/// The most hash functions a decoded filter, or any of its tiers, can use: the number
+/// the optimal sizing gives for the smallest false positive rate an f32 can hold.
+const MAX_NUM_HASH_FNS: u32 = 150;
+
+/// Returns true if a filter can be decoded with a number of hash functions read from
+/// the input. Filters without any would report every element as a member, while too
+/// many would make every lookup loop as many times.
+pub(crate) fn valid_num_hash_fns(num_hash_fns: u32) -> bool {
+    (1..=MAX_NUM_HASH_FNS).contains(&num_hash_fns)
+}
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Encodes the bloom filter as bytes in a self-describing, versioned format, so that
+    /// it can be persisted or sent to another process and decoded with `from_bytes`.
+    ///
+    /// The layout is the magic bytes `FBLM`, followed by the version of the format as a
+    /// single byte and a byte of flags, followed by the capacity and number of hash
+    /// functions as u32s, the hasher id, the seed, the maximum false positive rate and
+    /// the number of inserted elements as u64s, the number of tiers as a u32 followed by
+    /// the number of hash functions of each tier as u32s, and finally the number of
+    /// bytes of the bit array as a u64 followed by the bit array itself. All numbers are
+    /// big-endian, and the seed and maximum false positive rate are zero unless their
+    /// flags, bits 0 and 1, are set. Capacities that do not fit in a u32 are encoded as
+    /// a u64 instead, and set flag bit 4. The encoding ends in the CRC-32 checksum of all
+    /// bytes before it, as a u32, so that corrupted filters are rejected when decoded.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let restored: BloomFilter<&str> = BloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+    /// assert!(restored.has("hello"));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(62 + 4 * self.class_hash_fns.len() + self.byte_len());
+        match self.write_to(&mut out) {
+            Ok(()) => out,
+            // Writing to a vector never fails.
+            Err(_) => unreachable!(),
+        }
+    }
+    /// Streams the bloom filter to a writer in the format of `to_bytes`, without
+    /// building an intermediate copy of its bits, so large filters can be written
+    /// directly to files or sockets. The writer is not buffered or flushed.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let mut file = vec![];
+    /// bf.write_to(&mut file).unwrap();
+    /// let restored: BloomFilter<&str> = BloomFilter::read_from(&file[..]).unwrap();
+    /// assert!(restored.has("hello"));
+    /// ```
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_encoded(w, Compression::None)
+    }
+    pub(crate) fn write_encoded<W: Write>(&self, w: W, compression: Compression) -> io::Result<()> {
+        let mut w = Crc32Writer { inner: w, crc: 0 };
+        let mut header = self.header();
+        header.compression = compression;
+        header.write_to(&mut w)?;
+        if compression == Compression::None {
+            words::write_bytes(&mut w, &self.bits, self.byte_len())?;
+        } else {
+            let payload = compression.compress(&self.bits_to_bytes())?;
+            w.write_all(&(payload.len() as u64).to_be_bytes())?;
+            w.write_all(&payload)?;
+        }
+        w.inner.write_all(&w.crc.to_be_bytes())
+    }
+    /// Decodes a bloom filter previously encoded with `to_bytes`, restoring it with the
+    /// hasher identified in the encoding. Only filters built with the hashers of the
+    /// package can be decoded, including seeded ones. An error is returned if the input
+    /// is malformed, was written by a newer version of the format, does not match its
+    /// checksum, or names a hasher that cannot be restored, such as a custom one.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BloomFilter<T>, Error> {
+        BloomFilter::decode(bytes, Header::hasher)
+    }
+    /// Decodes a bloom filter previously encoded with `to_bytes`, restoring it with a
+    /// hasher that cannot be restored from its id and seed, such as an `HmacHasher`
+    /// holding a secret key. Fails as `from_bytes` does, and with
+    /// `Error::IncompatibleParams` if the hasher's id or seed differ from those the
+    /// filter was built with, such as a hasher keyed by another key.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, HmacHasher};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+    ///     .hasher_instance(HmacHasher::new(b"secret key"))
+    ///     .build();
+    /// bf.insert("hunter2");
+    ///
+    /// let bytes = bf.to_bytes();
+    /// let restored: BloomFilter<&str> =
+    ///     BloomFilter::from_bytes_with_hasher(&bytes, HmacHasher::new(b"secret key")).unwrap();
+    /// assert!(restored.has("hunter2"));
+    /// ```
+    pub fn from_bytes_with_hasher<H: HasherInstance<T> + 'static>(
+        bytes: &[u8],
+        hasher: H,
+    ) -> Result<BloomFilter<T>, Error> {
+        let hasher: Arc<dyn HasherInstance<T>> = Arc::new(hasher);
+        BloomFilter::decode(bytes, |header| {
+            let found = FilterParams {
+                hasher_id: hasher.id(),
+                seed: hasher.seed(),
+                ..header.params.clone()
+            };
+            if found != header.params {
+                return Err(invalid(Error::IncompatibleParams {
+                    expected: Box::new(header.params.clone()),
+                    found: Box::new(found),
+                }));
+            }
+            Ok(hasher)
+        })
+    }
+    /// Decodes all of the given bytes into a filter, restoring its hasher from its
+    /// header with a function.
+    fn decode<F>(bytes: &[u8], hasher: F) -> Result<BloomFilter<T>, Error>
+    where
+        F: FnOnce(&Header) -> io::Result<Arc<dyn HasherInstance<T>>>,
+    {
+        let mut input = bytes;
+        let filter = BloomFilter::read_with(&mut input, hasher).map_err(|err| {
+            match err
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<Error>())
+            {
+                Some(err) => err.clone(),
+                None => Error::InvalidEncoding,
+            }
+        })?;
+        if !input.is_empty() {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(filter)
+    }
+    /// Streams a bloom filter encoded with `to_bytes` or `write_to` from a reader,
+    /// reading exactly as many bytes as the filter occupies. Errors of the reader are
+    /// returned as is, while malformed input is reported as an error of kind
+    /// `InvalidData` wrapping the `Error` describing it. Truncated input is reported
+    /// as an error of kind `UnexpectedEof`.
+    pub fn read_from<R: Read>(r: R) -> io::Result<BloomFilter<T>> {
+        BloomFilter::read_with(r, Header::hasher)
+    }
+    fn read_with<R: Read, F>(r: R, hasher: F) -> io::Result<BloomFilter<T>>
+    where
+        F: FnOnce(&Header) -> io::Result<Arc<dyn HasherInstance<T>>>,
+    {
+        let mut r = Crc32Reader { inner: r, crc: 0 };
+        let header = Header::read_from(&mut r)?;
+        let hasher = hasher(&header)?;
+        let payload_len = match header.compression {
+            Compression::None => header.byte_len(),
+            _ => u64::from_be_bytes(read_array(&mut r)?),
+        };
+        // The length comes from the input, so the bits are read incrementally rather
+        // than allocated upfront, in case the input is truncated or malicious.
+        let mut payload = vec![];
+        (&mut r).take(payload_len).read_to_end(&mut payload)?;
+        if payload.len() as u64 != payload_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if header.version >= CHECKSUM_VERSION {
+            let crc = r.crc;
+            if u32::from_be_bytes(read_array(&mut r.inner)?) != crc {
+                return Err(invalid(Error::Corrupted));
+            }
+        }
+        let bits = header.compression.decompress(payload, header.byte_len())?;
+        Ok(header.into_filter(hasher, bits))
+    }
+    /// Returns the header the filter is encoded with by `to_bytes`.
+    pub(crate) fn header(&self) -> Header {
+        Header {
+            version: VERSION,
+            params: self.params(),
+            max_fp_rate: self.max_fp_rate,
+            num_inserted: self.num_inserted as u64,
+            compression: Compression::None,
+        }
+    }
+}
+
+/// The header of an encoded filter, which holds everything but its bits.
+pub(crate) struct Header {
+    pub(crate) version: u8,
+    pub(crate) params: FilterParams,
+    pub(crate) max_fp_rate: Option<f64>,
+    pub(crate) num_inserted: u64,
+    pub(crate) compression: Compression,
+}
+
+impl Header {
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let params = &self.params;
+        let mut flags = 0;
+        if params.seed.is_some() {
+            flags |= FLAG_SEED;
+        }
+        if self.max_fp_rate.is_some() {
+            flags |= FLAG_MAX_FP_RATE;
+        }
+        flags |= self.compression.codec() << COMPRESSION_SHIFT;
+        let capacity = u32::try_from(params.capacity);
+        if capacity.is_err() {
+            flags |= FLAG_WIDE_CAPACITY;
+        }
+        w.write_all(&MAGIC)?;
+        w.write_all(&[self.version, flags])?;
+        match capacity {
+            Ok(capacity) => w.write_all(&capacity.to_be_bytes())?,
+            Err(_) => w.write_all(&params.capacity.to_be_bytes())?,
+        }
+        w.write_all(&params.num_hash_fns.to_be_bytes())?;
+        w.write_all(&params.hasher_id.to_be_bytes())?;
+        w.write_all(&params.seed.unwrap_or(0).to_be_bytes())?;
+        w.write_all(&self.max_fp_rate.unwrap_or(0.0).to_bits().to_be_bytes())?;
+        w.write_all(&self.num_inserted.to_be_bytes())?;
+        w.write_all(&(params.tier_hash_fns.len() as u32).to_be_bytes())?;
+        for num_hash_fns in &params.tier_hash_fns {
+            w.write_all(&num_hash_fns.to_be_bytes())?;
+        }
+        w.write_all(&self.byte_len().to_be_bytes())
+    }
+    pub(crate) fn read_from<R: Read>(r: &mut R) -> io::Result<Header> {
+        if read_array(r)? != MAGIC {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let [version, flags] = read_array(r)?;
+        if version == 0 || version > VERSION {
+            return Err(invalid(Error::UnsupportedVersion(version)));
+        }
+        if flags & !(FLAG_SEED | FLAG_MAX_FP_RATE | COMPRESSION_MASK | FLAG_WIDE_CAPACITY) != 0 {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let compression = Compression::from_codec((flags & COMPRESSION_MASK) >> COMPRESSION_SHIFT)
+            .map_err(invalid)?;
+        let capacity = match flags & FLAG_WIDE_CAPACITY != 0 {
+            true => u64::from_be_bytes(read_array(r)?),
+            false => u32::from_be_bytes(read_array(r)?) as u64,
+        };
+        let num_hash_fns = u32::from_be_bytes(read_array(r)?);
+        let hasher_id = u64::from_be_bytes(read_array(r)?);
+        let seed = u64::from_be_bytes(read_array(r)?);
+        let max_fp_rate = f64::from_bits(u64::from_be_bytes(read_array(r)?));
+        let num_inserted = u64::from_be_bytes(read_array(r)?);
+        let num_tiers = u32::from_be_bytes(read_array(r)?);
+        if !valid_num_hash_fns(num_hash_fns) || num_tiers > MAX_TIERS {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let mut tier_hash_fns = vec![];
+        for _ in 0..num_tiers {
+            let num_hash_fns = u32::from_be_bytes(read_array(r)?);
+            if !valid_num_hash_fns(num_hash_fns) {
+                return Err(invalid(Error::InvalidEncoding));
+            }
+            tier_hash_fns.push(num_hash_fns);
+        }
+        let byte_len = u64::from_be_bytes(read_array(r)?);
+        if byte_len == 0 || byte_len > u64::MAX / 8 {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        Ok(Header {
+            version,
+            params: FilterParams {
+                capacity,
+                bit_len: byte_len * 8,
+                num_hash_fns,
+                tier_hash_fns,
+                hasher_id,
+                seed: (flags & FLAG_SEED != 0).then_some(seed),
+            },
+            max_fp_rate: (flags & FLAG_MAX_FP_RATE != 0).then_some(max_fp_rate),
+            num_inserted,
+            compression,
+        })
+    }
+    pub(crate) fn byte_len(&self) -> u64 {
+        self.params.bit_len / 8
+    }
+    /// Returns the offset of the number of inserted elements within the encoded header,
+    /// past the magic bytes, version, flags, capacity, number of hash functions, hasher
+    /// id, seed and maximum false positive rate, so that it can be updated in place.
+    #[cfg(any(feature = "mmap", test))]
+    pub(crate) fn num_inserted_offset(&self) -> usize {
+        let capacity_len = match u32::try_from(self.params.capacity) {
+            Ok(_) => 4,
+            Err(_) => 8,
+        };
+        MAGIC.len() + 2 + capacity_len + 4 + 8 + 8 + 8
+    }
+    /// Builds the filter the header was encoded from, given its hasher and bits.
+    pub(crate) fn into_filter<T: AsRef<[u8]>>(
+        self,
+        hasher: Arc<dyn HasherInstance<T>>,
+        bits: Vec<u8>,
+    ) -> BloomFilter<T> {
+        BloomFilter {
+            bits: AlignedWords::from_bytes(&bits),
+            byte_len: bits.len(),
+            capacity: self.params.capacity,
+            num_hash_fns: self.params.num_hash_fns,
+            hasher,
+            class_hash_fns: self.params.tier_hash_fns,
+            num_inserted: self.num_inserted as usize,
+            max_fp_rate: self.max_fp_rate,
+            probe_hashers: vec![],
+            dirty: vec![],
+        }
+    }
+    /// Restores the hasher the filter was built with, see `builtin_hasher`.
+    pub(crate) fn hasher<T: AsRef<[u8]>>(&self) -> io::Result<Arc<dyn HasherInstance<T>>> {
+        let FilterParams {
+            hasher_id, seed, ..
+        } = self.params;
+        builtin_hasher(hasher_id, seed).ok_or_else(|| invalid(Error::UnknownHasher(hasher_id)))
+    }
+}
+
+pub(crate) fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+pub(crate) fn invalid(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// A writer computing the CRC-32 checksum of everything written through it.
+struct Crc32Writer<W> {
+    inner: W,
+    crc: u32,
+}
+
+impl<W: Write> Write for Crc32Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader computing the CRC-32 checksum of everything read through it.
+struct Crc32Reader<R> {
+    inner: R,
+    crc: u32,
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+}
+
+/// The lookup table of the reflected CRC-32 polynomial used by zlib and Ethernet.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Extends a CRC-32 checksum, starting from zero, with more bytes.
+pub(crate) fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, b| {
+        CRC32_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BloomBuilder, HmacHasher};
+
+    #[test]
+    fn roundtrip() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .seed(42)
+            .tier(0.0001)
+            .max_fp_rate(0.05)
+            .build();
+        bf.insert("foo");
+        bf.insert_with_class("bar", 0).unwrap();
+        let restored: BloomFilter<&str> = BloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+        assert_eq!(Some(0.05), restored.max_fp_rate);
+        assert!(restored.has("foo"));
+        assert!(restored.has_with_class("bar", 0).unwrap());
+        assert_eq!(bf.to_bytes(), restored.to_bytes());
+    }
+
+    #[test]
+    fn layout() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(8, 0.1).deterministic().build();
+        bf.insert("nyan");
+        let mut expected = b"FBLM".to_vec();
+        expected.extend_from_slice(&[2, 0]);
+        expected.extend_from_slice(&8u32.to_be_bytes());
+        expected.extend_from_slice(&4u32.to_be_bytes());
+        expected.extend_from_slice(&1u64.to_be_bytes());
+        expected.extend_from_slice(&[0; 16]);
+        expected.extend_from_slice(&1u64.to_be_bytes());
+        expected.extend_from_slice(&0u32.to_be_bytes());
+        expected.extend_from_slice(&5u64.to_be_bytes());
+        expected.extend_from_slice(&[16, 8, 64, 0, 2]);
+        let crc = crc32_update(0, &expected);
+        expected.extend_from_slice(&crc.to_be_bytes());
+        assert_eq!(expected, bf.to_bytes());
+    }
+
+    #[test]
+    fn wide_capacity() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        let mut header = bf.header();
+        header.params.capacity = 1 << 33;
+        let mut encoded = vec![];
+        header.write_to(&mut encoded).unwrap();
+        assert_eq!(FLAG_WIDE_CAPACITY, encoded[5]);
+        assert_eq!((1u64 << 33).to_be_bytes(), encoded[6..14]);
+
+        let decoded = Header::read_from(&mut &encoded[..]).unwrap();
+        assert_eq!(header.params, decoded.params);
+        assert_eq!(bf.len() as u64, decoded.num_inserted);
+    }
+
+    #[test]
+    fn num_inserted_offset() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        for capacity in [100, 1 << 33] {
+            let mut header = bf.header();
+            header.params.capacity = capacity;
+            header.num_inserted = 0x0102_0304_0506_0708;
+            let mut encoded = vec![];
+            header.write_to(&mut encoded).unwrap();
+            let offset = header.num_inserted_offset();
+            assert_eq!(
+                header.num_inserted.to_be_bytes(),
+                encoded[offset..offset + 8]
+            );
+        }
+    }
+
+    #[test]
+    fn bounded_hash_fns() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        let decode = |edit: &dyn Fn(&mut Header)| {
+            let mut header = bf.header();
+            edit(&mut header);
+            let mut encoded = vec![];
+            header.write_to(&mut encoded).unwrap();
+            Header::read_from(&mut &encoded[..]).map(|_| ())
+        };
+        assert!(decode(&|h| h.params.tier_hash_fns = vec![MAX_NUM_HASH_FNS]).is_ok());
+        for num_hash_fns in [0, MAX_NUM_HASH_FNS + 1, u32::MAX] {
+            let err = decode(&|h| h.params.num_hash_fns = num_hash_fns).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+            let err = decode(&|h| h.params.tier_hash_fns = vec![3, num_hash_fns]).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        }
+
+        // The number of tiers is checked before any of them are read.
+        let mut encoded = vec![];
+        bf.header().write_to(&mut encoded).unwrap();
+        encoded[46..50].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = Header::read_from(&mut &encoded[..])
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn malformed() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        let bytes = bf.to_bytes();
+        let decode = |bytes: &[u8]| BloomFilter::<&str>::from_bytes(bytes).err();
+
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            decode(&bytes[..bytes.len() - 1])
+        );
+        assert_eq!(Some(Error::InvalidEncoding), decode(&bytes[..10]));
+        assert_eq!(Some(Error::InvalidEncoding), decode(&[]));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(Some(Error::InvalidEncoding), decode(&trailing));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(Some(Error::InvalidEncoding), decode(&bad_magic));
+
+        let mut newer = bytes.clone();
+        newer[4] = 3;
+        assert_eq!(Some(Error::UnsupportedVersion(3)), decode(&newer));
+
+        let keyed: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hasher_instance(HmacHasher::new(b"secret"))
+            .build();
+        assert_eq!(
+            Some(Error::UnknownHasher(keyed.params().hasher_id)),
+            decode(&keyed.to_bytes())
+        );
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(0xcbf43926, crc32_update(0, b"123456789"));
+        assert_eq!(
+            crc32_update(0, b"123456789"),
+            crc32_update(crc32_update(0, b"1234"), b"56789")
+        );
+
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        bf.insert("foo");
+        let bytes = bf.to_bytes();
+        let mut flipped = bytes.clone();
+        flipped[62] ^= 1 << 3;
+        assert_eq!(
+            Some(Error::Corrupted),
+            BloomFilter::<&str>::from_bytes(&flipped).err()
+        );
+
+        // Version 1 filters have no checksum.
+        let mut v1 = bytes[..bytes.len() - 4].to_vec();
+        v1[4] = 1;
+        let restored: BloomFilter<&str> = BloomFilter::from_bytes(&v1).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).build();
+        bf.insert("foo");
+        let mut out = vec![];
+        bf.write_to(&mut out).unwrap();
+        assert_eq!(bf.to_bytes(), out);
+
+        // Reading stops at the end of the filter, leaving whatever follows it.
+        out.extend_from_slice(b"rest");
+        let mut input = &out[..];
+        let restored: BloomFilter<&str> = BloomFilter::read_from(&mut input).unwrap();
+        assert!(restored.has("foo"));
+        assert_eq!(b"rest", input);
+
+        let truncated = &out[..out.len() - 10];
+        let err = BloomFilter::<&str>::read_from(truncated).err().unwrap();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+
+        let err = BloomFilter::<&str>::read_from(&b"nope"[..]).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
@@ -0,0 +1,322 @@
+use std::sync::Arc;
+
+use crate::words::{AlignedWords, LINE_WORDS};
+use crate::{
+    base_hashes, class_num_hash_fns, filter_params, nth_probe, probes, tier_num_hash_fns,
+    BloomBuilder, BloomFilter, Error, FilterParams, HasherInstance,
+};
+
+impl<T: AsRef<[u8]>> BloomBuilder<T> {
+    /// Builds a bloom filter that can be cleared in constant time, see
+    /// `GenerationalBloomFilter`. The filter is sized and hashes items exactly as the
+    /// filter returned by `build` would, but no maximum false positive rate is enforced.
+    pub fn build_generational(self) -> GenerationalBloomFilter<T> {
+        let (num_hash_fns, size) = self.checked_sizing();
+        let num_words = size.div_ceil(8);
+        GenerationalBloomFilter {
+            words: AlignedWords::zeroed(num_words),
+            line_generations: vec![0; num_words.div_ceil(LINE_WORDS)],
+            generation: 0,
+            bit_len: size as u64 * 8,
+            capacity: self.capacity,
+            num_hash_fns,
+            hasher: self.hasher,
+            class_hash_fns: self.tiers.iter().map(|p| tier_num_hash_fns(*p)).collect(),
+            num_inserted: 0,
+            probe_hashers: self.probe_hashers,
+        }
+    }
+}
+
+/// A bloom filter whose `clear` bumps a generation counter instead of zeroing its bits,
+/// for filters cleared often enough, such as when rotated, that zeroing many megabytes
+/// or gigabytes at once shows up as latency spikes. Built with
+/// `BloomBuilder::build_generational`.
+///
+/// Each cache line of words records the generation it was last written in. Lines
+/// written before the latest clear read as zero, and are reset the first time a bit
+/// in them is set again, so the cost of clearing is spread over later inserts. This
+/// takes an extra 4 bytes per 64 bytes of bits. Once the counter wraps around, after
+/// more than four billion clears, a clear zeroes the bits as `BloomFilter::clear`
+/// would.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, GenerationalBloomFilter};
+///
+/// let mut bf: GenerationalBloomFilter<&str> = BloomBuilder::new(1000, 0.01).build_generational();
+/// bf.insert("hello");
+/// bf.clear();
+/// assert!(!bf.has("hello"));
+/// assert!(bf.is_empty());
+/// ```
+pub struct GenerationalBloomFilter<T: AsRef<[u8]>> {
+    /// Laid out like the words of a `BloomFilter`'s bits, except for the words of lines
+    /// from an earlier generation, which hold stale bits. Each line of words sharing a
+    /// generation fills a cache line, so that resetting a stale line costs no more
+    /// memory traffic than reading one of its words.
+    words: AlignedWords,
+    line_generations: Vec<u32>,
+    generation: u32,
+    bit_len: u64,
+    capacity: u64,
+    num_hash_fns: u32,
+    hasher: Arc<dyn HasherInstance<T>>,
+    class_hash_fns: Vec<u32>,
+    num_inserted: usize,
+    probe_hashers: Vec<Arc<dyn HasherInstance<T>>>,
+}
+
+impl<T: AsRef<[u8]>> GenerationalBloomFilter<T> {
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, as in `BloomFilter::insert`.
+    pub fn insert(&mut self, elem: T) -> bool {
+        let num_hash_fns = self.num_hash_fns;
+        self.insert_with_probes(&elem, num_hash_fns)
+    }
+    /// Insert an element into the bloom filter with the number of hash functions of a
+    /// tier, as in `BloomFilter::insert_with_class`. Fails with `Error::UnknownClass` if the class
+    /// was not configured via `BloomBuilder::tier`.
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self.insert_with_probes(&elem, num_hash_fns))
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.probes(&elem, self.num_hash_fns)
+            .all(|idx| self.get_bit(idx))
+    }
+    /// Checks if the bloom filter contains an element inserted with a class, as in
+    /// `BloomFilter::has_with_class`.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self
+            .probes(&elem, num_hash_fns)
+            .all(|idx| self.get_bit(idx)))
+    }
+    /// Clear all set bits of the bloom filter in constant time, by starting a new
+    /// generation. The bits of earlier generations are reset as they are written again.
+    pub fn clear(&mut self) {
+        match self.generation.checked_add(1) {
+            Some(generation) => self.generation = generation,
+            None => {
+                self.words.iter_mut().for_each(|word| *word = 0);
+                self.line_generations.iter_mut().for_each(|g| *g = 0);
+                self.generation = 0;
+            }
+        }
+        self.num_inserted = 0;
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter
+    /// since it was last cleared.
+    pub fn len(&self) -> usize {
+        self.num_inserted
+    }
+    /// Returns true if no elements have been inserted into the bloom filter since it
+    /// was last cleared.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the number of bits set in the bloom filter.
+    pub fn count_ones(&self) -> usize {
+        self.current_lines()
+            .map(|line| line.iter().map(|w| w.count_ones() as usize).sum::<usize>())
+            .sum()
+    }
+    /// Returns the number of bits in the bloom filter.
+    pub fn bit_len(&self) -> usize {
+        self.bit_len as usize
+    }
+    /// Returns the structural parameters of the bloom filter, identical to those of a
+    /// `BloomFilter` built with the same builder.
+    pub fn params(&self) -> FilterParams {
+        filter_params(
+            self.capacity,
+            self.bit_len,
+            self.num_hash_fns,
+            &self.class_hash_fns,
+            &self.hasher,
+            &self.probe_hashers,
+        )
+    }
+    /// Returns a copy of the bloom filter as a `BloomFilter`, such as to persist it,
+    /// holding only the bits set since the filter was last cleared.
+    pub fn to_filter(&self) -> BloomFilter<T> {
+        let mut bits = AlignedWords::zeroed(self.words.len());
+        for (line, words) in self.current_lines().enumerate() {
+            bits[line * LINE_WORDS..][..words.len()].copy_from_slice(words);
+        }
+        BloomFilter {
+            bits,
+            byte_len: (self.bit_len / 8) as usize,
+            capacity: self.capacity,
+            num_hash_fns: self.num_hash_fns,
+            hasher: self.hasher.clone(),
+            class_hash_fns: self.class_hash_fns.clone(),
+            num_inserted: self.num_inserted,
+            max_fp_rate: None,
+            probe_hashers: self.probe_hashers.clone(),
+            dirty: vec![],
+        }
+    }
+    fn insert_with_probes(&mut self, elem: &T, num_hash_fns: u32) -> bool {
+        self.num_inserted += 1;
+        let hashes = base_hashes(&*self.hasher, &self.probe_hashers, elem);
+        let m = self.bit_len;
+        let mut changed = false;
+        for i in 0..num_hash_fns {
+            let idx = nth_probe(hashes, &self.probe_hashers, elem, i, m);
+            changed |= self.set_bit(idx);
+        }
+        changed
+    }
+    /// Sets the bit at an index, first resetting its line if it is from an earlier
+    /// generation, and returns true if it was not set before.
+    fn set_bit(&mut self, idx: u64) -> bool {
+        let pos = (idx / 64) as usize;
+        let line = pos / LINE_WORDS;
+        if self.line_generations[line] != self.generation {
+            let end = (line * LINE_WORDS + LINE_WORDS).min(self.words.len());
+            self.words[line * LINE_WORDS..end]
+                .iter_mut()
+                .for_each(|word| *word = 0);
+            self.line_generations[line] = self.generation;
+        }
+        let mask = 1 << (idx % 64);
+        let changed = self.words[pos] & mask == 0;
+        self.words[pos] |= mask;
+        changed
+    }
+    fn get_bit(&self, idx: u64) -> bool {
+        let pos = (idx / 64) as usize;
+        self.line_generations[pos / LINE_WORDS] == self.generation
+            && self.words[pos] & (1 << (idx % 64)) != 0
+    }
+    /// Returns the lines of words written in the current generation, stale lines being
+    /// returned as empty slices.
+    fn current_lines(&self) -> impl Iterator<Item = &[u64]> {
+        self.words
+            .chunks(LINE_WORDS)
+            .zip(self.line_generations.iter())
+            .map(|(line, generation)| match *generation == self.generation {
+                true => line,
+                false => &[],
+            })
+    }
+    fn probes<'a>(&'a self, elem: &'a T, num_hash_fns: u32) -> impl Iterator<Item = u64> + 'a {
+        probes(
+            &*self.hasher,
+            &self.probe_hashers,
+            elem,
+            num_hash_fns,
+            self.bit_len,
+        )
+    }
+}
+
+/// Converts a bloom filter into one that can be cleared in constant time, with the same
+/// parameters, hashers and set bits.
+impl<T: AsRef<[u8]>> From<BloomFilter<T>> for GenerationalBloomFilter<T> {
+    fn from(bf: BloomFilter<T>) -> GenerationalBloomFilter<T> {
+        GenerationalBloomFilter {
+            line_generations: vec![0; bf.bits.len().div_ceil(LINE_WORDS)],
+            generation: 0,
+            bit_len: bf.bit_len() as u64,
+            words: bf.bits,
+            capacity: bf.capacity,
+            num_hash_fns: bf.num_hash_fns,
+            hasher: bf.hasher,
+            class_hash_fns: bf.class_hash_fns,
+            num_inserted: bf.num_inserted,
+            probe_hashers: bf.probe_hashers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_matches_dense, Backend};
+
+    impl Backend for GenerationalBloomFilter<String> {
+        fn insert(&mut self, elem: String) -> bool {
+            Self::insert(self, elem)
+        }
+        fn insert_with_class(&mut self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::insert_with_class(self, elem, class)
+        }
+        fn has(&self, elem: String) -> bool {
+            Self::has(self, elem)
+        }
+        fn has_with_class(&self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::has_with_class(self, elem, class)
+        }
+        fn to_filter(&self) -> BloomFilter<String> {
+            Self::to_filter(self)
+        }
+    }
+
+    #[test]
+    fn matches_dense_filter() {
+        // An odd number of bytes leaves the last line partially used.
+        let builder = || BloomBuilder::new(1_000, 0.01).seed(7).tier(0.001);
+        let mut dense: BloomFilter<String> = builder().build();
+        assert_ne!(0, dense.bits.len() % LINE_WORDS);
+        assert_matches_dense(builder().build(), builder().build_generational(), 200);
+
+        dense.insert("bar".to_string());
+        let (params, bits) = (dense.params(), dense.bits.clone());
+        let converted = GenerationalBloomFilter::from(dense);
+        assert_eq!(params, converted.params());
+        assert_eq!(bits, converted.to_filter().bits);
+        assert!(converted.has("bar".to_string()));
+    }
+
+    #[test]
+    fn constant_time_clear() {
+        let mut bf: GenerationalBloomFilter<String> =
+            BloomBuilder::new(1_000, 0.01).seed(7).build_generational();
+        assert_eq!(0, bf.words.as_ptr() as usize % 64);
+        for round in 0..3 {
+            let elems: Vec<String> = (0..200).map(|i| format!("{}-{}", round, i)).collect();
+            for elem in elems.iter() {
+                assert!(bf.insert(elem.clone()));
+            }
+            assert!(elems.iter().all(|elem| bf.has(elem.clone())));
+            bf.clear();
+            // Clearing only bumps the generation, leaving the stale bits in place.
+            assert_eq!(round + 1, bf.generation);
+            assert!(bf.words.iter().any(|word| *word != 0));
+            assert!(bf.is_empty());
+            assert_eq!(0, bf.count_ones());
+            assert!(elems.iter().all(|elem| !bf.has(elem.clone())));
+        }
+
+        // Only the lines an insert touches are reset, and none of their stale bits
+        // survive it.
+        let mut dense: BloomFilter<String> = BloomBuilder::new(1_000, 0.01).seed(7).build();
+        dense.insert("foo".to_string());
+        bf.insert("foo".to_string());
+        let current = bf.line_generations.iter().filter(|g| **g == bf.generation);
+        assert!(current.count() <= dense.num_hash_fns() as usize);
+        assert_eq!(dense.bits, bf.to_filter().bits);
+        assert_eq!(dense.count_ones(), bf.count_ones());
+    }
+
+    #[test]
+    fn generation_wraps_around() {
+        let mut bf: GenerationalBloomFilter<&str> =
+            BloomBuilder::new(100, 0.01).build_generational();
+        bf.insert("foo");
+        bf.generation = u32::MAX;
+        bf.line_generations.iter_mut().for_each(|g| *g = u32::MAX);
+        assert!(bf.has("foo"));
+        bf.clear();
+        assert_eq!(0, bf.generation);
+        assert!(bf.words.iter().all(|word| *word == 0));
+        assert!(!bf.has("foo"));
+        assert!(bf.insert("bar"));
+        assert!(bf.has("bar"));
+    }
+}
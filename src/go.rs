@@ -0,0 +1,302 @@
+use crate::murmur::murmur3_x64_128;
+use crate::{format, Error};
+
+/// A bloom filter reproducing the `BloomFilter` of the Go package
+/// `github.com/bits-and-blooms/bloom/v3` bit for bit, so that filters can be exchanged
+/// with Go services through its `WriteTo` and `ReadFrom` binary encoding, or through its
+/// JSON encoding when the `serde` feature is enabled.
+///
+/// Items are hashed into four base hashes: the two halves of the 128-bit x64 MurmurHash3
+/// of the item's bytes, followed by the two halves of the hash of those bytes with a
+/// single `1` byte appended. Probe i of an item sets bit `(h[i % 2] + i * h[2 + ((i +
+/// i % 2) % 4) / 2]) mod m` of a filter of m bits. Versions of the Go package before
+/// v2, then published as `github.com/willf/bloom`, derived probes from FNV hashes
+/// instead, and their filters cannot be read. Requires the `murmur3` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::GoBloomFilter;
+///
+/// let mut bf = GoBloomFilter::new(1000, 0.01);
+/// bf.insert("hello");
+///
+/// // Bytes read by `bf.ReadFrom(r)` in Go.
+/// let bytes = bf.to_bytes();
+/// let restored = GoBloomFilter::from_bytes(&bytes).unwrap();
+/// assert!(restored.has("hello"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoBloomFilter {
+    m: u64,
+    k: u64,
+    words: Vec<u64>,
+}
+
+impl GoBloomFilter {
+    /// Creates a new filter for an expected number of items and false positive rate,
+    /// sized as `bloom.NewWithEstimates` sizes it. Panics if the false positive rate is
+    /// not between 0 and 1.
+    pub fn new(n: u64, fp_rate: f64) -> GoBloomFilter {
+        assert!(
+            fp_rate > 0.0 && fp_rate < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+        let ln2 = std::f64::consts::LN_2;
+        let m = (-(n as f64) * fp_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let k = (ln2 * m as f64 / n as f64).ceil() as u64;
+        GoBloomFilter::with_size(m, k)
+    }
+    /// Creates a new filter with a given number of bits and hash functions, as
+    /// `bloom.New` does, using at least one of each.
+    pub fn with_size(m: u64, k: u64) -> GoBloomFilter {
+        let m = m.max(1);
+        GoBloomFilter {
+            m,
+            k: k.max(1),
+            words: vec![0; m.div_ceil(64) as usize],
+        }
+    }
+    /// Inserts an item into the filter, as `Add` does. Returns true if any bit of the
+    /// filter changed, meaning the item was definitely not in it before.
+    pub fn insert<T: AsRef<[u8]>>(&mut self, item: T) -> bool {
+        let hashes = go_hashes(item.as_ref());
+        let mut changed = false;
+        for i in 0..self.k {
+            let idx = self.probe(&hashes, i);
+            let (word, mask) = ((idx / 64) as usize, 1 << (idx % 64));
+            changed |= self.words[word] & mask == 0;
+            self.words[word] |= mask;
+        }
+        changed
+    }
+    /// Checks if the filter contains an item, as `Test` does. Like any bloom filter, it
+    /// can produce false positives, but will never produce false negatives.
+    pub fn has<T: AsRef<[u8]>>(&self, item: T) -> bool {
+        let hashes = go_hashes(item.as_ref());
+        (0..self.k).all(|i| {
+            let idx = self.probe(&hashes, i);
+            self.words[(idx / 64) as usize] & (1 << (idx % 64)) != 0
+        })
+    }
+    /// Returns the number of bits of the filter, as `Cap` does.
+    pub fn bit_len(&self) -> u64 {
+        self.m
+    }
+    /// Returns the number of bits set for every item, as `K` does.
+    pub fn num_hash_fns(&self) -> u64 {
+        self.k
+    }
+    /// Encodes the filter as `WriteTo` does: the number of bits and hash functions as
+    /// u64s, followed by the bitset, which is its length in bits as a u64 followed by
+    /// its 64-bit words, all big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(24 + 8 * self.words.len());
+        out.extend_from_slice(&self.m.to_be_bytes());
+        out.extend_from_slice(&self.k.to_be_bytes());
+        out.extend_from_slice(&self.bitset_bytes());
+        out
+    }
+    /// Decodes a filter written by `WriteTo` or `to_bytes`. Fails with
+    /// `Error::InvalidEncoding` if the input is malformed, or if its bitset does not
+    /// hold as many bits as the filter.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GoBloomFilter, Error> {
+        if bytes.len() < 16 {
+            return Err(Error::InvalidEncoding);
+        }
+        let m = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let k = u64::from_be_bytes(bytes[8..16].try_into().unwrap());
+        GoBloomFilter::from_parts(m, k, &bytes[16..])
+    }
+    /// Encodes the bitset of the filter as the bitset package's `WriteTo` does.
+    fn bitset_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + 8 * self.words.len());
+        out.extend_from_slice(&self.m.to_be_bytes());
+        for word in self.words.iter() {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+    /// Restores a filter from its number of bits and hash functions and its encoded
+    /// bitset, which must hold exactly m bits. The number of hash functions is bounded
+    /// like that of the crate's own encodings, since every query probes that many bits.
+    fn from_parts(m: u64, k: u64, bitset: &[u8]) -> Result<GoBloomFilter, Error> {
+        let valid_k = u32::try_from(k).is_ok_and(format::valid_num_hash_fns);
+        if m == 0 || !valid_k || bitset.len() < 8 {
+            return Err(Error::InvalidEncoding);
+        }
+        let len = u64::from_be_bytes(bitset[..8].try_into().unwrap());
+        let words = &bitset[8..];
+        if len != m || words.len() as u64 != m.div_ceil(64).saturating_mul(8) {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(GoBloomFilter {
+            m,
+            k,
+            words: words
+                .chunks_exact(8)
+                .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+                .collect(),
+        })
+    }
+    fn probe(&self, h: &[u64; 4], i: u64) -> u64 {
+        let location = h[(i % 2) as usize]
+            .wrapping_add(i.wrapping_mul(h[2 + (((i + (i % 2)) % 4) / 2) as usize]));
+        location % self.m
+    }
+}
+
+/// Produces the four base hashes the Go package derives from an item's bytes.
+fn go_hashes(bytes: &[u8]) -> [u64; 4] {
+    let (h1, h2) = murmur3_x64_128(bytes, 0);
+    let mut appended = Vec::with_capacity(bytes.len() + 1);
+    appended.extend_from_slice(bytes);
+    appended.push(1);
+    let (h3, h4) = murmur3_x64_128(&appended, 0);
+    [h1, h2, h3, h4]
+}
+
+/// The JSON form of a filter, as written by the Go package's `MarshalJSON`: the number
+/// of bits and hash functions, and the bitset encoded as by `WriteTo` in URL-safe base64.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GoJson {
+    m: u64,
+    k: u64,
+    b: String,
+}
+
+/// Serializes the filter as the Go package's `MarshalJSON` does, so that serializing it
+/// with `serde_json` gives JSON that `UnmarshalJSON` accepts. Requires the `serde` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::GoBloomFilter;
+///
+/// let mut bf = GoBloomFilter::with_size(64, 3);
+/// bf.insert("hello");
+/// let json = serde_json::to_string(&bf).unwrap();
+/// assert!(json.starts_with(r#"{"m":64,"k":3,"b":""#));
+///
+/// let restored: GoBloomFilter = serde_json::from_str(&json).unwrap();
+/// assert!(restored.has("hello"));
+/// ```
+#[cfg(feature = "serde")]
+impl serde::Serialize for GoBloomFilter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use crate::base64::{base64_encode, BASE64_URL};
+        GoJson {
+            m: self.m,
+            k: self.k,
+            b: base64_encode(&self.bitset_bytes(), BASE64_URL),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes a filter serialized by the Go package's `MarshalJSON` or `Serialize`.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for GoBloomFilter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use crate::base64::{base64_decode, BASE64_URL};
+        use serde::de::Error as _;
+        let json = GoJson::deserialize(deserializer)?;
+        let bitset = base64_decode(&json.b, BASE64_URL)
+            .ok_or_else(|| D::Error::custom("filter bitset is not valid base64"))?;
+        GoBloomFilter::from_parts(json.m, json.k, &bitset).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        // bloom.EstimateParameters(1000, 0.01) returns 9586 bits and 7 hash functions.
+        let bf = GoBloomFilter::new(1000, 0.01);
+        assert_eq!(9586, bf.bit_len());
+        assert_eq!(7, bf.num_hash_fns());
+        assert_eq!(150, bf.words.len());
+        assert_eq!(1, GoBloomFilter::with_size(0, 0).bit_len());
+    }
+
+    #[test]
+    fn probes() {
+        let h = go_hashes(b"hello");
+        assert_eq!([0xcbd8a7b341bd9b02, 0x5b1e906a48ae1d19], h[..2]);
+        // The second pair is the hash of the bytes with a 1 appended.
+        assert_eq!(murmur3_x64_128(b"hello\x01", 0), (h[2], h[3]));
+        // Only probes are derived, so the filter needs no words.
+        let bf = GoBloomFilter {
+            m: u64::MAX,
+            k: 5,
+            words: vec![],
+        };
+        let expected = [
+            h[0],
+            h[1].wrapping_add(h[3]),
+            h[0].wrapping_add(2u64.wrapping_mul(h[3])),
+            h[1].wrapping_add(3u64.wrapping_mul(h[2])),
+            h[0].wrapping_add(4u64.wrapping_mul(h[2])),
+        ];
+        for (i, location) in expected.iter().enumerate() {
+            assert_eq!(location % u64::MAX, bf.probe(&h, i as u64));
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut bf = GoBloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            bf.insert(format!("{}", i));
+        }
+        let bytes = bf.to_bytes();
+        assert_eq!(16 + 8 + 150 * 8, bytes.len());
+        assert_eq!(&9586u64.to_be_bytes(), &bytes[16..24]);
+        let restored = GoBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(bf, restored);
+        for i in 0..1000 {
+            assert!(restored.has(format!("{}", i)));
+        }
+        let false_positives = (1000..11_000)
+            .filter(|i| restored.has(format!("{}", i)))
+            .count();
+        assert!(false_positives < 200, "{}", false_positives);
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = GoBloomFilter::new(100, 0.01).to_bytes();
+        let decode = |bytes: &[u8]| GoBloomFilter::from_bytes(bytes).err();
+        assert_eq!(Some(Error::InvalidEncoding), decode(&bytes[..20]));
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            decode(&bytes[..bytes.len() - 1])
+        );
+        let mut mismatched = bytes.clone();
+        mismatched[23] ^= 1;
+        assert_eq!(Some(Error::InvalidEncoding), decode(&mismatched));
+        for k in [0, 151, u64::MAX] {
+            let mut slow = bytes.clone();
+            slow[8..16].copy_from_slice(&k.to_be_bytes());
+            assert_eq!(Some(Error::InvalidEncoding), decode(&slow));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let mut bf = GoBloomFilter::with_size(8, 2);
+        bf.insert("nyan");
+        let json = serde_json::to_value(&bf).unwrap();
+        let bitset =
+            crate::base64::base64_decode(json["b"].as_str().unwrap(), crate::base64::BASE64_URL)
+                .unwrap();
+        assert_eq!(&8u64.to_be_bytes(), &bitset[..8]);
+        assert_eq!(&bf.words[0].to_be_bytes(), &bitset[8..]);
+        assert_eq!(bf, serde_json::from_value(json).unwrap());
+
+        let json = serde_json::json!({"m": 8, "k": 2, "b": "not base64"});
+        assert!(serde_json::from_value::<GoBloomFilter>(json).is_err());
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::BTreeSet;
+use std::hash::Hasher as _;
+
+use siphasher::sip::SipHasher24;
+
+use crate::{DefaultHasher, Hasher};
+
+/// The Golomb-Rice parameter of BIP-158 basic block filters.
+const BIP158_P: u8 = 19;
+/// The inverse false positive rate of BIP-158 basic block filters.
+const BIP158_M: u64 = 784931;
+
+/// A Golomb-coded set (GCS) is a static, query-only alternative to a bloom filter
+/// that is considerably more compact on the wire. Every item is hashed into the range
+/// [0, n * 2^p), the resulting values are sorted, and the differences between
+/// consecutive values are compressed with Golomb-Rice coding using a parameter p.
+///
+/// The false positive rate of the set is roughly 1 / 2^p, and each item costs around
+/// p + 1.5 bits, which gets within ~1.5x of the information theoretic lower bound
+/// compared to the ~1.44x overhead of a bloom filter *plus* the cost of storing
+/// a bit array sized for the target capacity.
+///
+/// Because the set is immutable once built, it is well suited for things such
+/// as blocklists that are computed once and then shipped to many clients.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::GolombSet;
+///
+/// let items = vec!["foo", "bar", "baz"];
+/// let desired_fp_rate = 0.01;
+/// let set: GolombSet<&str> = GolombSet::new(items, desired_fp_rate);
+///
+/// assert!(set.has("foo"));
+/// if !set.has("nyan") {
+///     println!("definitely not in the set");
+/// }
+///
+/// // The encoded set can be shipped around and decoded on the other side.
+/// let encoded = set.to_bytes();
+/// let decoded: GolombSet<&str> = GolombSet::from_bytes(&encoded).unwrap();
+/// assert!(decoded.has("bar"));
+/// ```
+pub struct GolombSet<T: AsRef<[u8]>> {
+    num_items: u64,
+    p: u8,
+    data: Vec<u8>,
+    hash_fn: fn(&T) -> u64,
+}
+
+impl<T: AsRef<[u8]>> GolombSet<T> {
+    /// Builds a Golomb-coded set over the given items using the package's default hasher,
+    /// choosing the Golomb-Rice parameter that satisfies the desired false positive rate.
+    pub fn new<I: IntoIterator<Item = T>>(items: I, desired_fp_rate: f32) -> GolombSet<T> {
+        GolombSet::with_hasher::<DefaultHasher, I>(items, desired_fp_rate)
+    }
+    /// Builds a Golomb-coded set over the given items using a custom hasher.
+    pub fn with_hasher<H: Hasher<T>, I: IntoIterator<Item = T>>(
+        items: I,
+        desired_fp_rate: f32,
+    ) -> GolombSet<T> {
+        let hash_fn: fn(&T) -> u64 = H::hash;
+        let p = optimal_rice_parameter(desired_fp_rate);
+        let hashes: Vec<u64> = items.into_iter().map(|item| hash_fn(&item)).collect();
+        let num_items = hashes.len() as u64;
+        let range = num_items << p;
+
+        let mut values: Vec<u64> = hashes
+            .into_iter()
+            .map(|h| reduce_to_range(h, range))
+            .collect();
+        values.sort_unstable();
+        values.dedup();
+
+        let mut writer = BitWriter::default();
+        let mut last = 0;
+        for value in values {
+            writer.write_rice(value - last, p);
+            last = value;
+        }
+        GolombSet {
+            num_items,
+            p,
+            data: writer.finish(),
+            hash_fn,
+        }
+    }
+    /// Checks if the set contains a specified element. Like a bloom filter, the
+    /// set can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        if self.num_items == 0 {
+            return false;
+        }
+        let target = reduce_to_range((self.hash_fn)(&elem), self.num_items << self.p);
+        let mut reader = BitReader::new(&self.data);
+        let mut value = 0u64;
+        while let Some(delta) = reader.read_rice(self.p) {
+            // Values overflowing are past any target, as are values beyond it.
+            let Some(next) = value.checked_add(delta) else {
+                return false;
+            };
+            value = next;
+            if value == target {
+                return true;
+            }
+            if value > target {
+                return false;
+            }
+        }
+        false
+    }
+    /// Returns the number of items the set was built from.
+    pub fn len(&self) -> usize {
+        self.num_items as usize
+    }
+    /// Returns true if the set was built from no items.
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+    /// Encodes the set as bytes. The layout is the number of items as a
+    /// big-endian u64, followed by the Golomb-Rice parameter as a single byte,
+    /// followed by the Golomb-Rice coded deltas.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(9 + self.data.len());
+        out.extend_from_slice(&self.num_items.to_be_bytes());
+        out.push(self.p);
+        out.extend_from_slice(&self.data);
+        out
+    }
+    /// Decodes a set previously encoded with `to_bytes` that was built
+    /// using the package's default hasher. Returns None if the input is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<GolombSet<T>> {
+        GolombSet::from_bytes_with_hasher::<DefaultHasher>(bytes)
+    }
+    /// Decodes a set previously encoded with `to_bytes` that was built using
+    /// a custom hasher. Returns None if the input is malformed.
+    pub fn from_bytes_with_hasher<H: Hasher<T>>(bytes: &[u8]) -> Option<GolombSet<T>> {
+        if bytes.len() < 9 {
+            return None;
+        }
+        let (header, data) = bytes.split_at(9);
+        let num_items = u64::from_be_bytes(header[..8].try_into().ok()?);
+        let p = header[8];
+        if p >= 64 || num_items.leading_zeros() < p as u32 {
+            return None;
+        }
+        Some(GolombSet {
+            num_items,
+            p,
+            data: data.to_vec(),
+            hash_fn: H::hash,
+        })
+    }
+}
+
+/// A compact block filter as defined by Bitcoin's BIP-158, the Golomb-coded set light
+/// clients download to find out whether a block touches any of their scripts.
+///
+/// Unlike `GolombSet`, the parameters are fixed by the protocol: items are hashed with
+/// SipHash-2-4 keyed by the first 16 bytes of the block hash, mapped into the range
+/// [0, n * 784931), and their deltas are Golomb-Rice coded with a parameter of 19,
+/// giving a false positive rate of 1 / 784931. Duplicate items are only counted once.
+/// The block hash is given in its internal byte order, the reverse of how it is
+/// usually displayed.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::Bip158Filter;
+///
+/// let block_hash = [7u8; 32];
+/// let scripts = vec![vec![0x51], vec![0x00, 0x14, 0xab]];
+/// let filter = Bip158Filter::new(&block_hash, &scripts);
+/// assert!(filter.has(&scripts[0]));
+///
+/// // The serialized filter is what peers serve in cfilter messages.
+/// let decoded = Bip158Filter::from_bytes(&block_hash, &filter.to_bytes()).unwrap();
+/// assert!(decoded.has_any(&scripts));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bip158Filter {
+    key: (u64, u64),
+    num_items: u64,
+    data: Vec<u8>,
+}
+
+impl Bip158Filter {
+    /// Builds the filter of a block from its hash and the scripts it should match,
+    /// which for a basic filter are the output scripts the block creates and the
+    /// previous output scripts it spends, leaving out empty and `OP_RETURN` scripts.
+    pub fn new<I, T>(block_hash: &[u8; 32], items: I) -> Bip158Filter
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let key = bip158_key(block_hash);
+        let items: BTreeSet<Vec<u8>> = items.into_iter().map(|i| i.as_ref().to_vec()).collect();
+        let num_items = items.len() as u64;
+        let range = num_items * BIP158_M;
+        let mut values: Vec<u64> = items
+            .iter()
+            .map(|item| reduce_to_range(bip158_hash(key, item), range))
+            .collect();
+        values.sort_unstable();
+
+        let mut writer = BitWriter::default();
+        let mut last = 0;
+        for value in values {
+            writer.write_rice(value - last, BIP158_P);
+            last = value;
+        }
+        Bip158Filter {
+            key,
+            num_items,
+            data: writer.finish(),
+        }
+    }
+    /// Checks if the filter matches a script. Like a bloom filter, it can produce false
+    /// positives, but will never produce false negatives.
+    pub fn has<T: AsRef<[u8]>>(&self, item: T) -> bool {
+        self.has_any([item])
+    }
+    /// Checks if the filter matches any of a number of scripts, such as all scripts of
+    /// a wallet, decoding the filter only once.
+    pub fn has_any<I, T>(&self, items: I) -> bool
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let range = self.num_items * BIP158_M;
+        let mut targets: Vec<u64> = items
+            .into_iter()
+            .map(|item| reduce_to_range(bip158_hash(self.key, item.as_ref()), range))
+            .collect();
+        targets.sort_unstable();
+        let mut targets = targets.into_iter().peekable();
+        let mut reader = BitReader::new(&self.data);
+        let mut value = 0u64;
+        for _ in 0..self.num_items {
+            match reader
+                .read_rice(BIP158_P)
+                .and_then(|delta| value.checked_add(delta))
+            {
+                Some(next) => value = next,
+                None => return false,
+            }
+            while let Some(target) = targets.peek() {
+                if *target == value {
+                    return true;
+                }
+                if *target > value {
+                    break;
+                }
+                targets.next();
+            }
+        }
+        false
+    }
+    /// Returns the number of distinct items the filter was built from.
+    pub fn len(&self) -> usize {
+        self.num_items as usize
+    }
+    /// Returns true if the filter was built from no items.
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+    /// Encodes the filter as BIP-158 serializes it: the number of items as a Bitcoin
+    /// CompactSize, followed by the Golomb-Rice coded deltas.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(9 + self.data.len());
+        match self.num_items {
+            n @ 0..=0xfc => out.push(n as u8),
+            n @ 0xfd..=0xffff => {
+                out.push(0xfd);
+                out.extend_from_slice(&(n as u16).to_le_bytes());
+            }
+            n @ 0x10000..=0xffff_ffff => {
+                out.push(0xfe);
+                out.extend_from_slice(&(n as u32).to_le_bytes());
+            }
+            n => {
+                out.push(0xff);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&self.data);
+        out
+    }
+    /// Decodes the filter of a block from its serialized form, such as the contents of
+    /// a cfilter message. Returns None if the input is malformed.
+    pub fn from_bytes(block_hash: &[u8; 32], bytes: &[u8]) -> Option<Bip158Filter> {
+        let (num_items, data) = match *bytes.first()? {
+            0xfd => (
+                u16::from_le_bytes(bytes.get(1..3)?.try_into().ok()?) as u64,
+                3,
+            ),
+            0xfe => (
+                u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?) as u64,
+                5,
+            ),
+            0xff => (u64::from_le_bytes(bytes.get(1..9)?.try_into().ok()?), 9),
+            n => (n as u64, 1),
+        };
+        num_items.checked_mul(BIP158_M)?;
+        Some(Bip158Filter {
+            key: bip158_key(block_hash),
+            num_items,
+            data: bytes[data..].to_vec(),
+        })
+    }
+}
+
+/// Derives the SipHash key of a block's filter from the first 16 bytes of its hash.
+fn bip158_key(block_hash: &[u8; 32]) -> (u64, u64) {
+    (
+        u64::from_le_bytes(block_hash[..8].try_into().unwrap()),
+        u64::from_le_bytes(block_hash[8..16].try_into().unwrap()),
+    )
+}
+
+fn bip158_hash((k0, k1): (u64, u64), item: &[u8]) -> u64 {
+    let mut hasher = SipHasher24::new_with_keys(k0, k1);
+    hasher.write(item);
+    hasher.finish()
+}
+
+/// Computes the Golomb-Rice parameter p such that 1 / 2^p is at most the
+/// desired false positive rate. Rounds up to the nearest integer.
+fn optimal_rice_parameter(fp_rate: f32) -> u8 {
+    assert!(fp_rate > 0.0 && fp_rate < 1.0);
+    let p = (1.0 / fp_rate).log2().ceil();
+    p.clamp(1.0, 32.0) as u8
+}
+
+/// Maps a uniformly distributed u64 into the range [0, range) without
+/// the bias or cost of a modulo operation.
+fn reduce_to_range(hash: u64, range: u64) -> u64 {
+    ((hash as u128 * range as u128) >> 64) as u64
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_pos: u8,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bit_pos == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            // The last byte always exists, as we push one when starting a new byte.
+            if let Some(b) = self.bytes.last_mut() {
+                *b |= 1 << (7 - self.bit_pos);
+            }
+        }
+        self.bit_pos = (self.bit_pos + 1) % 8;
+    }
+    fn write_rice(&mut self, value: u64, p: u8) {
+        // The quotient is written in unary, terminated by a zero bit.
+        for _ in 0..(value >> p) {
+            self.write_bit(true);
+        }
+        self.write_bit(false);
+        // The remainder is written as p bits, most significant bit first.
+        for i in (0..p).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader { bytes, pos: 0 }
+    }
+    fn read_bit(&mut self) -> Option<bool> {
+        let b = self.bytes.get(self.pos / 8)?;
+        let bit = (b >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit == 1)
+    }
+    fn read_rice(&mut self, p: u8) -> Option<u64> {
+        let mut quotient = 0u64;
+        while self.read_bit()? {
+            quotient += 1;
+        }
+        let mut remainder = 0u64;
+        for _ in 0..p {
+            remainder = (remainder << 1) | self.read_bit()? as u64;
+        }
+        Some((quotient << p) | remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let items: Vec<String> = (0..1_000).map(|i| format!("{}", i)).collect();
+        let set: GolombSet<String> = GolombSet::new(items.clone(), 0.01);
+        assert_eq!(1_000, set.len());
+        for item in items {
+            assert!(set.has(item));
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let set: GolombSet<&str> = GolombSet::new(vec![], 0.01);
+        assert!(set.is_empty());
+        assert!(!set.has("nyan"));
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let items = vec!["wow", "rust", "is", "so", "cool"];
+        let set: GolombSet<&str> = GolombSet::new(items.clone(), 0.03);
+        let decoded: GolombSet<&str> = GolombSet::from_bytes(&set.to_bytes()).unwrap();
+        assert_eq!(set.len(), decoded.len());
+        for item in items {
+            assert!(decoded.has(item));
+        }
+        assert!(GolombSet::<&str>::from_bytes(&[0; 4]).is_none());
+    }
+
+    #[test]
+    fn overflowing_deltas() {
+        // Two items at p = 62, the first at 1 and the second a delta of 2^64 - 1 past it.
+        let bits = format!("0{:062b}1110{:062b}", 1, (1u64 << 62) - 1);
+        let mut bytes = vec![0, 0, 0, 0, 0, 0, 0, 2, 62];
+        for chunk in bits.as_bytes().chunks(8) {
+            let chunk = std::str::from_utf8(chunk).unwrap();
+            bytes.push(u8::from_str_radix(&format!("{:0<8}", chunk), 2).unwrap());
+        }
+        let set: GolombSet<&str> = GolombSet::from_bytes(&bytes).unwrap();
+        assert!(!set.has("nyan"));
+    }
+
+    #[test]
+    fn test_real_fp_rate() {
+        let wanted_fp_rate = 0.03;
+        let num_items = 1_000;
+        let items: Vec<String> = (0..num_items).map(|i| format!("{}", i)).collect();
+        let set: GolombSet<String> = GolombSet::new(items, wanted_fp_rate);
+
+        let num_tests = 1_000;
+        let mut false_positives = 0;
+        for i in num_items..num_items + num_tests {
+            if set.has(format!("{}", i)) {
+                false_positives += 1;
+            }
+        }
+        let real_fp_rate = false_positives as f32 / num_tests as f32;
+        assert!(real_fp_rate <= wanted_fp_rate + 0.02);
+    }
+
+    #[test]
+    fn smaller_than_bloom_filter() {
+        let items: Vec<String> = (0..1_000).map(|i| format!("{}", i)).collect();
+        let set: GolombSet<String> = GolombSet::new(items, 0.01);
+        let bloom_bytes = (crate::optimal_bits_needed(1_000, 0.01) as f64 / 8.0).ceil() as usize;
+        assert!(set.to_bytes().len() < bloom_bytes);
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bip158_vectors() {
+        // The basic filter of the testnet genesis block from the test vectors of
+        // BIP-158, matching the pay-to-pubkey output script of its coinbase.
+        let mut block_hash: [u8; 32] =
+            hex("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943")
+                .try_into()
+                .unwrap();
+        block_hash.reverse();
+        let script = hex(concat!(
+            "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4c",
+            "ef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac"
+        ));
+        let filter = Bip158Filter::new(&block_hash, [&script]);
+        assert_eq!(hex("019dfca8"), filter.to_bytes());
+        let decoded = Bip158Filter::from_bytes(&block_hash, &hex("019dfca8")).unwrap();
+        assert_eq!(filter, decoded);
+        assert!(decoded.has(&script));
+        assert!(!decoded.has([0x51]));
+
+        let empty = Bip158Filter::new(&block_hash, Vec::<Vec<u8>>::new());
+        assert_eq!(vec![0], empty.to_bytes());
+        assert!(!empty.has(&script));
+    }
+
+    #[test]
+    fn bip158_matching() {
+        let block_hash = [42u8; 32];
+        let scripts: Vec<Vec<u8>> = (0..300u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        // Duplicates are only counted once.
+        let filter = Bip158Filter::new(&block_hash, scripts.iter().chain(&scripts[..10]));
+        assert_eq!(300, filter.len());
+        let bytes = filter.to_bytes();
+        assert_eq!(&[0xfd, 0x2c, 0x01], &bytes[..3]);
+        let decoded = Bip158Filter::from_bytes(&block_hash, &bytes).unwrap();
+        for script in scripts.iter() {
+            assert!(decoded.has(script));
+        }
+        let wallet: Vec<Vec<u8>> = (1000..1100u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        assert!(!decoded.has_any(&wallet));
+        assert!(decoded.has_any(wallet.iter().chain([&scripts[150]])));
+        // Filters are keyed by their block, so another block's key does not match.
+        let other = Bip158Filter::from_bytes(&[0; 32], &bytes).unwrap();
+        assert!(!scripts.iter().all(|script| other.has(script)));
+        assert!(Bip158Filter::from_bytes(&block_hash, &[0xfd, 0x2c]).is_none());
+    }
+}
@@ -0,0 +1,550 @@
+use std::collections::VecDeque;
+
+use crate::{DefaultHasher, Hasher};
+
+/// The most bits a fingerprint can have, so that a slot, holding a fingerprint, the bit
+/// marking its end and three metadata bits, fits in a u64.
+const MAX_FINGERPRINT_BITS: u32 = 60;
+
+/// Set on a slot if some key has the slot's index as its quotient.
+const OCCUPIED: u64 = 1;
+/// Set on a slot holding a fingerprint of the same quotient as the slot before it.
+const CONTINUATION: u64 = 1 << 1;
+/// Set on a slot holding a fingerprint whose quotient is not the slot's index.
+const SHIFTED: u64 = 1 << 2;
+
+/// A growable filter that doubles its size in place without needing access to
+/// the original key set, in the style of taffy filters.
+///
+/// Rather than setting bits in a bit array, every key is hashed into a u64 whose top
+/// bits are its quotient and whose following bits are kept as the key's fingerprint.
+/// Fingerprints are stored as in a quotient filter, packed into a single array of
+/// slots, each fingerprint in the slot of its quotient or shifted past it, sorted by
+/// quotient. When the filter grows, the number of slots is doubled and each fingerprint
+/// donates its most significant bit to the quotient it moves to, splitting each slot's
+/// fingerprints between two new ones. No key has to be re-hashed, at the cost of old
+/// fingerprints getting one bit shorter with every growth.
+///
+/// To keep the overall false positive rate bounded, keys inserted after the filter
+/// has grown are given one extra fingerprint bit per growth, so each generation of keys
+/// contributes a geometrically shrinking share of false positives. Fingerprints left
+/// with no bits match every key of their quotient and are kept under both quotients
+/// they split into, but as only the oldest keys run out of bits, they only ever make up
+/// a share of about the desired false positive rate of the filter's fingerprints.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::GrowableFilter;
+///
+/// let initial_capacity = 16;
+/// let desired_fp_rate = 0.01;
+/// let mut filter = GrowableFilter::new(initial_capacity, desired_fp_rate);
+///
+/// // The filter grows on its own once its capacity is exceeded.
+/// for i in 0..1000 {
+///     filter.insert(format!("{}", i));
+/// }
+/// assert!(filter.capacity() >= 1000);
+/// assert!(filter.has(format!("{}", 42)));
+/// ```
+pub struct GrowableFilter<T: AsRef<[u8]>> {
+    // Slots of `3 + remainder_bits` bits each, packed back to back into words. A slot
+    // holds the three metadata bits followed by a fingerprint of up to
+    // `remainder_bits - 1` bits, shifted up past a set bit marking its end, so that
+    // fingerprints of every length fit and an empty slot is all zeros. The first
+    // `1 << quotient_bits` slots are those of each quotient, and slots past them hold
+    // fingerprints shifted beyond the last one.
+    slots: Vec<u64>,
+    num_slots: usize,
+    quotient_bits: u32,
+    remainder_bits: u32,
+    fingerprint_bits: u32,
+    len: usize,
+    growths: u32,
+    hash_fn: fn(&T) -> u64,
+}
+
+impl<T: AsRef<[u8]>> GrowableFilter<T> {
+    /// Creates a new growable filter using the package's default hasher with an
+    /// initial capacity and desired false positive rate. The filter has a power of two
+    /// number of slots, three quarters of which it fills before growing, so the
+    /// capacity is rounded up accordingly. Panics if the false positive rate is not
+    /// between 2^-60 and 1.
+    pub fn new(initial_capacity: usize, desired_fp_rate: f32) -> GrowableFilter<T> {
+        GrowableFilter::with_hasher::<DefaultHasher>(initial_capacity, desired_fp_rate)
+    }
+    /// Creates a new growable filter using a custom hasher. Panics if the false positive
+    /// rate is not between 2^-60 and 1, as `new` does.
+    pub fn with_hasher<H: Hasher<T>>(
+        initial_capacity: usize,
+        desired_fp_rate: f32,
+    ) -> GrowableFilter<T> {
+        let fingerprint_bits = (1.0 / desired_fp_rate).log2().ceil();
+        assert!(
+            (1.0..=MAX_FINGERPRINT_BITS as f32).contains(&fingerprint_bits),
+            "desired_fp_rate must be between 2^-60 and 1"
+        );
+        let num_slots = initial_capacity
+            .saturating_mul(4)
+            .div_ceil(3)
+            .max(4)
+            .next_power_of_two();
+        let quotient_bits = num_slots.trailing_zeros();
+        let fingerprint_bits = fingerprint_bits as u32;
+        let remainder_bits = max_fingerprint_bits(fingerprint_bits, quotient_bits) + 1;
+        GrowableFilter {
+            slots: vec![0; (num_slots * (3 + remainder_bits) as usize).div_ceil(64)],
+            num_slots,
+            quotient_bits,
+            remainder_bits,
+            fingerprint_bits,
+            len: 0,
+            growths: 0,
+            hash_fn: H::hash,
+        }
+    }
+    /// Insert an element into the filter, doubling its size first
+    /// if the filter has reached its capacity.
+    pub fn insert(&mut self, elem: T) {
+        if self.len >= self.capacity() {
+            self.grow();
+        }
+        self.len += 1;
+        let hash = (self.hash_fn)(&elem);
+        // A fingerprint already matching the key, such as one of a previous insert of
+        // the same key, keeps matching it as the filter grows.
+        if self.has_hash(hash) {
+            return;
+        }
+        let len = self.remainder_bits - 1;
+        let fingerprint = fingerprint_of(hash, self.quotient_bits, len);
+        let remainder = encode(fingerprint, len, self.remainder_bits);
+        self.insert_remainder(self.quotient_of(hash), remainder);
+    }
+    /// Checks if the filter contains a specified element. The filter can produce
+    /// false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.has_hash((self.hash_fn)(&elem))
+    }
+    /// Doubles the number of slots in the filter by moving each fingerprint to one of
+    /// two new quotients based on its most significant bit. The slots are rewritten
+    /// within their own array, extended to fit them, rather than copied into a new one.
+    /// Panics if the filter cannot grow any further, past 2^62 slots.
+    pub fn grow(&mut self) {
+        assert!(self.quotient_bits < 62, "filter cannot grow any further");
+        let old_remainder_bits = self.remainder_bits;
+        let old_width = 3 + old_remainder_bits as usize;
+        let old_num_slots = self.num_slots;
+        let old_len = self.slots.len();
+        let quotient_bits = self.quotient_bits + 1;
+        let remainder_bits =
+            max_fingerprint_bits(self.fingerprint_bits + self.growths + 1, quotient_bits) + 1;
+        let width = 3 + remainder_bits as usize;
+        let min_slots = 1usize << quotient_bits;
+
+        // The old slots are moved to the end of the extended array, and rewritten from
+        // its start. Every fingerprint lands at or past its old position, scaled to the
+        // new slot width, so the rewrite only catches up with slots it has yet to read
+        // around the last clusters, which are read ahead of it.
+        let len = (min_slots * width).div_ceil(64).max(old_len);
+        self.slots.resize(len, 0);
+        self.slots.copy_within(..old_len, len - old_len);
+        let mut reader = SlotReader {
+            base: (len - old_len) * 64,
+            width: old_width,
+            num_slots: old_num_slots,
+            next: 0,
+            quotients: VecDeque::new(),
+            quotient: 0,
+            entries: VecDeque::new(),
+        };
+        let mut writer = SlotWriter {
+            width,
+            next: 0,
+            quotient: None,
+        };
+        let mut run = vec![];
+        loop {
+            while reader.entries.is_empty() && reader.read(&self.slots) {}
+            let Some(&(quotient, _)) = reader.entries.front() else {
+                break;
+            };
+            // Read until the whole run of the quotient has been read.
+            while reader.entries.back().is_some_and(|(q, _)| *q == quotient)
+                && reader.read(&self.slots)
+            {}
+            run.clear();
+            while let Some((_, remainder)) = reader.entries.front().filter(|(q, _)| *q == quotient)
+            {
+                run.push(*remainder);
+                reader.entries.pop_front();
+            }
+            let decoded = run.iter().map(|r| decode(*r, old_remainder_bits));
+            if decoded.clone().any(|(_, len)| len == 0) {
+                // A fingerprint with no bits left matches every key of its quotient, so
+                // it has to be kept under both new quotients, and makes any other
+                // fingerprint of its quotient redundant.
+                for q in [quotient * 2, quotient * 2 + 1] {
+                    writer.write(
+                        &mut self.slots,
+                        &mut reader,
+                        q,
+                        encode(0, 0, remainder_bits),
+                    );
+                }
+                continue;
+            }
+            for top_bit in [0, 1] {
+                for (fingerprint, len) in decoded.clone() {
+                    if (fingerprint >> (len - 1)) & 1 != top_bit {
+                        continue;
+                    }
+                    let shrunk = fingerprint & ((1 << (len - 1)) - 1);
+                    let remainder = encode(shrunk, len - 1, remainder_bits);
+                    writer.write(
+                        &mut self.slots,
+                        &mut reader,
+                        quotient * 2 + top_bit as usize,
+                        remainder,
+                    );
+                }
+            }
+        }
+
+        self.num_slots = writer.next.max(min_slots);
+        self.slots.resize((self.num_slots * width).div_ceil(64), 0);
+        clear_from(&mut self.slots, writer.next * width);
+        self.quotient_bits = quotient_bits;
+        self.remainder_bits = remainder_bits;
+        self.growths += 1;
+    }
+    /// Returns the number of elements inserted into the filter.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Returns true if no elements have been inserted into the filter.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Returns the number of elements the filter can hold before it grows.
+    pub fn capacity(&self) -> usize {
+        3 << (self.quotient_bits - 2)
+    }
+    /// Returns the number of times the filter has doubled in size.
+    pub fn growths(&self) -> u32 {
+        self.growths
+    }
+    fn has_hash(&self, hash: u64) -> bool {
+        let quotient = self.quotient_of(hash);
+        if self.slot(quotient) & OCCUPIED == 0 {
+            return false;
+        }
+        let mut s = self.run_start(quotient);
+        loop {
+            let (fingerprint, len) = decode(self.slot(s) >> 3, self.remainder_bits);
+            if fingerprint == fingerprint_of(hash, self.quotient_bits, len) {
+                return true;
+            }
+            s += 1;
+            if self.slot(s) & CONTINUATION == 0 {
+                return false;
+            }
+        }
+    }
+    fn insert_remainder(&mut self, quotient: usize, mut remainder: u64) {
+        let slot = self.slot(quotient);
+        if slot >> 3 == 0 {
+            self.set_slot(quotient, remainder << 3 | OCCUPIED);
+            return;
+        }
+        self.set_slot(quotient, slot | OCCUPIED);
+        let mut s = self.run_start(quotient);
+        let mut meta = 0;
+        if slot & OCCUPIED != 0 {
+            // Append the fingerprint to the existing run of its quotient.
+            loop {
+                s += 1;
+                if self.slot(s) & CONTINUATION == 0 {
+                    break;
+                }
+            }
+            meta |= CONTINUATION;
+        }
+        if s != quotient {
+            meta |= SHIFTED;
+        }
+        // Shift every fingerprint up to the next empty slot one slot further, leaving
+        // the occupied bits with their slots.
+        loop {
+            if s == self.num_slots {
+                self.num_slots += 1;
+                self.slots
+                    .resize((self.num_slots * self.width()).div_ceil(64), 0);
+            }
+            let slot = self.slot(s);
+            self.set_slot(s, remainder << 3 | meta | (slot & OCCUPIED));
+            if slot >> 3 == 0 {
+                return;
+            }
+            remainder = slot >> 3;
+            meta = (slot & CONTINUATION) | SHIFTED;
+            s += 1;
+        }
+    }
+    /// Returns the slot at which the run of fingerprints of a quotient starts, or would
+    /// start if the quotient had none, by walking back to the start of its cluster and
+    /// skipping the runs of the occupied quotients before it.
+    fn run_start(&self, quotient: usize) -> usize {
+        let mut q = quotient;
+        while self.slot(q) & SHIFTED != 0 {
+            q -= 1;
+        }
+        let mut s = q;
+        while q != quotient {
+            loop {
+                s += 1;
+                if self.slot(s) & CONTINUATION == 0 {
+                    break;
+                }
+            }
+            loop {
+                q += 1;
+                if self.slot(q) & OCCUPIED != 0 {
+                    break;
+                }
+            }
+        }
+        s
+    }
+    fn width(&self) -> usize {
+        3 + self.remainder_bits as usize
+    }
+    fn slot(&self, s: usize) -> u64 {
+        if s >= self.num_slots {
+            return 0;
+        }
+        read_bits(&self.slots, s * self.width(), self.width())
+    }
+    fn set_slot(&mut self, s: usize, slot: u64) {
+        let width = self.width();
+        write_bits(&mut self.slots, s * width, width, slot);
+    }
+    fn quotient_of(&self, hash: u64) -> usize {
+        (hash >> (64 - self.quotient_bits)) as usize
+    }
+}
+
+/// Reads the fingerprints of the slots being rewritten by `GrowableFilter::grow` in
+/// order, along with their quotients.
+struct SlotReader {
+    // The bit offset of the first slot.
+    base: usize,
+    width: usize,
+    num_slots: usize,
+    next: usize,
+    // The occupied quotients whose runs have yet to start.
+    quotients: VecDeque<usize>,
+    quotient: usize,
+    // Fingerprints read but not yet rewritten, with their quotients.
+    entries: VecDeque<(usize, u64)>,
+}
+
+impl SlotReader {
+    /// Reads the next slot, returning false if there are none left.
+    fn read(&mut self, slots: &[u64]) -> bool {
+        if self.next == self.num_slots {
+            return false;
+        }
+        let slot = read_bits(slots, self.base + self.next * self.width, self.width);
+        if slot & OCCUPIED != 0 {
+            self.quotients.push_back(self.next);
+        }
+        self.next += 1;
+        if slot >> 3 != 0 {
+            if slot & CONTINUATION == 0 {
+                self.quotient = self.quotients.pop_front().unwrap_or_default();
+            }
+            self.entries.push_back((self.quotient, slot >> 3));
+        }
+        true
+    }
+    /// Reads every slot starting before a bit offset, so that it can be overwritten.
+    fn read_before(&mut self, slots: &[u64], offset: usize) {
+        while self.base + self.next * self.width < offset && self.read(slots) {}
+    }
+}
+
+/// Writes the fingerprints of a filter being grown into their new slots, in order.
+struct SlotWriter {
+    width: usize,
+    next: usize,
+    // The quotient of the last fingerprint written.
+    quotient: Option<usize>,
+}
+
+impl SlotWriter {
+    fn write(
+        &mut self,
+        slots: &mut Vec<u64>,
+        reader: &mut SlotReader,
+        quotient: usize,
+        remainder: u64,
+    ) {
+        let s = self.next.max(quotient);
+        let end = (s + 1) * self.width;
+        reader.read_before(slots, end);
+        if end > slots.len() * 64 {
+            slots.resize(end.div_ceil(64), 0);
+        }
+        for empty in self.next..s {
+            write_bits(slots, empty * self.width, self.width, 0);
+        }
+        let mut meta = 0;
+        if self.quotient == Some(quotient) {
+            meta |= CONTINUATION;
+        } else if s != quotient {
+            let offset = quotient * self.width;
+            let slot = read_bits(slots, offset, self.width);
+            write_bits(slots, offset, self.width, slot | OCCUPIED);
+        } else {
+            meta |= OCCUPIED;
+        }
+        if s != quotient {
+            meta |= SHIFTED;
+        }
+        write_bits(slots, s * self.width, self.width, remainder << 3 | meta);
+        self.next = s + 1;
+        self.quotient = Some(quotient);
+    }
+}
+
+/// Returns the number of bits of the fingerprints of keys inserted into a filter,
+/// bounded by the bits of the hash left past the quotient.
+fn max_fingerprint_bits(fingerprint_bits: u32, quotient_bits: u32) -> u32 {
+    fingerprint_bits
+        .min(64 - quotient_bits)
+        .min(MAX_FINGERPRINT_BITS)
+}
+
+fn fingerprint_of(hash: u64, quotient_bits: u32, len: u32) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    (hash << quotient_bits) >> (64 - len)
+}
+
+/// Encodes a fingerprint of a number of bits into the remainder of a slot, followed by
+/// a set bit marking its end and padded with zeros.
+fn encode(fingerprint: u64, len: u32, remainder_bits: u32) -> u64 {
+    ((fingerprint << 1) | 1) << (remainder_bits - 1 - len)
+}
+
+/// Decodes the fingerprint of a non-empty remainder, along with its number of bits.
+fn decode(remainder: u64, remainder_bits: u32) -> (u64, u32) {
+    let padding = remainder.trailing_zeros();
+    (remainder >> (padding + 1), remainder_bits - 1 - padding)
+}
+
+fn mask(len: usize) -> u64 {
+    if len == 64 {
+        u64::MAX
+    } else {
+        (1 << len) - 1
+    }
+}
+
+fn read_bits(words: &[u64], offset: usize, len: usize) -> u64 {
+    let (word, bit) = (offset / 64, offset % 64);
+    let mut value = words[word] >> bit;
+    if bit + len > 64 {
+        value |= words[word + 1] << (64 - bit);
+    }
+    value & mask(len)
+}
+
+fn write_bits(words: &mut [u64], offset: usize, len: usize, value: u64) {
+    let (word, bit) = (offset / 64, offset % 64);
+    let mask = mask(len);
+    words[word] = (words[word] & !(mask << bit)) | ((value & mask) << bit);
+    if bit + len > 64 {
+        let written = 64 - bit;
+        words[word + 1] = (words[word + 1] & !(mask >> written)) | ((value & mask) >> written);
+    }
+}
+
+/// Clears every bit of a slice of words from a bit offset onwards.
+fn clear_from(words: &mut [u64], offset: usize) {
+    let (word, bit) = (offset / 64, offset % 64);
+    if word >= words.len() {
+        return;
+    }
+    words[word] &= mask(bit);
+    words[word + 1..].fill(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives_across_growths() {
+        let mut filter: GrowableFilter<String> = GrowableFilter::new(8, 0.01);
+        for i in 0..1_000 {
+            filter.insert(format!("{}", i));
+        }
+        assert_eq!(1_000, filter.len());
+        assert_eq!(7, filter.growths());
+        for i in 0..1_000 {
+            assert!(filter.has(format!("{}", i)));
+        }
+    }
+
+    #[test]
+    fn test_real_fp_rate() {
+        let wanted_fp_rate = 0.01;
+        let mut filter: GrowableFilter<String> = GrowableFilter::new(8, wanted_fp_rate);
+        let num_items = 1_000;
+        for i in 0..num_items {
+            filter.insert(format!("{}", i));
+        }
+        let num_tests = 1_000;
+        let mut false_positives = 0;
+        for i in num_items..num_items + num_tests {
+            if filter.has(format!("{}", i)) {
+                false_positives += 1;
+            }
+        }
+        let real_fp_rate = false_positives as f32 / num_tests as f32;
+        assert!(real_fp_rate <= wanted_fp_rate * 2.0 + 0.02);
+    }
+
+    #[test]
+    fn packed_slots() {
+        let mut filter: GrowableFilter<String> = GrowableFilter::new(8, 0.01);
+        for i in 0..20_000 {
+            filter.insert(format!("{}", i));
+        }
+        assert!((0..20_000).all(|i| filter.has(format!("{}", i))));
+        // Fingerprints that ran out of bits are only a small share of all of them.
+        let used = (0..filter.num_slots)
+            .filter(|s| filter.slot(*s) >> 3 != 0)
+            .count();
+        assert!(used <= filter.len() + filter.len() / 20);
+        // A few bytes per key, rather than a separately allocated fingerprint each.
+        assert!(filter.slots.len() * 8 < 10 * filter.len());
+    }
+
+    #[test]
+    fn grows_without_inserts() {
+        let mut filter: GrowableFilter<String> = GrowableFilter::new(100, 0.1);
+        for i in 0..75 {
+            filter.insert(format!("{}", i));
+        }
+        for _ in 0..8 {
+            filter.grow();
+            assert!((0..75).all(|i| filter.has(format!("{}", i))));
+        }
+        assert_eq!(8, filter.growths());
+        assert_eq!(3 << 14, filter.capacity());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::murmur::murmur3_x64_128;
+use crate::Error;
+
+/// The strategies Guava derives the bits of an item with, identified by their ordinal
+/// in Guava's `BloomFilterStrategies` enum, which is written at the start of a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuavaStrategy {
+    /// Derives probes from the low 64 bits of an item's MurmurHash3 hash, split into
+    /// two 32-bit halves. Used by filters created before Guava 13.
+    Murmur128Mitz32,
+    /// Derives probes from both 64-bit halves of an item's MurmurHash3 hash, the
+    /// strategy of every filter created by Guava since version 13.
+    Murmur128Mitz64,
+}
+
+impl GuavaStrategy {
+    fn ordinal(self) -> u8 {
+        match self {
+            GuavaStrategy::Murmur128Mitz32 => 0,
+            GuavaStrategy::Murmur128Mitz64 => 1,
+        }
+    }
+    fn from_ordinal(ordinal: u8) -> Option<GuavaStrategy> {
+        match ordinal {
+            0 => Some(GuavaStrategy::Murmur128Mitz32),
+            1 => Some(GuavaStrategy::Murmur128Mitz64),
+            _ => None,
+        }
+    }
+}
+
+/// A bloom filter reproducing Guava's `com.google.common.hash.BloomFilter` bit for bit,
+/// so that filters written by JVM services with `BloomFilter#writeTo` can be queried
+/// here, and filters built here can be read with `BloomFilter#readFrom`.
+///
+/// Items are hashed with the 128-bit x64 variant of MurmurHash3 over the bytes Guava's
+/// funnel would produce for them. For strings funneled with `Funnels.stringFunnel(UTF_8)`
+/// and byte arrays funneled with `Funnels.byteArrayFunnel()`, those are the bytes of
+/// the item itself. Requires the `murmur3` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::GuavaBloomFilter;
+///
+/// let mut bf = GuavaBloomFilter::new(1000, 0.01);
+/// bf.insert("hello");
+///
+/// // Bytes read by `BloomFilter.readFrom(in, Funnels.stringFunnel(UTF_8))` in Java.
+/// let bytes = bf.to_bytes();
+/// let restored = GuavaBloomFilter::from_bytes(&bytes).unwrap();
+/// assert!(restored.has("hello"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuavaBloomFilter {
+    words: Vec<u64>,
+    num_hash_fns: u8,
+    strategy: GuavaStrategy,
+}
+
+impl GuavaBloomFilter {
+    /// Creates a new filter for an expected number of insertions and false positive
+    /// rate, sized as Guava's `BloomFilter.create` sizes it, using the default
+    /// `MURMUR128_MITZ_64` strategy. Panics if the false positive rate is not between
+    /// 0 and 1.
+    pub fn new(expected_insertions: u64, fpp: f64) -> GuavaBloomFilter {
+        assert!(
+            fpp > 0.0 && fpp < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+        let n = expected_insertions.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((-n * fpp.ln() / (ln2 * ln2)) as u64).max(1);
+        let num_hash_fns = ((num_bits as f64 / n * ln2).round() as u8).max(1);
+        GuavaBloomFilter {
+            words: vec![0; num_bits.div_ceil(64) as usize],
+            num_hash_fns,
+            strategy: GuavaStrategy::Murmur128Mitz64,
+        }
+    }
+    /// Inserts an item into the filter, as `BloomFilter#put` does. Returns true if any
+    /// bit of the filter changed, meaning the item was definitely not in it before.
+    pub fn insert<T: AsRef<[u8]>>(&mut self, item: T) -> bool {
+        let mut changed = false;
+        for idx in self.probes(item.as_ref()) {
+            let (word, mask) = ((idx / 64) as usize, 1 << (idx % 64));
+            changed |= self.words[word] & mask == 0;
+            self.words[word] |= mask;
+        }
+        changed
+    }
+    /// Checks if the filter contains an item, as `BloomFilter#mightContain` does. Like
+    /// any bloom filter, it can produce false positives, but will never produce false
+    /// negatives.
+    pub fn has<T: AsRef<[u8]>>(&self, item: T) -> bool {
+        self.probes(item.as_ref())
+            .all(|idx| self.words[(idx / 64) as usize] & (1 << (idx % 64)) != 0)
+    }
+    /// Returns the number of bits of the filter, always a multiple of 64.
+    pub fn bit_size(&self) -> u64 {
+        self.words.len() as u64 * 64
+    }
+    /// Returns the number of bits set for every item.
+    pub fn num_hash_fns(&self) -> u8 {
+        self.num_hash_fns
+    }
+    /// Returns the strategy the filter derives the bits of an item with.
+    pub fn strategy(&self) -> GuavaStrategy {
+        self.strategy
+    }
+    /// Encodes the filter as `BloomFilter#writeTo` does: the ordinal of its strategy and
+    /// its number of hash functions as single bytes, followed by the number of 64-bit
+    /// words of its bit array as an i32 and the words themselves, all big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(6 + 8 * self.words.len());
+        out.push(self.strategy.ordinal());
+        out.push(self.num_hash_fns);
+        out.extend_from_slice(&(self.words.len() as i32).to_be_bytes());
+        for word in self.words.iter() {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+    /// Decodes a filter written by `BloomFilter#writeTo` or `to_bytes`. Fails with
+    /// `Error::UnknownHasher` if the filter uses a strategy other than Guava's
+    /// MurmurHash3 ones, and with `Error::InvalidEncoding` if the input is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GuavaBloomFilter, Error> {
+        if bytes.len() < 6 {
+            return Err(Error::InvalidEncoding);
+        }
+        let strategy =
+            GuavaStrategy::from_ordinal(bytes[0]).ok_or(Error::UnknownHasher(bytes[0] as u64))?;
+        let num_hash_fns = bytes[1];
+        let num_words = i32::from_be_bytes(bytes[2..6].try_into().unwrap());
+        let words = &bytes[6..];
+        if num_hash_fns == 0 || num_words <= 0 || words.len() != num_words as usize * 8 {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(GuavaBloomFilter {
+            words: words
+                .chunks_exact(8)
+                .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+                .collect(),
+            num_hash_fns,
+            strategy,
+        })
+    }
+    /// Returns the indices of the bits of an item, derived as Guava's strategies derive
+    /// them, including their arithmetic on Java's signed integers.
+    fn probes(&self, bytes: &[u8]) -> impl Iterator<Item = u64> {
+        let (h1, h2) = murmur3_x64_128(bytes, 0);
+        let bit_size = self.bit_size();
+        let strategy = self.strategy;
+        (0..self.num_hash_fns as u64).map(move |i| match strategy {
+            GuavaStrategy::Murmur128Mitz32 => {
+                let (hash1, hash2) = (h1 as i32, (h1 >> 32) as i32);
+                let combined = hash1.wrapping_add((i as i32 + 1).wrapping_mul(hash2));
+                let combined = if combined < 0 { !combined } else { combined };
+                combined as u64 % bit_size
+            }
+            GuavaStrategy::Murmur128Mitz64 => {
+                let combined = h1.wrapping_add(i.wrapping_mul(h2));
+                (combined & i64::MAX as u64) % bit_size
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        // BloomFilter.create(funnel, 1000, 0.01) uses 9585 bits and 7 hash functions.
+        let bf = GuavaBloomFilter::new(1000, 0.01);
+        assert_eq!(9600, bf.bit_size());
+        assert_eq!(7, bf.num_hash_fns());
+        assert_eq!(GuavaStrategy::Murmur128Mitz64, bf.strategy());
+        assert_eq!(64, GuavaBloomFilter::new(0, 0.5).bit_size());
+    }
+
+    #[test]
+    fn probes() {
+        // "hello" hashes to 0xcbd8a7b341bd9b02 and 0x5b1e906a48ae1d19, from which Guava's
+        // strategies derive these bits of a 9600-bit filter with 7 hash functions.
+        let mut bf = GuavaBloomFilter::new(1000, 0.01);
+        let bits = [898, 8731, 6964, 3405, 1638, 9471, 5912];
+        assert_eq!(bits.to_vec(), bf.probes(b"hello").collect::<Vec<_>>());
+        bf.strategy = GuavaStrategy::Murmur128Mitz32;
+        let bits = [8885, 2455, 4196, 7758, 6017, 4276, 7064];
+        assert_eq!(bits.to_vec(), bf.probes(b"hello").collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn layout() {
+        let bf = GuavaBloomFilter {
+            words: vec![1 << 63 | 5, u64::MAX - 1],
+            num_hash_fns: 7,
+            strategy: GuavaStrategy::Murmur128Mitz64,
+        };
+        let mut expected = vec![1, 7, 0, 0, 0, 2];
+        expected.extend_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 5]);
+        expected.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(expected, bf.to_bytes());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut bf = GuavaBloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            bf.insert(format!("{}", i));
+        }
+        let restored = GuavaBloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+        assert_eq!(bf, restored);
+        for i in 0..1000 {
+            assert!(restored.has(format!("{}", i)));
+        }
+        let false_positives = (1000..11_000)
+            .filter(|i| restored.has(format!("{}", i)))
+            .count();
+        assert!(false_positives < 200, "{}", false_positives);
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = GuavaBloomFilter::new(100, 0.01).to_bytes();
+        let decode = |bytes: &[u8]| GuavaBloomFilter::from_bytes(bytes).err();
+        assert_eq!(Some(Error::InvalidEncoding), decode(&bytes[..5]));
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            decode(&bytes[..bytes.len() - 1])
+        );
+        let mut unknown = bytes.clone();
+        unknown[0] = 2;
+        assert_eq!(Some(Error::UnknownHasher(2)), decode(&unknown));
+        let mut no_hashes = bytes.clone();
+        no_hashes[1] = 0;
+        assert_eq!(Some(Error::InvalidEncoding), decode(&no_hashes));
+    }
+}
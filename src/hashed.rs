@@ -0,0 +1,130 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{split_hash, BloomBuilder, BloomFilter};
+
+/// A bloom filter over any type implementing `std::hash::Hash`, such as integers, tuples,
+/// or structs, which does not require items to be referenced as byte slices. Items are
+/// hashed with a `std::hash::BuildHasher`, and bit positions are derived from the result.
+///
+/// By default, items are hashed with the standard library's `RandomState`, which is
+/// randomly keyed per process. Filters that need to be combined or persisted across
+/// processes should use a deterministic `BuildHasher` instead.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::HashedBloomFilter;
+///
+/// #[derive(Hash)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// let mut bf = HashedBloomFilter::new(1000, 0.01);
+/// bf.insert(&Point { x: 1, y: 2 });
+///
+/// assert!(bf.has(&Point { x: 1, y: 2 }));
+/// ```
+pub struct HashedBloomFilter<T: ?Sized, S = RandomState> {
+    inner: BloomFilter<[u8; 8]>,
+    build_hasher: S,
+    _item: std::marker::PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> HashedBloomFilter<T, RandomState> {
+    /// Creates a new bloom filter with a specified capacity and desired false positive
+    /// rate, hashing items with a randomly keyed `RandomState`.
+    pub fn new(capacity: u64, desired_fp_rate: f32) -> HashedBloomFilter<T, RandomState> {
+        HashedBloomFilter::with_hasher(capacity, desired_fp_rate, RandomState::new())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> HashedBloomFilter<T, S> {
+    /// Creates a new bloom filter with a specified capacity and desired false positive
+    /// rate, hashing items with the given `BuildHasher`.
+    pub fn with_hasher(
+        capacity: u64,
+        desired_fp_rate: f32,
+        build_hasher: S,
+    ) -> HashedBloomFilter<T, S> {
+        // Items are hashed up front and inserted by their hash, so the inner filter's
+        // own hasher only needs to read back a hash from its bytes.
+        let inner = BloomBuilder::new(capacity, desired_fp_rate)
+            .hash_fn(|hash: &[u8; 8]| u64::from_be_bytes(*hash))
+            .build();
+        HashedBloomFilter {
+            inner,
+            build_hasher,
+            _item: std::marker::PhantomData,
+        }
+    }
+    /// Insert an element into the bloom filter. Returns true if the
+    /// element was probably not in the filter before.
+    pub fn insert(&mut self, elem: &T) -> bool {
+        let hashes = split_hash(self.build_hasher.hash_one(elem));
+        self.inner.insert_hashed(hashes, self.inner.num_hash_fns)
+    }
+    /// Checks if the bloom filter contains a specified element. The bloom filter can
+    /// produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: &T) -> bool {
+        let hashes = split_hash(self.build_hasher.hash_one(elem));
+        self.inner.has_hashed(hashes, self.inner.num_hash_fns)
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+    /// Clear all set bits of the bloom filter, setting them back to zero.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+    /// Returns the underlying bloom filter over the hashes of the inserted elements,
+    /// which can be used to inspect how full the filter is.
+    pub fn as_bloom_filter(&self) -> &BloomFilter<[u8; 8]> {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::BuildHasherDefault;
+
+    #[test]
+    fn arbitrary_keys() {
+        let mut bf: HashedBloomFilter<(u64, &str)> = HashedBloomFilter::new(1_000, 0.01);
+        for i in 0..100 {
+            bf.insert(&(i, "nyan"));
+        }
+        for i in 0..100 {
+            assert!(bf.has(&(i, "nyan")));
+        }
+        assert_eq!(100, bf.len());
+        bf.clear();
+        assert!(bf.is_empty());
+    }
+
+    #[test]
+    fn unsized_keys() {
+        let mut bf: HashedBloomFilter<str> = HashedBloomFilter::new(1_000, 0.01);
+        bf.insert("hello");
+        assert!(bf.has("hello"));
+    }
+
+    #[test]
+    fn deterministic_hasher() {
+        type Deterministic = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+        let mut a: HashedBloomFilter<u64, Deterministic> =
+            HashedBloomFilter::with_hasher(1_000, 0.01, Deterministic::default());
+        let mut b: HashedBloomFilter<u64, Deterministic> =
+            HashedBloomFilter::with_hasher(1_000, 0.01, Deterministic::default());
+        a.insert(&42);
+        b.insert(&42);
+        assert_eq!(a.as_bloom_filter().bits, b.as_bloom_filter().bits);
+    }
+}
@@ -0,0 +1,590 @@
+use sha3::{Digest, Keccak256};
+use siphasher::sip::SipHasher13;
+use std::hash::Hasher as _;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{
+    fnv1a, random_seed, split_hash, DefaultHasher, Hasher, DEFAULT_HASHER_ID, FNV_OFFSET_BASIS,
+};
+
+/// The id of the keyed SipHash hasher used by seeded filters.
+pub(crate) const SIPHASH_HASHER_ID: u64 = 2;
+/// The id of the Keccak-256 hasher.
+pub(crate) const KECCAK256_HASHER_ID: u64 = 6;
+/// The id of the Murmur3 hasher.
+#[cfg(feature = "murmur3")]
+pub(crate) const MURMUR3_HASHER_ID: u64 = 4;
+/// The id of the BLAKE3 hasher.
+#[cfg(feature = "blake3")]
+pub(crate) const BLAKE3_HASHER_ID: u64 = 5;
+/// The id of the wyhash hasher.
+#[cfg(feature = "fast-hash")]
+pub(crate) const WYHASH_HASHER_ID: u64 = 7;
+/// The id of the FxHash hasher.
+#[cfg(feature = "fast-hash")]
+pub(crate) const FXHASH_HASHER_ID: u64 = 8;
+/// The id of the Poseidon hasher.
+#[cfg(feature = "poseidon")]
+pub(crate) const POSEIDON_HASHER_ID: u64 = 9;
+/// The id of the HMAC-SHA3-256 hasher.
+pub(crate) const HMAC_HASHER_ID: u64 = 10;
+/// The id of the xxh3 hasher.
+#[cfg(feature = "xxhash")]
+pub(crate) const XXHASH_HASHER_ID: u64 = 3;
+
+/// HasherInstance defines a hasher that can produce a u64 from an item that can be
+/// referenced as a byte slice, like the Hasher trait, but does so through an instance
+/// of the hasher. This lets hashers carry keys, seeds, or precomputed state, whereas
+/// implementations of Hasher are stateless.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, HasherInstance};
+///
+/// pub struct SaltedHasher {
+///     salt: Vec<u8>,
+/// }
+///
+/// impl<T: AsRef<[u8]>> HasherInstance<T> for SaltedHasher {
+///     fn hash(&self, item: &T) -> u64 {
+///         self.salt
+///             .iter()
+///             .chain(item.as_ref())
+///             .fold(0, |acc, b| acc.wrapping_mul(31).wrapping_add(*b as u64))
+///     }
+/// }
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher_instance(SaltedHasher { salt: b"pepper".to_vec() })
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+pub trait HasherInstance<T: AsRef<[u8]>>: Send + Sync {
+    fn hash(&self, item: &T) -> u64;
+    /// Identifies the hasher, so that filters built with different hashers are never
+    /// combined. Defaults to a hash of the hasher's type name, and should be overridden
+    /// with a fixed value if filters built with the hasher are persisted.
+    fn id(&self) -> u64 {
+        fnv1a(std::any::type_name::<Self>().as_bytes())
+    }
+    /// Returns the seed the hasher is keyed with, if any. Filters must
+    /// share the same seed to be combined.
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+    /// Returns true if the hasher's output is guaranteed to be identical across
+    /// platforms and versions of the package, see `Hasher::is_stable`.
+    fn is_stable(&self) -> bool {
+        false
+    }
+    /// Produces the two base hashes bit positions are derived from, see
+    /// `Hasher::hash_pair`. Defaults to splitting the output of `hash`.
+    fn hash_pair(&self, item: &T) -> (u64, u64) {
+        split_hash(self.hash(item))
+    }
+    /// Produces the two base hashes of an item from its bytes alone, which must match
+    /// `hash_pair` for any item with those bytes. This lets filters be queried by a
+    /// borrowed form of their items, such as a `&str` for a filter over `String`s.
+    /// Defaults to None, for hashers that need the item itself, such as closures.
+    fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+        let _ = bytes;
+        None
+    }
+}
+
+/// Any thread-safe closure or function producing a u64 from an item, including a boxed
+/// `HashFn`, can be used as a hasher. Closures can capture seeds or configuration,
+/// which should be reflected in their id if filters built with them are combined.
+impl<T: AsRef<[u8]>, F: Fn(&T) -> u64 + Send + Sync> HasherInstance<T> for F {
+    fn hash(&self, item: &T) -> u64 {
+        self(item)
+    }
+}
+
+/// A closure set via `BloomBuilder::hash_fn`, identified by a random id, as closures
+/// defined in the same place share a type, and so the id derived from it.
+pub(crate) struct ClosureHasher<F> {
+    f: F,
+    id: u64,
+}
+
+impl<F> ClosureHasher<F> {
+    pub(crate) fn new(f: F) -> ClosureHasher<F> {
+        ClosureHasher {
+            f,
+            id: random_seed(),
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>, F: Fn(&T) -> u64 + Send + Sync> HasherInstance<T> for ClosureHasher<F> {
+    fn hash(&self, item: &T) -> u64 {
+        (self.f)(item)
+    }
+    fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// WideHasher defines a struct that can produce a u128 from an item that can be
+/// referenced as a byte slice. Hash functions with at least 128 bits of output can
+/// supply the two independent base hashes used for double hashing from a single pass
+/// over the item, where the high and low halves of the output are used as the first
+/// and second base hash respectively.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, WideHasher};
+///
+/// pub struct FoldingHasher {}
+///
+/// impl<T: AsRef<[u8]>> WideHasher<T> for FoldingHasher {
+///     fn hash128(item: &T) -> u128 {
+///         item.as_ref().iter().fold(0x9e3779b97f4a7c15, |acc: u128, b| {
+///             (acc ^ *b as u128).wrapping_mul(0x1000000000000000000013b)
+///         })
+///     }
+/// }
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .wide_hasher::<FoldingHasher>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+pub trait WideHasher<T: AsRef<[u8]>> {
+    fn hash128(item: &T) -> u128;
+    /// Identifies the hasher, see `Hasher::id`.
+    fn id() -> u64 {
+        fnv1a(std::any::type_name::<Self>().as_bytes())
+    }
+    /// Returns true if the hasher's output is stable, see `Hasher::is_stable`.
+    fn is_stable() -> bool {
+        false
+    }
+    /// Produces the hash of an item from its bytes alone, see `Hasher::hash_pair_bytes`.
+    /// Defaults to None.
+    fn hash128_bytes(bytes: &[u8]) -> Option<u128> {
+        let _ = bytes;
+        None
+    }
+}
+
+/// Splits a 128-bit hash into its high and low halves.
+pub(crate) fn split_wide(hash: u128) -> (u64, u64) {
+    ((hash >> 64) as u64, hash as u64)
+}
+
+/// Adapts a stateless Hasher into a HasherInstance.
+pub struct StatelessHasher<H> {
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<H> StatelessHasher<H> {
+    pub fn new() -> StatelessHasher<H> {
+        StatelessHasher {
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H> Default for StatelessHasher<H> {
+    fn default() -> Self {
+        StatelessHasher::new()
+    }
+}
+
+impl<T: AsRef<[u8]>, H: Hasher<T>> HasherInstance<T> for StatelessHasher<H> {
+    fn hash(&self, item: &T) -> u64 {
+        <H as Hasher<T>>::hash(item)
+    }
+    fn id(&self) -> u64 {
+        <H as Hasher<T>>::id()
+    }
+    fn is_stable(&self) -> bool {
+        <H as Hasher<T>>::is_stable()
+    }
+    fn hash_pair(&self, item: &T) -> (u64, u64) {
+        <H as Hasher<T>>::hash_pair(item)
+    }
+    fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+        <H as Hasher<T>>::hash_pair_bytes(bytes)
+    }
+}
+
+/// Adapts a stateless WideHasher into a HasherInstance.
+pub struct StatelessWideHasher<H> {
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<H> StatelessWideHasher<H> {
+    pub fn new() -> StatelessWideHasher<H> {
+        StatelessWideHasher {
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H> Default for StatelessWideHasher<H> {
+    fn default() -> Self {
+        StatelessWideHasher::new()
+    }
+}
+
+impl<T: AsRef<[u8]>, H: WideHasher<T>> HasherInstance<T> for StatelessWideHasher<H> {
+    fn hash(&self, item: &T) -> u64 {
+        split_wide(<H as WideHasher<T>>::hash128(item)).0
+    }
+    fn id(&self) -> u64 {
+        <H as WideHasher<T>>::id()
+    }
+    fn is_stable(&self) -> bool {
+        <H as WideHasher<T>>::is_stable()
+    }
+    fn hash_pair(&self, item: &T) -> (u64, u64) {
+        split_wide(<H as WideHasher<T>>::hash128(item))
+    }
+    fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+        <H as WideHasher<T>>::hash128_bytes(bytes).map(split_wide)
+    }
+}
+
+/// A hasher using SipHash-1-3 keyed by a seed. Unlike the default hasher, the bits
+/// an item maps to cannot be predicted without knowing the seed, so an attacker
+/// cannot craft inputs that all collide into the same bits.
+pub struct KeyedHasher {
+    seed: u64,
+}
+
+impl KeyedHasher {
+    pub const fn new(seed: u64) -> KeyedHasher {
+        KeyedHasher { seed }
+    }
+    fn hash_bytes(&self, bytes: &[u8]) -> u64 {
+        let mut hasher =
+            SipHasher13::new_with_keys(self.seed, self.seed.rotate_left(32) ^ FNV_OFFSET_BASIS);
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
+
+impl<T: AsRef<[u8]>> HasherInstance<T> for KeyedHasher {
+    fn hash(&self, item: &T) -> u64 {
+        self.hash_bytes(item.as_ref())
+    }
+    fn id(&self) -> u64 {
+        SIPHASH_HASHER_ID
+    }
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+    fn is_stable(&self) -> bool {
+        true
+    }
+    fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(split_hash(self.hash_bytes(bytes)))
+    }
+}
+
+/// A hasher using the non-cryptographic xxh3 hash function, which is considerably
+/// faster than the SHA3 based default hasher. It is a good choice when the inputs are
+/// not chosen by an adversary. Requires the `xxhash` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, XxHasher};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher::<XxHasher>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+#[cfg(feature = "xxhash")]
+pub struct XxHasher {}
+
+#[cfg(feature = "xxhash")]
+impl<T: AsRef<[u8]>> Hasher<T> for XxHasher {
+    fn hash(item: &T) -> u64 {
+        xxhash_rust::xxh3::xxh3_64(item.as_ref())
+    }
+    fn id() -> u64 {
+        XXHASH_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<XxHasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+/// A hasher using Keccak-256, the hash function used throughout Ethereum. The first
+/// two 64-bit words of its output, read as big-endian numbers, are used as the base
+/// hashes for double hashing.
+pub struct Keccak256Hasher {}
+
+impl<T: AsRef<[u8]>> Hasher<T> for Keccak256Hasher {
+    fn hash(item: &T) -> u64 {
+        <Keccak256Hasher as Hasher<T>>::hash_pair(item).0
+    }
+    fn id() -> u64 {
+        KECCAK256_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair(item: &T) -> (u64, u64) {
+        split_wide(<Keccak256Hasher as WideHasher<T>>::hash128(item))
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<Keccak256Hasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+impl<T: AsRef<[u8]>> WideHasher<T> for Keccak256Hasher {
+    fn hash128(item: &T) -> u128 {
+        u128::from_be_bytes(digest_prefix(&Keccak256::digest(item.as_ref())))
+    }
+    fn id() -> u64 {
+        KECCAK256_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash128_bytes(bytes: &[u8]) -> Option<u128> {
+        Some(<Keccak256Hasher as WideHasher<&[u8]>>::hash128(&bytes))
+    }
+}
+
+/// A hasher built from any hash function implementing the `Digest` trait of the
+/// RustCrypto project, such as the SHA-2 and SHA-3 families. The first 8 bytes of
+/// an item's digest, read as a big-endian number, form its hash, and the first two
+/// 64-bit words form the base hashes for double hashing. Digests shorter than 16
+/// bytes are padded with zeros.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, DigestHasher};
+/// use sha3::Sha3_512;
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher::<DigestHasher<Sha3_512>>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+pub struct DigestHasher<D> {
+    _digest: PhantomData<fn() -> D>,
+}
+
+impl<T: AsRef<[u8]>, D: Digest> Hasher<T> for DigestHasher<D> {
+    fn hash(item: &T) -> u64 {
+        u64::from_be_bytes(digest_prefix(&D::digest(item.as_ref())))
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair(item: &T) -> (u64, u64) {
+        split_wide(<DigestHasher<D> as WideHasher<T>>::hash128(item))
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<DigestHasher<D> as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+impl<T: AsRef<[u8]>, D: Digest> WideHasher<T> for DigestHasher<D> {
+    fn hash128(item: &T) -> u128 {
+        u128::from_be_bytes(digest_prefix(&D::digest(item.as_ref())))
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash128_bytes(bytes: &[u8]) -> Option<u128> {
+        Some(<DigestHasher<D> as WideHasher<&[u8]>>::hash128(&bytes))
+    }
+}
+
+/// Copies the first N bytes of a digest into an array, padding it with zeros if the
+/// digest is shorter, so that reading a number from a digest can never fail.
+pub(crate) fn digest_prefix<const N: usize>(digest: &[u8]) -> [u8; N] {
+    let mut buf = [0; N];
+    let len = digest.len().min(N);
+    buf[..len].copy_from_slice(&digest[..len]);
+    buf
+}
+
+/// A hasher using the BLAKE3 cryptographic hash function, which is much faster than
+/// the SHA3 based default hasher for large keys. The first two 64-bit words of its
+/// output are used as the base hashes for double hashing. Requires the `blake3` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, Blake3Hasher};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher::<Blake3Hasher>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+#[cfg(feature = "blake3")]
+pub struct Blake3Hasher {}
+
+#[cfg(feature = "blake3")]
+impl<T: AsRef<[u8]>> Hasher<T> for Blake3Hasher {
+    fn hash(item: &T) -> u64 {
+        <Blake3Hasher as Hasher<T>>::hash_pair(item).0
+    }
+    fn id() -> u64 {
+        BLAKE3_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair(item: &T) -> (u64, u64) {
+        split_wide(<Blake3Hasher as WideHasher<T>>::hash128(item))
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<Blake3Hasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl<T: AsRef<[u8]>> WideHasher<T> for Blake3Hasher {
+    fn hash128(item: &T) -> u128 {
+        u128::from_be_bytes(digest_prefix(blake3::hash(item.as_ref()).as_bytes()))
+    }
+    fn id() -> u64 {
+        BLAKE3_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash128_bytes(bytes: &[u8]) -> Option<u128> {
+        Some(<Blake3Hasher as WideHasher<&[u8]>>::hash128(&bytes))
+    }
+}
+
+/// Restores one of the package's hashers from its id and seed, as reported by
+/// `FilterParams`. Returns None for ids of custom hashers, and for hashers that
+/// cannot be restored from their seed alone.
+pub(crate) fn builtin_hasher<T: AsRef<[u8]>>(
+    hasher_id: u64,
+    seed: Option<u64>,
+) -> Option<Arc<dyn HasherInstance<T>>> {
+    let hasher: Arc<dyn HasherInstance<T>> = match (hasher_id, seed) {
+        (DEFAULT_HASHER_ID, None) => Arc::new(StatelessHasher::<DefaultHasher>::new()),
+        (SIPHASH_HASHER_ID, Some(seed)) => Arc::new(KeyedHasher::new(seed)),
+        (KECCAK256_HASHER_ID, None) => Arc::new(StatelessHasher::<Keccak256Hasher>::new()),
+        #[cfg(feature = "xxhash")]
+        (XXHASH_HASHER_ID, None) => Arc::new(StatelessHasher::<XxHasher>::new()),
+        #[cfg(feature = "murmur3")]
+        (MURMUR3_HASHER_ID, None) => Arc::new(StatelessHasher::<crate::Murmur3Hasher>::new()),
+        #[cfg(feature = "blake3")]
+        (BLAKE3_HASHER_ID, None) => Arc::new(StatelessHasher::<Blake3Hasher>::new()),
+        #[cfg(feature = "fast-hash")]
+        (WYHASH_HASHER_ID, None) => Arc::new(StatelessHasher::<crate::WyHasher>::new()),
+        #[cfg(feature = "fast-hash")]
+        (FXHASH_HASHER_ID, None) => Arc::new(StatelessHasher::<crate::FxHasher>::new()),
+        #[cfg(feature = "poseidon")]
+        (POSEIDON_HASHER_ID, None) => Arc::new(StatelessHasher::<crate::PoseidonHasher>::new()),
+        _ => return None,
+    };
+    Some(hasher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stateless_hasher() {
+        let instance = StatelessHasher::<DefaultHasher>::new();
+        assert_eq!(
+            DefaultHasher::hash(&"nyan"),
+            HasherInstance::<&str>::hash(&instance, &"nyan")
+        );
+        assert_eq!(
+            <DefaultHasher as Hasher<&str>>::id(),
+            HasherInstance::<&str>::id(&instance)
+        );
+    }
+
+    #[test]
+    fn closure_hasher() {
+        let seed = 42u64;
+        let closure = move |item: &&str| DefaultHasher::hash(item) ^ seed;
+        assert_eq!(DefaultHasher::hash(&"nyan") ^ 42, closure.hash(&"nyan"));
+        let boxed: crate::HashFn<&str> = Box::new(closure);
+        assert_eq!(closure.hash(&"nyan"), boxed.hash(&"nyan"));
+    }
+
+    #[test]
+    fn keyed_hasher() {
+        let a = KeyedHasher::new(1);
+        let b = KeyedHasher::new(2);
+        assert_eq!(a.hash(&"nyan"), a.hash(&"nyan"));
+        assert_ne!(a.hash(&"nyan"), b.hash(&"nyan"));
+        assert_eq!(Some(1), HasherInstance::<&str>::seed(&a));
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn xxhash_hasher() {
+        assert_eq!(
+            xxhash_rust::xxh3::xxh3_64(b"nyan"),
+            <XxHasher as Hasher<&str>>::hash(&"nyan")
+        );
+        assert_eq!(XXHASH_HASHER_ID, <XxHasher as Hasher<&str>>::id());
+    }
+
+    #[test]
+    fn wide_hasher() {
+        let instance = StatelessWideHasher::<Keccak256Hasher>::new();
+        let wide = <Keccak256Hasher as WideHasher<&str>>::hash128(&"nyan");
+        assert_eq!(
+            ((wide >> 64) as u64, wide as u64),
+            HasherInstance::<&str>::hash_pair(&instance, &"nyan")
+        );
+        assert_eq!(
+            <Keccak256Hasher as Hasher<&str>>::hash_pair(&"nyan"),
+            HasherInstance::<&str>::hash_pair(&instance, &"nyan")
+        );
+        assert_eq!(KECCAK256_HASHER_ID, HasherInstance::<&str>::id(&instance));
+    }
+
+    #[test]
+    fn digest_hasher() {
+        use sha3::Sha3_256;
+        let digest = Sha3_256::digest(b"nyan");
+        let (h1, h2) = <DigestHasher<Sha3_256> as Hasher<&str>>::hash_pair(&"nyan");
+        assert_eq!(&h1.to_be_bytes(), &digest[..8]);
+        assert_eq!(&h2.to_be_bytes(), &digest[8..16]);
+        assert_eq!(h1, <DigestHasher<Sha3_256> as Hasher<&str>>::hash(&"nyan"));
+
+        assert_eq!([1, 2, 0, 0], digest_prefix::<4>(&[1, 2]));
+        assert_eq!([1, 2], digest_prefix::<2>(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn keccak256_hasher() {
+        // keccak256("") = c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470
+        assert_eq!(
+            (0xc5d2460186f7233c, 0x927e7db2dcc703c0),
+            <Keccak256Hasher as Hasher<&str>>::hash_pair(&"")
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_hasher() {
+        let digest = blake3::hash(b"nyan");
+        let (h1, h2) = <Blake3Hasher as Hasher<&str>>::hash_pair(&"nyan");
+        assert_eq!(&h1.to_be_bytes(), &digest.as_bytes()[..8]);
+        assert_eq!(&h2.to_be_bytes(), &digest.as_bytes()[8..16]);
+        assert_eq!(h1, <Blake3Hasher as Hasher<&str>>::hash(&"nyan"));
+    }
+}
@@ -0,0 +1,151 @@
+use sha3::{Digest, Sha3_256};
+
+use crate::hasher::{digest_prefix, split_wide, HMAC_HASHER_ID};
+use crate::HasherInstance;
+
+/// The block size of SHA3-256 in bytes, which is the rate of its sponge.
+const BLOCK_BYTES: usize = 136;
+
+/// The message whose MAC identifies a key, see `HmacHasher::seed`.
+const FINGERPRINT_MESSAGE: &[u8] = b"flowerbloom key fingerprint";
+
+/// A hasher computing HMAC-SHA3-256 of items under a secret key, for filters that are
+/// published to third parties, such as sets of compromised credentials. Without the key,
+/// the bits an item maps to cannot be computed, so the published filter cannot be used
+/// to test guesses against it offline the way an unkeyed hash function would allow.
+///
+/// The key is supplied when building the filter and is never part of anything the filter
+/// exposes. Its fingerprint, the MAC of a fixed message, is reported as the hasher's seed
+/// so that filters built with different keys are never combined. The first two 64-bit
+/// words of the MAC, read as big-endian numbers, are used as the base hashes for double
+/// hashing.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, HmacHasher};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher_instance(HmacHasher::new(b"secret key"))
+///     .build();
+/// bf.insert("hunter2");
+/// assert!(bf.has("hunter2"));
+/// ```
+pub struct HmacHasher {
+    // The hash states after absorbing the padded key, so each MAC only hashes the item.
+    inner: Sha3_256,
+    outer: Sha3_256,
+    fingerprint: u64,
+}
+
+impl HmacHasher {
+    /// Creates a hasher keyed by a secret key of any length. Keys longer than the
+    /// 136-byte block size of SHA3-256 are hashed first, as specified by HMAC.
+    pub fn new<K: AsRef<[u8]>>(key: K) -> HmacHasher {
+        let key = key.as_ref();
+        let mut block = [0u8; BLOCK_BYTES];
+        if key.len() > BLOCK_BYTES {
+            block[..32].copy_from_slice(&Sha3_256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let pad = |byte: u8| {
+            let mut hasher = Sha3_256::new();
+            hasher.update(block.map(|b| b ^ byte));
+            hasher
+        };
+        let mut hasher = HmacHasher {
+            inner: pad(0x36),
+            outer: pad(0x5c),
+            fingerprint: 0,
+        };
+        hasher.fingerprint = u64::from_be_bytes(digest_prefix(&hasher.mac(FINGERPRINT_MESSAGE)));
+        hasher
+    }
+    fn mac(&self, bytes: &[u8]) -> [u8; 32] {
+        let mut inner = self.inner.clone();
+        inner.update(bytes);
+        let mut outer = self.outer.clone();
+        outer.update(inner.finalize());
+        outer.finalize().into()
+    }
+    fn hash_bytes(&self, bytes: &[u8]) -> (u64, u64) {
+        split_wide(u128::from_be_bytes(digest_prefix(&self.mac(bytes))))
+    }
+}
+
+impl<T: AsRef<[u8]>> HasherInstance<T> for HmacHasher {
+    fn hash(&self, item: &T) -> u64 {
+        self.hash_bytes(item.as_ref()).0
+    }
+    fn id(&self) -> u64 {
+        HMAC_HASHER_ID
+    }
+    fn seed(&self) -> Option<u64> {
+        Some(self.fingerprint)
+    }
+    fn is_stable(&self) -> bool {
+        true
+    }
+    fn hash_pair(&self, item: &T) -> (u64, u64) {
+        self.hash_bytes(item.as_ref())
+    }
+    fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(self.hash_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BloomBuilder, BloomFilter, Error};
+
+    #[test]
+    fn hmac_sha3_256_vector() {
+        // From the NIST HMAC-SHA3-256 examples, with a key shorter than the block size.
+        let key: Vec<u8> = (0..32).collect();
+        let mac = HmacHasher::new(key).mac(b"Sample message for keylen<blocklen");
+        let expected = [
+            0x4f, 0xe8, 0xe2, 0x02, 0xc4, 0xf0, 0x58, 0xe8, 0xdd, 0xdc, 0x23, 0xd8, 0xc3, 0x4e,
+            0x46, 0x73, 0x43, 0xe2, 0x35, 0x55, 0xe2, 0x4f, 0xc2, 0xf0, 0x25, 0xd5, 0x98, 0xf5,
+            0x58, 0xf6, 0x72, 0x05,
+        ];
+        assert_eq!(expected, mac);
+    }
+
+    #[test]
+    fn keys_are_not_compatible() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hasher_instance(HmacHasher::new(b"key-a"))
+            .build();
+        let b: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hasher_instance(HmacHasher::new(b"key-b"))
+            .build();
+        a.insert("foo");
+        assert!(a.has("foo"));
+        assert_ne!(a.params_digest(), b.params_digest());
+        assert!(a.union(&b).is_err());
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hasher_instance(HmacHasher::new(b"key-a"))
+            .build();
+        bf.insert("foo");
+        let bytes = bf.to_bytes();
+        assert_eq!(
+            Some(Error::UnknownHasher(HMAC_HASHER_ID)),
+            BloomFilter::<&str>::from_bytes(&bytes).err()
+        );
+
+        let restored: BloomFilter<&str> =
+            BloomFilter::from_bytes_with_hasher(&bytes, HmacHasher::new(b"key-a")).unwrap();
+        assert_eq!(bf.params(), restored.params());
+        assert!(restored.has("foo"));
+        assert!(!restored.has("bar"));
+
+        let wrong_key =
+            BloomFilter::<&str>::from_bytes_with_hasher(&bytes, HmacHasher::new(b"key-b"));
+        assert!(matches!(wrong_key, Err(Error::IncompatibleParams { .. })));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{BloomBuilder, FpRate};
+
+/// One in parts per billion.
+const BILLION: u64 = 1_000_000_000;
+/// log2(e) and ln(2) in Q32 fixed point, rounded to the nearest representable value.
+const LOG2_E_Q32: u128 = 6_196_328_019;
+const LN_2_Q32: u128 = 2_977_044_472;
+
+impl<T: AsRef<[u8]>> BloomBuilder<T> {
+    /// Creates a builder for a bloom filter sized to hold a number of items with a false
+    /// positive rate given in parts per billion, such as 10_000_000 for 0.01. The filter
+    /// is sized with `optimal_bits_needed_ppb` and `optimal_num_hash_fns_ppb`, which use
+    /// integer math only, so that targets without a floating point unit build filters
+    /// without software floats, and every platform builds bit-identical filters. Panics
+    /// if the rate is not in the range [1, 1_000_000_000).
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new_ppb(1000, 10_000_000).build();
+    /// bf.insert("hello");
+    /// assert!(bf.has("hello"));
+    /// assert_eq!(7, bf.num_hash_fns());
+    /// ```
+    pub fn new_ppb(capacity: u64, fp_rate_ppb: u32) -> BloomBuilder<T> {
+        check_ppb(fp_rate_ppb);
+        let mut builder = BloomBuilder::new(capacity, 0.0);
+        builder.fp_rate = FpRate::PartsPerBillion(fp_rate_ppb);
+        builder
+    }
+}
+
+/// Computes the optimal bits needed to store n items with a false positive rate given
+/// in parts per billion, as `optimal_bits_needed` does, using integer math only:
+///
+/// optimal_bits_required = n * log2(1 / p) * log2(e)
+///
+/// where log2(1 / p) is computed in Q32 fixed point. The result is identical on every
+/// platform, and within a few bits per billion items of the exact value. Rounds up to
+/// the nearest integer, saturating at `u64::MAX` as `optimal_bits_needed` does. Panics
+/// if the rate is not in the range [1, 1_000_000_000).
+pub fn optimal_bits_needed_ppb(num_items: u64, fp_rate_ppb: u32) -> u64 {
+    check_ppb(fp_rate_ppb);
+    let inverse_log2 = (log2_q32(BILLION) - log2_q32(fp_rate_ppb as u64)) as u128;
+    let bits_per_item = (inverse_log2 * LOG2_E_Q32) >> 32;
+    u64::try_from((num_items as u128 * bits_per_item).div_ceil(1 << 32)).unwrap_or(u64::MAX)
+}
+
+/// Computes the optimal number of hash functions for n items and a false positive rate
+/// given in parts per billion, as `optimal_num_hash_fns` does, using integer math only:
+///
+/// optimal_hash_fns = (m / n) * ln(2)
+///
+/// where m is `optimal_bits_needed_ppb`. Rounds up to the nearest integer. Panics if
+/// there are no items, or if the rate is not in the range [1, 1_000_000_000).
+pub fn optimal_num_hash_fns_ppb(num_items: u64, fp_rate_ppb: u32) -> u32 {
+    assert!(num_items > 0);
+    let bits = optimal_bits_needed_ppb(num_items, fp_rate_ppb) as u128;
+    (bits * LN_2_Q32).div_ceil((num_items as u128) << 32) as u32
+}
+
+fn check_ppb(fp_rate_ppb: u32) {
+    assert!(
+        valid_ppb(fp_rate_ppb),
+        "fp_rate_ppb must be in [1, 1_000_000_000)"
+    );
+}
+
+/// Returns true if a false positive rate given in parts per billion is in the range
+/// [1, 1_000_000_000).
+pub(crate) fn valid_ppb(fp_rate_ppb: u32) -> bool {
+    fp_rate_ppb > 0 && (fp_rate_ppb as u64) < BILLION
+}
+
+/// Computes log2 of a positive integer in Q32 fixed point. The integer part is the
+/// position of the highest set bit, and each fractional bit is found by squaring the
+/// remaining mantissa in [1, 2), which doubles its logarithm, and halving it whenever
+/// it reaches 2. The result is truncated, so it is below the exact value by less than
+/// 2^-31.
+fn log2_q32(x: u64) -> u64 {
+    let int = 63 - x.leading_zeros();
+    // The mantissa in [1, 2) as Q62 fixed point, so that its square fits in a u128.
+    let mut y = match int {
+        0..=62 => (x as u128) << (62 - int),
+        _ => (x as u128) >> 1,
+    };
+    let mut frac = 0;
+    for bit in (0..32).rev() {
+        y = (y * y) >> 62;
+        if y >= 2 << 62 {
+            y >>= 1;
+            frac |= 1 << bit;
+        }
+    }
+    ((int as u64) << 32) | frac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{optimal_bits_needed, optimal_num_hash_fns, BloomFilter};
+
+    #[test]
+    fn matches_float_math() {
+        for (num_items, fp_rate_ppb) in [
+            (1, 10_000_000),
+            (100, 200_000_000),
+            (100, 30_000_000),
+            (100, 10_000_000),
+            (12_345, 1_000_000),
+            (1_000_000, 1_000),
+            (10_000_000_000, 1_000_000),
+        ] {
+            let fp_rate = fp_rate_ppb as f32 / 1e9;
+            let bits = optimal_bits_needed_ppb(num_items, fp_rate_ppb);
+            // Rates in f32 are inexact, which shows in the float math for many items.
+            let tolerance = 1 + num_items / 1_000_000;
+            assert!(bits.abs_diff(optimal_bits_needed(num_items, fp_rate)) <= tolerance);
+            assert_eq!(
+                optimal_num_hash_fns(num_items, fp_rate),
+                optimal_num_hash_fns_ppb(num_items, fp_rate_ppb)
+            );
+        }
+        assert_eq!(959, optimal_bits_needed_ppb(100, 10_000_000));
+        // The exact value is 143_775_875_661.
+        assert_eq!(
+            143_775_875_664,
+            optimal_bits_needed_ppb(10_000_000_000, 1_000_000)
+        );
+        // Numbers of bits too large for a u64 saturate rather than wrap.
+        assert_eq!(u64::MAX, optimal_bits_needed(u64::MAX, 1e-9));
+        assert_eq!(u64::MAX, optimal_bits_needed_ppb(u64::MAX, 1));
+        assert_eq!(7, optimal_num_hash_fns_ppb(100, 10_000_000));
+        assert_eq!(1, optimal_num_hash_fns_ppb(100, 999_999_999));
+    }
+
+    #[test]
+    fn log2() {
+        assert_eq!(0, log2_q32(1));
+        assert_eq!(10 << 32, log2_q32(1024));
+        assert_eq!(63 << 32, log2_q32(1 << 63));
+        for x in [3, 10, 1_000_000_000, u64::MAX] {
+            let exact = (x as f64).log2() * (1u64 << 32) as f64;
+            assert!((log2_q32(x) as f64 - exact).abs() < 2.0);
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let bf: BloomFilter<&str> = BloomBuilder::new_ppb(1_000, 10_000_000).build();
+        let bits = optimal_bits_needed_ppb(1_000, 10_000_000);
+        assert_eq!(bits.div_ceil(8) * 8, bf.bit_len() as u64);
+        assert_eq!(
+            optimal_num_hash_fns_ppb(1_000, 10_000_000),
+            bf.num_hash_fns()
+        );
+    }
+}
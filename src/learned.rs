@@ -0,0 +1,150 @@
+use crate::{BloomBuilder, BloomFilter};
+
+/// ScoreFn defines a model that produces a score from an input value
+/// and is thread-safe. Higher scores indicate a higher confidence that
+/// the value is a member of the set.
+pub type ScoreFn<T> = Box<dyn Fn(&T) -> f32 + Send + Sync>;
+
+/// A learned bloom filter uses a user-supplied model to predict set membership,
+/// falling back to regular bloom filters to guarantee there are no false negatives.
+///
+/// This follows the "sandwiched" design: an initial bloom filter over every key
+/// rejects most non-members before the model is ever consulted, the model then
+/// accepts any item scoring at or above a threshold, and a small backup bloom filter
+/// holds the keys the model would have wrongly rejected. Since the backup only needs
+/// to hold the model's false negatives, it is typically much smaller than a regular
+/// bloom filter over the full key set.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::LearnedBloomFilter;
+///
+/// let keys = vec!["apple", "avocado", "banana"];
+/// // A toy model that believes all fruit start with the letter 'a'.
+/// let model = |item: &&str| if item.starts_with('a') { 1.0 } else { 0.0 };
+/// let bf = LearnedBloomFilter::new(keys, model, 0.5, 0.1, 0.01);
+///
+/// assert!(bf.has("apple"));
+/// // The model misses "banana", but the backup filter catches it.
+/// assert!(bf.has("banana"));
+/// ```
+pub struct LearnedBloomFilter<T: AsRef<[u8]>> {
+    initial: BloomFilter<T>,
+    model: ScoreFn<T>,
+    threshold: f32,
+    backup: BloomFilter<T>,
+}
+
+impl<T: AsRef<[u8]> + Clone> LearnedBloomFilter<T> {
+    /// Creates a new learned bloom filter over a set of keys. Items the model
+    /// scores at or above the threshold are considered members of the set, and all
+    /// other keys, including those the model scores as NaN, are inserted into the
+    /// backup filter.
+    ///
+    /// The false positive rate of the whole structure is the initial filter's rate
+    /// multiplied by the combined rate of the model and backup filter.
+    pub fn new<I, F>(
+        keys: I,
+        model: F,
+        threshold: f32,
+        initial_fp_rate: f32,
+        backup_fp_rate: f32,
+    ) -> LearnedBloomFilter<T>
+    where
+        I: IntoIterator<Item = T>,
+        F: Fn(&T) -> f32 + Send + Sync + 'static,
+    {
+        let keys: Vec<T> = keys.into_iter().collect();
+        let false_negatives: Vec<T> = keys
+            .iter()
+            // Keys scored as NaN are not accepted by the model either, so they must
+            // end up in the backup too.
+            .filter(|key| !accepts(model(key), threshold))
+            .cloned()
+            .collect();
+
+        let mut initial = BloomBuilder::new(keys.len().max(1) as u64, initial_fp_rate).build();
+        for key in keys.into_iter() {
+            initial.insert(key);
+        }
+        let mut backup =
+            BloomBuilder::new(false_negatives.len().max(1) as u64, backup_fp_rate).build();
+        for key in false_negatives.into_iter() {
+            backup.insert(key);
+        }
+        LearnedBloomFilter {
+            initial,
+            model: Box::new(model),
+            threshold,
+            backup,
+        }
+    }
+    /// Checks if the filter contains a specified element. Like a regular bloom filter,
+    /// this can produce false positives but will never produce false negatives for
+    /// any of the keys the filter was built from.
+    pub fn has(&self, elem: T) -> bool {
+        if !self.initial.has(elem.clone()) {
+            return false;
+        }
+        if accepts((self.model)(&elem), self.threshold) {
+            return true;
+        }
+        self.backup.has(elem)
+    }
+    /// Returns the score the model assigns to an element.
+    pub fn score(&self, elem: &T) -> f32 {
+        (self.model)(elem)
+    }
+    /// Returns the backup bloom filter holding the keys the model fails to recognize.
+    pub fn backup(&self) -> &BloomFilter<T> {
+        &self.backup
+    }
+}
+
+/// Checks whether the model accepts an item as a member of the set from its score,
+/// which is never the case for NaN scores.
+fn accepts(score: f32, threshold: f32) -> bool {
+    score >= threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let keys: Vec<String> = (0..500).map(|i| format!("{}", i)).collect();
+        // A model that only recognizes even numbers.
+        let model = |item: &String| {
+            let n: u32 = item.parse().unwrap_or(1);
+            if n.is_multiple_of(2) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let bf = LearnedBloomFilter::new(keys.clone(), model, 0.5, 0.05, 0.01);
+        for key in keys {
+            assert!(bf.has(key));
+        }
+    }
+
+    #[test]
+    fn perfect_model() {
+        let keys = vec!["foo", "bar", "baz"];
+        let bf = LearnedBloomFilter::new(keys, |_: &&str| 1.0, 0.5, 0.03, 0.03);
+        assert!(bf.has("foo"));
+        assert_eq!(1.0, bf.score(&"nyan"));
+        assert!(bf.backup().bits.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn nan_scores() {
+        let keys: Vec<String> = (0..100).map(|i| format!("{}", i)).collect();
+        let bf = LearnedBloomFilter::new(keys.clone(), |_: &String| f32::NAN, 0.5, 0.05, 0.01);
+        assert!(bf.score(&"0".to_string()).is_nan());
+        for key in keys {
+            assert!(bf.has(key));
+        }
+    }
+}
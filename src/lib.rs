@@ -1,5 +1,145 @@
-use sha3::{Digest, Sha3_256};
-use std::{io::Read, iter};
+use sha3::Sha3_256;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hasher as _};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use words::AlignedWords;
+
+// Lets code generated by the derive macro refer to the crate by name within its own tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as flowerbloom;
+
+#[cfg(feature = "rkyv")]
+mod archive;
+#[cfg(feature = "tokio")]
+mod async_io;
+mod atomic;
+mod base64;
+mod batch;
+mod bulk;
+mod chunked;
+mod compress;
+mod container;
+mod cow;
+pub mod crdt;
+mod delta;
+pub mod diagnostics;
+mod doorkeeper;
+mod encode;
+mod error;
+mod eth;
+#[cfg(feature = "fast-hash")]
+mod fast;
+mod fixed;
+mod format;
+mod generation;
+#[cfg(feature = "murmur3")]
+mod go;
+mod golomb;
+mod growable;
+#[cfg(feature = "murmur3")]
+mod guava;
+mod hashed;
+mod hasher;
+mod hmac;
+mod integer;
+mod learned;
+mod memo;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "murmur3")]
+mod murmur;
+#[cfg(feature = "object-store")]
+mod object;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "xxhash")]
+mod parquet;
+mod pipeline;
+#[cfg(feature = "poseidon")]
+mod poseidon;
+#[cfg(feature = "proto")]
+pub mod proto;
+mod redis;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sharded;
+#[cfg(feature = "mmap")]
+mod shm;
+mod snapshot;
+#[cfg(feature = "roaring")]
+mod sparse;
+mod stats;
+mod store;
+#[cfg(test)]
+mod testing;
+mod wal;
+#[cfg(any(feature = "postcard", feature = "bincode"))]
+mod wire;
+mod words;
+
+#[cfg(feature = "rkyv")]
+pub use archive::{ArchivedBloomFilter, ArchivedFilterView};
+pub use atomic::{AtomicBloomFilter, AtomicBloomWriter};
+pub use chunked::ChunkedBloomFilter;
+pub use compress::{Compression, MAX_DECOMPRESSED_LEN};
+pub use container::FilterArchive;
+pub use cow::{BloomSnapshot, CowBloomFilter};
+pub use doorkeeper::Doorkeeper;
+pub use encode::{BloomEncode, EncodedBloomFilter};
+pub use error::Error;
+pub use eth::EthLogsBloom;
+#[cfg(feature = "fast-hash")]
+pub use fast::{FxHasher, WyHasher};
+pub use fixed::{fixed_bytes_needed, FixedBloomFilter};
+#[cfg(feature = "derive")]
+pub use flowerbloom_derive::BloomEncode;
+pub use generation::GenerationalBloomFilter;
+#[cfg(feature = "murmur3")]
+pub use go::GoBloomFilter;
+pub use golomb::{Bip158Filter, GolombSet};
+pub use growable::GrowableFilter;
+#[cfg(feature = "murmur3")]
+pub use guava::{GuavaBloomFilter, GuavaStrategy};
+pub use hashed::HashedBloomFilter;
+#[cfg(feature = "blake3")]
+pub use hasher::Blake3Hasher;
+use hasher::ClosureHasher;
+#[cfg(feature = "xxhash")]
+pub use hasher::XxHasher;
+pub use hasher::{
+    DigestHasher, HasherInstance, Keccak256Hasher, KeyedHasher, StatelessHasher,
+    StatelessWideHasher, WideHasher,
+};
+pub use hmac::HmacHasher;
+pub use integer::{optimal_bits_needed_ppb, optimal_num_hash_fns_ppb};
+pub use learned::{LearnedBloomFilter, ScoreFn};
+pub use memo::MemoizedHasher;
+#[cfg(feature = "mmap")]
+pub use mmap::{MmapBloomFilter, ReadOnlyMmapFilter};
+#[cfg(feature = "murmur3")]
+pub use murmur::Murmur3Hasher;
+#[cfg(feature = "object-store")]
+pub use object::OBJECT_PART_SIZE;
+#[cfg(feature = "xxhash")]
+pub use parquet::SplitBlockBloomFilter;
+pub use pipeline::Pipeline;
+#[cfg(feature = "poseidon")]
+pub use poseidon::PoseidonHasher;
+pub use redis::RedisBloomFilter;
+pub use sharded::ShardedBloomFilter;
+#[cfg(feature = "mmap")]
+pub use shm::{SharedBloomFilter, SharedFilterHeader};
+pub use snapshot::{SnapshotDelta, DIRTY_CHUNK_SIZE};
+#[cfg(feature = "roaring")]
+pub use sparse::RoaringBloomFilter;
+pub use stats::BloomStats;
+pub use store::{PersistentBloomStore, StoreBackend};
+pub use wal::WalBloomFilter;
 
 /// Hasher defines a struct that can produce a u64 from an item that can be
 /// referenced as a byte slice. Our bloom filter implementation maps
@@ -7,56 +147,107 @@ use std::{io::Read, iter};
 /// representation.
 pub trait Hasher<T: AsRef<[u8]>> {
     fn hash(item: &T) -> u64;
+    /// Identifies the hasher, so that filters built with different hashers are never
+    /// combined. Defaults to a hash of the hasher's type name, and should be overridden
+    /// with a fixed value if filters built with the hasher are persisted.
+    fn id() -> u64 {
+        fnv1a(std::any::type_name::<Self>().as_bytes())
+    }
+    /// Returns true if the hasher's output is guaranteed to be identical across
+    /// architectures, pointer widths, and versions of the package, so that filters
+    /// built on one machine can be queried on another. Stable hashers read multi-byte
+    /// values with an explicit byte order and never depend on `usize`. Defaults to
+    /// false, as nothing is known about custom hashers.
+    fn is_stable() -> bool {
+        false
+    }
+    /// Produces the two base hashes bit positions are derived from with double hashing.
+    /// Defaults to splitting the output of `hash`, and can be overridden by hash
+    /// functions with 128 bits of output to provide two independent halves.
+    fn hash_pair(item: &T) -> (u64, u64) {
+        split_hash(Self::hash(item))
+    }
+    /// Produces the base hashes of an item from its bytes alone, which must match
+    /// `hash_pair` for any item with those bytes, see `HasherInstance::hash_pair_bytes`.
+    /// Defaults to None, so that filters built with the hasher cannot be queried by
+    /// borrowed forms of their items with `BloomFilter::has_borrowed`.
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        let _ = bytes;
+        None
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Computes the 64-bit FNV-1a hash of some bytes, which is cheap and
+/// stable across platforms, for hashing small amounts of metadata.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |acc, b| {
+        (acc ^ *b as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Derives two base hashes for double hashing from a single 64-bit hash, using the
+/// hash itself and the hash with its halves swapped.
+fn split_hash(hash: u64) -> (u64, u64) {
+    (hash, hash.rotate_left(32))
+}
+
+/// Picks a random seed for a new filter, drawing on the randomly keyed
+/// `RandomState` of the standard library.
+fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
 }
 
+/// The id of the package's default hasher.
+const DEFAULT_HASHER_ID: u64 = 1;
+
 /// HashFn defines a function that can produce a u64
 /// from an input value and is thread-safe.
 pub type HashFn<T> = Box<dyn Fn(&T) -> u64 + Send + Sync>;
 
 /// The default hasher for the bloom filter simply takes the first
-/// 8 bytes from a sha256 hash of an item and reads that
+/// 8 bytes from a sha3-256 hash of an item and reads that
 /// as a big-endian, u64 number. It implements the Hasher trait.
+/// Hashers for other digests can be built with `DigestHasher`.
 pub struct DefaultHasher {}
 
 impl<T: AsRef<[u8]>> Hasher<T> for DefaultHasher {
     fn hash(item: &T) -> u64 {
-        let mut hasher = Sha3_256::new();
-        hasher.update(item);
-        let result = hasher.finalize();
-        let mut buf = [0; 8];
-        let mut handle = result.take(8);
-        handle.read_exact(&mut buf).unwrap();
-        u64::from_be_bytes(buf)
+        <DigestHasher<Sha3_256> as Hasher<T>>::hash(item)
+    }
+    fn id() -> u64 {
+        DEFAULT_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<DefaultHasher as Hasher<&[u8]>>::hash_pair(&bytes))
     }
 }
 
 /// Provides a way to build a bloom filter with optional fields,
 /// such as customizing the Hasher used or the number of
-/// hash functions used in its representation. Will use a DefaultHasher
-/// if no other hasher is specified, and will use the optimal number
+/// hash functions used in its representation. Will use SipHash keyed by
+/// a random seed if no other hasher is specified, and will use the optimal number
 /// of hash functions depending on the number of items by default.
 ///
 /// ## Example
 /// ```
-/// use std::io::Read;
-/// use sha3::{Digest, Sha3_512};
-/// use flowerbloom::{BloomBuilder, BloomFilter, Hasher};
+/// use sha3::Sha3_512;
+/// use flowerbloom::{BloomBuilder, BloomFilter, DigestHasher, Hasher};
 ///
 /// pub struct CustomHasher {}
-
+///
 /// impl<T: AsRef<[u8]>> Hasher<T> for CustomHasher {
 ///     fn hash(item: &T) -> u64 {
-///         let mut hasher = Sha3_512::new();
-///         hasher.update(item);
-///         let result = hasher.finalize();
-///         let mut buf = [0; 8];
-///         let mut handle = result.take(8);
-///         handle.read_exact(&mut buf).unwrap();
-///         u64::from_be_bytes(buf)
+///         <DigestHasher<Sha3_512> as Hasher<T>>::hash(item)
 ///     }
 /// }
-
-/// let capacity: u32 = 50;
+///
+/// let capacity: u64 = 50;
 /// let fp_rate: f32 = 0.03;
 /// let mut bf: BloomFilter<&str> = BloomBuilder::new(capacity, fp_rate)
 ///     .hasher::<CustomHasher>()
@@ -66,19 +257,25 @@ impl<T: AsRef<[u8]>> Hasher<T> for DefaultHasher {
 /// let _ = bf.has("nyan");
 /// ```
 pub struct BloomBuilder<T: AsRef<[u8]>> {
-    capacity: u32,
-    fp_rate: f32,
+    capacity: u64,
+    fp_rate: FpRate,
     num_hash_fns: Option<u32>,
-    hash_fn: fn(&T) -> u64,
+    hasher: Arc<dyn HasherInstance<T>>,
+    tiers: Vec<f32>,
+    max_fp_rate: Option<f64>,
+    probe_hashers: Vec<Arc<dyn HasherInstance<T>>>,
 }
 
 impl<T: AsRef<[u8]>> BloomBuilder<T> {
-    pub fn new(capacity: u32, fp_rate: f32) -> BloomBuilder<T> {
+    pub fn new(capacity: u64, fp_rate: f32) -> BloomBuilder<T> {
         Self {
             capacity,
             num_hash_fns: None,
-            fp_rate,
-            hash_fn: DefaultHasher::hash,
+            fp_rate: FpRate::Float(fp_rate),
+            hasher: Arc::new(KeyedHasher::new(random_seed())),
+            tiers: vec![],
+            max_fp_rate: None,
+            probe_hashers: vec![],
         }
     }
     #[allow(dead_code)]
@@ -87,42 +284,284 @@ impl<T: AsRef<[u8]>> BloomBuilder<T> {
         self
     }
     #[allow(dead_code)]
-    pub fn hasher<H: Hasher<T>>(mut self) -> BloomBuilder<T> {
-        self.hash_fn = H::hash;
+    pub fn hasher<H: Hasher<T> + 'static>(mut self) -> BloomBuilder<T> {
+        self.hasher = Arc::new(StatelessHasher::<H>::new());
+        self
+    }
+    /// Uses a hasher producing 128 bits of output, whose two halves are used as the
+    /// base hashes for double hashing, so items only need to be hashed once.
+    pub fn wide_hasher<H: WideHasher<T> + 'static>(mut self) -> BloomBuilder<T> {
+        self.hasher = Arc::new(StatelessWideHasher::<H>::new());
+        self
+    }
+    /// Uses a closure as the hash function, which can capture seeds or configuration.
+    /// Every call gives the closure a random id, so the filter can only be combined with
+    /// itself and filters derived from it, such as its clones. Filters built separately
+    /// that must be combined should use a `HasherInstance` with a fixed id instead.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, DefaultHasher, Hasher};
+    ///
+    /// let salt = 0xdeadbeef;
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+    ///     .hash_fn(move |item: &&str| DefaultHasher::hash(item) ^ salt)
+    ///     .build();
+    /// bf.insert("hello");
+    /// assert!(bf.has("hello"));
+    /// ```
+    pub fn hash_fn<F: Fn(&T) -> u64 + Send + Sync + 'static>(mut self, f: F) -> BloomBuilder<T> {
+        self.hasher = Arc::new(ClosureHasher::new(f));
+        self
+    }
+    /// Uses an instance of a hasher, which can carry its own keys, seeds,
+    /// or precomputed state. See `HasherInstance`.
+    pub fn hasher_instance<H: HasherInstance<T> + 'static>(mut self, hasher: H) -> BloomBuilder<T> {
+        self.hasher = Arc::new(hasher);
+        self
+    }
+    /// Seeds the bloom filter, replacing its hasher with SipHash keyed by the seed. An
+    /// attacker who knows the hash function but not the seed cannot craft inputs that all
+    /// collide into the same bits and inflate the false positive rate. Filters must share
+    /// the same seed to be combined. Setting a custom hasher afterwards removes the seed.
+    ///
+    /// Filters are given a random seed by default, so this is only needed for filters
+    /// that are combined with filters built elsewhere, such as replicas on other nodes.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let secret_seed = 0x5eed;
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+    ///     .seed(secret_seed)
+    ///     .build();
+    /// bf.insert("hello");
+    /// assert!(bf.has("hello"));
+    /// ```
+    pub fn seed(mut self, seed: u64) -> BloomBuilder<T> {
+        self.hasher = Arc::new(KeyedHasher::new(seed));
+        self
+    }
+    /// Opts out of the random seed every filter is given by default, hashing items with
+    /// the unseeded `DefaultHasher` instead. Independently built filters then map every
+    /// item to the same bits, which makes them reproducible and combinable without
+    /// sharing a seed, but lets anyone who can choose the inserted items craft keys
+    /// that collide.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let a: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).deterministic().build();
+    /// let b: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).deterministic().build();
+    /// assert_eq!(a.params_digest(), b.params_digest());
+    /// ```
+    pub fn deterministic(self) -> BloomBuilder<T> {
+        self.hasher::<DefaultHasher>()
+    }
+    /// Uses k distinct hash functions, one per probe, instead of deriving every probe
+    /// from a single hash with double hashing. Probe i of an item is the first base hash
+    /// the i-th hasher produces for it, see `HasherInstance::hash_pair`, modulo the
+    /// number of bits in the filter, which gives provably
+    /// independent probe streams and can match the exact scheme of other
+    /// implementations, at the cost of hashing every item k times.
+    ///
+    /// The number of hash functions of the filter is the number of hashers given,
+    /// regardless of the desired false positive rate. Independent hashers cannot be
+    /// combined with tiers, see `try_build`, and filters built with them cannot be used
+    /// with `BloomFilter::insert_hash` or `BloomFilter::has_hash`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, HasherInstance, KeyedHasher};
+    ///
+    /// let hashers: Vec<Box<dyn HasherInstance<&str>>> = (0..5)
+    ///     .map(|seed| Box::new(KeyedHasher::new(seed)) as Box<dyn HasherInstance<&str>>)
+    ///     .collect();
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+    ///     .hashers(hashers)
+    ///     .build();
+    /// bf.insert("hello");
+    /// assert!(bf.has("hello"));
+    /// assert_eq!(5, bf.num_hash_fns());
+    /// ```
+    pub fn hashers(mut self, hashers: Vec<Box<dyn HasherInstance<T>>>) -> BloomBuilder<T> {
+        self.probe_hashers = hashers.into_iter().map(Arc::from).collect();
+        self
+    }
+    /// Adds a class of items with its own desired false positive rate. Classes are
+    /// numbered in the order they are added, starting at zero, and are used
+    /// with `BloomFilter::insert_with_class` and `BloomFilter::has_with_class`.
+    ///
+    /// Items of a class are inserted and checked with more or fewer hash functions
+    /// than the rest of the filter, so hot keys can be given a lower false positive
+    /// rate at the cost of setting more bits. The bit array is still sized for the
+    /// builder's capacity and false positive rate, so tiers with low rates should
+    /// only hold a small portion of the items. Rates must be between 0 and 1, and high
+    /// enough to need no more hash functions than a filter can be decoded with, see
+    /// `try_build`.
+    pub fn tier(mut self, fp_rate: f32) -> BloomBuilder<T> {
+        self.tiers.push(fp_rate);
+        self
+    }
+    /// Sets the false positive rate past which `BloomFilter::try_insert` refuses to
+    /// insert further elements, based on how full the filter actually is.
+    pub fn max_fp_rate(mut self, max_fp_rate: f64) -> BloomBuilder<T> {
+        self.max_fp_rate = Some(max_fp_rate);
         self
     }
+    /// Builds the bloom filter. Panics if the builder's configuration is invalid, such
+    /// as a capacity of zero or tiers combined with independent hashers, see
+    /// `try_build`.
     pub fn build(self) -> BloomFilter<T> {
+        match self.try_build() {
+            Ok(bf) => bf,
+            Err(e) => panic!("cannot build filter: {}", e),
+        }
+    }
+    /// Builds the bloom filter, failing with `Error::InvalidParams` if the builder's
+    /// configuration is invalid: a capacity of zero, a false positive rate that is not
+    /// between 0 and 1, more hash functions than a filter can be decoded with, the same
+    /// for the rate of any tier, or tiers combined with independent hashers set via
+    /// `hashers`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, Error, HasherInstance, KeyedHasher};
+    ///
+    /// let hashers: Vec<Box<dyn HasherInstance<&str>>> = vec![Box::new(KeyedHasher::new(1))];
+    /// let bf = BloomBuilder::new(1000, 0.01).hashers(hashers).tier(0.001).try_build();
+    /// assert_eq!(Some(Error::InvalidParams), bf.err());
+    ///
+    /// let bf: Result<BloomFilter<&str>, Error> = BloomBuilder::new(0, 0.01).try_build();
+    /// assert_eq!(Some(Error::InvalidParams), bf.err());
+    /// ```
+    pub fn try_build(self) -> Result<BloomFilter<T>, Error> {
+        let (num_hash_fns, size) = self.sizing()?;
+        Ok(BloomFilter {
+            bits: AlignedWords::zeroed(words::word_len(size)),
+            byte_len: size,
+            capacity: self.capacity,
+            num_hash_fns,
+            hasher: self.hasher,
+            class_hash_fns: self.tiers.iter().map(|p| tier_num_hash_fns(*p)).collect(),
+            num_inserted: 0,
+            max_fp_rate: self.max_fp_rate,
+            probe_hashers: self.probe_hashers,
+            dirty: vec![],
+        })
+    }
+    /// Returns the number of hash functions and the number of bytes of the filter
+    /// being built, failing with `Error::InvalidParams` if the configuration is invalid,
+    /// see `try_build`.
+    fn sizing(&self) -> Result<(u32, usize), Error> {
+        let valid_fp_rate = match self.fp_rate {
+            FpRate::Float(p) => p > 0.0 && p < 1.0,
+            FpRate::PartsPerBillion(ppb) => integer::valid_ppb(ppb),
+        };
+        if self.capacity == 0 || !valid_fp_rate {
+            return Err(Error::InvalidParams);
+        }
+        let valid_tier =
+            |p: &f32| *p > 0.0 && *p < 1.0 && format::valid_num_hash_fns(tier_num_hash_fns(*p));
+        if !self.tiers.iter().all(valid_tier) {
+            return Err(Error::InvalidParams);
+        }
         let num_hash_fns = match self.num_hash_fns {
+            _ if !self.probe_hashers.is_empty() => {
+                // Tiers probe more or fewer bits than there are independent hashers.
+                if !self.tiers.is_empty() {
+                    return Err(Error::InvalidParams);
+                }
+                self.probe_hashers.len() as u32
+            }
             Some(n) => n,
-            None => optimal_num_hash_fns(self.capacity, self.fp_rate),
+            None => match self.fp_rate {
+                FpRate::Float(p) => optimal_num_hash_fns(self.capacity, p),
+                FpRate::PartsPerBillion(ppb) => optimal_num_hash_fns_ppb(self.capacity, ppb),
+            },
+        };
+        if !format::valid_num_hash_fns(num_hash_fns) {
+            return Err(Error::InvalidParams);
+        }
+        let required_bits = match self.fp_rate {
+            FpRate::Float(p) => optimal_bits_needed(self.capacity, p),
+            FpRate::PartsPerBillion(ppb) => optimal_bits_needed_ppb(self.capacity, ppb),
         };
-        let required_bits = optimal_bits_needed(self.capacity, self.fp_rate);
 
-        // We'll use u64's to store data in our bloom filter.
-        let size = (required_bits as f64 / 8.0).ceil() as usize;
-        BloomFilter {
-            bits: iter::repeat(0).take(size).collect(),
-            capacity: self.capacity,
-            num_hash_fns,
-            hash_fn: self.hash_fn,
+        // We'll use u64's to store data in our bloom filter. Every filter has at least
+        // one byte, so that probes are always reduced modulo a non-zero bit length.
+        let size = required_bits.div_ceil(8).max(1) as usize;
+        Ok((num_hash_fns, size))
+    }
+    /// Returns the sizing of the filter being built by builders of other kinds of
+    /// filters, which panic on invalid configurations as `build` does.
+    fn checked_sizing(&self) -> (u32, usize) {
+        match self.sizing() {
+            Ok(sizing) => sizing,
+            Err(e) => panic!("cannot build filter: {}", e),
         }
     }
 }
 
+/// The false positive rate a builder sizes filters for, either as a float, or in parts
+/// per billion for sizing with integer math only, see `BloomBuilder::new_ppb`.
+#[derive(Clone, Copy)]
+enum FpRate {
+    Float(f32),
+    PartsPerBillion(u32),
+}
+
 /// Defines a bloom filter for items of a given type provided a
 /// capacity and a desired false positive rate.
 pub struct BloomFilter<T: AsRef<[u8]>> {
-    pub bits: Vec<u8>,
-    capacity: u32,
+    // The bits of the filter, bit `i` being bit `i % 64` of word `i / 64`. When the
+    // filter's number of bytes is not a multiple of 8, the last word is padded with
+    // zero bits past `bit_len`. Every constructor holds exactly `byte_len.div_ceil(8)`
+    // words for at least one byte, so every probe reduced modulo the bit length falls
+    // within them. The words start on a cache line, see `AlignedWords`.
+    bits: AlignedWords,
+    byte_len: usize,
+    capacity: u64,
     num_hash_fns: u32,
-    hash_fn: fn(&T) -> u64,
+    hasher: Arc<dyn HasherInstance<T>>,
+    class_hash_fns: Vec<u32>,
+    num_inserted: usize,
+    max_fp_rate: Option<f64>,
+    // Independent hashers, one per probe, set via `BloomBuilder::hashers`. When empty,
+    // every probe is derived from the output of the filter's single hasher.
+    probe_hashers: Vec<Arc<dyn HasherInstance<T>>>,
+    // A bitmap of the chunks of bits changed since the last call to `snapshot_delta`,
+    // allocated on the first change.
+    dirty: Vec<u64>,
 }
 
+/// The structural parameters of a bloom filter. Two filters can only be combined, or a
+/// serialized filter loaded into another, if all of their parameters are identical.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterParams {
+    /// The number of items the filter was sized for.
+    pub capacity: u64,
+    /// The number of bits in the filter.
+    pub bit_len: u64,
+    /// The number of hash functions used for each item.
+    pub num_hash_fns: u32,
+    /// The number of hash functions used for each class configured via `BloomBuilder::tier`.
+    pub tier_hash_fns: Vec<u32>,
+    /// Identifies the hasher used by the filter, see `Hasher::id`.
+    pub hasher_id: u64,
+    /// The seed used by the filter's hasher, if it is seeded.
+    pub seed: Option<u64>,
+}
 impl<T: AsRef<[u8]>> BloomFilter<T> {
-    /// Creates a new bloom filter using the package's default hasher
-    /// with a specified capacity and desired false positive rate. In order to customize
-    /// the bloom filter further, such as using a custom hash function, use the
-    /// BloomBuilder struct instead.
+    /// Creates a new bloom filter with a specified capacity and desired false positive
+    /// rate, hashing items with SipHash keyed by a random seed. Two filters created this
+    /// way have different seeds, and so different collision patterns, and cannot be
+    /// combined. In order to customize the bloom filter further, such as sharing a seed
+    /// between filters or using a custom hash function, use the BloomBuilder struct
+    /// instead.
     ///
     /// ## Example
     /// ```
@@ -138,20 +577,30 @@ impl<T: AsRef<[u8]>> BloomFilter<T> {
     ///     println!("definitely not in the bloom filter");
     /// }
     /// ```
-    pub fn new(capacity: u32, desired_fp_rate: f32) -> BloomFilter<T> {
+    pub fn new(capacity: u64, desired_fp_rate: f32) -> BloomFilter<T> {
         let required_bits = optimal_bits_needed(capacity, desired_fp_rate);
         let num_hashes = optimal_num_hash_fns(capacity, desired_fp_rate);
 
-        // We'll use u64's to store data in our bloom filter.
-        let size = (required_bits as f64 / 8.0).ceil() as usize;
+        // We'll use u64's to store data in our bloom filter. Every filter has at least
+        // one byte, so that probes are always reduced modulo a non-zero bit length.
+        let size = required_bits.div_ceil(8).max(1) as usize;
         BloomFilter {
-            bits: iter::repeat(0).take(size).collect(),
+            bits: AlignedWords::zeroed(words::word_len(size)),
+            byte_len: size,
             capacity,
             num_hash_fns: num_hashes,
-            hash_fn: DefaultHasher::hash,
+            hasher: Arc::new(KeyedHasher::new(random_seed())),
+            class_hash_fns: vec![],
+            num_inserted: 0,
+            max_fp_rate: None,
+            probe_hashers: vec![],
+            dirty: vec![],
         }
     }
-    /// Insert an element into the bloom filter
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, meaning at least one of its bits was unset,
+    /// and false if it was possibly inserted already. This gives a "first time seen"
+    /// signal without a separate call to `has`.
     /// ## Example
     /// ```
     /// use flowerbloom::BloomFilter;
@@ -162,22 +611,185 @@ impl<T: AsRef<[u8]>> BloomFilter<T> {
     /// bf.insert("foo");
     /// bf.insert("bar");
     /// bf.insert("baz");
+    ///
+    /// if !bf.insert("foo") {
+    ///     println!("foo was probably seen before");
+    /// }
     /// ```
-    pub fn insert(&mut self, elem: T) {
-        for i in 0..self.num_hash_fns {
-            let num = (self.hash_fn)(&elem);
-            let num = num.checked_add(i as u64).unwrap();
-            let idx = num % (self.capacity as u64);
-            let pos = idx / 8;
-            let pos_within_bits = idx % 8;
-            match self.bits.get_mut(pos as usize) {
-                Some(b) => {
-                    *b |= 1 << pos_within_bits;
-                }
-                // The position will always refer to a valid index of our bits vector.
-                None => unreachable!(),
+    pub fn insert(&mut self, elem: T) -> bool {
+        self.insert_with_probes(&elem, self.num_hash_fns)
+    }
+    /// Insert an element into the bloom filter unless the filter is full, returning
+    /// whether the element was probably new as in `insert`. The filter is full once
+    /// as many elements have been inserted as it was sized for, or once its current
+    /// false positive rate exceeds the maximum set via `BloomBuilder::max_fp_rate`.
+    /// Callers can use the resulting error to rotate to a fresh filter instead of
+    /// silently degrading accuracy.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomFilter, Error};
+    ///
+    /// let mut bf = BloomFilter::new(1, 0.01);
+    /// assert_eq!(Ok(true), bf.try_insert("foo"));
+    /// assert_eq!(Err(Error::CapacityExceeded), bf.try_insert("bar"));
+    /// ```
+    pub fn try_insert(&mut self, elem: T) -> Result<bool, Error> {
+        if self.num_inserted as u64 >= self.capacity {
+            return Err(Error::CapacityExceeded);
+        }
+        if let Some(max_fp_rate) = self.max_fp_rate {
+            if self.current_fp_rate() > max_fp_rate {
+                return Err(Error::CapacityExceeded);
+            }
+        }
+        Ok(self.insert(elem))
+    }
+    /// Checks if the bloom filter contains a specified element and inserts it, in a
+    /// single traversal over its bits. Returns true if the element was possibly present
+    /// before being inserted, exactly as `has` would have, and false if it was definitely
+    /// not. This is cheaper than calling `has` followed by `insert`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf = BloomFilter::new(1000, 0.01);
+    /// assert!(!bf.check_and_insert("foo"));
+    /// assert!(bf.check_and_insert("foo"));
+    /// ```
+    pub fn check_and_insert(&mut self, elem: T) -> bool {
+        !self.insert_with_probes(&elem, self.num_hash_fns)
+    }
+    /// Insert an element into the bloom filter as a member of a class configured
+    /// via `BloomBuilder::tier`, using that class's number of hash functions. Returns
+    /// true if the element was probably not in the filter before, as in `insert`.
+    /// Fails with `Error::UnknownClass` if the class was not configured on the builder.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.03)
+    ///     .tier(0.001)
+    ///     .build();
+    ///
+    /// let hot_class = 0;
+    /// bf.insert_with_class("hot-key", hot_class).unwrap();
+    /// assert!(bf.has_with_class("hot-key", hot_class).unwrap());
+    /// assert!(bf.has_with_class("hot-key", 1).is_err());
+    /// ```
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = self.class_num_hash_fns(class)?;
+        Ok(self.insert_with_probes(&elem, num_hash_fns))
+    }
+    /// Insert an element into the bloom filter by a hash computed ahead of time, such
+    /// as by a storage engine that already keeps stable hashes of its keys, skipping
+    /// the filter's hasher entirely. Bit positions are derived from the hash exactly
+    /// as they are from the output of `Hasher::hash`, so inserting the hash of an
+    /// element is equivalent to inserting the element, unless the filter's hasher
+    /// overrides `hash_pair`. Returns true if the hash was probably not in the filter
+    /// before, as in `insert`. Fails with `Error::IndependentHashers` if the filter was
+    /// built with independent hashers via `BloomBuilder::hashers`, as there is no single
+    /// hash to derive probes from.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter, DefaultHasher, Hasher};
+    ///
+    /// let mut bf = BloomBuilder::new(1000, 0.01).deterministic().build();
+    /// bf.insert_hash(DefaultHasher::hash(&"foo")).unwrap();
+    /// assert!(bf.has("foo"));
+    /// assert!(bf.has_hash(DefaultHasher::hash(&"foo")).unwrap());
+    /// ```
+    pub fn insert_hash(&mut self, hash: u64) -> Result<bool, Error> {
+        self.check_single_hasher()?;
+        Ok(self.insert_hashed(split_hash(hash), self.num_hash_fns))
+    }
+    /// Checks if the bloom filter contains an element by a hash computed ahead of time,
+    /// see `insert_hash`. Like `has`, this can produce false positives but will never
+    /// produce false negatives.
+    pub fn has_hash(&self, hash: u64) -> Result<bool, Error> {
+        self.check_single_hasher()?;
+        Ok(self.has_hashed(split_hash(hash), self.num_hash_fns))
+    }
+    fn check_single_hasher(&self) -> Result<(), Error> {
+        if !self.probe_hashers.is_empty() {
+            return Err(Error::IndependentHashers);
+        }
+        Ok(())
+    }
+    fn insert_with_probes(&mut self, elem: &T, num_hash_fns: u32) -> bool {
+        if let Some(hashes) = base_hashes(&*self.hasher, &self.probe_hashers, elem) {
+            return self.insert_hashed(hashes, num_hash_fns);
+        }
+        self.num_inserted += 1;
+        let m = self.bit_len() as u64;
+        let mut changed = false;
+        for i in 0..num_hash_fns {
+            let idx = nth_probe(None, &self.probe_hashers, elem, i, m);
+            changed |= self.set_bit(idx);
+        }
+        changed
+    }
+    /// Sets the probed bits of an element by computing all of their indices first, and
+    /// sorting them so that bits falling in the same word are set with a single OR.
+    fn insert_hashed(&mut self, hashes: (u64, u64), num_hash_fns: u32) -> bool {
+        self.num_inserted += 1;
+        let m = self.bit_len() as u64;
+        let mut changed = false;
+        let mut indices = [0; MERGED_PROBES];
+        let mut i = 0;
+        while i < num_hash_fns {
+            let n = (num_hash_fns - i).min(MERGED_PROBES as u32);
+            let indices = &mut indices[..n as usize];
+            for (j, idx) in (i..i + n).zip(indices.iter_mut()) {
+                *idx = probe_index(hashes, j, m);
+            }
+            indices.sort_unstable();
+            changed |= self.set_word_bits(indices);
+            i += n;
+        }
+        changed
+    }
+    /// Sets the bits at sorted indices with one OR per word they fall in, returning true
+    /// if any of them was not set before.
+    fn set_word_bits(&mut self, indices: &[u64]) -> bool {
+        let mut changed = false;
+        let mut indices = indices.iter().peekable();
+        while let Some(idx) = indices.next() {
+            let word = idx / 64;
+            let mut mask = 1 << (idx % 64);
+            while let Some(idx) = indices.next_if(|idx| *idx / 64 == word) {
+                mask |= 1 << (idx % 64);
             }
+            let w = &mut self.bits[word as usize];
+            let new = mask & !*w;
+            *w |= mask;
+            if new != 0 {
+                changed = true;
+                let start = word as usize * 8;
+                let first = start + new.trailing_zeros() as usize / 8;
+                let last = start + (63 - new.leading_zeros()) as usize / 8;
+                self.mark_dirty(first, last + 1);
+            }
+        }
+        changed
+    }
+    /// Sets the bit at an index, returning true if it was not set before.
+    fn set_bit(&mut self, idx: u64) -> bool {
+        let w = &mut self.bits[(idx / 64) as usize];
+        let mask = 1 << (idx % 64);
+        let changed = *w & mask == 0;
+        *w |= mask;
+        if changed {
+            let byte = (idx / 8) as usize;
+            self.mark_dirty(byte, byte + 1);
         }
+        changed
+    }
+    fn get_bit(&self, idx: u64) -> bool {
+        (self.bits[(idx / 64) as usize] >> (idx % 64)) & 1 == 1
     }
     /// Checks if the bloom filter contains a specified element. The bloom filter
     /// can produce false positives from this function at the rate specified
@@ -188,7 +800,7 @@ impl<T: AsRef<[u8]>> BloomFilter<T> {
     /// use flowerbloom::{BloomBuilder, BloomFilter};
     ///
     /// /// Initialize a bloom filter with a default hasher over strings.
-    /// let capacity: u32 = 50;
+    /// let capacity: u64 = 50;
     /// let desired_fp_rate: f32 = 0.03;
     /// let mut bf: BloomFilter<&str> = BloomBuilder::new(capacity, desired_fp_rate)
     ///                 .build();
@@ -202,144 +814,1160 @@ impl<T: AsRef<[u8]>> BloomFilter<T> {
     /// }
     /// ```
     pub fn has(&self, elem: T) -> bool {
-        for i in 0..self.num_hash_fns {
-            let num = (self.hash_fn)(&elem);
-            let num = num.checked_add(i as u64).unwrap();
-            let idx = num % (self.capacity as u64);
-            let pos = idx / 8;
-            let pos_within_bits = idx % 8;
-            match self.bits.get(pos as usize) {
-                Some(b) => {
-                    // Get the individual bit at the position determined by the hasher function.
-                    let bit = (*b >> pos_within_bits) & 1;
-                    // If the bit is 0, the element is definitely not in the bloom filter.
-                    if bit == 0 {
-                        return false;
-                    }
-                }
-                // The position will always refer to a valid index of our bits vector.
-                None => unreachable!(),
+        self.has_with_probes(&elem, self.num_hash_fns)
+    }
+    /// Checks if the bloom filter contains an element by a borrowed form of it, in the
+    /// same way a `HashMap<String, V>` can be queried with a `&str`. This avoids having
+    /// to allocate an owned element just to ask whether it is in the filter. Fails with
+    /// `Error::UnhashableBytes` if the filter's hasher cannot hash bytes directly, such
+    /// as a closure set via `BloomBuilder::hash_fn`, or a custom `Hasher` that does not
+    /// implement `Hasher::hash_pair_bytes`, see `HasherInstance::hash_pair_bytes`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf: BloomFilter<String> = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo".to_string());
+    ///
+    /// let key: &str = "foo";
+    /// assert!(bf.has_borrowed(key).unwrap());
+    /// ```
+    pub fn has_borrowed<Q>(&self, elem: &Q) -> Result<bool, Error>
+    where
+        T: Borrow<Q>,
+        Q: AsRef<[u8]> + ?Sized,
+    {
+        let bytes = elem.as_ref();
+        self.has_with_hashers(self.num_hash_fns, |hasher| {
+            hasher.hash_pair_bytes(bytes).ok_or(Error::UnhashableBytes)
+        })
+    }
+    /// Checks if the bloom filter contains a specified element of a class configured
+    /// via `BloomBuilder::tier`. Elements of a class are checked with that class's
+    /// number of hash functions, giving them the class's false positive rate.
+    /// Fails with `Error::UnknownClass` if the class was not configured on the builder.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        Ok(self.has_with_probes(&elem, self.class_num_hash_fns(class)?))
+    }
+    /// Checks if the bloom filter contains all of the specified elements, stopping at
+    /// the first element that is definitely not in the filter. Each element is hashed
+    /// only once, with all of its bit positions derived from that single hash.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo");
+    /// bf.insert("bar");
+    ///
+    /// assert!(bf.has_all(vec!["foo", "bar"]));
+    /// assert!(bf.has_any(vec!["nyan", "foo"]));
+    /// ```
+    pub fn has_all<I: IntoIterator<Item = T>>(&self, elems: I) -> bool {
+        elems
+            .into_iter()
+            .all(|elem| self.has_with_probes(&elem, self.num_hash_fns))
+    }
+    /// Checks if the bloom filter possibly contains any of the specified elements,
+    /// stopping at the first element that is possibly in the filter. Each element is
+    /// hashed only once, with all of its bit positions derived from that single hash.
+    pub fn has_any<I: IntoIterator<Item = T>>(&self, elems: I) -> bool {
+        elems
+            .into_iter()
+            .any(|elem| self.has_with_probes(&elem, self.num_hash_fns))
+    }
+    fn has_with_probes(&self, elem: &T, num_hash_fns: u32) -> bool {
+        let Ok(has) = self.has_with_hashers(num_hash_fns, |hasher| {
+            Ok::<_, Infallible>(hasher.hash_pair(elem))
+        });
+        has
+    }
+    /// Checks the probed bits of an element given a function producing its base hashes
+    /// with a hasher, called once with the filter's hasher, or once with each of its
+    /// independent hashers, see `independent_probe`. Stops at the first error.
+    fn has_with_hashers<E>(
+        &self,
+        num_hash_fns: u32,
+        hash_pair: impl Fn(&dyn HasherInstance<T>) -> Result<(u64, u64), E>,
+    ) -> Result<bool, E> {
+        if self.probe_hashers.is_empty() {
+            return Ok(self.has_hashed(hash_pair(&*self.hasher)?, num_hash_fns));
+        }
+        let m = self.bit_len() as u64;
+        for hasher in self.probe_hashers.iter() {
+            let idx = independent_probe(hash_pair(&**hasher)?, m);
+            // If any bit is 0, the element is definitely not in the bloom filter.
+            if !self.get_bit(idx) {
+                return Ok(false);
             }
         }
-        true
+        Ok(true)
+    }
+    fn hash(&self, elem: &T) -> (u64, u64) {
+        self.hasher.hash_pair(elem)
+    }
+    fn has_hashed(&self, hashes: (u64, u64), num_hash_fns: u32) -> bool {
+        let m = self.bit_len() as u64;
+        // If any bit is 0, the element is definitely not in the bloom filter.
+        (0..num_hash_fns).all(|i| self.get_bit(probe_index(hashes, i, m)))
+    }
+    fn class_num_hash_fns(&self, class: usize) -> Result<u32, Error> {
+        class_num_hash_fns(&self.class_hash_fns, class)
     }
     /// Clear all set bits of the bloom filter, setting them back to zero.
     pub fn clear(&mut self) {
-        self.bits.iter_mut().for_each(|elem| *elem = 0);
+        self.bits.iter_mut().for_each(|word| *word = 0);
+        self.num_inserted = 0;
+        self.mark_all_dirty();
     }
-}
-
-/// Computes the optimal bits needed to store n items with an expected false positive
-/// rate in the range [0, 1.0]. The formula is derived analytically as a well-known
-/// result for bloom filters, computed as follows:
-///
-/// n = num_items we expect to store in the bloom filter
-/// p = false positive rate
-/// optimal_bits_required = - n * ln(p) / ln(2) ^ 2
-///
-/// Rounds up to the nearest integer.
-pub fn optimal_bits_needed(num_items: u32, fp_rate: f32) -> u32 {
-    let bits = (-(num_items as f32) * fp_rate.ln()) / 2f32.ln().powi(2);
-    bits.ceil() as u32
-}
-
-/// Computes the optimal number of hash functions needed a bloom filter
-/// with an expected n num_items, and a desired false positive rate.
-/// Rounds up to the nearest integer.
-///
-/// This is derived from an analytical result as follows:
-///
-/// m = optimal bits needed for num_items and fp_rate
-/// n = num_items we expect to store in the bloom filter
-/// optimal_hash_fns = (m / n) * ln(2)
-pub fn optimal_num_hash_fns(num_items: u32, fp_rate: f32) -> u32 {
-    assert!(num_items > 0);
-    let bits = optimal_bits_needed(num_items, fp_rate);
-    let num_hash_fns = (bits as f32 / num_items as f32) * 2f32.ln();
-    num_hash_fns.ceil() as u32
-}
-
-/// Converts an iterator into a bloom filter with a default hasher
-/// and sensible false positive rate of 0.03.
-///
-/// ## Example
-/// ```
-/// use flowerbloom::{BloomFilter};
-///
-/// let items = vec!["foo", "bar", "baz"];
-/// let bf: BloomFilter<&str> = items.into_iter().collect();
-/// let _ = bf.has("nyan");
-/// ```
-impl<T: AsRef<[u8]>> FromIterator<T> for BloomFilter<T> {
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let items: Vec<T> = iter.into_iter().collect();
-        // TODO: Determine how to set via this trait?
-        let capacity = items.len() + 100;
-        let mut bloom_filter = BloomBuilder::<T>::new(capacity as u32, 0.03).build();
-        for i in items.into_iter() {
-            bloom_filter.insert(i);
-        }
-        bloom_filter
+    /// Returns the number of times an element has been inserted into the bloom filter,
+    /// including repeated inserts of the same element. After a union, this is the sum
+    /// of the number of inserts into each filter, and after an intersection it is the
+    /// smaller of the two.
+    pub fn len(&self) -> usize {
+        self.num_inserted
     }
-}
-
-/// Displays the bloom filter as a lowercase hex string.
-impl<T: AsRef<[u8]>> std::fmt::Display for BloomFilter<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return write!(f, "{:#x?}", self.bits);
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use sha3::Sha3_512;
-
-    use super::*;
-    use std::sync::{Arc, Mutex};
-    use std::thread;
-
-    #[test]
-    fn ok() {
-        let capacity: u32 = 50;
-        let fp_rate: f32 = 0.03;
-        let bf: BloomFilter<&str> = BloomBuilder::new(capacity, fp_rate).build();
-        let wanted_bit_count = optimal_bits_needed(capacity, fp_rate);
-        let wanted_byte_count = (wanted_bit_count as f64 / 8.0).ceil() as u32;
-        assert_eq!(wanted_byte_count, bf.bits.len() as u32);
-        let _ = bf.has("world");
+    /// Creates a new bloom filter containing the elements of both filters by
+    /// OR-ing their bits together. The resulting filter is exactly the filter that would
+    /// have been built by inserting every element of both filters into a single one.
+    /// Both filters must have been built with identical parameters and hashers,
+    /// otherwise an error is returned.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut a = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let mut b = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// a.insert("foo");
+    /// b.insert("bar");
+    ///
+    /// let merged = a.union(&b).unwrap();
+    /// assert!(merged.has("foo"));
+    /// assert!(merged.has("bar"));
+    /// ```
+    pub fn union(&self, other: &Self) -> Result<Self, Error> {
+        self.check_compatible(other)?;
+        let bits = self
+            .bits
+            .iter()
+            .zip(other.bits.iter())
+            .map(|(a, b)| a | b)
+            .collect();
+        Ok(self.with_bits(bits, self.num_inserted.saturating_add(other.num_inserted)))
     }
-
-    #[test]
-    fn from_iterator() {
-        let items = vec!["wow", "rust", "is", "so", "cool"];
-        let bf: BloomFilter<&str> = items.into_iter().collect();
-        let _ = bf.has("go");
+    /// Creates a new bloom filter containing the elements of every given filter by
+    /// OR-ing all of their bits together, 64 bits at a time. This is much faster than
+    /// merging a large number of filters one pair at a time. All filters must have been
+    /// built with identical parameters and hashers, and at least one filter must be
+    /// given, otherwise an error is returned.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut partitions = vec![];
+    /// for i in 0..10 {
+    ///     let mut bf = BloomBuilder::new(1000, 0.01).seed(42).build();
+    ///     bf.insert(format!("{}", i));
+    ///     partitions.push(bf);
+    /// }
+    ///
+    /// let merged = BloomFilter::union_many(&partitions).unwrap();
+    /// assert!(merged.has(format!("{}", 7)));
+    /// ```
+    pub fn union_many<'a, I>(filters: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Self>,
+        T: 'a,
+    {
+        let mut filters = filters.into_iter();
+        let first = filters.next().ok_or(Error::NoFilters)?;
+        let mut merged = first.with_bits(first.bits.clone(), first.num_inserted);
+        for other in filters {
+            merged.check_compatible(other)?;
+            or_into(&mut merged.bits, &other.bits);
+            merged.num_inserted = merged.num_inserted.saturating_add(other.num_inserted);
+        }
+        Ok(merged)
     }
-
-    #[test]
-    fn custom_hasher() {
-        pub struct CustomHasher {}
-
+    /// Creates a new bloom filter whose positives approximate the intersection of
+    /// the elements of both filters by AND-ing their bits together. Both filters must
+    /// have been built with identical parameters and hashers, otherwise an error is returned.
+    ///
+    /// Every element present in both filters is still reported as present, so there are
+    /// no false negatives. However, the result is not identical to a filter built from
+    /// the intersection directly: a bit can survive the AND because it was set by
+    /// different elements in each input. As such, the false positive rate of the
+    /// intersection is at most that of the less accurate input filter, and is typically
+    /// higher than a filter built from only the common elements would have.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut a = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let mut b = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// a.insert("foo");
+    /// a.insert("bar");
+    /// b.insert("bar");
+    ///
+    /// let common = a.intersect(&b).unwrap();
+    /// assert!(common.has("bar"));
+    /// ```
+    pub fn intersect(&self, other: &Self) -> Result<Self, Error> {
+        self.check_compatible(other)?;
+        let bits = self
+            .bits
+            .iter()
+            .zip(other.bits.iter())
+            .map(|(a, b)| a & b)
+            .collect();
+        Ok(self.with_bits(bits, self.num_inserted.min(other.num_inserted)))
+    }
+    /// Checks if all elements of this bloom filter are probably in another one, by
+    /// checking that every bit set in this filter is also set in the other.
+    /// If this returns false, at least one element of this filter is definitely
+    /// missing from the other. Filters that were not built with identical parameters
+    /// cannot be compared and are never subsets of one another.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut primary = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let mut replica = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// primary.insert("foo");
+    /// replica.insert("foo");
+    /// replica.insert("bar");
+    ///
+    /// assert!(primary.is_subset(&replica));
+    /// assert!(replica.is_superset(&primary));
+    /// ```
+    pub fn is_subset(&self, other: &Self) -> bool {
+        if self.check_compatible(other).is_err() {
+            return false;
+        }
+        self.bits
+            .iter()
+            .zip(other.bits.iter())
+            .all(|(a, b)| a & b == *a)
+    }
+    /// Checks if all elements of another bloom filter are probably in this one.
+    /// See `BloomFilter::is_subset` for details.
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+    /// Estimates the number of distinct elements inserted into the bloom filter from
+    /// the number of bits that are set. The estimate is derived as follows:
+    ///
+    /// m = number of bits in the filter
+    /// k = number of hash functions
+    /// X = number of set bits
+    /// estimated_count = -(m / k) * ln(1 - X / m)
+    ///
+    /// The estimate grows without bound as the filter fills up, and is
+    /// infinite once every bit is set.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo");
+    /// bf.insert("bar");
+    ///
+    /// println!("roughly {} items inserted", bf.estimate_count().round());
+    /// ```
+    pub fn estimate_count(&self) -> f64 {
+        self.estimate_count_from_set_bits(self.count_ones() as u64)
+    }
+    fn estimate_count_from_set_bits(&self, set_bits: u64) -> f64 {
+        let m = self.bit_len() as f64;
+        let k = self.num_hash_fns as f64;
+        let x = set_bits as f64;
+        -(m / k) * (1.0 - x / m).ln()
+    }
+    /// Estimates the Jaccard similarity of the sets of elements inserted into two bloom
+    /// filters, which is the size of their intersection divided by the size of their union.
+    /// The sizes are estimated from the set bits of both filters and of their union as in
+    /// `BloomFilter::estimate_count`, and the result is clamped to the range [0, 1.0].
+    /// Two empty filters are considered identical. Both filters must have been built
+    /// with identical parameters and hashers, otherwise an error is returned, and fails
+    /// with `Error::Saturated` if every bit of their union is set, as their sizes are
+    /// then unbounded.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut a = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let mut b = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// a.insert("foo");
+    /// a.insert("bar");
+    /// b.insert("bar");
+    /// b.insert("baz");
+    ///
+    /// println!("similarity is roughly {}", a.jaccard(&b).unwrap());
+    /// ```
+    pub fn jaccard(&self, other: &Self) -> Result<f64, Error> {
+        let (intersection, union) = self.estimate_overlap(other)?;
+        if union == 0.0 {
+            return Ok(1.0);
+        }
+        Ok((intersection / union).clamp(0.0, 1.0))
+    }
+    /// Estimates the number of distinct elements inserted into both bloom filters using
+    /// the inclusion-exclusion principle over the estimated sizes of both filters and
+    /// of their union, each computed as in `BloomFilter::estimate_count`:
+    ///
+    /// estimated_intersection = |A| + |B| - |A ∪ B|
+    ///
+    /// Negative estimates, which can occur for nearly disjoint sets, are clamped to zero.
+    /// Both filters must have been built with identical parameters and hashers,
+    /// otherwise an error is returned, and fails with `Error::Saturated` if every bit
+    /// of their union is set.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut a = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let mut b = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// a.insert("foo");
+    /// a.insert("bar");
+    /// b.insert("bar");
+    ///
+    /// println!("roughly {} items in common", a.estimate_intersection(&b).unwrap());
+    /// ```
+    pub fn estimate_intersection(&self, other: &Self) -> Result<f64, Error> {
+        let (intersection, _) = self.estimate_overlap(other)?;
+        Ok(intersection.max(0.0))
+    }
+    /// Estimates the sizes of the intersection and of the union of two filters. Both
+    /// are finite unless the union is saturated, as neither filter can have more bits
+    /// set than their union.
+    fn estimate_overlap(&self, other: &Self) -> Result<(f64, f64), Error> {
+        self.check_compatible(other)?;
+        let union = self.estimate_union_count(other);
+        if !union.is_finite() {
+            return Err(Error::Saturated);
+        }
+        let intersection = self.estimate_count() + other.estimate_count() - union;
+        Ok((intersection, union))
+    }
+    fn estimate_union_count(&self, other: &Self) -> f64 {
+        let union_bits = self
+            .bits
+            .iter()
+            .zip(other.bits.iter())
+            .map(|(a, b)| (a | b).count_ones() as u64)
+            .sum();
+        self.estimate_count_from_set_bits(union_bits)
+    }
+    /// Computes the probability of a false positive given how full the bloom filter
+    /// actually is, rather than the rate targeted when it was created. A query for an
+    /// absent element is a false positive when all of its k bits happen to be set:
+    ///
+    /// m = number of bits in the filter
+    /// k = number of hash functions
+    /// X = number of set bits
+    /// current_fp_rate = (X / m) ^ k
+    ///
+    /// Once this exceeds the desired false positive rate, the filter holds more
+    /// elements than it was sized for and should be rebuilt or rotated.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let desired_fp_rate = 0.01;
+    /// let mut bf = BloomFilter::new(1000, desired_fp_rate);
+    /// bf.insert("foo");
+    ///
+    /// if bf.current_fp_rate() > desired_fp_rate as f64 {
+    ///     println!("bloom filter is over capacity");
+    /// }
+    /// ```
+    pub fn current_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.num_hash_fns as i32)
+    }
+    /// Returns the fraction of the bloom filter's bits that are set, in the range [0, 1.0].
+    /// A well-sized filter at capacity is around half full.
+    pub fn fill_ratio(&self) -> f64 {
+        self.count_ones() as f64 / self.bit_len() as f64
+    }
+    /// Checks if the fraction of set bits has reached a threshold in the range [0, 1.0],
+    /// indicating the filter is too full to give useful answers.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo");
+    ///
+    /// assert!(!bf.is_saturated(0.5));
+    /// ```
+    pub fn is_saturated(&self, threshold: f64) -> bool {
+        self.fill_ratio() >= threshold
+    }
+    /// Creates a smaller bloom filter with half as many bits by OR-ing the two halves of
+    /// this filter's bits together. Since an element's bit index in the folded filter is
+    /// its index in this filter modulo the new size, every element of this filter is still
+    /// present in the folded one. This lets over-provisioned filters be right-sized after
+    /// the fact, such as before being sent over the network.
+    ///
+    /// Folding comes at a calculable cost in accuracy. A bit of the folded filter is unset
+    /// only if both bits folded into it were unset, so:
+    ///
+    /// f = fill ratio of this filter
+    /// k = number of hash functions
+    /// folded_fill_ratio = 1 - (1 - f) ^ 2
+    /// folded_fp_rate = folded_fill_ratio ^ k
+    ///
+    /// Filters whose number of bytes is a power of two can be folded repeatedly.
+    /// An error is returned if the bits cannot be split into two equal halves
+    /// of whole bytes.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo");
+    ///
+    /// if let Ok(folded) = bf.fold() {
+    ///     assert!(folded.has("foo"));
+    /// }
+    /// ```
+    pub fn fold(&self) -> Result<Self, Error> {
+        let byte_len = self.byte_len;
+        if byte_len == 0 || !byte_len.is_multiple_of(2) {
+            return Err(Error::NotFoldable);
+        }
+        let bytes = words::to_bytes(&self.bits, byte_len);
+        let (low, high) = bytes.split_at(byte_len / 2);
+        let mut bits = AlignedWords::from_bytes(low);
+        or_into(&mut bits, &words::from_bytes(high));
+        let mut folded = self.with_bits(bits, self.num_inserted);
+        folded.byte_len = byte_len / 2;
+        Ok(folded)
+    }
+    /// Returns the number of set bits in the bloom filter, counted 64 bits at a time.
+    pub fn count_ones(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    /// Returns the number of items the bloom filter was sized for.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+    /// Returns the number of hash functions used for each item.
+    pub fn num_hash_fns(&self) -> u32 {
+        self.num_hash_fns
+    }
+    /// Returns the number of bits in the bloom filter.
+    pub fn bit_len(&self) -> usize {
+        self.byte_len * 8
+    }
+    /// Returns the number of bytes used to store the bloom filter's bits.
+    pub fn byte_len(&self) -> usize {
+        self.byte_len
+    }
+    /// Returns the bloom filter's bits as words, bit `i` being bit `i % 64` of word
+    /// `i / 64`, the last word being padded with zero bits past `bit_len`. The bits can
+    /// only be modified through the filter, so that every probe stays within them. The
+    /// words start on a 64-byte cache line, as does every 8th word after the first.
+    pub fn as_words(&self) -> &[u64] {
+        &self.bits
+    }
+    /// Returns a copy of the bloom filter's bits as bytes, bit `i` being bit `i % 8` of
+    /// byte `i / 8`, as they are laid out in the filter's encodings.
+    pub fn bits_to_bytes(&self) -> Vec<u8> {
+        words::to_bytes(&self.bits, self.byte_len)
+    }
+    /// Returns a snapshot of the bloom filter's parameters and how full it is.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let mut bf = BloomFilter::new(1000, 0.01);
+    /// bf.insert("foo");
+    ///
+    /// println!("{}", bf.stats());
+    /// ```
+    pub fn stats(&self) -> BloomStats {
+        BloomStats {
+            capacity: self.capacity,
+            num_hash_fns: self.num_hash_fns,
+            bit_len: self.bit_len(),
+            set_bits: self.count_ones(),
+            fill_ratio: self.fill_ratio(),
+            estimated_count: self.estimate_count(),
+            estimated_fp_rate: self.current_fp_rate(),
+        }
+    }
+    /// Returns the structural parameters of the bloom filter.
+    pub fn params(&self) -> FilterParams {
+        filter_params(
+            self.capacity,
+            self.bit_len() as u64,
+            self.num_hash_fns,
+            &self.class_hash_fns,
+            &self.hasher,
+            &self.probe_hashers,
+        )
+    }
+    /// Consumes the bloom filter, returning its structural parameters and its bits, so
+    /// that the bits can be moved into custom storage, such as an arena or shared
+    /// memory, and the filter rebuilt from them later with `from_parts`.
+    pub fn into_parts(self) -> (FilterParams, Vec<u64>) {
+        (self.params(), self.bits.to_vec())
+    }
+    /// Rebuilds a bloom filter from parts returned by `into_parts`, restoring it with
+    /// the hasher identified by its parameters. Fails with `Error::InvalidEncoding` if
+    /// the bits do not match the parameters, including if padding bits past the bit
+    /// length are set, or if the parameters hold a number of hash functions or tiers
+    /// `from_bytes` would reject, and with `Error::UnknownHasher` if the filter was not
+    /// built with one of the package's hashers, such as a custom one. The bits are
+    /// copied into storage aligned to 64-byte cache lines, see `as_words`.
+    ///
+    /// The number of inserted elements is not part of the parts, so it is estimated
+    /// from the bits as in `estimate_count`, capped at the number of bits for filters
+    /// whose every bit is set, and no maximum false positive rate is set.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let (params, bits) = bf.into_parts();
+    /// let bf: BloomFilter<&str> = BloomFilter::from_parts(params, bits).unwrap();
+    /// assert!(bf.has("hello"));
+    /// ```
+    pub fn from_parts(params: FilterParams, bits: Vec<u64>) -> Result<BloomFilter<T>, Error> {
+        BloomFilter::from_words(params, AlignedWords::from_slice(&bits))
+    }
+    fn from_words(params: FilterParams, bits: AlignedWords) -> Result<BloomFilter<T>, Error> {
+        let byte_len = params.bit_len / 8;
+        let padded = bits.len() as u64 * 64 - params.bit_len.min(bits.len() as u64 * 64);
+        if byte_len == 0
+            || !params.bit_len.is_multiple_of(8)
+            || bits.len() as u64 != byte_len.div_ceil(8)
+            || bits
+                .last()
+                .is_some_and(|w| padded > 0 && w >> (64 - padded) != 0)
+            || !format::valid_num_hash_fns(params.num_hash_fns)
+            || params.tier_hash_fns.len() > format::MAX_TIERS as usize
+            || !params
+                .tier_hash_fns
+                .iter()
+                .all(|n| format::valid_num_hash_fns(*n))
+        {
+            return Err(Error::InvalidEncoding);
+        }
+        // `AlignedWords` allocates the words starting on a 64-byte cache line.
+        debug_assert!(bits.as_ptr().cast::<u8>().align_offset(64) == 0);
+        let hasher = hasher::builtin_hasher(params.hasher_id, params.seed)
+            .ok_or(Error::UnknownHasher(params.hasher_id))?;
+        let mut bf = BloomFilter {
+            bits,
+            byte_len: byte_len as usize,
+            capacity: params.capacity,
+            num_hash_fns: params.num_hash_fns,
+            hasher,
+            class_hash_fns: params.tier_hash_fns,
+            num_inserted: 0,
+            max_fp_rate: None,
+            probe_hashers: vec![],
+            dirty: vec![],
+        };
+        bf.num_inserted = bf.estimate_count().round().min(bf.bit_len() as f64) as usize;
+        Ok(bf)
+    }
+    /// Rebuilds a bloom filter from its parameters and the bytes its bits are encoded
+    /// as, failing with `Error::InvalidEncoding` if there are not as many bytes as bits.
+    #[cfg(any(feature = "serde", feature = "proto", feature = "rkyv"))]
+    pub(crate) fn from_byte_parts(
+        params: FilterParams,
+        bytes: &[u8],
+    ) -> Result<BloomFilter<T>, Error> {
+        if bytes.len() as u64 * 8 != params.bit_len {
+            return Err(Error::InvalidEncoding);
+        }
+        BloomFilter::from_words(params, AlignedWords::from_bytes(bytes))
+    }
+    /// Returns a digest of the bloom filter's structural parameters. Filters with equal
+    /// digests can be combined, so distributed nodes can exchange digests to cheaply
+    /// check compatibility before sending entire filters. See `FilterParams::digest`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let a: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// let b: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).seed(42).build();
+    /// assert_eq!(a.params_digest(), b.params_digest());
+    ///
+    /// // Filters with random seeds are never compatible.
+    /// let c: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+    /// assert_ne!(a.params_digest(), c.params_digest());
+    /// ```
+    pub fn params_digest(&self) -> u64 {
+        self.params().digest()
+    }
+    /// Returns true if the bits an item maps to are guaranteed to be identical across
+    /// architectures, pointer widths, and versions of the package, so a filter built on
+    /// one machine can be safely queried on another. This holds when the filter's
+    /// hasher is stable, see `Hasher::is_stable`, as bit positions are derived from
+    /// the hash with fixed-width integer math and bits are numbered from the least
+    /// significant bit of each byte.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::BloomFilter;
+    ///
+    /// let bf: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+    /// assert!(bf.is_stable());
+    /// ```
+    pub fn is_stable(&self) -> bool {
+        match self.probe_hashers.is_empty() {
+            true => self.hasher.is_stable(),
+            false => self.probe_hashers.iter().all(|hasher| hasher.is_stable()),
+        }
+    }
+    fn check_compatible(&self, other: &Self) -> Result<(), Error> {
+        self.params().check_compatible(&other.params())
+    }
+    fn with_bits(&self, bits: AlignedWords, num_inserted: usize) -> Self {
+        BloomFilter {
+            bits,
+            byte_len: self.byte_len,
+            capacity: self.capacity,
+            num_hash_fns: self.num_hash_fns,
+            hasher: self.hasher.clone(),
+            class_hash_fns: self.class_hash_fns.clone(),
+            num_inserted,
+            max_fp_rate: self.max_fp_rate,
+            probe_hashers: self.probe_hashers.clone(),
+            dirty: vec![],
+        }
+    }
+}
+
+impl FilterParams {
+    /// Computes a 64-bit FNV-1a digest over the big-endian encoding of every
+    /// parameter, which is identical across platforms.
+    pub fn digest(&self) -> u64 {
+        let mut buf = vec![];
+        // Capacities that fit in a u32 are encoded as one, so that their digests are
+        // unchanged from when capacities were limited to u32s.
+        match u32::try_from(self.capacity) {
+            Ok(capacity) => buf.extend_from_slice(&capacity.to_be_bytes()),
+            Err(_) => buf.extend_from_slice(&self.capacity.to_be_bytes()),
+        }
+        buf.extend_from_slice(&self.bit_len.to_be_bytes());
+        buf.extend_from_slice(&self.num_hash_fns.to_be_bytes());
+        buf.extend_from_slice(&(self.tier_hash_fns.len() as u32).to_be_bytes());
+        for n in self.tier_hash_fns.iter() {
+            buf.extend_from_slice(&n.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.hasher_id.to_be_bytes());
+        match self.seed {
+            Some(seed) => {
+                buf.push(1);
+                buf.extend_from_slice(&seed.to_be_bytes());
+            }
+            None => buf.push(0),
+        }
+        fnv1a(&buf)
+    }
+    /// Checks that another filter's parameters are identical to these ones,
+    /// returning a descriptive error if they are not.
+    pub fn check_compatible(&self, other: &FilterParams) -> Result<(), Error> {
+        if self != other {
+            return Err(Error::IncompatibleParams {
+                expected: Box::new(self.clone()),
+                found: Box::new(other.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Computes the optimal bits needed to store n items with an expected false positive
+/// rate in the range [0, 1.0]. The formula is derived analytically as a well-known
+/// result for bloom filters, computed as follows:
+///
+/// n = num_items we expect to store in the bloom filter
+/// p = false positive rate
+/// optimal_bits_required = - n * ln(p) / ln(2) ^ 2
+///
+/// Rounds up to the nearest integer, computed in double precision so that the number
+/// of bits of filters sized for billions of items is exact. Saturates at `u64::MAX`.
+pub fn optimal_bits_needed(num_items: u64, fp_rate: f32) -> u64 {
+    let bits = (-(num_items as f64) * (fp_rate as f64).ln()) / 2f64.ln().powi(2);
+    bits.ceil() as u64
+}
+
+/// Computes the optimal number of hash functions needed a bloom filter
+/// with an expected n num_items, and a desired false positive rate.
+/// Rounds up to the nearest integer.
+///
+/// This is derived from an analytical result as follows:
+///
+/// m = optimal bits needed for num_items and fp_rate
+/// n = num_items we expect to store in the bloom filter
+/// optimal_hash_fns = (m / n) * ln(2)
+pub fn optimal_num_hash_fns(num_items: u64, fp_rate: f32) -> u32 {
+    assert!(num_items > 0);
+    let bits = optimal_bits_needed(num_items, fp_rate);
+    let num_hash_fns = (bits as f64 / num_items as f64) * 2f64.ln();
+    num_hash_fns.ceil() as u32
+}
+
+/// Identifies the hashing of a filter, combining its independent hashers if it has any.
+fn hasher_id<T: AsRef<[u8]>>(
+    hasher: &Arc<dyn HasherInstance<T>>,
+    probe_hashers: &[Arc<dyn HasherInstance<T>>],
+) -> u64 {
+    if probe_hashers.is_empty() {
+        return hasher.id();
+    }
+    // Independent hashers are only compatible if every one of them is, in order.
+    let mut bytes = Vec::with_capacity(probe_hashers.len() * 16);
+    for hasher in probe_hashers {
+        bytes.extend_from_slice(&hasher.id().to_le_bytes());
+        bytes.extend_from_slice(&hasher.seed().unwrap_or(0).to_le_bytes());
+    }
+    fnv1a(&bytes)
+}
+
+/// The most probes of an element whose bits are merged by word at once when inserting,
+/// more than the number of hash functions of any practical false positive rate.
+const MERGED_PROBES: usize = 32;
+
+/// Derives the index of the i-th probe into a bloom filter with m addressable bits
+/// from a pair of base hashes using Kirsch-Mitzenmacher double hashing, where probe i
+/// is computed as follows:
+///
+/// index = (h1 + i * (h2 | 1)) mod m
+///
+/// Kirsch and Mitzenmacher showed this gives the same asymptotic false positive
+/// rate as k independent hash functions. Forcing h2 to be odd keeps probes from
+/// collapsing onto the same index when m is a power of two.
+///
+/// The sum and product wrap around rather than overflow, so every probe is in bounds
+/// for any hashes. Every filter has at least one byte, so m is never zero.
+fn probe_index((h1, h2): (u64, u64), i: u32, m: u64) -> u64 {
+    let h2 = h2 | 1;
+    h1.wrapping_add((i as u64).wrapping_mul(h2)) % m
+}
+
+/// Derives the probe of an independent hasher set via `BloomBuilder::hashers` into a
+/// bloom filter with m addressable bits from the base hashes it produces for an item.
+/// Inserts and queries, including queries by a borrowed form of an item, which only
+/// have its base hashes, must all probe through this, or any hasher whose first base
+/// hash differs from its `hash` would see false negatives.
+fn independent_probe((h1, _): (u64, u64), m: u64) -> u64 {
+    h1 % m
+}
+
+/// Hashes an element into the base hashes every probe of it is derived from, or None
+/// for filters with independent hashers, which hash it once per probe instead.
+pub(crate) fn base_hashes<T: AsRef<[u8]>>(
+    hasher: &dyn HasherInstance<T>,
+    probe_hashers: &[Arc<dyn HasherInstance<T>>],
+    elem: &T,
+) -> Option<(u64, u64)> {
+    probe_hashers.is_empty().then(|| hasher.hash_pair(elem))
+}
+
+/// Derives the i-th probe of an element into a filter with m addressable bits, from
+/// its base hashes if it has any, see `base_hashes`, or with the i-th independent
+/// hasher otherwise. Filters with independent hashers have exactly one per hash
+/// function, and no tiers, see `BloomBuilder::hashers`.
+pub(crate) fn nth_probe<T: AsRef<[u8]>>(
+    hashes: Option<(u64, u64)>,
+    probe_hashers: &[Arc<dyn HasherInstance<T>>],
+    elem: &T,
+    i: u32,
+    m: u64,
+) -> u64 {
+    match hashes {
+        Some(hashes) => probe_index(hashes, i, m),
+        None => independent_probe(probe_hashers[i as usize].hash_pair(elem), m),
+    }
+}
+
+/// Returns the probes of an element into a filter with m addressable bits, for
+/// filters that check bits one by one rather than through a `BloomFilter`.
+pub(crate) fn probes<'a, T: AsRef<[u8]>>(
+    hasher: &dyn HasherInstance<T>,
+    probe_hashers: &'a [Arc<dyn HasherInstance<T>>],
+    elem: &'a T,
+    num_hash_fns: u32,
+    m: u64,
+) -> impl Iterator<Item = u64> + 'a {
+    let hashes = base_hashes(hasher, probe_hashers, elem);
+    (0..num_hash_fns).map(move |i| nth_probe(hashes, probe_hashers, elem, i, m))
+}
+
+/// Looks up the number of hash functions of a class configured via
+/// `BloomBuilder::tier`, failing with `Error::UnknownClass` if there is no such class.
+pub(crate) fn class_num_hash_fns(class_hash_fns: &[u32], class: usize) -> Result<u32, Error> {
+    class_hash_fns
+        .get(class)
+        .copied()
+        .ok_or(Error::UnknownClass(class))
+}
+
+/// Builds the structural parameters of a filter, identical for every kind of filter
+/// built with the same builder.
+pub(crate) fn filter_params<T: AsRef<[u8]>>(
+    capacity: u64,
+    bit_len: u64,
+    num_hash_fns: u32,
+    class_hash_fns: &[u32],
+    hasher: &Arc<dyn HasherInstance<T>>,
+    probe_hashers: &[Arc<dyn HasherInstance<T>>],
+) -> FilterParams {
+    FilterParams {
+        capacity,
+        bit_len,
+        num_hash_fns,
+        tier_hash_fns: class_hash_fns.to_vec(),
+        hasher_id: hasher_id(hasher, probe_hashers),
+        seed: match probe_hashers.is_empty() {
+            true => hasher.seed(),
+            false => None,
+        },
+    }
+}
+
+/// Checks whether all bits probed for an item's base hashes are set in a bit array,
+/// for filters whose bits are not held in a `BloomFilter`, such as mapped files. The
+/// bits are validated to hold at least one byte when the filter is opened, as they are
+/// for a `BloomFilter`.
+#[cfg(any(feature = "mmap", feature = "rkyv"))]
+fn has_probes(bits: &[u8], hashes: (u64, u64), num_hash_fns: u32) -> bool {
+    let m = bits.len() as u64 * 8;
+    // If any bit is 0, the element is definitely not in the bloom filter.
+    (0..num_hash_fns).all(|i| {
+        let idx = probe_index(hashes, i, m);
+        (bits[(idx / 8) as usize] >> (idx % 8)) & 1 == 1
+    })
+}
+
+/// ORs the words of src into dst.
+fn or_into(dst: &mut [u64], src: &[u64]) {
+    for (a, b) in dst.iter_mut().zip(src.iter()) {
+        *a |= b;
+    }
+}
+
+/// Computes the number of hash functions needed for items of a tier to reach
+/// a desired false positive rate, assuming the filter is around half full as
+/// it is when sized optimally. Each probe then halves the false positive rate:
+///
+/// p = false positive rate
+/// tier_hash_fns = log2(1 / p)
+///
+/// Rounds up to the nearest integer.
+fn tier_num_hash_fns(fp_rate: f32) -> u32 {
+    let num_hash_fns = (1.0 / fp_rate).log2().ceil() as u32;
+    num_hash_fns.max(1)
+}
+
+/// Converts an iterator into a bloom filter with a default hasher
+/// and sensible false positive rate of 0.03.
+///
+/// Iterators whose `size_hint` gives their exact length, such as those of collections
+/// and ranges, size the filter for exactly their elements, which are inserted as the
+/// iterator is consumed, without holding on to them. Other iterators, such as those of
+/// adapters like `filter` and `take_while`, are first collected to count their elements.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter};
+///
+/// let items = vec!["foo", "bar", "baz"];
+/// let bf: BloomFilter<&str> = items.into_iter().collect();
+/// let _ = bf.has("nyan");
+/// ```
+impl<T: AsRef<[u8]>> FromIterator<T> for BloomFilter<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => from_iter_sized(upper, iter),
+            _ => {
+                let items: Vec<T> = iter.collect();
+                from_iter_sized(items.len(), items)
+            }
+        }
+    }
+}
+
+/// Inserts the elements of an iterator yielding a number of elements into a bloom
+/// filter sized for them.
+fn from_iter_sized<T: AsRef<[u8]>, I: IntoIterator<Item = T>>(
+    len: usize,
+    items: I,
+) -> BloomFilter<T> {
+    let capacity = (len as u64).saturating_add(100);
+    let mut bloom_filter = BloomBuilder::<T>::new(capacity, 0.03).build();
+    for i in items {
+        bloom_filter.insert(i);
+    }
+    bloom_filter
+}
+
+/// Clones the bloom filter's bits and parameters, sharing its hashers with the clone.
+/// Unlike a derived implementation, this does not require the element type to be
+/// `Clone`, as no elements are held.
+impl<T: AsRef<[u8]>> Clone for BloomFilter<T> {
+    fn clone(&self) -> Self {
+        let mut bf = self.with_bits(self.bits.clone(), self.num_inserted);
+        bf.dirty = self.dirty.clone();
+        bf
+    }
+}
+
+/// Displays the bloom filter as the standard base64 encoding of `to_bytes`, a compact
+/// single line holding its parameters and bits, so that a filter printed into logs or
+/// passed through command line flags or environment variables can be parsed back
+/// exactly with `FromStr`.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(10, 0.01).build();
+/// bf.insert("hello");
+///
+/// let restored: BloomFilter<&str> = bf.to_string().parse().unwrap();
+/// assert!(restored.has("hello"));
+/// ```
+impl<T: AsRef<[u8]>> std::fmt::Display for BloomFilter<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&base64::base64_encode(
+            &self.to_bytes(),
+            base64::BASE64_STANDARD,
+        ))
+    }
+}
+
+/// Parses a bloom filter displayed with `Display`, failing as `from_bytes` does, or
+/// with `Error::InvalidEncoding` if the string is not valid base64.
+impl<T: AsRef<[u8]>> FromStr for BloomFilter<T> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<BloomFilter<T>, Error> {
+        let bytes =
+            base64::base64_decode(s, base64::BASE64_STANDARD).ok_or(Error::InvalidEncoding)?;
+        BloomFilter::from_bytes(&bytes)
+    }
+}
+
+/// Computes the union of two bloom filters, as in `BloomFilter::union`.
+/// Panics if the filters were not built with identical parameters.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter};
+///
+/// let mut a = BloomBuilder::new(1000, 0.01).seed(42).build();
+/// let mut b = BloomBuilder::new(1000, 0.01).seed(42).build();
+/// a.insert("foo");
+/// b.insert("bar");
+///
+/// let merged = &a | &b;
+/// assert!(merged.has("foo") && merged.has("bar"));
+/// ```
+impl<T: AsRef<[u8]>> BitOr for &BloomFilter<T> {
+    type Output = BloomFilter<T>;
+
+    fn bitor(self, rhs: Self) -> BloomFilter<T> {
+        match self.union(rhs) {
+            Ok(bf) => bf,
+            Err(e) => panic!("cannot compute union: {}", e),
+        }
+    }
+}
+
+/// Computes the intersection of two bloom filters, as in `BloomFilter::intersect`.
+/// Panics if the filters were not built with identical parameters.
+impl<T: AsRef<[u8]>> BitAnd for &BloomFilter<T> {
+    type Output = BloomFilter<T>;
+
+    fn bitand(self, rhs: Self) -> BloomFilter<T> {
+        match self.intersect(rhs) {
+            Ok(bf) => bf,
+            Err(e) => panic!("cannot compute intersection: {}", e),
+        }
+    }
+}
+
+/// Merges the elements of another bloom filter into this one in place.
+/// Panics if the filters were not built with identical parameters.
+impl<T: AsRef<[u8]>> BitOrAssign<&BloomFilter<T>> for BloomFilter<T> {
+    fn bitor_assign(&mut self, rhs: &BloomFilter<T>) {
+        if let Err(e) = self.check_compatible(rhs) {
+            panic!("cannot compute union: {}", e);
+        }
+        or_into(&mut self.bits, &rhs.bits);
+        self.num_inserted = self.num_inserted.saturating_add(rhs.num_inserted);
+        self.mark_all_dirty();
+    }
+}
+
+/// Intersects this bloom filter with another one in place.
+/// Panics if the filters were not built with identical parameters.
+impl<T: AsRef<[u8]>> BitAndAssign<&BloomFilter<T>> for BloomFilter<T> {
+    fn bitand_assign(&mut self, rhs: &BloomFilter<T>) {
+        if let Err(e) = self.check_compatible(rhs) {
+            panic!("cannot compute intersection: {}", e);
+        }
+        for (a, b) in self.bits.iter_mut().zip(rhs.bits.iter()) {
+            *a &= b;
+        }
+        self.num_inserted = self.num_inserted.min(rhs.num_inserted);
+        self.mark_all_dirty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha3::{Digest, Sha3_512};
+
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn ok() {
+        let capacity: u64 = 50;
+        let fp_rate: f32 = 0.03;
+        let bf: BloomFilter<&str> = BloomBuilder::new(capacity, fp_rate).build();
+        let wanted_bit_count = optimal_bits_needed(capacity, fp_rate);
+        let wanted_byte_count = (wanted_bit_count as f64 / 8.0).ceil() as u32;
+        assert_eq!(wanted_byte_count, bf.byte_len() as u32);
+        let _ = bf.has("world");
+    }
+
+    #[test]
+    fn from_iterator() {
+        let items = vec!["wow", "rust", "is", "so", "cool"];
+        let bf: BloomFilter<&str> = items.into_iter().collect();
+        let _ = bf.has("go");
+
+        // Iterators of an unknown length are collected, and consumed once.
+        let mut consumed = 0;
+        let bf: BloomFilter<String> = (0..1_000)
+            .inspect(|_| consumed += 1)
+            .filter(|i| i % 2 == 0)
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(1_000, consumed);
+        assert_eq!(600, bf.capacity());
+        assert_eq!(500, bf.len());
+        assert!(bf.has("42".to_string()));
+
+        let mut remaining = 300;
+        let unbounded = std::iter::from_fn(|| {
+            remaining -= 1;
+            (remaining >= 0).then(|| remaining.to_string())
+        });
+        let bf: BloomFilter<String> = unbounded.collect();
+        assert_eq!(400, bf.capacity());
+        assert!(bf.has("0".to_string()));
+
+        // An upper bound far past the elements yielded does not size the filter.
+        let bf: BloomFilter<String> = (0..u64::MAX)
+            .take_while(|i| *i < 10)
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(110, bf.capacity());
+        assert_eq!(10, bf.len());
+    }
+
+    #[test]
+    fn custom_hasher() {
+        pub struct CustomHasher {}
+
         impl<T: AsRef<[u8]>> Hasher<T> for CustomHasher {
             fn hash(item: &T) -> u64 {
-                let mut hasher = Sha3_512::new();
-                hasher.update(item);
-                let result = hasher.finalize();
-                let mut buf = [0; 8];
-                let mut handle = result.take(8);
-                handle.read_exact(&mut buf).unwrap();
-                u64::from_be_bytes(buf)
+                <DigestHasher<Sha3_512> as Hasher<T>>::hash(item)
             }
         }
 
-        let num_items: u32 = 50;
+        let num_items: u64 = 50;
         let fp_rate: f32 = 0.03;
         let mut bf: BloomFilter<&str> = BloomBuilder::new(num_items, fp_rate)
             .hasher::<CustomHasher>()
             .build();
         bf.insert("hello");
         bf.insert("world");
-        let _ = bf.has("nyan");
+        assert!(bf.has("hello"));
+        let digest = Sha3_512::digest("hello");
+        assert_eq!(
+            u64::from_be_bytes(digest[..8].try_into().unwrap()),
+            <CustomHasher as Hasher<&str>>::hash(&"hello")
+        );
+    }
+
+    #[test]
+    fn queries_never_panic() {
+        // Filters sized for less than a bit still hold a byte for probes to fall in.
+        let hashers: Vec<Box<dyn HasherInstance<&str>>> = (0..3)
+            .map(|seed| Box::new(KeyedHasher::new(seed)) as Box<dyn HasherInstance<&str>>)
+            .collect();
+        let mut empty: BloomFilter<&str> = BloomBuilder::new(1, 0.99).hashers(hashers).build();
+        assert_eq!(8, empty.bit_len());
+        empty.insert("foo");
+        assert!(empty.has("foo"));
+
+        // Hashes at the extremes of the range wrap around rather than overflow.
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).build();
+        for hash in [0, 1, u64::MAX, u64::MAX - 1, 1 << 63] {
+            bf.insert_hash(hash).unwrap();
+            assert!(bf.has_hash(hash).unwrap());
+        }
+    }
+
+    #[test]
+    fn insert_merges_words() {
+        // Small filters put many probes in the same word, and more probes than are
+        // merged at once are split across batches.
+        for num_hash_fns in [1, 7, 32, 40] {
+            let mut bf: BloomFilter<String> = BloomBuilder::new(10, 0.1)
+                .deterministic()
+                .num_hash_funcs(num_hash_fns)
+                .build();
+            let mut expected = vec![0u64; bf.bits.len()];
+            let m = bf.bit_len() as u64;
+            for i in 0..20 {
+                let elem = format!("{}", i);
+                let hashes = bf.hash(&elem);
+                let before = expected.clone();
+                for j in 0..num_hash_fns {
+                    let idx = probe_index(hashes, j, m);
+                    expected[(idx / 64) as usize] |= 1 << (idx % 64);
+                }
+                assert_eq!(before != expected, bf.insert(elem));
+                assert_eq!(expected, bf.as_words());
+            }
+        }
     }
 
     #[test]
@@ -349,17 +1977,752 @@ mod tests {
         assert_eq!(959, optimal_bits_needed(100, 0.01));
         assert_eq!(10, optimal_bits_needed(1, 0.01));
         assert_eq!(96, optimal_bits_needed(10, 0.01));
+        // Beyond the number of bits a u32 can count.
+        assert_eq!(143_775_874_672, optimal_bits_needed(10_000_000_000, 0.001));
 
         assert_eq!(3, optimal_num_hash_fns(100, 0.20));
         assert_eq!(6, optimal_num_hash_fns(100, 0.03));
         assert_eq!(7, optimal_num_hash_fns(100, 0.01));
         assert_eq!(7, optimal_num_hash_fns(1, 0.01));
         assert_eq!(7, optimal_num_hash_fns(10, 0.01));
+        assert_eq!(10, optimal_num_hash_fns(10_000_000_000, 0.001));
+    }
+
+    #[test]
+    fn tiers() {
+        let capacity = 1_000;
+        let mut bf: BloomFilter<String> = BloomBuilder::new(capacity, 0.1)
+            .tier(0.001)
+            .tier(0.5)
+            .build();
+        assert_eq!(vec![10, 1], bf.class_hash_fns);
+
+        for i in 0..100 {
+            bf.insert_with_class(format!("hot{}", i), 0).unwrap();
+            bf.insert_with_class(format!("cold{}", i), 1).unwrap();
+        }
+        for i in 0..100 {
+            assert!(bf.has_with_class(format!("hot{}", i), 0).unwrap());
+            assert!(bf.has_with_class(format!("cold{}", i), 1).unwrap());
+        }
+    }
+
+    #[test]
+    fn unknown_tier() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.03).tier(0.001).build();
+        assert_eq!(Err(Error::UnknownClass(1)), bf.insert_with_class("nyan", 1));
+        assert_eq!(Err(Error::UnknownClass(1)), bf.has_with_class("nyan", 1));
+        assert!(bf.is_empty());
+    }
+
+    #[test]
+    fn union() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let mut b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        a.insert("foo");
+        b.insert("bar");
+        let merged = a.union(&b).unwrap();
+        assert!(merged.has("foo"));
+        assert!(merged.has("bar"));
+
+        // Counts restored from untrusted encodings can be arbitrarily large.
+        a.num_inserted = usize::MAX;
+        assert_eq!(usize::MAX, a.union(&b).unwrap().len());
+        assert_eq!(usize::MAX, BloomFilter::union_many([&a, &b]).unwrap().len());
+
+        let c: BloomFilter<&str> = BloomBuilder::new(200, 0.03).deterministic().build();
+        assert!(matches!(a.union(&c), Err(Error::IncompatibleParams { .. })));
+    }
+
+    #[test]
+    fn intersect() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let mut b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        a.insert("foo");
+        a.insert("bar");
+        b.insert("bar");
+        b.insert("baz");
+        let common = a.intersect(&b).unwrap();
+        assert!(common.has("bar"));
+
+        let c: BloomFilter<&str> = BloomBuilder::new(200, 0.03).deterministic().build();
+        assert!(matches!(
+            a.intersect(&c),
+            Err(Error::IncompatibleParams { .. })
+        ));
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let mut b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        a.insert("foo");
+        b.insert("bar");
+
+        let merged = &a | &b;
+        assert_eq!(a.union(&b).unwrap().bits, merged.bits);
+        let common = &a & &b;
+        assert_eq!(a.intersect(&b).unwrap().bits, common.bits);
+
+        let mut c = a.union(&b).unwrap();
+        c &= &b;
+        assert!(c.has("bar"));
+        a |= &b;
+        assert_eq!(merged.bits, a.bits);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bitwise_operators_mismatch() {
+        let a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).build();
+        let b: BloomFilter<&str> = BloomBuilder::new(200, 0.03).build();
+        let _ = &a | &b;
+    }
+
+    #[test]
+    fn subset() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let mut b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        a.insert("foo");
+        b.insert("foo");
+        b.insert("bar");
+        assert!(a.is_subset(&b));
+        assert!(b.is_superset(&a));
+        assert!(!b.is_subset(&a));
+        assert!(a.is_subset(&a));
+
+        let c: BloomFilter<&str> = BloomBuilder::new(200, 0.03).deterministic().build();
+        assert!(!c.is_subset(&a));
+    }
+
+    #[test]
+    fn estimate_count() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(10_000, 0.03).build();
+        assert_eq!(0.0, bf.estimate_count());
+        for i in 0..500 {
+            bf.insert(format!("{}", i));
+        }
+        let estimate = bf.estimate_count();
+        assert!((450.0..=550.0).contains(&estimate), "{}", estimate);
+    }
+
+    #[test]
+    fn current_fp_rate() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.03).build();
+        assert_eq!(0.0, bf.current_fp_rate());
+        bf.insert("foo".to_string());
+        assert!(bf.current_fp_rate() < 0.03);
+        for i in 0..10_000 {
+            bf.insert(format!("{}", i));
+        }
+        assert!(bf.current_fp_rate() > 0.03);
+    }
+
+    #[test]
+    fn fill_ratio() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.03).build();
+        assert_eq!(0.0, bf.fill_ratio());
+        assert!(!bf.is_saturated(0.5));
+        for i in 0..10_000 {
+            bf.insert(format!("{}", i));
+        }
+        assert!(bf.fill_ratio() > 0.5 && bf.fill_ratio() <= 1.0);
+        assert!(bf.is_saturated(0.5));
+    }
+
+    #[test]
+    fn jaccard() {
+        let mut a: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).deterministic().build();
+        let mut b: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).deterministic().build();
+        assert_eq!(Ok(1.0), a.jaccard(&b));
+        // Both filters share half of their 400 elements.
+        for i in 0..400 {
+            a.insert(format!("{}", i));
+            b.insert(format!("{}", i + 200));
+        }
+        let similarity = a.jaccard(&b).unwrap();
+        let wanted = 200.0 / 600.0;
+        assert!((similarity - wanted).abs() < 0.05, "{}", similarity);
+        assert_eq!(Ok(1.0), a.jaccard(&a));
+
+        let c: BloomFilter<String> = BloomBuilder::new(200, 0.03).deterministic().build();
+        assert!(matches!(
+            a.jaccard(&c),
+            Err(Error::IncompatibleParams { .. })
+        ));
+    }
+
+    #[test]
+    fn estimate_intersection() {
+        let mut a: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).deterministic().build();
+        let mut b: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).deterministic().build();
+        assert_eq!(Ok(0.0), a.estimate_intersection(&b));
+        for i in 0..400 {
+            a.insert(format!("{}", i));
+            b.insert(format!("{}", i + 200));
+        }
+        let intersection = a.estimate_intersection(&b).unwrap();
+        assert!((170.0..=230.0).contains(&intersection), "{}", intersection);
+
+        let c: BloomFilter<String> = BloomBuilder::new(200, 0.03).deterministic().build();
+        assert!(matches!(
+            a.estimate_intersection(&c),
+            Err(Error::IncompatibleParams { .. })
+        ));
+    }
+
+    #[test]
+    fn saturated_overlap() {
+        let mut full: BloomFilter<String> = BloomBuilder::new(1, 0.5).deterministic().build();
+        let empty: BloomFilter<String> = BloomBuilder::new(1, 0.5).deterministic().build();
+        let mut i = 0;
+        while full.count_ones() < full.bit_len() {
+            full.insert(format!("{}", i));
+            i += 1;
+        }
+        assert_eq!(f64::INFINITY, full.estimate_count());
+        assert_eq!(Err(Error::Saturated), full.jaccard(&full));
+        assert_eq!(Err(Error::Saturated), full.jaccard(&empty));
+        assert_eq!(Err(Error::Saturated), empty.estimate_intersection(&full));
+    }
+
+    #[test]
+    fn union_many() {
+        let mut filters = vec![];
+        for i in 0..20 {
+            let mut bf: BloomFilter<String> =
+                BloomBuilder::new(1_000, 0.03).deterministic().build();
+            bf.insert(format!("{}", i));
+            filters.push(bf);
+        }
+        let merged = BloomFilter::union_many(&filters).unwrap();
+        let pairwise = filters
+            .iter()
+            .skip(1)
+            .fold(filters[0].union(&filters[0]).unwrap(), |acc, bf| {
+                acc.union(bf).unwrap()
+            });
+        assert_eq!(pairwise.bits, merged.bits);
+        for i in 0..20 {
+            assert!(merged.has(format!("{}", i)));
+        }
+
+        let empty: Vec<BloomFilter<String>> = vec![];
+        assert_eq!(
+            Some(Error::NoFilters),
+            BloomFilter::union_many(&empty).err()
+        );
+        filters.push(BloomBuilder::new(200, 0.03).deterministic().build());
+        assert!(matches!(
+            BloomFilter::union_many(&filters),
+            Err(Error::IncompatibleParams { .. })
+        ));
+    }
+
+    #[test]
+    fn params() {
+        let a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let params = a.params();
+        assert_eq!(100, params.capacity);
+        assert_eq!(a.bit_len() as u64, params.bit_len);
+        assert_eq!(DEFAULT_HASHER_ID, params.hasher_id);
+
+        pub struct OtherHasher {}
+
+        impl<T: AsRef<[u8]>> Hasher<T> for OtherHasher {
+            fn hash(item: &T) -> u64 {
+                DefaultHasher::hash(item)
+            }
+        }
+
+        let b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).hasher::<OtherHasher>().build();
+        assert_ne!(DEFAULT_HASHER_ID, b.params().hasher_id);
+        match a.union(&b) {
+            Err(Error::IncompatibleParams { expected, found }) => {
+                assert_eq!(params, *expected);
+                assert_eq!(b.params(), *found);
+            }
+            _ => panic!("expected incompatible params"),
+        }
+    }
+
+    #[test]
+    fn count_ones() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(1_000, 0.03).build();
+        assert_eq!(0, bf.count_ones());
+        bf.insert("foo");
+        let wanted: u32 = bf.bits.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(wanted as usize, bf.count_ones());
+        assert!(bf.count_ones() > 0);
+    }
+
+    #[test]
+    fn accessors() {
+        let capacity = 100;
+        let fp_rate = 0.03;
+        let bf: BloomFilter<&str> = BloomBuilder::new(capacity, fp_rate).build();
+        assert_eq!(capacity, bf.capacity());
+        assert_eq!(optimal_num_hash_fns(capacity, fp_rate), bf.num_hash_fns());
+        assert_eq!(words::word_len(bf.byte_len()), bf.bits.len());
+        assert_eq!(bf.byte_len() * 8, bf.bit_len());
+        assert!(bf.bit_len() >= optimal_bits_needed(capacity, fp_rate) as usize);
+    }
+
+    #[test]
+    fn len() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let mut b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        assert!(a.is_empty());
+        a.insert("foo");
+        a.insert("foo");
+        b.insert("bar");
+        assert_eq!(2, a.len());
+        assert_eq!(3, a.union(&b).unwrap().len());
+        assert_eq!(1, a.intersect(&b).unwrap().len());
+
+        a.clear();
+        assert!(a.is_empty());
+    }
+
+    #[test]
+    fn stats() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.03).build();
+        bf.insert("foo");
+        let stats = bf.stats();
+        assert_eq!(100, stats.capacity);
+        assert_eq!(bf.num_hash_fns(), stats.num_hash_fns);
+        assert_eq!(bf.bit_len(), stats.bit_len);
+        assert_eq!(bf.count_ones(), stats.set_bits);
+        assert_eq!(bf.fill_ratio(), stats.fill_ratio);
+        assert_eq!(bf.estimate_count(), stats.estimated_count);
+        assert_eq!(bf.current_fp_rate(), stats.estimated_fp_rate);
+        assert!(format!("{}", stats).starts_with("capacity=100, "));
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&stats).unwrap();
+            assert_eq!(100, json["capacity"]);
+            assert_eq!(stats, serde_json::from_value(json).unwrap());
+        }
+    }
+
+    #[test]
+    fn params_digest() {
+        let a: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let b: BloomFilter<&str> = BloomBuilder::new(100, 0.03).deterministic().build();
+        let c: BloomFilter<&str> = BloomBuilder::new(100, 0.03).tier(0.01).build();
+        let d: BloomFilter<&str> = BloomBuilder::new(200, 0.03).deterministic().build();
+        assert_eq!(a.params_digest(), b.params_digest());
+        assert_ne!(a.params_digest(), c.params_digest());
+        assert_ne!(a.params_digest(), d.params_digest());
+    }
+
+    #[test]
+    fn fold() {
+        // Find a filter whose bytes can be folded twice.
+        let capacity = (1_000..)
+            .find(|c| optimal_bits_needed(*c, 0.01).div_ceil(8).is_multiple_of(4))
+            .unwrap();
+        let mut bf: BloomFilter<String> = BloomBuilder::new(capacity, 0.01).build();
+        for i in 0..50 {
+            bf.insert(format!("{}", i));
+        }
+        let folded = bf.fold().unwrap().fold().unwrap();
+        assert_eq!(bf.bit_len() / 4, folded.bit_len());
+        assert_eq!(bf.capacity(), folded.capacity());
+        for i in 0..50 {
+            assert!(folded.has(format!("{}", i)));
+        }
+        let f = bf.fill_ratio();
+        let wanted = 1.0 - (1.0 - f).powi(2);
+        let once = bf.fold().unwrap().fill_ratio();
+        assert!((once - wanted).abs() < 0.05, "{} {}", once, wanted);
+
+        let odd = (1_000..)
+            .find(|c| !optimal_bits_needed(*c, 0.01).div_ceil(8).is_multiple_of(2))
+            .unwrap();
+        let odd: BloomFilter<String> = BloomBuilder::new(odd, 0.01).build();
+        assert_eq!(Some(Error::NotFoldable), odd.fold().err());
+    }
+
+    #[test]
+    fn insert_reports_new_elements() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).build();
+        assert!(bf.insert("foo"));
+        assert!(!bf.insert("foo"));
+        assert!(bf.insert("bar"));
+        assert_eq!(3, bf.len());
+    }
+
+    #[test]
+    fn check_and_insert() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.01).build();
+        for i in 0..100 {
+            let item = format!("{}", i);
+            let wanted = bf.has(item.clone());
+            assert_eq!(wanted, bf.check_and_insert(item.clone()));
+            assert!(bf.has(item));
+        }
+    }
+
+    #[test]
+    fn has_all_and_has_any() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).build();
+        bf.insert("foo");
+        bf.insert("bar");
+        assert!(bf.has_all(vec!["foo", "bar"]));
+        assert!(!bf.has_all(vec!["foo", "nyan"]));
+        assert!(bf.has_any(vec!["nyan", "bar"]));
+        assert!(!bf.has_any(vec!["nyan", "cat"]));
+        assert!(bf.has_all(vec![]));
+        assert!(!bf.has_any(vec![]));
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(10, 0.01).build();
+        for i in 0..10 {
+            assert!(bf.try_insert(format!("{}", i)).is_ok());
+        }
+        assert_eq!(Err(Error::CapacityExceeded), bf.try_insert("nyan".into()));
+        assert_eq!(10, bf.len());
+
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.01).max_fp_rate(0.0).build();
+        assert!(bf.try_insert("foo".into()).is_ok());
+        assert_eq!(Err(Error::CapacityExceeded), bf.try_insert("bar".into()));
+    }
+
+    #[test]
+    fn has_borrowed() {
+        let mut bf: BloomFilter<String> = BloomFilter::new(100, 0.01);
+        bf.insert("foo".to_string());
+        assert_eq!(Ok(true), bf.has_borrowed("foo"));
+        assert_eq!(Ok(false), bf.has_borrowed("bar"));
+
+        let mut bf: BloomFilter<Vec<u8>> = BloomBuilder::new(100, 0.01).seed(42).build();
+        bf.insert(b"foo".to_vec());
+        assert_eq!(Ok(true), bf.has_borrowed(&b"foo"[..]));
+        assert_eq!(Ok(false), bf.has_borrowed(&b"bar"[..]));
+
+        let bf: BloomFilter<String> = BloomBuilder::new(100, 0.01)
+            .hash_fn(|item: &String| item.len() as u64)
+            .build();
+        assert_eq!(Err(Error::UnhashableBytes), bf.has_borrowed("foo"));
+
+        // Hashers implemented only for the filter's items can still be used.
+        struct StringHasher;
+        impl Hasher<String> for StringHasher {
+            fn hash(item: &String) -> u64 {
+                item.len() as u64
+            }
+        }
+        let mut bf: BloomFilter<String> = BloomBuilder::new(100, 0.01)
+            .hasher::<StringHasher>()
+            .build();
+        bf.insert("foo".to_string());
+        assert!(bf.has("foo".to_string()));
+        assert_eq!(Err(Error::UnhashableBytes), bf.has_borrowed("foo"));
+
+        let mut bf: BloomFilter<String> = BloomBuilder::new(100, 0.01)
+            .hasher::<DefaultHasher>()
+            .build();
+        bf.insert("foo".to_string());
+        assert_eq!(Ok(true), bf.has_borrowed("foo"));
+    }
+
+    #[test]
+    fn random_seed_by_default() {
+        let a: BloomFilter<&str> = BloomFilter::new(100, 0.01);
+        let b: BloomFilter<&str> = BloomBuilder::new(100, 0.01).build();
+        assert!(a.params().seed.is_some());
+        assert_ne!(a.params().seed, b.params().seed);
+        assert!(a.union(&b).is_err());
+        assert!(a.is_stable());
+
+        // The seed is carried over to filters derived from this one.
+        let merged = a.union(&a).unwrap();
+        assert_eq!(a.params(), merged.params());
+
+        let c: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        assert_eq!(None, c.params().seed);
+        assert_eq!(DEFAULT_HASHER_ID, c.params().hasher_id);
+    }
+
+    #[test]
+    fn hashes_once_per_operation() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.001)
+            .hash_fn(|item: &&str| {
+                CALLS.fetch_add(1, Ordering::SeqCst);
+                DefaultHasher::hash(item)
+            })
+            .build();
+        assert!(bf.num_hash_fns() > 1);
+        bf.insert("foo");
+        assert_eq!(1, CALLS.load(Ordering::SeqCst));
+        assert!(bf.has("foo"));
+        assert_eq!(2, CALLS.load(Ordering::SeqCst));
+        assert!(bf.has_all(vec!["foo", "foo"]));
+        assert_eq!(4, CALLS.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn independent_hashers() {
+        let hashers = |seeds: &[u64]| -> Vec<Box<dyn HasherInstance<String>>> {
+            seeds
+                .iter()
+                .map(|seed| Box::new(KeyedHasher::new(*seed)) as Box<dyn HasherInstance<String>>)
+                .collect()
+        };
+        let mut bf: BloomFilter<String> = BloomBuilder::new(100, 0.01)
+            .hashers(hashers(&[1, 2, 3]))
+            .build();
+        assert_eq!(3, bf.num_hash_fns());
+        assert!(bf.insert("foo".to_string()));
+        assert!(bf.has("foo".to_string()));
+        assert_eq!(Ok(true), bf.has_borrowed("foo"));
+        assert!(bf.has_all(vec!["foo".to_string()]));
+        assert!(!bf.has("bar".to_string()));
+        assert!(bf.is_stable());
+
+        // Each probe is the output of its own hasher.
+        let m = bf.bit_len() as u64;
+        let mut expected = BloomFilter::with_bits(&bf, AlignedWords::zeroed(bf.bits.len()), 0);
+        for seed in [1, 2, 3] {
+            expected.set_bit(KeyedHasher::new(seed).hash(&"foo".to_string()) % m);
+        }
+        assert_eq!(expected.bits, bf.bits);
+
+        // Tiers cannot be combined with independent hashers.
+        let tiered = BloomBuilder::new(100, 0.01)
+            .hashers(hashers(&[1, 2, 3]))
+            .tier(0.001)
+            .try_build();
+        assert_eq!(Some(Error::InvalidParams), tiered.err());
+
+        // Filters are only compatible if their hashers match in order.
+        let same: BloomFilter<String> = BloomBuilder::new(100, 0.01)
+            .hashers(hashers(&[1, 2, 3]))
+            .build();
+        let reordered: BloomFilter<String> = BloomBuilder::new(100, 0.01)
+            .hashers(hashers(&[3, 2, 1]))
+            .build();
+        assert_eq!(bf.params_digest(), same.params_digest());
+        assert_ne!(bf.params_digest(), reordered.params_digest());
+    }
+
+    #[test]
+    fn try_build_invalid_params() {
+        let build = |builder: BloomBuilder<&str>| builder.try_build().err();
+        assert_eq!(
+            Some(Error::InvalidParams),
+            build(BloomBuilder::new(0, 0.01))
+        );
+        for fp_rate in [0.0, -0.5, 1.0, 1.5, f32::NAN] {
+            assert_eq!(
+                Some(Error::InvalidParams),
+                build(BloomBuilder::new(100, fp_rate))
+            );
+        }
+        let mut ppb = BloomBuilder::new_ppb(100, 10_000_000);
+        ppb.fp_rate = FpRate::PartsPerBillion(1_000_000_000);
+        assert_eq!(Some(Error::InvalidParams), build(ppb));
+        assert_eq!(
+            Some(Error::InvalidParams),
+            build(BloomBuilder::new_ppb(0, 10_000_000))
+        );
+
+        // Filters without hash functions, or with more than can be decoded.
+        for num_hash_fns in [0, 151] {
+            let builder = BloomBuilder::new(100, 0.01).num_hash_funcs(num_hash_fns);
+            assert_eq!(Some(Error::InvalidParams), build(builder));
+        }
+        let hashers: Vec<Box<dyn HasherInstance<&str>>> = (0..151)
+            .map(|seed| Box::new(KeyedHasher::new(seed)) as Box<dyn HasherInstance<&str>>)
+            .collect();
+        assert_eq!(
+            Some(Error::InvalidParams),
+            build(BloomBuilder::new(100, 0.01).hashers(hashers))
+        );
+        assert!(BloomBuilder::<&str>::new(1, 0.99).try_build().is_ok());
+    }
+
+    #[test]
+    fn try_build_invalid_tiers() {
+        for fp_rate in [0.0, -0.5, 1.0, 1.5, f32::NAN, 1e-45] {
+            let bf = BloomBuilder::<&str>::new(100, 0.01)
+                .tier(0.001)
+                .tier(fp_rate)
+                .try_build();
+            assert_eq!(Some(Error::InvalidParams), bf.err());
+        }
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).tier(1e-30).build();
+        assert_eq!(vec![100], bf.params().tier_hash_fns);
+    }
+
+    #[test]
+    fn independent_hashers_overriding_hash_pair() {
+        // A hasher whose base hashes are not derived from its plain hash.
+        struct Skewed(u64);
+        impl HasherInstance<String> for Skewed {
+            fn hash(&self, item: &String) -> u64 {
+                KeyedHasher::new(self.0).hash(item)
+            }
+            fn hash_pair(&self, item: &String) -> (u64, u64) {
+                self.hash_pair_bytes(item.as_bytes()).unwrap()
+            }
+            fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+                let hash = KeyedHasher::new(self.0).hash(&bytes);
+                Some((!hash, hash))
+            }
+        }
+        let hasher = Skewed(1);
+        let item = "foo".to_string();
+        assert_ne!(hasher.hash(&item), hasher.hash_pair(&item).0);
+
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.01)
+            .hashers((1..4).map(|seed| Box::new(Skewed(seed)) as _).collect())
+            .build();
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        for i in 0..100 {
+            assert!(bf.has(format!("{}", i)));
+            assert_eq!(Ok(true), bf.has_borrowed(format!("{}", i).as_str()));
+        }
+    }
+
+    #[test]
+    fn stable_hashing() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        assert!(bf.is_stable());
+        let closure: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hash_fn(|item: &&str| item.len() as u64)
+            .build();
+        assert!(!closure.is_stable());
+
+        // These values are frozen. If this test fails, filters built by previous
+        // versions of the package can no longer be queried by this one.
+        assert_eq!(0x6e1cdface8a36eec, DefaultHasher::hash(&"nyan"));
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(8, 0.1).deterministic().build();
+        bf.insert("nyan");
+        assert_eq!(vec![16, 8, 64, 0, 2], bf.bits_to_bytes());
+    }
+
+    #[test]
+    fn insert_hash() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        assert!(bf.insert_hash(DefaultHasher::hash(&"foo")).unwrap());
+        assert!(!bf.insert("foo"));
+        assert!(bf.has_hash(DefaultHasher::hash(&"foo")).unwrap());
+        assert!(!bf.has_hash(DefaultHasher::hash(&"bar")).unwrap());
+        assert_eq!(2, bf.len());
+
+        let hashers: Vec<Box<dyn HasherInstance<&str>>> = vec![Box::new(KeyedHasher::new(1))];
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).hashers(hashers).build();
+        assert_eq!(Err(Error::IndependentHashers), bf.insert_hash(1));
+        assert_eq!(Err(Error::IndependentHashers), bf.has_hash(1));
+        assert_eq!(Err(Error::IndependentHashers), bf.bulk_insert_hashes(&[1]));
+        assert!(bf.is_empty());
+    }
+
+    #[test]
+    fn probe_indices_are_spread() {
+        let m = 1 << 20;
+        let hashes = DefaultHasher::hash_pair(&"nyan");
+        let mut indices: Vec<u64> = (0..7).map(|i| probe_index(hashes, i, m)).collect();
+        // Consecutive probes should not land on neighboring bits.
+        for pair in indices.windows(2) {
+            assert!(pair[0].abs_diff(pair[1]) > 1);
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        assert_eq!(7, indices.len());
+    }
+
+    #[test]
+    fn fp_rate_at_capacity() {
+        for (capacity, wanted_fp_rate) in [(1_000, 0.1), (2_000, 0.01), (5_000, 0.05)] {
+            let mut bf: BloomFilter<String> = BloomBuilder::new(capacity, wanted_fp_rate).build();
+            for i in 0..capacity {
+                bf.insert(format!("{}", i));
+            }
+            let num_tests = 20_000;
+            let false_positives = (capacity..capacity + num_tests)
+                .filter(|i| bf.has(format!("{}", i)))
+                .count();
+            let real_fp_rate = false_positives as f32 / num_tests as f32;
+            assert!(
+                real_fp_rate >= wanted_fp_rate * 0.5 && real_fp_rate <= wanted_fp_rate * 1.5,
+                "capacity={}, wanted_fp_rate={}, fp_rate={}",
+                capacity,
+                wanted_fp_rate,
+                real_fp_rate,
+            );
+            // Bits should be set evenly across the whole allocated bit array.
+            let (low, high) = bf.bits.split_at(bf.bits.len() / 2);
+            let low_ones: u32 = low.iter().map(|b| b.count_ones()).sum();
+            let high_ones: u32 = high.iter().map(|b| b.count_ones()).sum();
+            assert!((low_ones as f32 / high_ones as f32 - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn seeded() {
+        let mut a: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).seed(1).build();
+        let mut b: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).seed(2).build();
+        let mut c: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).build();
+        a.insert("foo");
+        b.insert("foo");
+        c.insert("foo");
+        assert!(a.has("foo") && b.has("foo"));
+        // Different seeds map the same item to different bits.
+        assert_ne!(a.bits, b.bits);
+        assert_ne!(a.bits, c.bits);
+        assert_eq!(Some(1), a.params().seed);
+        assert_eq!(hasher::SIPHASH_HASHER_ID, a.params().hasher_id);
+        assert!(matches!(a.union(&b), Err(Error::IncompatibleParams { .. })));
+        assert_ne!(a.params_digest(), b.params_digest());
+
+        let d: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01)
+            .seed(1)
+            .hasher::<DefaultHasher>()
+            .build();
+        assert_eq!(None, d.params().seed);
+    }
+
+    #[test]
+    fn closure_hash_fn() {
+        let salt = 7u64;
+        let mut a: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01)
+            .hash_fn(move |item: &&str| DefaultHasher::hash(item).wrapping_add(salt))
+            .build();
+        let boxed: HashFn<&str> = Box::new(|item: &&str| DefaultHasher::hash(item));
+        let b: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).hash_fn(boxed).build();
+        a.insert("foo");
+        assert!(a.has("foo"));
+        assert!(a.union(&b).is_err());
+        assert!(a.union(&a.clone()).unwrap().has("foo"));
+
+        // Closures defined in the same place still differ.
+        let builds: Vec<BloomFilter<&str>> = [1u64, 2]
+            .iter()
+            .map(|salt| {
+                let salt = *salt;
+                BloomBuilder::new(1_000, 0.01)
+                    .hash_fn(move |item: &&str| DefaultHasher::hash(item) ^ salt)
+                    .build()
+            })
+            .collect();
+        assert!(matches!(
+            builds[0].union(&builds[1]),
+            Err(Error::IncompatibleParams { .. })
+        ));
+        assert!(builds[0].intersect(&builds[1]).is_err());
     }
 
     #[test]
     fn threads() {
-        let num_items: u32 = 50;
+        let num_items: u64 = 50;
         let fp_rate: f32 = 0.03;
         let bf: BloomFilter<String> = BloomBuilder::new(num_items, fp_rate).build();
         let bf = Arc::new(Mutex::new(bf));
@@ -375,7 +2738,7 @@ mod tests {
         for handle in handles {
             handle.join().unwrap();
         }
-        assert_eq!(false, bf.lock().unwrap().has("4".to_string()));
+        assert!(!bf.lock().unwrap().has("4".to_string()));
     }
 
     #[test]
@@ -384,12 +2747,12 @@ mod tests {
         let wanted_fp_rate = 0.03;
         let mut bf: BloomFilter<String> = BloomBuilder::new(capacity, wanted_fp_rate).build();
 
-        let num_items = 100;
+        let num_items = capacity;
         for i in 0..num_items {
             bf.insert(format!("{}", i));
         }
 
-        let num_tests = 100;
+        let num_tests = 10_000;
         let mut false_positives = 0;
         for i in num_items..num_items + num_tests {
             if bf.has(format!("{}", i)) {
@@ -398,9 +2761,8 @@ mod tests {
         }
 
         let real_fp_rate = false_positives as f32 / num_tests as f32;
-        let tolerance = 0.02;
-        assert_eq!(
-            true,
+        let tolerance = 0.01;
+        assert!(
             real_fp_rate >= wanted_fp_rate - tolerance
                 && real_fp_rate <= wanted_fp_rate + tolerance
         );
@@ -409,4 +2771,89 @@ mod tests {
             num_items, num_items, wanted_fp_rate, real_fp_rate,
         );
     }
+
+    #[test]
+    fn display_roundtrip() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(100, 0.01).tier(0.001).build();
+        for i in 0..10 {
+            bf.insert(format!("{}", i));
+        }
+        let displayed = bf.to_string();
+        assert!(!displayed.contains(char::is_whitespace));
+        assert_eq!(
+            bf.to_bytes(),
+            base64::base64_decode(&displayed, base64::BASE64_STANDARD).unwrap()
+        );
+        let restored: BloomFilter<String> = displayed.parse().unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+
+        let parse = |s: &str| s.parse::<BloomFilter<String>>().err();
+        assert_eq!(Some(Error::InvalidEncoding), parse("not base64!"));
+        assert_eq!(Some(Error::InvalidEncoding), parse(&displayed[4..]));
+        assert_eq!(Some(Error::InvalidEncoding), parse(""));
+    }
+
+    #[test]
+    fn parts_roundtrip() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1000, 0.01).tier(0.001).build();
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        let params = bf.params();
+        let bits = bf.bits.clone();
+        let (parts_params, parts_bits) = bf.into_parts();
+        assert_eq!(params, parts_params);
+        assert_eq!(&bits[..], &parts_bits[..]);
+
+        let restored: BloomFilter<String> =
+            BloomFilter::from_parts(parts_params.clone(), parts_bits.clone()).unwrap();
+        assert_eq!(params, restored.params());
+        assert!((0..100).all(|i| restored.has(format!("{}", i))));
+        assert!((90..110).contains(&restored.len()));
+        // Bits are moved onto cache lines, whatever the alignment of the parts.
+        assert_eq!(0, restored.as_words().as_ptr() as usize % 64);
+
+        let truncated = parts_bits[1..].to_vec();
+        let err = BloomFilter::<String>::from_parts(parts_params.clone(), truncated).err();
+        assert_eq!(Some(Error::InvalidEncoding), err);
+        let custom = FilterParams {
+            hasher_id: 1,
+            ..parts_params.clone()
+        };
+        let err = BloomFilter::<String>::from_parts(custom, parts_bits.clone()).err();
+        assert_eq!(Some(Error::UnknownHasher(1)), err);
+
+        // Parameters `from_bytes` would reject are rejected here too.
+        let invalid = [
+            FilterParams {
+                num_hash_fns: 0,
+                ..parts_params.clone()
+            },
+            FilterParams {
+                num_hash_fns: u32::MAX,
+                ..parts_params.clone()
+            },
+            FilterParams {
+                tier_hash_fns: vec![u32::MAX],
+                ..parts_params.clone()
+            },
+            FilterParams {
+                tier_hash_fns: vec![1; format::MAX_TIERS as usize + 1],
+                ..parts_params.clone()
+            },
+        ];
+        for params in invalid {
+            let err = BloomFilter::<String>::from_parts(params, parts_bits.clone()).err();
+            assert_eq!(Some(Error::InvalidEncoding), err);
+        }
+
+        // A saturated filter has an infinite estimate.
+        let mut full = vec![u64::MAX; parts_bits.len()];
+        let padded = full.len() as u64 * 64 - parts_params.bit_len;
+        *full.last_mut().unwrap() >>= padded;
+        let restored: BloomFilter<String> = BloomFilter::from_parts(parts_params, full).unwrap();
+        assert_eq!(restored.bit_len(), restored.len());
+    }
 }
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use crate::HasherInstance;
+
+/// A hasher that remembers the hashes of the most recently hashed items, so that
+/// repeated lookups of the same hot keys skip hashing entirely. This pays off for
+/// skewed access patterns with an expensive hasher, such as the SHA3 based default
+/// hasher, at the cost of a lock and a cheaper hash map lookup per operation.
+///
+/// Items are cached by their bytes, and the least recently used item is evicted
+/// once the cache is full. Any hasher instance can be wrapped, including stateless
+/// hashers adapted with `StatelessHasher`.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, DefaultHasher, MemoizedHasher, StatelessHasher};
+///
+/// let hasher = StatelessHasher::<DefaultHasher>::new();
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher_instance(MemoizedHasher::new(hasher, 128))
+///     .build();
+/// bf.insert("hot-key");
+/// assert!(bf.has("hot-key"));
+/// ```
+pub struct MemoizedHasher<H> {
+    inner: H,
+    cache: Mutex<LruCache>,
+}
+
+impl<H> MemoizedHasher<H> {
+    /// Wraps a hasher with a cache holding the hashes of up to capacity items.
+    pub fn new(inner: H, capacity: usize) -> MemoizedHasher<H> {
+        MemoizedHasher {
+            inner,
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+    /// Returns the cached hashes of some bytes, or hashes them and caches the result.
+    /// Failures to hash are returned as is, and not cached.
+    fn memoized<E, F>(&self, bytes: &[u8], hash: F) -> Result<(u64, u64), E>
+    where
+        F: FnOnce() -> Result<(u64, u64), E>,
+    {
+        // A poisoned lock only means another thread panicked mid-update, and the
+        // cache is only ever a shortcut, so hash without it in that case.
+        let mut cache = match self.cache.lock() {
+            Ok(cache) => cache,
+            Err(_) => return hash(),
+        };
+        if let Some(hashes) = cache.get(bytes) {
+            return Ok(hashes);
+        }
+        let hashes = hash()?;
+        cache.put(bytes, hashes);
+        Ok(hashes)
+    }
+}
+
+impl<T: AsRef<[u8]>, H: HasherInstance<T>> HasherInstance<T> for MemoizedHasher<H> {
+    fn hash(&self, item: &T) -> u64 {
+        self.inner.hash(item)
+    }
+    fn id(&self) -> u64 {
+        self.inner.id()
+    }
+    fn seed(&self) -> Option<u64> {
+        self.inner.seed()
+    }
+    fn is_stable(&self) -> bool {
+        self.inner.is_stable()
+    }
+    fn hash_pair(&self, item: &T) -> (u64, u64) {
+        let Ok(hashes) = self.memoized(item.as_ref(), || {
+            Ok::<_, Infallible>(self.inner.hash_pair(item))
+        });
+        hashes
+    }
+    fn hash_pair_bytes(&self, bytes: &[u8]) -> Option<(u64, u64)> {
+        self.memoized(bytes, || self.inner.hash_pair_bytes(bytes).ok_or(()))
+            .ok()
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+struct Entry {
+    key: Vec<u8>,
+    hashes: (u64, u64),
+    prev: usize,
+    next: usize,
+}
+
+/// A least recently used cache from bytes to hashes, keeping its entries in a
+/// doubly linked list threaded through a vector, ordered from most to least
+/// recently used.
+struct LruCache {
+    capacity: usize,
+    index: HashMap<Vec<u8>, usize>,
+    entries: Vec<Entry>,
+    head: usize,
+    tail: usize,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> LruCache {
+        LruCache {
+            capacity,
+            index: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+    fn get(&mut self, key: &[u8]) -> Option<(u64, u64)> {
+        let idx = *self.index.get(key)?;
+        self.unlink(idx);
+        self.push_front(idx);
+        Some(self.entries[idx].hashes)
+    }
+    fn put(&mut self, key: &[u8], hashes: (u64, u64)) {
+        if self.capacity == 0 {
+            return;
+        }
+        let idx = if self.entries.len() < self.capacity {
+            self.entries.push(Entry {
+                key: key.to_vec(),
+                hashes,
+                prev: NIL,
+                next: NIL,
+            });
+            self.entries.len() - 1
+        } else {
+            // Reuse the least recently used entry.
+            let idx = self.tail;
+            self.unlink(idx);
+            let entry = &mut self.entries[idx];
+            self.index.remove(&entry.key);
+            entry.key.clear();
+            entry.key.extend_from_slice(key);
+            entry.hashes = hashes;
+            idx
+        };
+        self.index.insert(key.to_vec(), idx);
+        self.push_front(idx);
+    }
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.entries[idx].prev, self.entries[idx].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.entries[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.entries[next].prev = prev,
+        }
+    }
+    fn push_front(&mut self, idx: usize) {
+        self.entries[idx].prev = NIL;
+        self.entries[idx].next = self.head;
+        match self.head {
+            NIL => self.tail = idx,
+            head => self.entries[head].prev = idx,
+        }
+        self.head = idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DefaultHasher, Hasher, StatelessHasher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn lru_eviction() {
+        let mut cache = LruCache::new(2);
+        cache.put(b"a", (1, 1));
+        cache.put(b"b", (2, 2));
+        assert_eq!(Some((1, 1)), cache.get(b"a"));
+        // "b" is now the least recently used entry.
+        cache.put(b"c", (3, 3));
+        assert_eq!(None, cache.get(b"b"));
+        assert_eq!(Some((1, 1)), cache.get(b"a"));
+        assert_eq!(Some((3, 3)), cache.get(b"c"));
+    }
+
+    #[test]
+    fn skips_hashing_hot_keys() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let counting = |item: &&str| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            DefaultHasher::hash(item)
+        };
+        let memo = MemoizedHasher::new(counting, 8);
+        let first = memo.hash_pair(&"hot");
+        assert_eq!(first, memo.hash_pair(&"hot"));
+        assert_eq!(1, CALLS.load(Ordering::SeqCst));
+
+        let stateless: Arc<dyn HasherInstance<&str>> =
+            Arc::new(StatelessHasher::<DefaultHasher>::new());
+        assert_eq!(stateless.hash_pair(&"hot"), first);
+    }
+}
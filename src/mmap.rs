@@ -0,0 +1,421 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::format::{crc32_update, invalid, Header, CHECKSUM_VERSION};
+use crate::{
+    has_probes, optimal_bits_needed, optimal_num_hash_fns, probe_index, random_seed, Compression,
+    Error, FilterParams, HasherInstance, KeyedHasher,
+};
+
+/// The version of the encoding used by memory-mapped files. Their bits change in place,
+/// so they use the first version of the format, which has no trailing checksum.
+const MMAP_VERSION: u8 = 1;
+
+/// A bloom filter whose bits live in a memory-mapped file rather than on the heap, so
+/// that filters larger than memory can be used, and filters persist across restarts
+/// without explicit save and load cycles. Requires the `mmap` feature.
+///
+/// Inserts write through to the mapped file, and the operating system writes dirty pages
+/// back to disk on its own schedule. Calling `flush` writes them back immediately, along
+/// with the number of inserted elements, and should be done before the process exits.
+///
+/// The file uses the encoding of `BloomFilter::to_bytes`, without the trailing checksum,
+/// so it can also be loaded into memory with `BloomFilter::from_bytes`. Filters are given
+/// a random seed when created, which is stored in the file.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::MmapBloomFilter;
+///
+/// let path = std::env::temp_dir().join("flowerbloom-doc.bloom");
+/// // SAFETY: the file is private to this example.
+/// let mut bf: MmapBloomFilter<&str> =
+///     unsafe { MmapBloomFilter::create(&path, 1000, 0.01) }.unwrap();
+/// bf.insert("hello");
+/// bf.flush().unwrap();
+/// drop(bf);
+///
+/// let bf: MmapBloomFilter<&str> = unsafe { MmapBloomFilter::open(&path) }.unwrap();
+/// assert!(bf.has("hello"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct MmapBloomFilter<T: AsRef<[u8]>> {
+    map: MmapMut,
+    // The offset of the bits within the map, right after the header.
+    offset: usize,
+    // The offset of the number of inserted elements within the header.
+    num_inserted_offset: usize,
+    params: FilterParams,
+    hasher: Arc<dyn HasherInstance<T>>,
+    num_inserted: u64,
+}
+
+impl<T: AsRef<[u8]>> MmapBloomFilter<T> {
+    /// Creates a file at a path holding an empty bloom filter with a specified capacity
+    /// and desired false positive rate, replacing any existing file, and maps it into
+    /// memory. The file is extended to its full size upfront, which most file systems
+    /// store sparsely until bits are set. Fails with an error of kind `InvalidData`
+    /// wrapping `Error::InvalidParams` if the capacity is zero or the false positive
+    /// rate is not between 0 and 1.
+    ///
+    /// # Safety
+    ///
+    /// The file is modified through the mapping, which is undefined behavior if the file
+    /// is truncated or modified by anything else while the filter is alive, including
+    /// another filter mapping the same file. Callers must ensure the file is not shared.
+    pub unsafe fn create<P: AsRef<Path>>(
+        path: P,
+        capacity: u64,
+        desired_fp_rate: f32,
+    ) -> io::Result<MmapBloomFilter<T>> {
+        if capacity == 0 || !(desired_fp_rate > 0.0 && desired_fp_rate < 1.0) {
+            return Err(invalid(Error::InvalidParams));
+        }
+        let required_bits = optimal_bits_needed(capacity, desired_fp_rate);
+        let byte_len = required_bits.div_ceil(8).max(1);
+        let seed = random_seed();
+        let hasher: Arc<dyn HasherInstance<T>> = Arc::new(KeyedHasher::new(seed));
+        let header = Header {
+            version: MMAP_VERSION,
+            params: FilterParams {
+                capacity,
+                bit_len: byte_len * 8,
+                num_hash_fns: optimal_num_hash_fns(capacity, desired_fp_rate),
+                tier_hash_fns: vec![],
+                hasher_id: hasher.id(),
+                seed: Some(seed),
+            },
+            max_fp_rate: None,
+            num_inserted: 0,
+            compression: Compression::None,
+        };
+        let mut encoded = vec![];
+        header.write_to(&mut encoded)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(&encoded)?;
+        file.set_len(encoded.len() as u64 + byte_len)?;
+        // SAFETY: upheld by the caller.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(MmapBloomFilter {
+            map,
+            offset: encoded.len(),
+            num_inserted_offset: header.num_inserted_offset(),
+            params: header.params,
+            hasher,
+            num_inserted: 0,
+        })
+    }
+    /// Maps a file created by `create` into memory. Files holding filters built with
+    /// custom hashers, or encoded with a checksum by `BloomFilter::write_to`, cannot be
+    /// opened, as their bits cannot be modified in place.
+    ///
+    /// # Safety
+    ///
+    /// As for `create`, the file must not be truncated or modified by anything else,
+    /// such as another process or another filter opened from it, while the filter is
+    /// alive.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapBloomFilter<T>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: upheld by the caller.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let mut input = &map[..];
+        let header = Header::read_from(&mut input)?;
+        if header.version != MMAP_VERSION {
+            return Err(invalid(Error::UnsupportedVersion(header.version)));
+        }
+        if input.len() as u64 != header.byte_len() {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let offset = map.len() - input.len();
+        let hasher = header.hasher()?;
+        Ok(MmapBloomFilter {
+            map,
+            offset,
+            num_inserted_offset: header.num_inserted_offset(),
+            params: header.params,
+            hasher,
+            num_inserted: header.num_inserted,
+        })
+    }
+    /// Insert an element into the bloom filter, writing through to the mapped file.
+    /// Returns true if the element was probably not in the filter before, as in
+    /// `BloomFilter::insert`.
+    pub fn insert(&mut self, elem: T) -> bool {
+        let hashes = self.hasher.hash_pair(&elem);
+        let m = self.params.bit_len;
+        let bits = &mut self.map[self.offset..];
+        let mut changed = false;
+        for i in 0..self.params.num_hash_fns {
+            let idx = probe_index(hashes, i, m);
+            let b = &mut bits[(idx / 8) as usize];
+            changed |= (*b >> (idx % 8)) & 1 == 0;
+            *b |= 1 << (idx % 8);
+        }
+        self.num_inserted += 1;
+        changed
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        has_probes(
+            self.bits(),
+            self.hasher.hash_pair(&elem),
+            self.params.num_hash_fns,
+        )
+    }
+    /// Writes the number of inserted elements and all modified bits back to the file,
+    /// returning once they are durably stored.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let offset = self.num_inserted_offset;
+        self.map[offset..offset + 8].copy_from_slice(&self.num_inserted.to_be_bytes());
+        self.map.flush()
+    }
+    /// Returns the number of elements inserted into the bloom filter, including
+    /// elements inserted before the file was last opened and flushed.
+    pub fn len(&self) -> usize {
+        self.num_inserted as usize
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the bits of the bloom filter, as found in the mapped file.
+    pub fn bits(&self) -> &[u8] {
+        &self.map[self.offset..]
+    }
+    /// Returns the structural parameters of the bloom filter, see `BloomFilter::params`.
+    pub fn params(&self) -> FilterParams {
+        self.params.clone()
+    }
+}
+
+/// A read-only bloom filter queried directly from a memory-mapped file, without copying
+/// its bits onto the heap, so that even very large filters are ready to be queried as
+/// soon as they are opened, with pages loaded from disk on first access. Requires the
+/// `mmap` feature.
+///
+/// Clones share the same mapping, so the filter can be queried from many threads at
+/// once, and the operating system shares its pages between all processes mapping the
+/// same file. Any encoding of `BloomFilter::to_bytes` or `MmapBloomFilter` can be
+/// opened, as long as it was built with one of the package's hashers.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter, ReadOnlyMmapFilter};
+///
+/// let path = std::env::temp_dir().join("flowerbloom-readonly-doc.bloom");
+/// let mut bf: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+/// bf.insert("hello");
+/// std::fs::write(&path, bf.to_bytes()).unwrap();
+///
+/// // SAFETY: the file is private to this example.
+/// let snapshot: ReadOnlyMmapFilter<&str> = unsafe { ReadOnlyMmapFilter::open(&path) }.unwrap();
+/// assert!(snapshot.has("hello"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct ReadOnlyMmapFilter<T: AsRef<[u8]>> {
+    map: Arc<Mmap>,
+    version: u8,
+    // The offset of the bits within the map, right after the header.
+    offset: usize,
+    params: FilterParams,
+    hasher: Arc<dyn HasherInstance<T>>,
+    num_inserted: u64,
+}
+
+impl<T: AsRef<[u8]>> ReadOnlyMmapFilter<T> {
+    /// Maps an encoded filter into memory for reading. Only the header is read upfront,
+    /// so the checksum of the encoding, if any, is not verified, see `verify`.
+    ///
+    /// # Safety
+    ///
+    /// Queries read the file through the mapping, which is undefined behavior if the
+    /// file is truncated or modified while the filter or any of its clones is alive,
+    /// such as by a `MmapBloomFilter` opened from the same file. Callers must ensure
+    /// the file is not written to while it is mapped.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<ReadOnlyMmapFilter<T>> {
+        let file = File::open(path)?;
+        // SAFETY: upheld by the caller.
+        let map = unsafe { Mmap::map(&file)? };
+        let mut input = &map[..];
+        let header = Header::read_from(&mut input)?;
+        let checksum_len = match header.version >= CHECKSUM_VERSION {
+            true => 4,
+            false => 0,
+        };
+        // Compressed bits cannot be queried in place.
+        if header.compression != Compression::None
+            || input.len() as u64 != header.byte_len() + checksum_len
+        {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let offset = map.len() - input.len();
+        let hasher = header.hasher()?;
+        Ok(ReadOnlyMmapFilter {
+            map: Arc::new(map),
+            version: header.version,
+            offset,
+            params: header.params,
+            hasher,
+            num_inserted: header.num_inserted,
+        })
+    }
+    /// Verifies the checksum of the encoding against its contents, which reads the
+    /// entire file. Encodings without a checksum always pass.
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version < CHECKSUM_VERSION {
+            return Ok(());
+        }
+        let (contents, checksum) = self.map.split_at(self.map.len() - 4);
+        match crc32_update(0, contents).to_be_bytes() == checksum {
+            true => Ok(()),
+            false => Err(Error::Corrupted),
+        }
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        has_probes(
+            self.bits(),
+            self.hasher.hash_pair(&elem),
+            self.params.num_hash_fns,
+        )
+    }
+    /// Returns the number of elements inserted into the bloom filter when it was encoded.
+    pub fn len(&self) -> usize {
+        self.num_inserted as usize
+    }
+    /// Returns true if no elements were inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the bits of the bloom filter, as found in the mapped file.
+    pub fn bits(&self) -> &[u8] {
+        let byte_len = self.params.bit_len as usize / 8;
+        &self.map[self.offset..self.offset + byte_len]
+    }
+    /// Returns the structural parameters of the bloom filter, see `BloomFilter::params`.
+    pub fn params(&self) -> FilterParams {
+        self.params.clone()
+    }
+}
+
+impl<T: AsRef<[u8]>> Clone for ReadOnlyMmapFilter<T> {
+    fn clone(&self) -> Self {
+        ReadOnlyMmapFilter {
+            map: self.map.clone(),
+            version: self.version,
+            offset: self.offset,
+            params: self.params.clone(),
+            hasher: self.hasher.clone(),
+            num_inserted: self.num_inserted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomFilter;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("flowerbloom-{}-{}.bloom", name, std::process::id()))
+    }
+
+    #[test]
+    fn persists_across_opens() {
+        let path = temp_path("persist");
+        let mut bf: MmapBloomFilter<&str> =
+            unsafe { MmapBloomFilter::create(&path, 1_000, 0.01) }.unwrap();
+        assert!(bf.is_empty());
+        assert!(bf.insert("foo"));
+        assert!(!bf.insert("foo"));
+        bf.flush().unwrap();
+        drop(bf);
+
+        let mut bf: MmapBloomFilter<&str> = unsafe { MmapBloomFilter::open(&path) }.unwrap();
+        assert_eq!(2, bf.len());
+        assert!(bf.has("foo"));
+        assert!(!bf.has("bar"));
+        bf.insert("bar");
+        bf.flush().unwrap();
+
+        // The file can also be loaded into memory as a regular filter.
+        let loaded: BloomFilter<&str> =
+            BloomFilter::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(bf.bits(), loaded.bits_to_bytes());
+        assert_eq!(bf.params(), loaded.params());
+        assert_eq!(3, loaded.len());
+        assert!(loaded.has("foo") && loaded.has("bar"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_only_snapshots() {
+        let path = temp_path("snapshot");
+        let mut bf: BloomFilter<String> = BloomFilter::new(1_000, 0.01);
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        let mut file = File::create(&path).unwrap();
+        bf.write_to(&mut file).unwrap();
+        drop(file);
+
+        let snapshot: ReadOnlyMmapFilter<String> =
+            unsafe { ReadOnlyMmapFilter::open(&path) }.unwrap();
+        assert_eq!(Ok(()), snapshot.verify());
+        assert_eq!(bf.bits_to_bytes(), snapshot.bits());
+        assert_eq!(100, snapshot.len());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let snapshot = snapshot.clone();
+                std::thread::spawn(move || (0..100).all(|i| snapshot.has(format!("{}", i))))
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+
+        // Flip a bit of the filter on disk.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[70] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let snapshot: ReadOnlyMmapFilter<String> =
+            unsafe { ReadOnlyMmapFilter::open(&path) }.unwrap();
+        assert_eq!(Err(Error::Corrupted), snapshot.verify());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_checksummed_files() {
+        let path = temp_path("checksummed");
+        let bf: BloomFilter<&str> = BloomFilter::new(100, 0.01);
+        std::fs::write(&path, bf.to_bytes()).unwrap();
+        let err = unsafe { MmapBloomFilter::<&str>::open(&path) }
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        let path = temp_path("invalid");
+        for (capacity, fp_rate) in [(0, 0.01), (100, 0.0), (100, 1.0), (100, f32::NAN)] {
+            let err = unsafe { MmapBloomFilter::<&str>::create(&path, capacity, fp_rate) }
+                .err()
+                .unwrap();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        }
+        assert!(!path.exists());
+    }
+}
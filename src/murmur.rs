@@ -0,0 +1,158 @@
+use crate::hasher::MURMUR3_HASHER_ID;
+use crate::{Hasher, WideHasher};
+
+/// A hasher using the 128-bit x64 variant of MurmurHash3 with a seed of zero, which is
+/// the hash function used by many existing bloom filter implementations such as
+/// Guava's and Cassandra's. The two 64-bit halves of its output are used directly as
+/// the base hashes for double hashing. Requires the `murmur3` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, Murmur3Hasher};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher::<Murmur3Hasher>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+pub struct Murmur3Hasher {}
+
+impl<T: AsRef<[u8]>> Hasher<T> for Murmur3Hasher {
+    fn hash(item: &T) -> u64 {
+        murmur3_x64_128(item.as_ref(), 0).0
+    }
+    fn id() -> u64 {
+        MURMUR3_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair(item: &T) -> (u64, u64) {
+        murmur3_x64_128(item.as_ref(), 0)
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<Murmur3Hasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+impl<T: AsRef<[u8]>> WideHasher<T> for Murmur3Hasher {
+    fn hash128(item: &T) -> u128 {
+        let (h1, h2) = murmur3_x64_128(item.as_ref(), 0);
+        ((h1 as u128) << 64) | h2 as u128
+    }
+    fn id() -> u64 {
+        MURMUR3_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash128_bytes(bytes: &[u8]) -> Option<u128> {
+        Some(<Murmur3Hasher as WideHasher<&[u8]>>::hash128(&bytes))
+    }
+}
+
+const C1: u64 = 0x87c37b91114253d5;
+const C2: u64 = 0x4cf5ad432745937f;
+
+/// Computes the 128-bit x64 variant of MurmurHash3, returning its two 64-bit halves.
+/// The canonical byte representation of the hash is both halves in little-endian order.
+pub(crate) fn murmur3_x64_128(bytes: &[u8], seed: u32) -> (u64, u64) {
+    let mut h1 = seed as u64;
+    let mut h2 = seed as u64;
+
+    let mut blocks = bytes.chunks_exact(16);
+    for block in &mut blocks {
+        let (lo, hi) = block.split_at(8);
+        let k1 = u64::from_le_bytes(lo.try_into().unwrap());
+        let k2 = u64::from_le_bytes(hi.try_into().unwrap());
+
+        h1 ^= mix_k1(k1);
+        h1 = h1.rotate_left(27).wrapping_add(h2);
+        h1 = h1.wrapping_mul(5).wrapping_add(0x52dce729);
+
+        h2 ^= mix_k2(k2);
+        h2 = h2.rotate_left(31).wrapping_add(h1);
+        h2 = h2.wrapping_mul(5).wrapping_add(0x38495ab5);
+    }
+
+    // The remaining bytes are read as little-endian numbers, zero padded.
+    let tail = blocks.remainder();
+    let mut k1 = 0u64;
+    let mut k2 = 0u64;
+    for (i, b) in tail.iter().enumerate() {
+        if i < 8 {
+            k1 |= (*b as u64) << (8 * i);
+        } else {
+            k2 |= (*b as u64) << (8 * (i - 8));
+        }
+    }
+    if tail.len() > 8 {
+        h2 ^= mix_k2(k2);
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(k1);
+    }
+
+    h1 ^= bytes.len() as u64;
+    h2 ^= bytes.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+fn mix_k1(k1: u64) -> u64 {
+    k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2)
+}
+
+fn mix_k2(k2: u64) -> u64 {
+    k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1)
+}
+
+/// The finalization mix, which forces all bits of a hash block to avalanche.
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51afd7ed558ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+    k ^= k >> 33;
+    k
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        let (h1, h2) = murmur3_x64_128(bytes, 0);
+        h1.to_le_bytes()
+            .iter()
+            .chain(h2.to_le_bytes().iter())
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!("00000000000000000000000000000000", hex(b""));
+        assert_eq!(
+            (0xcbd8a7b341bd9b02, 0x5b1e906a48ae1d19),
+            murmur3_x64_128(b"hello", 0)
+        );
+        assert_eq!(
+            "6c1b07bc7bbc4be347939ac4a93c437a",
+            hex(b"The quick brown fox jumps over the lazy dog")
+        );
+    }
+
+    #[test]
+    fn hash_pair() {
+        let (h1, h2) = <Murmur3Hasher as Hasher<&str>>::hash_pair(&"nyan");
+        assert_eq!(h1, <Murmur3Hasher as Hasher<&str>>::hash(&"nyan"));
+        assert_ne!(h1, h2);
+    }
+}
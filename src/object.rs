@@ -0,0 +1,158 @@
+use std::io;
+use std::sync::Arc;
+
+use object_store::buffered::{BufReader, BufWriter};
+use object_store::path::Path;
+use object_store::ObjectStore;
+use tokio::io::AsyncWriteExt;
+
+use crate::BloomFilter;
+
+/// The size of each part of the multipart uploads of filters to object storage, and of
+/// each range read when downloading them.
+pub const OBJECT_PART_SIZE: usize = 10 * 1024 * 1024;
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Uploads the bloom filter to an object in object storage, such as an S3 or GCS
+    /// bucket, in the format of `to_bytes`. Filters larger than `OBJECT_PART_SIZE` are
+    /// uploaded in parts with a multipart upload, streamed from the filter's bits without
+    /// building an intermediate copy of them. The object only becomes visible once the
+    /// upload completes. Requires the `object-store` feature, along with the features of
+    /// the `object_store` package for the chosen provider.
+    ///
+    /// ## Example
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    /// use object_store::{memory::InMemory, path::Path, ObjectStore};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// // Any store works, such as `AmazonS3Builder::from_env().build()`.
+    /// let bucket: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    /// let location = Path::from("filters/users.bloom");
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    /// bf.write_to_object_store(bucket.clone(), &location).await.unwrap();
+    ///
+    /// let restored: BloomFilter<&str> =
+    ///     BloomFilter::read_from_object_store(bucket, &location).await.unwrap();
+    /// assert!(restored.has("hello"));
+    /// # });
+    /// ```
+    pub async fn write_to_object_store(
+        &self,
+        store: Arc<dyn ObjectStore>,
+        location: &Path,
+    ) -> io::Result<()> {
+        self.write_to_object_store_in_parts(store, location, OBJECT_PART_SIZE)
+            .await
+    }
+    /// Downloads a bloom filter uploaded with `write_to_object_store`, or any object
+    /// holding the encoding of `to_bytes`, with range reads of `OBJECT_PART_SIZE` bytes
+    /// decoded as they arrive. Errors are reported as by `read_from`, and a missing
+    /// object is reported as an error of kind `NotFound`. Requires the `object-store`
+    /// feature.
+    pub async fn read_from_object_store(
+        store: Arc<dyn ObjectStore>,
+        location: &Path,
+    ) -> io::Result<BloomFilter<T>> {
+        BloomFilter::read_from_object_store_in_parts(store, location, OBJECT_PART_SIZE).await
+    }
+    async fn write_to_object_store_in_parts(
+        &self,
+        store: Arc<dyn ObjectStore>,
+        location: &Path,
+        part_size: usize,
+    ) -> io::Result<()> {
+        let mut w = BufWriter::with_capacity(store, location.clone(), part_size);
+        if let Err(err) = self.write_to_async(&mut w).await {
+            // Aborting frees the parts uploaded so far, and its own errors matter less.
+            let _ = w.abort().await;
+            return Err(err);
+        }
+        w.shutdown().await
+    }
+    async fn read_from_object_store_in_parts(
+        store: Arc<dyn ObjectStore>,
+        location: &Path,
+        part_size: usize,
+    ) -> io::Result<BloomFilter<T>> {
+        let meta = store.head(location).await?;
+        let r = BufReader::with_capacity(store, &meta, part_size);
+        BloomFilter::read_from_async(r).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::{BloomBuilder, Error};
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn multipart_roundtrip() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let location = Path::from("filters/a.bloom");
+        let mut bf: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).build();
+        for i in 0..1_000 {
+            bf.insert(format!("{}", i));
+        }
+        assert!(bf.byte_len() > 10 * 1024);
+        block_on(bf.write_to_object_store_in_parts(store.clone(), &location, 1024)).unwrap();
+        let uploaded = block_on(async { store.get(&location).await?.bytes().await }).unwrap();
+        assert_eq!(bf.to_bytes(), uploaded);
+
+        let restored: BloomFilter<String> = block_on(BloomFilter::read_from_object_store_in_parts(
+            store.clone(),
+            &location,
+            1024,
+        ))
+        .unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert!((0..1_000).all(|i| restored.has(format!("{}", i))));
+
+        // Small filters are uploaded in a single request.
+        let small: BloomFilter<&str> = BloomBuilder::new(10, 0.01).build();
+        block_on(small.write_to_object_store(store.clone(), &location)).unwrap();
+        let restored: BloomFilter<&str> =
+            block_on(BloomFilter::read_from_object_store(store, &location)).unwrap();
+        assert_eq!(small.bits, restored.bits);
+    }
+
+    #[test]
+    fn invalid_objects() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let read = |location: &str| {
+            block_on(BloomFilter::<&str>::read_from_object_store(
+                store.clone(),
+                &Path::from(location),
+            ))
+            .err()
+            .unwrap()
+        };
+        assert_eq!(io::ErrorKind::NotFound, read("missing").kind());
+
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).build();
+        let mut bytes = bf.to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        block_on(store.put(&Path::from("corrupted"), bytes.into())).unwrap();
+        let err = read("corrupted");
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(
+            Error::Corrupted,
+            *err.into_inner().unwrap().downcast::<Error>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use rayon::iter::{
+    FromParallelIterator, IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    ParallelExtend, ParallelIterator,
+};
+use rayon::slice::ParallelSliceMut;
+
+use crate::words::AlignedWords;
+use crate::{or_into, BloomBuilder, BloomFilter, Error};
+
+/// The number of words of the merged filter each task of `union_many_par` ORs every
+/// filter into, small enough to stay in a core's cache while all filters are read.
+const UNION_CHUNK_WORDS: usize = 4096;
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Creates a new bloom filter containing the elements of every given filter, as
+    /// `union_many` does, using every thread of the rayon pool. The bits of the merged
+    /// filter are split into ranges, and each thread ORs the same range of every filter
+    /// into its own range, so that the work scales with the number of threads even for
+    /// a few very large filters. All filters must have been built with identical
+    /// parameters and hashers, and at least one filter must be given, otherwise an error
+    /// is returned. Requires the `rayon` feature.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut partitions = vec![];
+    /// for i in 0..100 {
+    ///     let mut bf = BloomBuilder::new(1000, 0.01).seed(42).build();
+    ///     bf.insert(format!("{}", i));
+    ///     partitions.push(bf);
+    /// }
+    ///
+    /// let merged = BloomFilter::union_many_par(&partitions).unwrap();
+    /// assert!(merged.has(format!("{}", 7)));
+    /// ```
+    pub fn union_many_par<'a, I>(filters: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Self>,
+        T: 'a,
+    {
+        let filters: Vec<&Self> = filters.into_iter().collect();
+        let first = filters.first().ok_or(Error::NoFilters)?;
+        for other in filters.iter() {
+            first.check_compatible(other)?;
+        }
+        let mut bits = AlignedWords::zeroed(first.bits.len());
+        bits.par_chunks_mut(UNION_CHUNK_WORDS)
+            .enumerate()
+            .for_each(|(i, chunk)| {
+                let start = i * UNION_CHUNK_WORDS;
+                for other in filters.iter() {
+                    or_into(chunk, &other.bits[start..]);
+                }
+            });
+        let num_inserted = filters
+            .iter()
+            .fold(0, |n: usize, bf| n.saturating_add(bf.num_inserted));
+        Ok(first.with_bits(bits, num_inserted))
+    }
+    /// Decodes filters encoded with `to_bytes` and merges them into a new bloom filter,
+    /// as `union_many_par` does, decoding the filters in parallel on every thread of
+    /// the rayon pool. Fails with the first error decoding any of them, or if they were
+    /// not built with identical parameters and hashers. Requires the `rayon` feature.
+    pub fn union_many_bytes_par<B: AsRef<[u8]> + Sync>(encoded: &[B]) -> Result<Self, Error> {
+        let filters = encoded
+            .par_iter()
+            .map(|bytes| BloomFilter::from_bytes(bytes.as_ref()))
+            .collect::<Result<Vec<_>, Error>>()?;
+        BloomFilter::union_many_par(&filters)
+    }
+}
+
+/// Builds a bloom filter from a parallel iterator using every thread of the rayon pool,
+/// inserting elements as by `ParallelExtend` without holding on to them. Iterators of a
+/// known length, such as those of ranges and collections, size the filter for exactly
+/// their elements, while others, such as those using `filter`, are first collected to
+/// count their elements. Requires the `rayon` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::BloomFilter;
+/// use rayon::prelude::*;
+///
+/// let bf: BloomFilter<String> = (0..10_000).into_par_iter().map(|i| i.to_string()).collect();
+/// assert!(bf.has("42".to_string()));
+/// ```
+impl<T: AsRef<[u8]> + Send> FromParallelIterator<T> for BloomFilter<T> {
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(par_iter: I) -> Self {
+        let par_iter = par_iter.into_par_iter();
+        match par_iter.opt_len() {
+            Some(len) => from_par_iter_sized(len, par_iter),
+            None => {
+                let items: Vec<T> = par_iter.collect();
+                from_par_iter_sized(items.len(), items)
+            }
+        }
+    }
+}
+
+/// Inserts the elements of a parallel iterator yielding a number of elements into a
+/// bloom filter sized for them.
+fn from_par_iter_sized<T: AsRef<[u8]> + Send, I: IntoParallelIterator<Item = T>>(
+    len: usize,
+    par_iter: I,
+) -> BloomFilter<T> {
+    let capacity = (len as u64).saturating_add(100);
+    let mut bloom_filter = BloomBuilder::<T>::new(capacity, 0.03).build();
+    bloom_filter.par_extend(par_iter);
+    bloom_filter
+}
+
+/// Inserts the elements of a parallel iterator into a bloom filter using every thread
+/// of the rayon pool. Each thread inserts its share of the elements into its own empty
+/// copy of the filter, and the copies are then merged into the filter by OR, so this
+/// pays off for large numbers of elements. Requires the `rayon` feature.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter};
+/// use rayon::prelude::*;
+///
+/// let mut bf: BloomFilter<String> = BloomBuilder::new(100_000, 0.01).build();
+/// bf.par_extend((0..100_000).into_par_iter().map(|i| i.to_string()));
+/// assert!(bf.has("42".to_string()));
+/// ```
+impl<T: AsRef<[u8]> + Send> ParallelExtend<T> for BloomFilter<T> {
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, par_iter: I) {
+        let empty = || self.with_bits(AlignedWords::zeroed(self.bits.len()), 0);
+        let partial = par_iter
+            .into_par_iter()
+            .fold(empty, |mut bf, elem| {
+                bf.insert(elem);
+                bf
+            })
+            .reduce(empty, |mut a, b| {
+                a |= &b;
+                a
+            });
+        *self |= &partial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn matches_sequential() {
+        let builder = || BloomBuilder::new(100_000, 0.01).seed(7);
+        let mut sequential: BloomFilter<String> = builder().build();
+        let mut parallel: BloomFilter<String> = builder().build();
+        for i in 0..50_000 {
+            sequential.insert(i.to_string());
+        }
+        parallel.insert("0".to_string());
+        parallel.par_extend((0..50_000).into_par_iter().map(|i| i.to_string()));
+        assert_eq!(sequential.bits, parallel.bits);
+        assert_eq!(50_001, parallel.len());
+    }
+
+    #[test]
+    fn union_many_par() {
+        let builder = || BloomBuilder::new(100_000, 0.01).seed(7);
+        let filters: Vec<BloomFilter<String>> = (0..50)
+            .map(|i| {
+                let mut bf = builder().build();
+                bf.insert(i.to_string());
+                bf
+            })
+            .collect();
+        let sequential = BloomFilter::union_many(&filters).unwrap();
+        let parallel = BloomFilter::union_many_par(&filters).unwrap();
+        assert!(parallel.bits.len() > UNION_CHUNK_WORDS);
+        assert_eq!(sequential.bits, parallel.bits);
+        assert_eq!(50, parallel.len());
+
+        let encoded: Vec<Vec<u8>> = filters.iter().map(|bf| bf.to_bytes()).collect();
+        let decoded = BloomFilter::<String>::union_many_bytes_par(&encoded).unwrap();
+        assert_eq!(sequential.bits, decoded.bits);
+
+        let empty: Vec<BloomFilter<String>> = vec![];
+        assert_eq!(
+            Some(Error::NoFilters),
+            BloomFilter::union_many_par(&empty).err()
+        );
+        let mut mixed = filters;
+        mixed[0].num_inserted = usize::MAX;
+        assert_eq!(
+            usize::MAX,
+            BloomFilter::union_many_par(&mixed).unwrap().len()
+        );
+        mixed.push(BloomBuilder::new(200, 0.01).seed(7).build());
+        assert!(matches!(
+            BloomFilter::union_many_par(&mixed),
+            Err(Error::IncompatibleParams { .. })
+        ));
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            BloomFilter::<String>::union_many_bytes_par(&[vec![1, 2, 3]]).err()
+        );
+    }
+
+    #[test]
+    fn collect() {
+        let bf: BloomFilter<String> = (0..10_000).into_par_iter().map(|i| i.to_string()).collect();
+        assert_eq!(10_000, bf.len());
+        assert!((0..10_000).all(|i| bf.has(i.to_string())));
+        assert_eq!(10_100, bf.params().capacity);
+
+        let bf: BloomFilter<String> = (0..10_000)
+            .into_par_iter()
+            .filter(|i| i % 2 == 0)
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(5_000, bf.len());
+        assert!((0..10_000).step_by(2).all(|i| bf.has(i.to_string())));
+        assert_eq!(5_100, bf.params().capacity);
+    }
+}
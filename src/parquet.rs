@@ -0,0 +1,545 @@
+use xxhash_rust::xxh64::xxh64;
+
+use crate::Error;
+
+/// The salts each word of a block multiplies the low half of a hash by.
+const SALT: [u32; 8] = [
+    0x47b6137b, 0x44974d91, 0x8824ad5b, 0xa2b7289d, 0x705495c7, 0x2df1424b, 0x9efc4947, 0x5c6bfb31,
+];
+/// The number of bytes of a block of eight 32-bit words.
+const BLOCK_BYTES: usize = 32;
+/// The smallest and largest number of bytes of bits writers produce.
+const MIN_BYTES: usize = BLOCK_BYTES;
+const MAX_BYTES: usize = 128 * 1024 * 1024;
+
+/// The Thrift compact protocol types found in a bloom filter header.
+const THRIFT_I32: u8 = 5;
+const THRIFT_STRUCT: u8 = 12;
+/// The deepest nesting of structs and containers skipped in a header, far beyond any
+/// field a writer could add, to bound the recursion of malformed input.
+const THRIFT_MAX_DEPTH: usize = 64;
+
+/// A split block bloom filter as stored in Parquet files, so that filters can be read
+/// from the column chunks of existing files and produced for Parquet writers.
+///
+/// The filter is an array of 256-bit blocks, each made of eight 32-bit words. An item
+/// is hashed with xxHash64 with a seed of zero, and the high half of its hash selects
+/// a block, in which the low half sets one bit of every word, chosen by multiplying it
+/// with a per-word salt. Parquet hashes values in their plain encoding, which for byte
+/// arrays and strings is their bytes, and for numbers is their little-endian bytes.
+/// Requires the `xxhash` feature, and with the `simd` feature, blocks are probed and
+/// set with AVX2 or NEON instructions when the processor supports them.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::SplitBlockBloomFilter;
+///
+/// let mut bf = SplitBlockBloomFilter::new(1000, 0.01);
+/// bf.insert("hello");
+/// bf.insert(42i64.to_le_bytes());
+///
+/// // The bytes found at a column chunk's bloom_filter_offset.
+/// let bytes = bf.to_bytes();
+/// let restored = SplitBlockBloomFilter::from_bytes(&bytes).unwrap();
+/// assert!(restored.has("hello"));
+/// assert!(restored.has(42i64.to_le_bytes()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitBlockBloomFilter {
+    blocks: Vec<Block>,
+}
+
+/// A block of the filter, aligned to its size so that no block straddles two 64-byte
+/// cache lines, and probing one touches a single line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C, align(32))]
+struct Block([u32; 8]);
+
+impl SplitBlockBloomFilter {
+    /// Creates a new filter for a number of distinct values and false positive rate,
+    /// sized as Parquet writers size it: rounded up to a power of two number of bytes,
+    /// between 32 bytes and 128 MiB. Panics if the false positive rate is not between
+    /// 0 and 1.
+    pub fn new(ndv: u64, fpp: f64) -> SplitBlockBloomFilter {
+        assert!(
+            fpp > 0.0 && fpp < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+        let num_bits = -8.0 * ndv as f64 / (1.0 - fpp.powf(1.0 / 8.0)).ln();
+        let num_bytes = ((num_bits / 8.0) as usize)
+            .min(MAX_BYTES)
+            .next_power_of_two()
+            .max(MIN_BYTES);
+        SplitBlockBloomFilter::with_num_bytes(num_bytes)
+    }
+    /// Creates a new filter with a given number of bytes of bits, rounded up to a whole
+    /// number of 32-byte blocks.
+    pub fn with_num_bytes(num_bytes: usize) -> SplitBlockBloomFilter {
+        SplitBlockBloomFilter {
+            blocks: vec![Block::default(); num_bytes.div_ceil(BLOCK_BYTES).max(1)],
+        }
+    }
+    /// Inserts the plain encoding of a value into the filter.
+    pub fn insert<T: AsRef<[u8]>>(&mut self, item: T) {
+        self.insert_hash(xxh64(item.as_ref(), 0));
+    }
+    /// Checks if the filter contains the plain encoding of a value. Like any bloom
+    /// filter, it can produce false positives, but will never produce false negatives.
+    pub fn has<T: AsRef<[u8]>>(&self, item: T) -> bool {
+        self.has_hash(xxh64(item.as_ref(), 0))
+    }
+    /// Inserts a value's xxHash64 hash into the filter, for writers that already hash
+    /// values to compute column statistics.
+    pub fn insert_hash(&mut self, hash: u64) {
+        let idx = self.block_index(hash);
+        block_insert(&mut self.blocks[idx].0, hash as u32);
+    }
+    /// Checks if the filter contains a value's xxHash64 hash.
+    pub fn has_hash(&self, hash: u64) -> bool {
+        block_check(&self.blocks[self.block_index(hash)].0, hash as u32)
+    }
+    /// Returns the number of bytes of bits of the filter.
+    pub fn num_bytes(&self) -> usize {
+        self.blocks.len() * BLOCK_BYTES
+    }
+    /// Encodes the filter as stored in a Parquet file: a `BloomFilterHeader` in the
+    /// Thrift compact protocol, naming the split block algorithm, xxHash64 and no
+    /// compression, followed by the bits as little-endian 32-bit words.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(20 + self.num_bytes());
+        // Field 1, numBytes, as a zigzag varint.
+        out.push(1 << 4 | THRIFT_I32);
+        let num_bytes = self.num_bytes() as i32;
+        let mut n = ((num_bytes << 1) ^ (num_bytes >> 31)) as u32;
+        while n >= 0x80 {
+            out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        out.push(n as u8);
+        // Fields 2 to 4, the algorithm, hash and compression, each a union whose
+        // first variant, an empty struct, is set.
+        for _ in 0..3 {
+            out.extend_from_slice(&[1 << 4 | THRIFT_STRUCT, 1 << 4 | THRIFT_STRUCT, 0, 0]);
+        }
+        out.push(0);
+        for block in self.blocks.iter() {
+            for word in block.0 {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        out
+    }
+    /// Decodes a filter as stored in a Parquet file, from its header up to the end of
+    /// its bits. Fails with `Error::UnknownHasher` if the filter does not hash values
+    /// with xxHash64, and with `Error::InvalidEncoding` if it does not use the split
+    /// block algorithm without compression, or if the input is malformed or has
+    /// trailing bytes, as Parquet defines no other variants.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SplitBlockBloomFilter, Error> {
+        let mut reader = ThriftReader { bytes, pos: 0 };
+        let mut num_bytes = None;
+        let mut last_id = 0;
+        while let Some((id, ty)) = reader.field_header(&mut last_id)? {
+            match (id, ty) {
+                (1, THRIFT_I32) => num_bytes = Some(reader.i32()?),
+                (2..=4, THRIFT_STRUCT) => {
+                    // Unions whose first variant is the only one defined by Parquet.
+                    let mut variant_id = 0;
+                    match reader.field_header(&mut variant_id)? {
+                        Some((1, THRIFT_STRUCT)) => reader.skip(THRIFT_STRUCT, 0)?,
+                        Some((variant, _)) if id == 3 => {
+                            return Err(Error::UnknownHasher(variant as u64))
+                        }
+                        _ => return Err(Error::InvalidEncoding),
+                    }
+                    if reader.field_header(&mut variant_id)?.is_some() {
+                        return Err(Error::InvalidEncoding);
+                    }
+                }
+                (_, ty) => reader.skip(ty, 0)?,
+            }
+        }
+        let num_bytes = num_bytes.ok_or(Error::InvalidEncoding)?;
+        let num_bytes = usize::try_from(num_bytes).map_err(|_| Error::InvalidEncoding)?;
+        let bits = &bytes[reader.pos..];
+        if num_bytes == 0 || !num_bytes.is_multiple_of(BLOCK_BYTES) || bits.len() != num_bytes {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(SplitBlockBloomFilter {
+            blocks: bits
+                .chunks_exact(BLOCK_BYTES)
+                .map(|block| {
+                    let mut words = [0; 8];
+                    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+                        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+                    }
+                    Block(words)
+                })
+                .collect(),
+        })
+    }
+    fn block_index(&self, hash: u64) -> usize {
+        (((hash >> 32) * self.blocks.len() as u64) >> 32) as usize
+    }
+}
+
+/// Returns the bit set in each word of a block for the low half of a hash.
+fn block_mask(x: u32) -> [u32; 8] {
+    let mut mask = [0; 8];
+    for (m, salt) in mask.iter_mut().zip(SALT) {
+        *m = 1 << (x.wrapping_mul(salt) >> 27);
+    }
+    mask
+}
+
+/// Sets the bits of the low half of a hash in a block. With the `simd` feature, a
+/// whole block is updated at once with AVX2 on x86_64 and NEON on aarch64, as in
+/// Impala's and Arrow's implementations, when the processor supports them.
+fn block_insert(block: &mut [u32; 8], x: u32) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is supported by the processor.
+        return unsafe { avx2::block_insert(block, x) };
+    }
+    #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON is supported by the processor.
+        return unsafe { neon::block_insert(block, x) };
+    }
+    block_insert_scalar(block, x)
+}
+
+/// Checks whether the bits of the low half of a hash are set in a block, with the
+/// same instructions as `block_insert`.
+fn block_check(block: &[u32; 8], x: u32) -> bool {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is supported by the processor.
+        return unsafe { avx2::block_check(block, x) };
+    }
+    #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON is supported by the processor.
+        return unsafe { neon::block_check(block, x) };
+    }
+    block_check_scalar(block, x)
+}
+
+fn block_insert_scalar(block: &mut [u32; 8], x: u32) {
+    for (word, mask) in block.iter_mut().zip(block_mask(x)) {
+        *word |= mask;
+    }
+}
+
+fn block_check_scalar(block: &[u32; 8], x: u32) -> bool {
+    block
+        .iter()
+        .zip(block_mask(x))
+        .all(|(word, mask)| word & mask != 0)
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::SALT;
+
+    /// Computes the mask of a block for the low half of a hash, one bit per word.
+    #[target_feature(enable = "avx2")]
+    unsafe fn block_mask(x: u32) -> __m256i {
+        let salt = _mm256_loadu_si256(SALT.as_ptr() as *const __m256i);
+        let shift = _mm256_srli_epi32::<27>(_mm256_mullo_epi32(_mm256_set1_epi32(x as i32), salt));
+        _mm256_sllv_epi32(_mm256_set1_epi32(1), shift)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn block_insert(block: &mut [u32; 8], x: u32) {
+        let ptr = block.as_mut_ptr() as *mut __m256i;
+        _mm256_storeu_si256(ptr, _mm256_or_si256(_mm256_loadu_si256(ptr), block_mask(x)));
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn block_check(block: &[u32; 8], x: u32) -> bool {
+        let words = _mm256_loadu_si256(block.as_ptr() as *const __m256i);
+        // Every bit of the mask is set in the block's words.
+        _mm256_testc_si256(words, block_mask(x)) != 0
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::SALT;
+
+    /// Computes the mask of each half of a block for the low half of a hash, one bit
+    /// per word.
+    #[target_feature(enable = "neon")]
+    unsafe fn block_mask(x: u32) -> [uint32x4_t; 2] {
+        [
+            half_mask(x, SALT.as_ptr()),
+            half_mask(x, SALT.as_ptr().add(4)),
+        ]
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn half_mask(x: u32, salt: *const u32) -> uint32x4_t {
+        let shift = vshrq_n_u32::<27>(vmulq_u32(vdupq_n_u32(x), vld1q_u32(salt)));
+        vshlq_u32(vdupq_n_u32(1), vreinterpretq_s32_u32(shift))
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn block_insert(block: &mut [u32; 8], x: u32) {
+        let ptr = block.as_mut_ptr();
+        let [lo, hi] = block_mask(x);
+        vst1q_u32(ptr, vorrq_u32(vld1q_u32(ptr), lo));
+        vst1q_u32(ptr.add(4), vorrq_u32(vld1q_u32(ptr.add(4)), hi));
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn block_check(block: &[u32; 8], x: u32) -> bool {
+        let ptr = block.as_ptr();
+        let [lo, hi] = block_mask(x);
+        // Lanes are all ones where the word's bit is set, so their minimum is zero
+        // unless every word has its bit set.
+        let set = vandq_u32(
+            vtstq_u32(vld1q_u32(ptr), lo),
+            vtstq_u32(vld1q_u32(ptr.add(4)), hi),
+        );
+        vminvq_u32(set) != 0
+    }
+}
+
+/// Reads the few Thrift compact protocol values found in a bloom filter header.
+struct ThriftReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ThriftReader<'_> {
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.bytes.get(self.pos).ok_or(Error::InvalidEncoding)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidEncoding)
+    }
+    /// Reads a zigzag encoded 32-bit integer, rejecting values out of its range.
+    fn i32(&mut self) -> Result<i32, Error> {
+        let n = self.varint()?;
+        let n = ((n >> 1) as i64) ^ -((n & 1) as i64);
+        i32::try_from(n).map_err(|_| Error::InvalidEncoding)
+    }
+    /// Reads the header of the next field of a struct, returning its id and type,
+    /// or None at the end of the struct.
+    fn field_header(&mut self, last_id: &mut i16) -> Result<Option<(i16, u8)>, Error> {
+        let byte = self.byte()?;
+        if byte == 0 {
+            return Ok(None);
+        }
+        let delta = (byte >> 4) as i16;
+        *last_id = if delta == 0 {
+            i16::try_from(self.i32()?).map_err(|_| Error::InvalidEncoding)?
+        } else {
+            last_id.checked_add(delta).ok_or(Error::InvalidEncoding)?
+        };
+        Ok(Some((*last_id, byte & 0x0f)))
+    }
+    /// Skips a value of a given type, nested within a number of structs and containers.
+    fn skip(&mut self, ty: u8, depth: usize) -> Result<(), Error> {
+        if depth > THRIFT_MAX_DEPTH {
+            return Err(Error::InvalidEncoding);
+        }
+        match ty {
+            // Booleans are held in the type of their field.
+            1 | 2 => Ok(()),
+            3 => self.byte().map(|_| ()),
+            4..=6 => self.varint().map(|_| ()),
+            7 => self.advance(8),
+            8 => {
+                let len = self.len()?;
+                self.advance(len)
+            }
+            9 | 10 => {
+                let header = self.byte()?;
+                let len = match header >> 4 {
+                    15 => self.len()?,
+                    len => len as usize,
+                };
+                (0..len).try_for_each(|_| self.skip_elem(header & 0x0f, depth + 1))
+            }
+            11 => {
+                let len = self.len()?;
+                if len == 0 {
+                    return Ok(());
+                }
+                let types = self.byte()?;
+                (0..len).try_for_each(|_| {
+                    self.skip_elem(types >> 4, depth + 1)?;
+                    self.skip_elem(types & 0x0f, depth + 1)
+                })
+            }
+            THRIFT_STRUCT => {
+                let mut last_id = 0;
+                while let Some((_, ty)) = self.field_header(&mut last_id)? {
+                    self.skip(ty, depth + 1)?;
+                }
+                Ok(())
+            }
+            _ => Err(Error::InvalidEncoding),
+        }
+    }
+    /// Skips an element of a container, where booleans are held in a byte each rather
+    /// than in the type of a field.
+    fn skip_elem(&mut self, ty: u8, depth: usize) -> Result<(), Error> {
+        match ty {
+            1 | 2 => self.advance(1),
+            ty => self.skip(ty, depth),
+        }
+    }
+    /// Reads the number of elements of a container, each of which takes at least a
+    /// byte, rejecting lengths longer than the rest of the input.
+    fn len(&mut self) -> Result<usize, Error> {
+        let len = self.varint()?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(len as usize)
+    }
+    fn advance(&mut self, len: usize) -> Result<(), Error> {
+        if self.bytes.len() - self.pos < len {
+            return Err(Error::InvalidEncoding);
+        }
+        self.pos += len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        // Writers size 1000 distinct values at 1% to about 1210 bytes, rounded up to 2048.
+        assert_eq!(2048, SplitBlockBloomFilter::new(1000, 0.01).num_bytes());
+        assert_eq!(32, SplitBlockBloomFilter::new(0, 0.01).num_bytes());
+        assert_eq!(64, SplitBlockBloomFilter::with_num_bytes(33).num_bytes());
+    }
+
+    #[test]
+    fn block_bits() {
+        // Every word of the selected block has exactly one bit set.
+        let mut bf = SplitBlockBloomFilter::with_num_bytes(256);
+        let hash = xxh64(b"nyan", 0);
+        bf.insert("nyan");
+        let idx = (((hash >> 32) * 8) >> 32) as usize;
+        for (i, block) in bf.blocks.iter().enumerate() {
+            assert!((block as *const Block as usize).is_multiple_of(32));
+            for (word, salt) in block.0.iter().zip(SALT) {
+                if i == idx {
+                    assert_eq!(1 << ((hash as u32).wrapping_mul(salt) >> 27), *word);
+                } else {
+                    assert_eq!(0, *word);
+                }
+            }
+        }
+        assert!(bf.has_hash(hash));
+    }
+
+    #[test]
+    fn kernels_match_scalar() {
+        let mut block = [0; 8];
+        let mut scalar = [0; 8];
+        for i in 0..1_000u64 {
+            let x = xxh64(&i.to_le_bytes(), 0) as u32;
+            // Checks agree on blocks filling up with bits, before and after inserting.
+            assert_eq!(block_check_scalar(&scalar, x), block_check(&block, x));
+            block_insert(&mut block, x);
+            block_insert_scalar(&mut scalar, x);
+            assert_eq!(scalar, block);
+            assert!(block_check(&block, x));
+            if i == 2 {
+                assert!(!block_check(&block, 0x1234_5678));
+            }
+        }
+    }
+
+    #[test]
+    fn header_layout() {
+        let bf = SplitBlockBloomFilter::with_num_bytes(64);
+        let bytes = bf.to_bytes();
+        let header = [
+            0x15, 0x80, 0x01, 0x1c, 0x1c, 0, 0, 0x1c, 0x1c, 0, 0, 0x1c, 0x1c, 0, 0, 0,
+        ];
+        assert_eq!(&header[..], &bytes[..16]);
+        assert_eq!(16 + 64, bytes.len());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut bf = SplitBlockBloomFilter::new(1000, 0.01);
+        for i in 0..1000i64 {
+            bf.insert(i.to_le_bytes());
+        }
+        let restored = SplitBlockBloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+        assert_eq!(bf, restored);
+        for i in 0..1000i64 {
+            assert!(restored.has(i.to_le_bytes()));
+        }
+        let false_positives = (1000..11_000i64)
+            .filter(|i| restored.has(i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 200, "{}", false_positives);
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = SplitBlockBloomFilter::with_num_bytes(32).to_bytes();
+        let decode = |bytes: &[u8]| SplitBlockBloomFilter::from_bytes(bytes).err();
+        assert_eq!(Some(Error::InvalidEncoding), decode(&bytes[..10]));
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            decode(&bytes[..bytes.len() - 1])
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(Some(Error::InvalidEncoding), decode(&trailing));
+        // A hash other than xxHash64, which Parquet does not define.
+        let mut unknown = bytes.clone();
+        unknown[7] = 0x2c;
+        assert_eq!(Some(Error::UnknownHasher(2)), decode(&unknown));
+        let mut compressed = bytes.clone();
+        compressed[11] = 0x2c;
+        assert_eq!(Some(Error::InvalidEncoding), decode(&compressed));
+
+        // Fields written by newer writers are skipped.
+        let mut extended = bytes[..14].to_vec();
+        extended.extend_from_slice(&[0x18, 2, b'o', b'k', 0]);
+        extended.extend_from_slice(&bytes[15..]);
+        assert!(SplitBlockBloomFilter::from_bytes(&extended).is_ok());
+
+        // Field ids overflowing, values out of the range of their type, and deeply
+        // nested or oversized values to skip.
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            decode(&[0x05, 0xfe, 0xff, 0x03, 0x00, 0x15, 0x00, 0x00])
+        );
+        let mut wide = vec![0x15, 0x80, 0x80, 0x80, 0x80, 0x10];
+        wide.extend_from_slice(&bytes[bytes.len() - 32..]);
+        assert_eq!(Some(Error::InvalidEncoding), decode(&wide));
+        let mut nested = vec![0x5c];
+        nested.extend(std::iter::repeat_n(0x1c, 100_000));
+        assert_eq!(Some(Error::InvalidEncoding), decode(&nested));
+        let bools = [0x59, 0xf1, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        assert_eq!(Some(Error::InvalidEncoding), decode(&bools));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::BloomFilter;
+
+/// The number of keys whose words are prefetched together. Large enough to keep many
+/// loads in flight, and small enough for their words to stay in cache until resolved.
+const WINDOW: usize = 32;
+
+/// Queries a bloom filter in two phases, to hide memory latency when checking many
+/// keys against a filter much larger than the processor's caches. For a window of
+/// keys at a time, the bit indices of every key are computed and the words holding
+/// them prefetched, and membership of the previous window is resolved while those
+/// words are loading, so that lookups no longer wait on one cache miss after another.
+///
+/// The pipeline keeps its buffers between queries, so a single pipeline can be reused
+/// for many batches without allocating.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter, Pipeline};
+///
+/// let mut bf: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+/// bf.insert("foo");
+/// bf.insert("bar");
+///
+/// let mut pipeline = Pipeline::new(&bf);
+/// assert_eq!(vec![true, false, true], pipeline.query(&["foo", "nyan", "bar"]));
+/// ```
+pub struct Pipeline<'a, T: AsRef<[u8]>> {
+    filter: &'a BloomFilter<T>,
+    /// The bit indices of the window being resolved, and of the window being fetched.
+    current: Vec<u64>,
+    next: Vec<u64>,
+}
+
+impl<'a, T: AsRef<[u8]>> Pipeline<'a, T> {
+    /// Creates a pipeline querying a bloom filter.
+    pub fn new(filter: &'a BloomFilter<T>) -> Pipeline<'a, T> {
+        Pipeline {
+            filter,
+            current: vec![],
+            next: vec![],
+        }
+    }
+    /// Checks if the bloom filter contains each of a batch of keys, in order, as `has`
+    /// would for each of them.
+    pub fn query(&mut self, keys: &[T]) -> Vec<bool> {
+        let mut found = Vec::with_capacity(keys.len());
+        let mut windows = keys.chunks(WINDOW);
+        let Some(first) = windows.next() else {
+            return found;
+        };
+        fetch(self.filter, first, &mut self.current);
+        let mut current_len = first.len();
+        for window in windows {
+            fetch(self.filter, window, &mut self.next);
+            self.resolve(current_len, &mut found);
+            std::mem::swap(&mut self.current, &mut self.next);
+            current_len = window.len();
+        }
+        self.resolve(current_len, &mut found);
+        found
+    }
+    /// Checks the bits of the window being resolved, which holds a number of keys.
+    fn resolve(&self, num_keys: usize, found: &mut Vec<bool>) {
+        let k = self.filter.num_hash_fns as usize;
+        found.extend((0..num_keys).map(|i| {
+            self.current[i * k..(i + 1) * k]
+                .iter()
+                .all(|idx| self.filter.get_bit(*idx))
+        }));
+    }
+}
+
+/// Computes the bit indices of a window of keys into a buffer, and prefetches the
+/// words holding them.
+fn fetch<T: AsRef<[u8]>>(filter: &BloomFilter<T>, keys: &[T], probes: &mut Vec<u64>) {
+    probes.clear();
+    let m = filter.bit_len() as u64;
+    for key in keys {
+        let (hasher, probe_hashers) = (&*filter.hasher, &filter.probe_hashers);
+        probes.extend(crate::probes(
+            hasher,
+            probe_hashers,
+            key,
+            filter.num_hash_fns,
+            m,
+        ));
+    }
+    // Indices reduced modulo the bit length always fall within the filter's words.
+    let words = filter.as_words();
+    for idx in probes.iter() {
+        prefetch(&words[(idx / 64) as usize]);
+    }
+}
+
+/// Hints the processor to start loading the cache line holding a word, without
+/// waiting for it. Does nothing on other architectures.
+#[inline(always)]
+fn prefetch(word: &u64) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        // SAFETY: prefetching has no observable effect besides caching, and SSE is part
+        // of the x86_64 baseline.
+        unsafe { _mm_prefetch::<_MM_HINT_T0>(word as *const u64 as *const i8) };
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        // SAFETY: prefetching has no observable effect besides caching.
+        unsafe {
+            std::arch::asm!(
+                "prfm pldl1keep, [{0}]",
+                in(reg) word as *const u64,
+                options(nostack, readonly, preserves_flags)
+            )
+        };
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let _ = word;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BloomBuilder, HasherInstance, KeyedHasher};
+
+    use super::*;
+
+    #[test]
+    fn matches_has() {
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.05).seed(5).build();
+        for i in 0..1_000 {
+            bf.insert(format!("{}", i));
+        }
+        let mut pipeline = Pipeline::new(&bf);
+        assert!(pipeline.query(&[]).is_empty());
+        // Batches spanning several windows, the last of which is not full.
+        for batch in [1, WINDOW, WINDOW * 3 + 7] {
+            let keys: Vec<String> = (990..990 + batch).map(|i| format!("{}", i)).collect();
+            let expected: Vec<bool> = keys.iter().map(|key| bf.has(key.clone())).collect();
+            assert_eq!(expected, pipeline.query(&keys));
+        }
+
+        let hashers: Vec<Box<dyn HasherInstance<String>>> = (0..3)
+            .map(|seed| Box::new(KeyedHasher::new(seed)) as Box<dyn HasherInstance<String>>)
+            .collect();
+        let mut bf: BloomFilter<String> = BloomBuilder::new(1_000, 0.05).hashers(hashers).build();
+        bf.insert("foo".to_string());
+        assert_eq!(
+            vec![true, false],
+            Pipeline::new(&bf).query(&["foo".to_string(), "bar".to_string()])
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+
+use ark_bn254::Fr;
+use ark_ff::{PrimeField, Zero};
+use light_poseidon::{Poseidon, PoseidonHasher as _};
+
+use crate::hasher::POSEIDON_HASHER_ID;
+use crate::Hasher;
+
+/// The number of bytes packed into each field element, which keeps every
+/// element below the BN254 scalar field modulus.
+const CHUNK_BYTES: usize = 31;
+
+thread_local! {
+    // Building the round constants is much more expensive than a single permutation,
+    // so each thread keeps its own instance around.
+    static POSEIDON: RefCell<Poseidon<Fr>> =
+        RefCell::new(Poseidon::<Fr>::new_circom(2).expect("2 is a valid number of inputs"));
+}
+
+/// A hasher using the Poseidon hash function over the BN254 scalar field, with the
+/// circom-compatible parameters, so that membership of an item in a bloom filter can
+/// be proven inside a zk circuit. Requires the `poseidon` feature.
+///
+/// ## Circuit layout
+///
+/// Everything a circuit needs to recompute the bits an item maps to is fixed:
+///
+/// 1. The item's bytes are split into chunks of 31 bytes, with a shorter last chunk,
+///    and each chunk is read as a big-endian integer to form a field element `c_j`.
+/// 2. The chunks are absorbed by chaining 2-input Poseidon permutations, starting
+///    from zero: `s_0 = 0` and `s_{j+1} = Poseidon(s_j, c_j)`. The item's length in
+///    bytes is absorbed last, giving `h = Poseidon(s_n, len)`.
+/// 3. The two base hashes are the lowest 64 bits of `h` for `h1` and the next
+///    64 bits for `h2`.
+/// 4. Probe `i` of a filter with `m` bits is `(h1 + i * (h2 | 1)) mod m`, computed
+///    with wrapping 64-bit arithmetic before the reduction.
+/// 5. Bit `idx` of the filter is bit `idx % 8` of byte `idx / 8`, counting from the
+///    least significant bit.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, PoseidonHasher};
+///
+/// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01)
+///     .hasher::<PoseidonHasher>()
+///     .build();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// ```
+pub struct PoseidonHasher {}
+
+impl<T: AsRef<[u8]>> Hasher<T> for PoseidonHasher {
+    fn hash(item: &T) -> u64 {
+        <PoseidonHasher as Hasher<T>>::hash_pair(item).0
+    }
+    fn id() -> u64 {
+        POSEIDON_HASHER_ID
+    }
+    fn is_stable() -> bool {
+        true
+    }
+    fn hash_pair(item: &T) -> (u64, u64) {
+        let limbs = poseidon_hash(item.as_ref()).into_bigint().0;
+        (limbs[0], limbs[1])
+    }
+    fn hash_pair_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        Some(<PoseidonHasher as Hasher<&[u8]>>::hash_pair(&bytes))
+    }
+}
+
+/// Hashes bytes into a field element by chaining 2-input Poseidon permutations
+/// over 31-byte chunks, followed by the length of the input.
+fn poseidon_hash(bytes: &[u8]) -> Fr {
+    POSEIDON.with(|poseidon| {
+        let mut poseidon = poseidon.borrow_mut();
+        let mut permute = |a: Fr, b: Fr| {
+            poseidon
+                .hash(&[a, b])
+                .expect("exactly 2 inputs are always given")
+        };
+        let state = bytes
+            .chunks(CHUNK_BYTES)
+            .map(Fr::from_be_bytes_mod_order)
+            .fold(Fr::zero(), &mut permute);
+        permute(state, Fr::from(bytes.len() as u64))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chained_permutations() {
+        // Inputs are absorbed chunk by chunk, so this must match a manual chain.
+        let bytes = [7u8; 40];
+        let mut poseidon = Poseidon::<Fr>::new_circom(2).unwrap();
+        let s1 = poseidon
+            .hash(&[Fr::zero(), Fr::from_be_bytes_mod_order(&bytes[..31])])
+            .unwrap();
+        let s2 = poseidon
+            .hash(&[s1, Fr::from_be_bytes_mod_order(&bytes[31..])])
+            .unwrap();
+        let expected = poseidon.hash(&[s2, Fr::from(40u64)]).unwrap();
+        assert_eq!(expected, poseidon_hash(&bytes));
+
+        // The length keeps inputs differing only in trailing zeros apart.
+        assert_ne!(poseidon_hash(&[0]), poseidon_hash(&[0, 0]));
+    }
+
+    #[test]
+    fn base_hashes() {
+        let limbs = poseidon_hash(b"nyan").into_bigint().0;
+        assert_eq!(
+            (limbs[0], limbs[1]),
+            <PoseidonHasher as Hasher<&str>>::hash_pair(&"nyan")
+        );
+    }
+}
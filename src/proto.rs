@@ -0,0 +1,165 @@
+//! Protobuf messages for exchanging bloom filters with gRPC services, matching the
+//! schema in `proto/flowerbloom.proto` of the package. The messages are plain prost
+//! types, so they can be embedded in a service's own generated messages, or encoded
+//! with `prost::Message` directly. Requires the `proto` feature.
+//!
+//! ## Example
+//! ```
+//! use flowerbloom::{proto, BloomBuilder, BloomFilter};
+//! use prost::Message;
+//!
+//! let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+//! bf.insert("hello");
+//!
+//! let bytes = proto::BloomFilter::from(&bf).encode_to_vec();
+//! let message = proto::BloomFilter::decode(&bytes[..]).unwrap();
+//! let restored: BloomFilter<&str> = message.try_into().unwrap();
+//! assert!(restored.has("hello"));
+//! ```
+use crate::Error;
+
+/// The structural parameters of a bloom filter, see `flowerbloom::FilterParams`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FilterParams {
+    #[prost(uint64, tag = "1")]
+    pub capacity: u64,
+    #[prost(uint64, tag = "2")]
+    pub bit_len: u64,
+    #[prost(uint32, tag = "3")]
+    pub num_hash_fns: u32,
+    #[prost(uint32, repeated, tag = "4")]
+    pub tier_hash_fns: Vec<u32>,
+    #[prost(uint64, tag = "5")]
+    pub hasher_id: u64,
+    #[prost(uint64, optional, tag = "6")]
+    pub seed: Option<u64>,
+}
+
+/// A bloom filter's parameters, bookkeeping and bits.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BloomFilter {
+    #[prost(message, optional, tag = "1")]
+    pub params: Option<FilterParams>,
+    #[prost(uint64, tag = "2")]
+    pub num_inserted: u64,
+    #[prost(double, optional, tag = "3")]
+    pub max_fp_rate: Option<f64>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub bits: Vec<u8>,
+}
+
+impl From<crate::FilterParams> for FilterParams {
+    fn from(params: crate::FilterParams) -> Self {
+        FilterParams {
+            capacity: params.capacity,
+            bit_len: params.bit_len,
+            num_hash_fns: params.num_hash_fns,
+            tier_hash_fns: params.tier_hash_fns,
+            hasher_id: params.hasher_id,
+            seed: params.seed,
+        }
+    }
+}
+
+impl From<FilterParams> for crate::FilterParams {
+    fn from(params: FilterParams) -> Self {
+        crate::FilterParams {
+            capacity: params.capacity,
+            bit_len: params.bit_len,
+            num_hash_fns: params.num_hash_fns,
+            tier_hash_fns: params.tier_hash_fns,
+            hasher_id: params.hasher_id,
+            seed: params.seed,
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> From<&crate::BloomFilter<T>> for BloomFilter {
+    fn from(bf: &crate::BloomFilter<T>) -> Self {
+        BloomFilter {
+            params: Some(bf.params().into()),
+            num_inserted: bf.num_inserted as u64,
+            max_fp_rate: bf.max_fp_rate,
+            bits: bf.bits_to_bytes(),
+        }
+    }
+}
+
+/// Restores a bloom filter from its message with the hasher identified by its
+/// parameters. Fails with `Error::InvalidEncoding` if the parameters are missing or do
+/// not match the bits, and with `Error::UnknownHasher` if the filter was not built with
+/// one of the package's hashers.
+impl<T: AsRef<[u8]>> TryFrom<BloomFilter> for crate::BloomFilter<T> {
+    type Error = Error;
+
+    fn try_from(message: BloomFilter) -> Result<Self, Error> {
+        let params = message.params.ok_or(Error::InvalidEncoding)?;
+        let mut bf = crate::BloomFilter::from_byte_parts(params.into(), &message.bits)?;
+        bf.num_inserted = message.num_inserted as usize;
+        bf.max_fp_rate = message.max_fp_rate;
+        Ok(bf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BloomBuilder, HmacHasher};
+    use prost::Message;
+
+    #[test]
+    fn roundtrip() {
+        let mut bf: crate::BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .seed(42)
+            .tier(0.0001)
+            .max_fp_rate(0.05)
+            .build();
+        bf.insert("foo");
+        bf.insert_with_class("bar", 0).unwrap();
+        let bytes = BloomFilter::from(&bf).encode_to_vec();
+        let restored: crate::BloomFilter<&str> =
+            BloomFilter::decode(&bytes[..]).unwrap().try_into().unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+        assert!(restored.has("foo"));
+        assert!(restored.has_with_class("bar", 0).unwrap());
+        assert!(bf.union(&restored).is_ok());
+    }
+
+    #[test]
+    fn layout() {
+        let mut bf: crate::BloomFilter<&str> = BloomBuilder::new(10, 0.01).deterministic().build();
+        bf.insert("foo");
+        // The params message holding the capacity, bit length, number of hash functions
+        // and hasher id, followed by the number of inserted elements and the bits.
+        let mut expected = vec![0x0a, 8, 0x08, 10, 0x10, 96, 0x18, 7, 0x28, 1];
+        expected.extend_from_slice(&[0x10, 1]);
+        expected.extend_from_slice(&[0x22, 12]);
+        expected.extend_from_slice(&bf.bits_to_bytes());
+        assert_eq!(expected, BloomFilter::from(&bf).encode_to_vec());
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let bf: crate::BloomFilter<&str> = BloomBuilder::new(10, 0.01).deterministic().build();
+        let mut message = BloomFilter::from(&bf);
+        message.bits.pop();
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            crate::BloomFilter::<&str>::try_from(message).err()
+        );
+        assert_eq!(
+            Some(Error::InvalidEncoding),
+            crate::BloomFilter::<&str>::try_from(BloomFilter::default()).err()
+        );
+
+        let bf: crate::BloomFilter<&str> = BloomBuilder::new(10, 0.01)
+            .hasher_instance(HmacHasher::new(b"secret"))
+            .build();
+        assert_eq!(
+            Some(Error::UnknownHasher(10)),
+            crate::BloomFilter::<&str>::try_from(BloomFilter::from(&bf)).err()
+        );
+    }
+}
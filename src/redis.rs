@@ -0,0 +1,585 @@
+use crate::{format, Error};
+
+/// The seed RedisBloom hashes items with.
+const REDIS_SEED: u64 = 0xc6a4a7935bd1e995;
+/// RedisBloom's option for sizing filters by their exact number of bits, rather than
+/// rounding them up to a power of two.
+const OPT_NOROUND: u32 = 1;
+/// RedisBloom's option for hashing items with 64-bit rather than 32-bit hashes.
+const OPT_FORCE64: u32 = 4;
+/// RedisBloom's option for filters that reject inserts once full instead of scaling.
+const OPT_NO_SCALING: u32 = 8;
+/// The ratio by which the error rate of every filter added to a chain tightens.
+const ERROR_TIGHTENING_RATIO: f64 = 0.5;
+/// The number of bytes of the header of a dump, before its per-filter headers.
+const HEADER_LEN: usize = 20;
+/// The number of bytes of the header of each filter in a dump.
+const LINK_LEN: usize = 53;
+/// The largest number of bytes of bits returned in a single chunk of a dump.
+const MAX_CHUNK_LEN: usize = 16 * 1024 * 1024;
+/// The largest number of bytes of bits a single filter of a chain can hold, which
+/// bounds the memory a malformed or hostile dump can make the process allocate.
+const MAX_LINK_LEN: u64 = 512 * 1024 * 1024;
+
+/// A scalable bloom filter reproducing RedisBloom's `BF.*` filters bit for bit, so that
+/// filters can be migrated between a Redis deployment and the process without being
+/// rebuilt from their source data.
+///
+/// Like RedisBloom, the filter is a chain of bloom filters: once the last one holds as
+/// many items as it was sized for, a new one with `expansion` times the capacity and
+/// half the error rate is appended, unless the filter was created as non-scaling.
+/// Items are hashed with MurmurHash64A, and probe i of an item sets bit
+/// `(a + i * b) mod m` of a filter of m bits, where a and b are its two base hashes.
+///
+/// Filters are exchanged through the chunks of `BF.SCANDUMP` and `BF.LOADCHUNK`: the
+/// first chunk is a header describing the chain, followed by the bits of each filter
+/// in order. Every chunk is paired with its iterator, which must be passed along with
+/// it to `BF.LOADCHUNK`, or to `load_chunk` when loading a dump taken from Redis. Only
+/// filters created with RedisBloom's default 64-bit hashing can be loaded, which is
+/// every filter created by RedisBloom 2.0 or later.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::RedisBloomFilter;
+///
+/// let mut bf = RedisBloomFilter::new(1000, 0.01).unwrap();
+/// bf.insert("hello").unwrap();
+///
+/// // Each chunk would be sent with `BF.LOADCHUNK key iter data`.
+/// let chunks = bf.dump();
+/// let restored = RedisBloomFilter::load(chunks).unwrap();
+/// assert!(restored.has("hello"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedisBloomFilter {
+    links: Vec<Link>,
+    options: u32,
+    expansion: u32,
+}
+
+/// A single filter of a chain, with the fields RedisBloom records for it.
+#[derive(Debug, Clone, PartialEq)]
+struct Link {
+    // The bits of the filter, which are only allocated up to the last byte loaded from
+    // a dump, so that a header alone cannot make the process allocate every filter it
+    // describes. Bytes past the end of the vector are unset.
+    bits: Vec<u8>,
+    num_bytes: u64,
+    num_bits: u64,
+    size: u64,
+    error: f64,
+    bpe: f64,
+    hashes: u32,
+    entries: u64,
+    n2: u8,
+}
+
+impl Link {
+    /// Sizes a filter for a number of entries exactly as RedisBloom does. Fails with
+    /// `Error::InvalidParams` if the error rate is not between 0 and 1, or the filter
+    /// would hold more than `MAX_LINK_LEN` bytes of bits.
+    fn new(entries: u64, error: f64, options: u32) -> Result<Link, Error> {
+        let bpe = -(error.ln() / (std::f64::consts::LN_2 * std::f64::consts::LN_2));
+        if entries == 0 || !bpe.is_finite() || bpe <= 0.0 {
+            return Err(Error::InvalidParams);
+        }
+        let bits_needed = entries as f64 * bpe;
+        let (num_bits, n2) = if options & OPT_NOROUND != 0 {
+            (bits_needed as u64, 0)
+        } else {
+            let n2 = bits_needed.log2().floor() as u32 + 1;
+            let num_bits = 1u64.checked_shl(n2).ok_or(Error::InvalidParams)?;
+            (num_bits, n2 as u8)
+        };
+        // Filters too small to hold a single bit are given a word, so that probes
+        // always have a bit to land in.
+        let num_bytes = num_bits.max(1).div_ceil(64) * 8;
+        if num_bytes > MAX_LINK_LEN {
+            return Err(Error::InvalidParams);
+        }
+        Ok(Link {
+            bits: vec![0; num_bytes as usize],
+            num_bytes,
+            num_bits: num_bytes * 8,
+            size: 0,
+            error,
+            bpe,
+            hashes: (std::f64::consts::LN_2 * bpe).ceil() as u32,
+            entries,
+            n2,
+        })
+    }
+    fn probe(&self, (a, b): (u64, u64), i: u32) -> u64 {
+        let x = a.wrapping_add((i as u64).wrapping_mul(b));
+        if self.n2 > 0 {
+            x & ((1u64 << self.n2) - 1)
+        } else {
+            x % self.num_bits
+        }
+    }
+    fn has(&self, hashes: (u64, u64)) -> bool {
+        (0..self.hashes).all(|i| {
+            let idx = self.probe(hashes, i);
+            self.bits
+                .get((idx / 8) as usize)
+                .is_some_and(|byte| (byte >> (idx % 8)) & 1 == 1)
+        })
+    }
+    fn insert(&mut self, hashes: (u64, u64)) {
+        self.bits.resize(self.num_bytes as usize, 0);
+        for i in 0..self.hashes {
+            let idx = self.probe(hashes, i);
+            self.bits[(idx / 8) as usize] |= 1 << (idx % 8);
+        }
+    }
+    fn write_header(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.num_bytes.to_le_bytes());
+        out.extend_from_slice(&self.num_bits.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out.extend_from_slice(&self.error.to_le_bytes());
+        out.extend_from_slice(&self.bpe.to_le_bytes());
+        out.extend_from_slice(&self.hashes.to_le_bytes());
+        out.extend_from_slice(&self.entries.to_le_bytes());
+        out.push(self.n2);
+    }
+    fn read_header(bytes: &[u8]) -> Result<Link, Error> {
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let num_bytes = u64_at(0);
+        let num_bits = u64_at(8);
+        let hashes = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+        let n2 = bytes[52];
+        let sized = num_bits > 0 && num_bytes == num_bits.div_ceil(8);
+        let rounded = n2 == 0 || (n2 < 64 && num_bits >= 1 << n2);
+        if !sized || !rounded || !format::valid_num_hash_fns(hashes) || num_bytes > MAX_LINK_LEN {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(Link {
+            bits: vec![],
+            num_bytes,
+            num_bits,
+            size: u64_at(16),
+            error: f64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            bpe: f64::from_le_bytes(bytes[32..40].try_into().unwrap()),
+            hashes,
+            entries: u64_at(44),
+            n2,
+        })
+    }
+}
+
+impl RedisBloomFilter {
+    /// Creates a new filter with a specified capacity and error rate, as created by
+    /// `BF.RESERVE key error_rate capacity`, which scales by doubling its capacity.
+    /// Fails with `Error::InvalidParams` if the capacity is zero, the error rate is not
+    /// between 0 and 1, or the filter would be too large to allocate.
+    pub fn new(capacity: u64, error_rate: f64) -> Result<RedisBloomFilter, Error> {
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err(Error::InvalidParams);
+        }
+        let options = OPT_NOROUND | OPT_FORCE64;
+        Ok(RedisBloomFilter {
+            links: vec![Link::new(capacity, error_rate, options)?],
+            options,
+            expansion: 2,
+        })
+    }
+    /// Sets the factor the capacity of the filter grows by when it scales, as set by
+    /// the `EXPANSION` argument of `BF.RESERVE`. Panics if the expansion is zero.
+    pub fn expansion(mut self, expansion: u32) -> RedisBloomFilter {
+        assert!(expansion > 0, "expansion must be greater than zero");
+        self.expansion = expansion;
+        self
+    }
+    /// Makes the filter reject inserts once it holds as many items as it was sized for,
+    /// rather than scaling, as set by the `NONSCALING` argument of `BF.RESERVE`.
+    pub fn non_scaling(mut self) -> RedisBloomFilter {
+        self.options |= OPT_NO_SCALING;
+        self
+    }
+    /// Inserts an item into the filter, as `BF.ADD` does. Returns true if the item was
+    /// definitely not in the filter before, and fails with `Error::CapacityExceeded` if
+    /// the filter is non-scaling and full, or has grown too large to scale again.
+    pub fn insert<T: AsRef<[u8]>>(&mut self, item: T) -> Result<bool, Error> {
+        let hashes = redis_hash(item.as_ref());
+        if self.links.iter().any(|link| link.has(hashes)) {
+            return Ok(false);
+        }
+        let last = self.links.last().unwrap();
+        if last.size >= last.entries {
+            if self.options & OPT_NO_SCALING != 0 {
+                return Err(Error::CapacityExceeded);
+            }
+            let entries = last.entries.saturating_mul(self.expansion as u64);
+            let error = last.error * ERROR_TIGHTENING_RATIO;
+            let link =
+                Link::new(entries, error, self.options).map_err(|_| Error::CapacityExceeded)?;
+            self.links.push(link);
+        }
+        let last = self.links.last_mut().unwrap();
+        last.insert(hashes);
+        last.size += 1;
+        Ok(true)
+    }
+    /// Checks if the filter contains an item, as `BF.EXISTS` does. Like any bloom
+    /// filter, it can produce false positives, but will never produce false negatives.
+    pub fn has<T: AsRef<[u8]>>(&self, item: T) -> bool {
+        let hashes = redis_hash(item.as_ref());
+        self.links.iter().any(|link| link.has(hashes))
+    }
+    /// Returns the number of items inserted into the filter.
+    pub fn len(&self) -> u64 {
+        self.links.iter().map(|link| link.size).sum()
+    }
+    /// Returns true if no items were inserted into the filter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the number of items the filter can hold before it scales again.
+    pub fn capacity(&self) -> u64 {
+        self.links.iter().map(|link| link.entries).sum()
+    }
+    /// Returns the number of filters in the chain, which grows as the filter scales.
+    pub fn num_filters(&self) -> usize {
+        self.links.len()
+    }
+    /// Returns the chunk of a dump following the given iterator, together with the
+    /// iterator to request the next chunk with, exactly as `BF.SCANDUMP key iter` does.
+    /// Iteration starts from 0, which returns the header, and ends once the returned
+    /// iterator is 0 and the chunk is empty.
+    pub fn scan_dump(&self, iter: i64) -> (i64, Vec<u8>) {
+        if iter <= 0 {
+            return (1, self.header());
+        }
+        let mut offset = (iter - 1) as u64;
+        for link in self.links.iter() {
+            let len = link.num_bytes;
+            if offset < len {
+                let chunk_len = (len - offset).min(MAX_CHUNK_LEN as u64);
+                let (start, end) = (offset as usize, (offset + chunk_len) as usize);
+                let mut chunk =
+                    link.bits[start.min(link.bits.len())..end.min(link.bits.len())].to_vec();
+                chunk.resize(chunk_len as usize, 0);
+                return (iter + chunk_len as i64, chunk);
+            }
+            offset -= len;
+        }
+        (0, vec![])
+    }
+    /// Returns every chunk of a dump of the filter, paired with its iterator, as
+    /// returned by calling `BF.SCANDUMP` until it returns an empty chunk.
+    pub fn dump(&self) -> Vec<(i64, Vec<u8>)> {
+        let mut chunks = vec![];
+        let mut iter = 0;
+        loop {
+            let (next, chunk) = self.scan_dump(iter);
+            if next == 0 {
+                return chunks;
+            }
+            chunks.push((next, chunk));
+            iter = next;
+        }
+    }
+    /// Restores a filter from the chunks of a dump, paired with the iterators returned
+    /// alongside them by `BF.SCANDUMP`. The first chunk must be the header. Fails with
+    /// `Error::InvalidEncoding` if the header is malformed or describes filters that do
+    /// not use 64-bit hashes, and with `Error::InvalidChunk` if a chunk does not fit
+    /// within the filter.
+    pub fn load<I, B>(chunks: I) -> Result<RedisBloomFilter, Error>
+    where
+        I: IntoIterator<Item = (i64, B)>,
+        B: AsRef<[u8]>,
+    {
+        let mut chunks = chunks.into_iter();
+        let mut bf = match chunks.next() {
+            Some((1, header)) => RedisBloomFilter::from_header(header.as_ref())?,
+            _ => return Err(Error::InvalidEncoding),
+        };
+        for (iter, chunk) in chunks {
+            bf.load_chunk(iter, chunk.as_ref())?;
+        }
+        Ok(bf)
+    }
+    /// Loads a chunk of bits of a dump into the filter, as `BF.LOADCHUNK key iter data`
+    /// does for any chunk following the header. Fails with `Error::InvalidChunk` if the
+    /// chunk does not fit within a single filter of the chain.
+    pub fn load_chunk(&mut self, iter: i64, chunk: &[u8]) -> Result<(), Error> {
+        let start = i64::try_from(chunk.len())
+            .ok()
+            .and_then(|len| iter.checked_sub(len)?.checked_sub(1))
+            .filter(|start| *start >= 0)
+            .ok_or(Error::InvalidChunk)?;
+        let mut offset = start as u64;
+        for link in self.links.iter_mut() {
+            let len = link.num_bytes;
+            if offset < len {
+                if chunk.len() as u64 > len - offset {
+                    return Err(Error::InvalidChunk);
+                }
+                let (start, end) = (offset as usize, offset as usize + chunk.len());
+                if link.bits.len() < end {
+                    link.bits.resize(end, 0);
+                }
+                link.bits[start..end].copy_from_slice(chunk);
+                return Ok(());
+            }
+            offset -= len;
+        }
+        Err(Error::InvalidChunk)
+    }
+    /// Encodes the header of a dump: the number of items, the number of filters, the
+    /// options and the expansion, followed by the header of every filter, all
+    /// little-endian and packed, as RedisBloom lays them out in memory.
+    fn header(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + LINK_LEN * self.links.len());
+        out.extend_from_slice(&self.len().to_le_bytes());
+        out.extend_from_slice(&(self.links.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.options.to_le_bytes());
+        out.extend_from_slice(&self.expansion.to_le_bytes());
+        for link in self.links.iter() {
+            link.write_header(&mut out);
+        }
+        out
+    }
+    /// Creates a filter with every bit unset from the header of a dump.
+    fn from_header(bytes: &[u8]) -> Result<RedisBloomFilter, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::InvalidEncoding);
+        }
+        let num_links = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let options = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        let expansion = u32::from_le_bytes(bytes[16..20].try_into().unwrap());
+        let links = &bytes[HEADER_LEN..];
+        if num_links == 0 || links.len() != num_links * LINK_LEN || options & OPT_FORCE64 == 0 {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(RedisBloomFilter {
+            links: links
+                .chunks(LINK_LEN)
+                .map(Link::read_header)
+                .collect::<Result<_, _>>()?,
+            options,
+            expansion,
+        })
+    }
+}
+
+/// Produces the two base hashes RedisBloom derives from an item's bytes.
+fn redis_hash(bytes: &[u8]) -> (u64, u64) {
+    let a = murmur64a(bytes, REDIS_SEED);
+    (a, murmur64a(bytes, a))
+}
+
+/// MurmurHash64A, the 64-bit variant of MurmurHash2 for 64-bit platforms.
+fn murmur64a(bytes: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (bytes.len() as u64).wrapping_mul(M);
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, b) in tail.iter().enumerate() {
+            h ^= (*b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        // 1000 items at 9.59 bits each need 9585 bits and 7 hashes, and the bits are
+        // rounded up to a multiple of 64.
+        let bf = RedisBloomFilter::new(1000, 0.01).unwrap();
+        let link = &bf.links[0];
+        assert_eq!(7, link.hashes);
+        assert_eq!(9600, link.num_bits);
+        assert_eq!(1200, link.bits.len());
+        assert_eq!(1000, bf.capacity());
+
+        // Needing less than a bit still gives the filter a word.
+        let mut bf = RedisBloomFilter::new(1, 0.9).unwrap();
+        assert_eq!(64, bf.links[0].num_bits);
+        bf.insert("a").unwrap();
+        assert!(bf.has("a"));
+
+        // Parameters that cannot size a filter are rejected rather than cast.
+        assert_eq!(Err(Error::InvalidParams), RedisBloomFilter::new(0, 0.01));
+        assert_eq!(Err(Error::InvalidParams), RedisBloomFilter::new(100, 0.0));
+        assert_eq!(
+            Err(Error::InvalidParams),
+            RedisBloomFilter::new(100, f64::NAN)
+        );
+        assert_eq!(
+            Err(Error::InvalidParams),
+            RedisBloomFilter::new(u64::MAX, 0.01)
+        );
+        assert_eq!(
+            Err(Error::InvalidParams),
+            Link::new(u64::MAX, 1e-300, OPT_FORCE64).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn scaling() {
+        let mut bf = RedisBloomFilter::new(100, 0.01).unwrap();
+        for i in 0..1000 {
+            bf.insert(format!("{}", i)).unwrap();
+        }
+        assert_eq!(4, bf.num_filters());
+        assert_eq!(100 + 200 + 400 + 800, bf.capacity());
+        assert_eq!(0.00125, bf.links[3].error);
+        assert!(bf.len() <= 1000 && bf.len() > 990);
+        for i in 0..1000 {
+            assert!(bf.has(format!("{}", i)));
+        }
+        assert!(!bf.insert("0").unwrap());
+
+        let mut bf = RedisBloomFilter::new(10, 0.01).unwrap().non_scaling();
+        for i in 0..10 {
+            bf.insert(format!("{}", i)).unwrap();
+        }
+        assert_eq!(Err(Error::CapacityExceeded), bf.insert("10"));
+        assert_eq!(Ok(false), bf.insert("0"));
+    }
+
+    #[test]
+    fn dump_and_load() {
+        let mut bf = RedisBloomFilter::new(100, 0.01).unwrap().expansion(4);
+        for i in 0..300 {
+            bf.insert(format!("{}", i)).unwrap();
+        }
+        let chunks = bf.dump();
+        assert_eq!(1 + bf.num_filters(), chunks.len());
+        assert_eq!((1, bf.header()), chunks[0]);
+        assert_eq!(HEADER_LEN + 2 * LINK_LEN, chunks[0].1.len());
+        let restored = RedisBloomFilter::load(chunks.clone()).unwrap();
+        assert_eq!(bf, restored);
+        assert_eq!((0, vec![]), bf.scan_dump(chunks.last().unwrap().0));
+
+        // Chunks are placed by their iterators, so their order does not matter.
+        let mut reordered = chunks.clone();
+        reordered[1..].reverse();
+        assert_eq!(bf, RedisBloomFilter::load(reordered).unwrap());
+    }
+
+    #[test]
+    fn header_layout() {
+        let mut bf = RedisBloomFilter::new(1000, 0.01).unwrap();
+        bf.insert("foo").unwrap();
+        let header = bf.scan_dump(0).1;
+        let mut expected = vec![];
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&5u32.to_le_bytes());
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(&1200u64.to_le_bytes());
+        expected.extend_from_slice(&9600u64.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&0.01f64.to_le_bytes());
+        expected.extend_from_slice(&bf.links[0].bpe.to_le_bytes());
+        expected.extend_from_slice(&7u32.to_le_bytes());
+        expected.extend_from_slice(&1000u64.to_le_bytes());
+        expected.push(0);
+        assert_eq!(expected, header);
+    }
+
+    #[test]
+    fn invalid_dumps() {
+        let bf = RedisBloomFilter::new(100, 0.01).unwrap();
+        let chunks = bf.dump();
+        let header = &chunks[0].1;
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            RedisBloomFilter::load([(1, &header[..header.len() - 1])])
+        );
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            RedisBloomFilter::load([(2, &header[..])])
+        );
+        // Filters hashing items with 32-bit hashes cannot be loaded.
+        let mut legacy = header.clone();
+        legacy[12] = OPT_NOROUND as u8;
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            RedisBloomFilter::load([(1, &legacy[..])])
+        );
+
+        let mut restored = RedisBloomFilter::load([(1, &header[..])]).unwrap();
+        let (iter, bits) = &chunks[1];
+        assert_eq!(
+            Err(Error::InvalidChunk),
+            restored.load_chunk(iter + 1, bits)
+        );
+        assert_eq!(Err(Error::InvalidChunk), restored.load_chunk(0, bits));
+        assert_eq!(
+            Err(Error::InvalidChunk),
+            restored.load_chunk(i64::MIN, bits)
+        );
+        assert_eq!(Ok(()), restored.load_chunk(*iter, bits));
+        assert_eq!(bf, restored);
+
+        // Headers describing filters too large to allocate are rejected.
+        let mut huge = header.clone();
+        let num_bits = (MAX_LINK_LEN + 8) * 8;
+        huge[HEADER_LEN..HEADER_LEN + 8].copy_from_slice(&(MAX_LINK_LEN + 8).to_le_bytes());
+        huge[HEADER_LEN + 8..HEADER_LEN + 16].copy_from_slice(&num_bits.to_le_bytes());
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            RedisBloomFilter::load([(1, &huge[..])])
+        );
+
+        // Headers with more hash functions than any sane filter needs are rejected.
+        let mut slow = header.clone();
+        slow[HEADER_LEN + 40..HEADER_LEN + 44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            RedisBloomFilter::load([(1, &slow[..])])
+        );
+    }
+
+    #[test]
+    fn bits_allocated_as_loaded() {
+        let mut bf = RedisBloomFilter::new(100, 0.01).unwrap();
+        bf.insert("foo").unwrap();
+        let chunks = bf.dump();
+        let mut restored = RedisBloomFilter::load([(1, &chunks[0].1)]).unwrap();
+        assert!(restored.links[0].bits.is_empty());
+        assert!(!restored.has("foo"));
+        assert_eq!(bf.scan_dump(1).1.len(), restored.scan_dump(1).1.len());
+        restored.load_chunk(chunks[1].0, &chunks[1].1).unwrap();
+        assert!(restored.has("foo"));
+    }
+
+    #[test]
+    fn murmur64a_vectors() {
+        // Computed with the reference C implementation, covering every kind of tail.
+        let input = b"The quick brown fox";
+        let vectors = [
+            (0, 0x1ab11ea5a7b2c56e),
+            (1, 0x83d6a552eeb4f730),
+            (3, 0x5c4dc69684f2fde3),
+            (8, 0xa786b610ee604f78),
+            (13, 0xb45d4dcd286e02fd),
+            (19, 0x0e9eab30b6eba02d),
+        ];
+        for (len, expected) in vectors {
+            assert_eq!(expected, murmur64a(&input[..len], REDIS_SEED), "{}", len);
+        }
+        assert_eq!((0x822b4f99b121f10d, 0x9d63d52a557f61c2), redis_hash(b"foo"));
+    }
+}
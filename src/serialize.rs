@@ -0,0 +1,205 @@
+use std::fmt;
+
+use serde::de::{Error as _, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::base64::{base64_decode, base64_encode, BASE64_STANDARD};
+use crate::{BloomFilter, Error, FilterParams};
+
+/// The serialized form of a bloom filter: its parameters, followed by the
+/// bookkeeping needed to keep estimates accurate, followed by its bits.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "BloomFilter")]
+#[serde(bound(
+    serialize = "Bits<B>: Serialize",
+    deserialize = "Bits<B>: Deserialize<'de>"
+))]
+pub(crate) struct Repr<B> {
+    params: FilterParams,
+    num_inserted: u64,
+    max_fp_rate: Option<f64>,
+    bits: Bits<B>,
+}
+
+/// The bits of a serialized bloom filter. Human-readable formats such as JSON carry
+/// them as a standard base64 string, while binary formats carry the raw bytes.
+struct Bits<B>(B);
+
+impl<B: AsRef<[u8]>> Serialize for Bits<B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64_encode(self.0.as_ref(), BASE64_STANDARD))
+        } else {
+            self.0.as_ref().serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Bits<Vec<u8>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(BitsVisitor).map(Bits)
+        } else {
+            Vec::deserialize(deserializer).map(Bits)
+        }
+    }
+}
+
+/// Accepts bits as a base64 string, or as an array of bytes as written by
+/// earlier versions of the package.
+struct BitsVisitor;
+
+impl<'de> Visitor<'de> for BitsVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a base64 string or an array of bytes")
+    }
+    fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Vec<u8>, E> {
+        base64_decode(s, BASE64_STANDARD)
+            .ok_or_else(|| E::custom("filter bits are not valid base64"))
+    }
+    fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bits = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bits.push(byte);
+        }
+        Ok(bits)
+    }
+}
+
+impl Repr<Vec<u8>> {
+    pub(crate) fn new<T: AsRef<[u8]>>(bf: &BloomFilter<T>) -> Self {
+        Repr {
+            params: bf.params(),
+            num_inserted: bf.num_inserted as u64,
+            max_fp_rate: bf.max_fp_rate,
+            bits: Bits(bf.bits_to_bytes()),
+        }
+    }
+    /// Restores the filter, failing if its bits do not match its parameters or
+    /// it was not built with one of the package's hashers.
+    pub(crate) fn into_filter<T: AsRef<[u8]>>(self) -> Result<BloomFilter<T>, Error> {
+        let mut bf = BloomFilter::from_byte_parts(self.params, &self.bits.0)?;
+        bf.num_inserted = self.num_inserted as usize;
+        bf.max_fp_rate = self.max_fp_rate;
+        Ok(bf)
+    }
+}
+
+/// Serializes the parameters and bits of a bloom filter, so it can be embedded in
+/// configuration or snapshot structs and restored after a restart. The hasher itself is
+/// not serialized, only its id and seed, see `FilterParams`. Requires the `serde` feature.
+///
+/// In human-readable formats such as JSON, the bits are a standard base64 string rather
+/// than an array of numbers, so filters stay compact when sent through JSON APIs and can
+/// be decoded by services in other languages. Binary formats carry the bits as raw bytes.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter};
+///
+/// let mut bf: BloomFilter<String> = BloomBuilder::new(1000, 0.01).build();
+/// bf.insert("hello".to_string());
+///
+/// let json = serde_json::to_string(&bf).unwrap();
+/// let restored: BloomFilter<String> = serde_json::from_str(&json).unwrap();
+/// assert!(restored.has("hello".to_string()));
+/// assert_eq!(bf.params(), restored.params());
+/// ```
+impl<T: AsRef<[u8]>> Serialize for BloomFilter<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Repr::new(self).serialize(serializer)
+    }
+}
+
+/// Deserializes a bloom filter serialized with `Serialize`. Filters are restored with
+/// the hasher identified by their parameters, so only filters built with the hashers of
+/// the package can be deserialized, including seeded ones. Filters built with custom
+/// hashers, hashers carrying secret keys, or independent hashers fail to deserialize,
+/// as do filters whose bits do not match their parameters.
+impl<'de, T: AsRef<[u8]>> Deserialize<'de> for BloomFilter<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Repr::<Vec<u8>>::deserialize(deserializer)?
+            .into_filter()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BloomBuilder, HmacHasher, Keccak256Hasher};
+
+    #[test]
+    fn roundtrip() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .seed(42)
+            .tier(0.0001)
+            .max_fp_rate(0.05)
+            .build();
+        bf.insert("foo");
+        bf.insert_with_class("bar", 0).unwrap();
+        let json = serde_json::to_string(&bf).unwrap();
+        let restored: BloomFilter<&str> = serde_json::from_str(&json).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+        assert!(restored.has("foo"));
+        assert!(restored.has_with_class("bar", 0).unwrap());
+        assert!(bf.union(&restored).is_ok());
+
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hasher::<Keccak256Hasher>()
+            .build();
+        let restored: BloomFilter<&str> =
+            serde_json::from_str(&serde_json::to_string(&bf).unwrap()).unwrap();
+        assert_eq!(bf.params(), restored.params());
+    }
+
+    #[test]
+    fn unknown_hashers() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hash_fn(|item: &&str| item.len() as u64)
+            .build();
+        let json = serde_json::to_string(&bf).unwrap();
+        assert!(serde_json::from_str::<BloomFilter<&str>>(&json).is_err());
+
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hasher_instance(HmacHasher::new(b"secret"))
+            .build();
+        let json = serde_json::to_string(&bf).unwrap();
+        assert!(serde_json::from_str::<BloomFilter<&str>>(&json).is_err());
+    }
+
+    #[test]
+    fn mismatched_bits() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01).deterministic().build();
+        let mut value = serde_json::to_value(&bf).unwrap();
+        value["bits"] = base64_encode(&bf.bits_to_bytes()[1..], BASE64_STANDARD).into();
+        assert!(serde_json::from_value::<BloomFilter<&str>>(value).is_err());
+    }
+
+    #[test]
+    fn base64_bits() {
+        let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).deterministic().build();
+        bf.insert("foo");
+        let mut value = serde_json::to_value(&bf).unwrap();
+        assert_eq!(
+            base64_encode(&bf.bits_to_bytes(), BASE64_STANDARD),
+            value["bits"].as_str().unwrap()
+        );
+        assert!(serde_json::to_string(&bf).unwrap().len() < bf.byte_len() * 2);
+
+        // Bits written as an array of bytes by earlier versions are still accepted.
+        value["bits"] = serde_json::to_value(bf.bits_to_bytes()).unwrap();
+        let restored: BloomFilter<&str> = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+
+        value["bits"] = "not base64!".into();
+        assert!(serde_json::from_value::<BloomFilter<&str>>(value).is_err());
+    }
+}
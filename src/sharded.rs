@@ -0,0 +1,285 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{BloomBuilder, BloomFilter, Error, FilterParams, HasherInstance};
+
+impl<T: AsRef<[u8]>> BloomBuilder<T> {
+    /// Builds a bloom filter split into a number of independently locked shards, see
+    /// `ShardedBloomFilter`. The capacity is spread evenly across the shards, each of
+    /// which is sized for its share with the builder's false positive rate. Panics if
+    /// the number of shards is zero.
+    pub fn build_sharded(self, num_shards: usize) -> ShardedBloomFilter<T> {
+        assert!(num_shards > 0, "a sharded filter needs at least one shard");
+        let capacity = self.capacity.div_ceil(num_shards as u64);
+        let shards = (0..num_shards)
+            .map(|_| {
+                BloomBuilder {
+                    capacity,
+                    fp_rate: self.fp_rate,
+                    num_hash_fns: self.num_hash_fns,
+                    hasher: self.hasher.clone(),
+                    tiers: self.tiers.clone(),
+                    max_fp_rate: self.max_fp_rate,
+                    probe_hashers: self.probe_hashers.clone(),
+                }
+                .build()
+            })
+            .collect();
+        ShardedBloomFilter::with_shards(shards)
+    }
+}
+
+/// A bloom filter split into shards, each covering its own part of the bit space behind
+/// its own lock, so that many threads inserting at once mostly contend on different
+/// locks and write to different cache lines. Every element is assigned to a single
+/// shard by the high bits of its hash, and all of its bits are set within that shard.
+/// Built with `BloomBuilder::build_sharded`.
+///
+/// Sharded filters with the same number of shards and the same shard parameters can be
+/// combined with `union`, and their shards exported as plain filters with `export`,
+/// such as to persist them, and restored with `from_shards`.
+///
+/// ## Example
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// use flowerbloom::{BloomBuilder, ShardedBloomFilter};
+///
+/// let bf: Arc<ShardedBloomFilter<String>> =
+///     Arc::new(BloomBuilder::new(10_000, 0.01).build_sharded(16));
+/// let handles: Vec<_> = (0..4)
+///     .map(|i| {
+///         let bf = bf.clone();
+///         thread::spawn(move || bf.insert(format!("{}", i)))
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+/// assert!((0..4).all(|i| bf.has(format!("{}", i))));
+/// ```
+pub struct ShardedBloomFilter<T: AsRef<[u8]>> {
+    shards: Vec<CachePadded<RwLock<BloomFilter<T>>>>,
+    /// Hashes elements to pick their shard, shared by every shard.
+    selector: Arc<dyn HasherInstance<T>>,
+}
+
+impl<T: AsRef<[u8]>> ShardedBloomFilter<T> {
+    /// Restores a sharded filter from shards returned by `export`, in the same order.
+    /// Fails with `Error::NoFilters` if there are no shards, and with
+    /// `Error::IncompatibleParams` if the shards were not built with identical
+    /// parameters.
+    pub fn from_shards(shards: Vec<BloomFilter<T>>) -> Result<ShardedBloomFilter<T>, Error> {
+        let first = shards.first().ok_or(Error::NoFilters)?;
+        for shard in shards.iter() {
+            first.check_compatible(shard)?;
+        }
+        Ok(ShardedBloomFilter::with_shards(shards))
+    }
+    /// Insert an element into its shard. Returns true if the element was probably not
+    /// in the filter before, as in `BloomFilter::insert`.
+    pub fn insert(&self, elem: T) -> bool {
+        self.write_shard(&elem).insert(elem)
+    }
+    /// Insert an element into its shard with the number of hash functions of a tier,
+    /// as in `BloomFilter::insert_with_class`. Fails with `Error::UnknownClass` if the
+    /// class was not configured via `BloomBuilder::tier`.
+    pub fn insert_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        self.write_shard(&elem).insert_with_class(elem, class)
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.read_shard(&elem).has(elem)
+    }
+    /// Checks if the bloom filter contains an element inserted with a class, as in
+    /// `BloomFilter::has_with_class`.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        self.read_shard(&elem).has_with_class(elem, class)
+    }
+    /// Clear all set bits of every shard, setting them back to zero.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            write(&shard.0).clear();
+        }
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(&shard.0).len()).sum()
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the number of shards of the bloom filter.
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+    /// Returns the structural parameters shared by every shard.
+    pub fn shard_params(&self) -> FilterParams {
+        read(&self.shards[0].0).params()
+    }
+    /// Merges another sharded filter into this one, shard by shard, so that it contains
+    /// the elements of both. Fails with `Error::IncompatibleShards` if the filters do not
+    /// have the same number of shards, and with `Error::IncompatibleParams` if their
+    /// shards were not built with identical parameters, leaving this filter untouched.
+    pub fn union(&self, other: &ShardedBloomFilter<T>) -> Result<(), Error> {
+        if self.shards.len() != other.shards.len() {
+            return Err(Error::IncompatibleShards {
+                expected: self.shards.len(),
+                found: other.shards.len(),
+            });
+        }
+        self.shard_params()
+            .check_compatible(&other.shard_params())?;
+        // A filter already contains itself.
+        if std::ptr::eq(self, other) {
+            return Ok(());
+        }
+        // Each shard of the other filter is copied before locking the shard it merges
+        // into, as holding both locks would deadlock with a concurrent union of the
+        // filters the other way around.
+        for (shard, other) in self.shards.iter().zip(other.shards.iter()) {
+            let other = read(&other.0).clone();
+            *write(&shard.0) |= &other;
+        }
+        Ok(())
+    }
+    /// Returns a copy of every shard as a plain filter, in order, such as to persist
+    /// them. Each shard is copied under its own lock, so inserts made concurrently with
+    /// the export may be included in some shards but not others.
+    pub fn export(&self) -> Vec<BloomFilter<T>> {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let shard = read(&shard.0);
+                shard.with_bits(shard.bits.clone(), shard.num_inserted)
+            })
+            .collect()
+    }
+    fn with_shards(shards: Vec<BloomFilter<T>>) -> ShardedBloomFilter<T> {
+        let first = &shards[0];
+        let selector = first.probe_hashers.first().unwrap_or(&first.hasher).clone();
+        ShardedBloomFilter {
+            shards: shards
+                .into_iter()
+                .map(|shard| CachePadded(RwLock::new(shard)))
+                .collect(),
+            selector,
+        }
+    }
+    /// Picks the shard of an element by the high bits of its hash, leaving the low bits
+    /// to pick its bits within the shard.
+    fn shard_of(&self, elem: &T) -> &RwLock<BloomFilter<T>> {
+        let hash = self.selector.hash(elem);
+        let idx = ((hash as u128 * self.shards.len() as u128) >> 64) as usize;
+        &self.shards[idx].0
+    }
+    fn read_shard(&self, elem: &T) -> RwLockReadGuard<'_, BloomFilter<T>> {
+        read(self.shard_of(elem))
+    }
+    fn write_shard(&self, elem: &T) -> RwLockWriteGuard<'_, BloomFilter<T>> {
+        write(self.shard_of(elem))
+    }
+}
+
+/// Aligns a shard's lock to its own cache lines, as the size of a cache line on most
+/// processors, so that threads locking neighboring shards never contend on the same
+/// line. The bits of each shard are allocated separately.
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+/// Locks a shard for reading. A panic while a shard is locked cannot leave its bits
+/// inconsistent, as bits are only ever set or cleared, so poisoning is ignored.
+fn read<T: AsRef<[u8]>>(shard: &RwLock<BloomFilter<T>>) -> RwLockReadGuard<'_, BloomFilter<T>> {
+    shard.read().unwrap_or_else(|err| err.into_inner())
+}
+
+fn write<T: AsRef<[u8]>>(shard: &RwLock<BloomFilter<T>>) -> RwLockWriteGuard<'_, BloomFilter<T>> {
+    shard.write().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn builder() -> BloomBuilder<String> {
+        BloomBuilder::new(10_000, 0.01).seed(7)
+    }
+
+    #[test]
+    fn threads() {
+        let bf = Arc::new(builder().build_sharded(8));
+        assert_eq!(8, bf.num_shards());
+        assert_eq!(1_250, bf.shard_params().capacity);
+        assert!(bf
+            .shards
+            .iter()
+            .all(|shard| (shard as *const _ as usize).is_multiple_of(64)));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let bf = bf.clone();
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        bf.insert(format!("{}-{}", t, i));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(4_000, bf.len());
+        assert!((0..4).all(|t| (0..1_000).all(|i| bf.has(format!("{}-{}", t, i)))));
+
+        // Elements are spread across every shard.
+        assert!(bf.export().iter().all(|shard| shard.len() > 300));
+        let false_positives = (0..10_000)
+            .filter(|i| bf.has(format!("absent-{}", i)))
+            .count();
+        assert!(false_positives < 200, "{}", false_positives);
+
+        bf.clear();
+        assert!(bf.is_empty());
+    }
+
+    #[test]
+    fn union_and_export() {
+        let a = builder().build_sharded(4);
+        let b = builder().build_sharded(4);
+        a.insert("foo".to_string());
+        b.insert("bar".to_string());
+        a.union(&b).unwrap();
+        a.union(&a).unwrap();
+        assert!(a.has("foo".to_string()) && a.has("bar".to_string()));
+        assert_eq!(2, a.len());
+
+        let restored = ShardedBloomFilter::from_shards(a.export()).unwrap();
+        assert!(restored.has("foo".to_string()) && restored.has("bar".to_string()));
+
+        // Filters merged into each other concurrently do not deadlock.
+        thread::scope(|s| {
+            s.spawn(|| (0..1_000).for_each(|_| a.union(&b).unwrap()));
+            s.spawn(|| (0..1_000).for_each(|_| b.union(&a).unwrap()));
+        });
+        assert!(b.has("foo".to_string()));
+
+        assert_eq!(
+            Err(Error::IncompatibleShards {
+                expected: 4,
+                found: 2
+            }),
+            a.union(&builder().build_sharded(2))
+        );
+        assert!(matches!(
+            a.union(&BloomBuilder::new(10_000, 0.01).seed(8).build_sharded(4)),
+            Err(Error::IncompatibleParams { .. })
+        ));
+        assert_eq!(
+            Some(Error::NoFilters),
+            ShardedBloomFilter::<String>::from_shards(vec![]).err()
+        );
+    }
+}
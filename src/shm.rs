@@ -0,0 +1,374 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use memmap2::MmapMut;
+
+use crate::container::tmp_path;
+use crate::format::invalid;
+use crate::{
+    optimal_bits_needed, optimal_num_hash_fns, probe_index, random_seed, BloomFilter, Error,
+    FilterParams, HasherInstance, KeyedHasher,
+};
+
+/// The magic bytes starting every shared filter segment.
+const SHARED_MAGIC: [u8; 8] = *b"FBLMSHM\0";
+
+/// The version of the shared filter layout.
+const SHARED_VERSION: u32 = 1;
+
+/// The length of the header of a shared filter segment, after which its words start.
+const HEADER_LEN: usize = size_of::<SharedFilterHeader>();
+
+/// The header at the start of a shared filter segment, followed by the words of the
+/// filter's bits. Every field is in the native byte order of the host, and the layout is
+/// fixed by `#[repr(C)]`, so that processes written in other languages can map the same
+/// segment. Bit `i` of the filter is bit `i % 64` of word `i / 64`, as in a `BloomFilter`,
+/// and items are hashed with a `KeyedHasher` keyed by the seed.
+///
+/// Every field but the number of inserted elements is written once, before the segment
+/// is published, and the words and that number are only ever modified atomically.
+#[repr(C)]
+pub struct SharedFilterHeader {
+    /// The magic bytes `FBLMSHM\0`.
+    pub magic: [u8; 8],
+    /// The version of the layout, currently 1.
+    pub version: u32,
+    pub num_hash_fns: u32,
+    pub capacity: u64,
+    /// The number of bits of the filter, a multiple of 8. The segment holds enough
+    /// words for them, the last of which may be partially used.
+    pub bit_len: u64,
+    pub seed: u64,
+    pub num_inserted: AtomicU64,
+}
+
+/// A bloom filter laid out in a shared memory segment, so that a producer process and
+/// any number of consumer processes on the same host can insert into and query a single
+/// filter, without sockets or copies. Requires the `mmap` feature.
+///
+/// The segment is a file mapped into every process, such as one under `/dev/shm` on
+/// Linux, holding a `SharedFilterHeader` followed by the filter's words. Its bits are
+/// set and read atomically, so every process sees an element once its insert returns,
+/// as with an `AtomicBloomFilter` shared between threads.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::SharedBloomFilter;
+///
+/// let path = std::env::temp_dir().join("flowerbloom-shared-doc.shm");
+/// // SAFETY: the segment is never truncated while it is mapped.
+/// let producer: SharedBloomFilter<&str> =
+///     unsafe { SharedBloomFilter::create(&path, 1000, 0.01) }.unwrap();
+/// producer.insert("hello");
+///
+/// // Typically in another process.
+/// let consumer: SharedBloomFilter<&str> = unsafe { SharedBloomFilter::open(&path) }.unwrap();
+/// assert!(consumer.has("hello"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct SharedBloomFilter<T: AsRef<[u8]>> {
+    // Kept alive for as long as the pointer into it is used.
+    _map: MmapMut,
+    // The start of the map, holding the header followed by the words.
+    base: NonNull<u8>,
+    num_words: usize,
+    hasher: KeyedHasher,
+    _marker: PhantomData<fn(&T)>,
+}
+
+// SAFETY: the map is owned by the filter and never remapped, and everything modified
+// through its pointer after the segment is published is only accessed atomically.
+unsafe impl<T: AsRef<[u8]>> Send for SharedBloomFilter<T> {}
+unsafe impl<T: AsRef<[u8]>> Sync for SharedBloomFilter<T> {}
+
+impl<T: AsRef<[u8]>> SharedBloomFilter<T> {
+    /// Returns the number of bytes of a shared filter segment sized to hold a number of
+    /// items with a desired false positive rate, as created by `create`.
+    pub fn segment_len(capacity: u64, desired_fp_rate: f32) -> usize {
+        let byte_len = optimal_bits_needed(capacity, desired_fp_rate)
+            .div_ceil(8)
+            .max(1);
+        HEADER_LEN + byte_len.div_ceil(8) as usize * 8
+    }
+    /// Creates a shared filter segment at a path, holding an empty bloom filter with a
+    /// specified capacity and desired false positive rate, and a random seed. The
+    /// segment is written to a temporary file which then replaces any existing file, so
+    /// processes that opened the previous segment keep using it, and processes opening
+    /// the path never see a partially written one. Fails with an error of kind
+    /// `InvalidInput` wrapping `Error::InvalidParams` if the capacity is zero or the
+    /// false positive rate is not between 0 and 1.
+    ///
+    /// # Safety
+    ///
+    /// The segment is accessed through the mapping, which is undefined behavior if the
+    /// file is truncated or otherwise modified, other than through a `SharedBloomFilter`,
+    /// while it is mapped by any process. Callers must ensure no process resizes or
+    /// rewrites the file until every filter mapping it is dropped.
+    pub unsafe fn create<P: AsRef<Path>>(
+        path: P,
+        capacity: u64,
+        desired_fp_rate: f32,
+    ) -> io::Result<SharedBloomFilter<T>> {
+        if capacity == 0 || !(desired_fp_rate > 0.0 && desired_fp_rate < 1.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                Error::InvalidParams,
+            ));
+        }
+        let path = path.as_ref();
+        let segment_len = SharedBloomFilter::<T>::segment_len(capacity, desired_fp_rate);
+        let tmp = tmp_path(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        file.set_len(segment_len as u64)?;
+        // SAFETY: upheld by the caller.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        let header = SharedFilterHeader {
+            magic: SHARED_MAGIC,
+            version: SHARED_VERSION,
+            num_hash_fns: optimal_num_hash_fns(capacity, desired_fp_rate),
+            capacity,
+            bit_len: optimal_bits_needed(capacity, desired_fp_rate)
+                .div_ceil(8)
+                .max(1)
+                * 8,
+            seed: random_seed(),
+            num_inserted: AtomicU64::new(0),
+        };
+        // SAFETY: the map starts on a page boundary and is longer than the header, and
+        // no other process can see the file until it is renamed into place.
+        unsafe { map.as_mut_ptr().cast::<SharedFilterHeader>().write(header) };
+        fs::rename(&tmp, path)?;
+        SharedBloomFilter::from_map(map)
+    }
+    /// Maps a shared filter segment created by `create` into memory. Fails with an
+    /// error of kind `InvalidData` if the file is not a shared filter segment.
+    ///
+    /// # Safety
+    ///
+    /// As for `create`, the file must not be truncated or otherwise modified, other than
+    /// through a `SharedBloomFilter`, while it is mapped by any process.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<SharedBloomFilter<T>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: upheld by the caller.
+        SharedBloomFilter::from_map(unsafe { MmapMut::map_mut(&file)? })
+    }
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, as in `BloomFilter::insert`. The result is
+    /// only best-effort when processes race to insert the same element: each of them may
+    /// set some of its bits first, so more than one of them can see it as new.
+    pub fn insert(&self, elem: T) -> bool {
+        self.header().num_inserted.fetch_add(1, Ordering::Relaxed);
+        let hashes = self.hasher.hash_pair(&elem);
+        let (words, m) = (self.words(), self.header().bit_len);
+        let mut changed = false;
+        for i in 0..self.header().num_hash_fns {
+            let idx = probe_index(hashes, i, m);
+            let mask = 1 << (idx % 64);
+            let prev = words[(idx / 64) as usize].fetch_or(mask, Ordering::Release);
+            changed |= prev & mask == 0;
+        }
+        changed
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        let hashes = self.hasher.hash_pair(&elem);
+        let (words, m) = (self.words(), self.header().bit_len);
+        (0..self.header().num_hash_fns).all(|i| {
+            let idx = probe_index(hashes, i, m);
+            words[(idx / 64) as usize].load(Ordering::Acquire) & (1 << (idx % 64)) != 0
+        })
+    }
+    /// Clear all set bits of the bloom filter, setting them back to zero, in every
+    /// process sharing it. Elements inserted concurrently with the clear may be
+    /// partially cleared.
+    pub fn clear(&self) {
+        for word in self.words() {
+            word.store(0, Ordering::Release);
+        }
+        self.header().num_inserted.store(0, Ordering::Relaxed);
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter,
+    /// by any process sharing it.
+    pub fn len(&self) -> usize {
+        self.header().num_inserted.load(Ordering::Relaxed) as usize
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the number of bits set in the bloom filter.
+    pub fn count_ones(&self) -> usize {
+        self.words()
+            .iter()
+            .map(|word| word.load(Ordering::Acquire).count_ones() as usize)
+            .sum()
+    }
+    /// Returns the number of bits in the bloom filter.
+    pub fn bit_len(&self) -> usize {
+        self.header().bit_len as usize
+    }
+    /// Returns the header of the segment, see `SharedFilterHeader`.
+    pub fn header(&self) -> &SharedFilterHeader {
+        // SAFETY: the header was validated by `from_map`, and its only field written
+        // after the segment is published is atomic.
+        unsafe { self.base.cast::<SharedFilterHeader>().as_ref() }
+    }
+    /// Returns the structural parameters of the bloom filter, identical to those of a
+    /// `BloomFilter` built with the same capacity, false positive rate and seed.
+    pub fn params(&self) -> FilterParams {
+        let header = self.header();
+        FilterParams {
+            capacity: header.capacity,
+            bit_len: header.bit_len,
+            num_hash_fns: header.num_hash_fns,
+            tier_hash_fns: vec![],
+            hasher_id: HasherInstance::<T>::id(&self.hasher),
+            seed: Some(header.seed),
+        }
+    }
+    /// Returns a copy of the bloom filter as a `BloomFilter`, such as to persist it.
+    /// Inserts made concurrently with the copy may be partially included.
+    pub fn to_filter(&self) -> BloomFilter<T> {
+        let header = self.header();
+        let hasher: Arc<dyn HasherInstance<T>> = Arc::new(KeyedHasher::new(header.seed));
+        BloomFilter {
+            bits: self
+                .words()
+                .iter()
+                .map(|word| word.load(Ordering::Acquire))
+                .collect(),
+            byte_len: (header.bit_len / 8) as usize,
+            capacity: header.capacity,
+            num_hash_fns: header.num_hash_fns,
+            hasher,
+            class_hash_fns: vec![],
+            num_inserted: self.len(),
+            max_fp_rate: None,
+            probe_hashers: vec![],
+            dirty: vec![],
+        }
+    }
+    fn from_map(mut map: MmapMut) -> io::Result<SharedBloomFilter<T>> {
+        if map.len() < HEADER_LEN || map[..8] != SHARED_MAGIC {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let base = NonNull::new(map.as_mut_ptr()).ok_or_else(|| invalid(Error::InvalidEncoding))?;
+        // SAFETY: the map starts on a page boundary and is at least as long as the
+        // header, whose plain fields accept any bytes.
+        let header = unsafe { base.cast::<SharedFilterHeader>().as_ref() };
+        if header.version != SHARED_VERSION {
+            return Err(invalid(Error::UnsupportedVersion(header.version as u8)));
+        }
+        let num_words = header.bit_len.div_ceil(64) as usize;
+        if header.bit_len == 0
+            || header.bit_len % 8 != 0
+            || header.num_hash_fns == 0
+            || map.len() != HEADER_LEN + num_words * 8
+        {
+            return Err(invalid(Error::InvalidEncoding));
+        }
+        let hasher = KeyedHasher::new(header.seed);
+        Ok(SharedBloomFilter {
+            _map: map,
+            base,
+            num_words,
+            hasher,
+            _marker: PhantomData,
+        })
+    }
+    fn words(&self) -> &[AtomicU64] {
+        // SAFETY: the words follow the header, which is a multiple of 8 bytes long, and
+        // `from_map` checked that the map holds exactly `num_words` of them.
+        unsafe {
+            let words = self.base.as_ptr().add(HEADER_LEN).cast::<AtomicU64>();
+            std::slice::from_raw_parts(words, self.num_words)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomBuilder;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("flowerbloom-{}-{}.shm", name, std::process::id()))
+    }
+
+    #[test]
+    fn shared_between_mappings() {
+        let path = temp_path("shared");
+        let producer: SharedBloomFilter<String> =
+            unsafe { SharedBloomFilter::create(&path, 1_000, 0.01) }.unwrap();
+        let consumer: SharedBloomFilter<String> =
+            unsafe { SharedBloomFilter::open(&path) }.unwrap();
+        assert_eq!(
+            SharedBloomFilter::<String>::segment_len(1_000, 0.01),
+            std::fs::metadata(&path).unwrap().len() as usize
+        );
+        for i in 0..500 {
+            producer.insert(format!("{}", i));
+        }
+        assert_eq!(500, consumer.len());
+        assert!((0..500).all(|i| consumer.has(format!("{}", i))));
+        assert_eq!(producer.params(), consumer.params());
+
+        // Items map to the same bits as in a dense filter with the same seed.
+        let seed = consumer.params().seed.unwrap();
+        let mut dense: BloomFilter<String> = BloomBuilder::new(1_000, 0.01).seed(seed).build();
+        for i in 0..500 {
+            dense.insert(format!("{}", i));
+        }
+        let converted = consumer.to_filter();
+        assert_eq!(dense.params(), converted.params());
+        assert_eq!(dense.bits, converted.bits);
+        assert_eq!(dense.count_ones(), consumer.count_ones());
+
+        consumer.clear();
+        assert!(producer.is_empty());
+        assert_eq!(0, producer.count_ones());
+
+        // Recreating the segment leaves existing mappings on the previous one.
+        let recreated: SharedBloomFilter<String> =
+            unsafe { SharedBloomFilter::create(&path, 1_000, 0.01) }.unwrap();
+        recreated.insert("foo".to_string());
+        assert!(!producer.has("foo".to_string()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_path("invalid");
+        let bf: BloomFilter<&str> = BloomFilter::new(100, 0.01);
+        std::fs::write(&path, bf.to_bytes()).unwrap();
+        let err = unsafe { SharedBloomFilter::<&str>::open(&path) }
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        let path = temp_path("params");
+        for (capacity, fp_rate) in [(0, 0.01), (100, 0.0), (100, 1.0), (100, f32::NAN)] {
+            let err = unsafe { SharedBloomFilter::<&str>::create(&path, capacity, fp_rate) }
+                .err()
+                .unwrap();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        }
+        assert!(!path.exists());
+    }
+}
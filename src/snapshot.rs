@@ -0,0 +1,192 @@
+use crate::crdt::Chunk;
+use crate::{words, BloomFilter, Error};
+
+/// The number of bytes of a filter's bits tracked as a single dirty chunk, the size of
+/// a typical memory page.
+pub const DIRTY_CHUNK_SIZE: usize = 4096;
+
+/// The changes made to a bloom filter since its last snapshot, made up of the current
+/// contents of every chunk of its bits that changed, produced by
+/// `BloomFilter::snapshot_delta`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotDelta {
+    /// The number of elements inserted into the filter.
+    pub num_inserted: u64,
+    /// The chunks of the filter's bits that changed, in increasing order of offset.
+    pub chunks: Vec<Chunk>,
+}
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Returns the chunks of the bloom filter's bits that changed since its last
+    /// snapshot, and marks the filter as clean, so that a large filter can be persisted
+    /// by writing only the chunks that changed over a previous snapshot rather than
+    /// the entire filter. Bits are tracked in chunks of `DIRTY_CHUNK_SIZE` bytes.
+    ///
+    /// Filters start out clean, so the first snapshot of a filter must be a full one,
+    /// such as with `to_bytes`.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1_000_000, 0.01).seed(42).build();
+    /// let mut snapshot: BloomFilter<&str> = BloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+    ///
+    /// bf.insert("hello");
+    /// let delta = bf.snapshot_delta();
+    /// assert!(delta.chunks.len() <= 7);
+    ///
+    /// snapshot.apply_snapshot_delta(&delta).unwrap();
+    /// assert!(snapshot.has("hello"));
+    /// ```
+    pub fn snapshot_delta(&mut self) -> SnapshotDelta {
+        let chunks = self
+            .dirty_chunks()
+            .map(|i| {
+                let offset = i * DIRTY_CHUNK_SIZE;
+                let end = (offset + DIRTY_CHUNK_SIZE).min(self.byte_len());
+                Chunk {
+                    offset,
+                    bytes: words::bytes_in(&self.bits, offset, end),
+                }
+            })
+            .collect();
+        self.dirty.clear();
+        SnapshotDelta {
+            num_inserted: self.num_inserted as u64,
+            chunks,
+        }
+    }
+    /// Applies a delta produced by `snapshot_delta` to a snapshot of a bloom filter,
+    /// overwriting its chunks with their contents in the delta. An error is returned,
+    /// leaving the filter untouched, if any chunk does not fit within the filter.
+    pub fn apply_snapshot_delta(&mut self, delta: &SnapshotDelta) -> Result<(), Error> {
+        for chunk in delta.chunks.iter() {
+            let end = chunk
+                .offset
+                .checked_add(chunk.bytes.len())
+                .ok_or(Error::InvalidChunk)?;
+            if end > self.byte_len() {
+                return Err(Error::InvalidChunk);
+            }
+        }
+        for chunk in delta.chunks.iter() {
+            let end = chunk.offset + chunk.bytes.len();
+            words::copy_from_bytes(&mut self.bits, chunk.offset, &chunk.bytes);
+            self.mark_dirty(chunk.offset, end);
+        }
+        self.num_inserted = delta.num_inserted as usize;
+        Ok(())
+    }
+    /// Returns the number of bytes of the bloom filter's bits that changed since its
+    /// last snapshot, rounded up to whole chunks, to decide whether a delta or a full
+    /// snapshot is cheaper to persist.
+    pub fn dirty_len(&self) -> usize {
+        self.dirty_chunks()
+            .map(|i| DIRTY_CHUNK_SIZE.min(self.byte_len() - i * DIRTY_CHUNK_SIZE))
+            .sum()
+    }
+    /// Marks the chunks overlapping a range of bytes of the bits as changed.
+    #[inline]
+    pub(crate) fn mark_dirty(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        if self.dirty.is_empty() {
+            let num_chunks = self.byte_len().div_ceil(DIRTY_CHUNK_SIZE);
+            self.dirty.resize(num_chunks.div_ceil(64), 0);
+        }
+        for chunk in start / DIRTY_CHUNK_SIZE..=(end - 1) / DIRTY_CHUNK_SIZE {
+            self.dirty[chunk / 64] |= 1 << (chunk % 64);
+        }
+    }
+    /// Marks all of the bits as changed.
+    pub(crate) fn mark_all_dirty(&mut self) {
+        self.mark_dirty(0, self.byte_len());
+    }
+    fn dirty_chunks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.dirty.iter().enumerate().flat_map(|(i, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crdt, BloomBuilder};
+
+    fn filter() -> BloomFilter<String> {
+        BloomBuilder::new(100_000, 0.01).seed(7).build()
+    }
+
+    #[test]
+    fn tracks_changed_chunks() {
+        let mut bf = filter();
+        assert!(bf.byte_len() > 4 * DIRTY_CHUNK_SIZE);
+        let mut snapshot = BloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+        assert_eq!(0, bf.dirty_len());
+        assert!(bf.snapshot_delta().chunks.is_empty());
+
+        bf.insert("foo".to_string());
+        let delta = bf.snapshot_delta();
+        assert!(!delta.chunks.is_empty() && delta.chunks.len() <= bf.num_hash_fns() as usize);
+        assert!(delta.chunks.windows(2).all(|w| w[0].offset < w[1].offset));
+        assert_eq!(0, bf.dirty_len());
+        snapshot.apply_snapshot_delta(&delta).unwrap();
+        assert!(snapshot.has("foo".to_string()));
+
+        // Inserting an element that is already present changes nothing.
+        bf.insert("foo".to_string());
+        assert!(bf.snapshot_delta().chunks.is_empty());
+
+        for i in 0..100 {
+            bf.insert(format!("{}", i));
+        }
+        let dirty_len = bf.dirty_len();
+        let delta = bf.snapshot_delta();
+        assert_eq!(
+            dirty_len,
+            delta.chunks.iter().map(|c| c.bytes.len()).sum::<usize>()
+        );
+        snapshot.apply_snapshot_delta(&delta).unwrap();
+        assert_eq!(bf.bits, snapshot.bits);
+        assert_eq!(bf.len(), snapshot.len());
+    }
+
+    #[test]
+    fn bulk_changes() {
+        let mut bf = filter();
+        let mut other = filter();
+        other.insert("foo".to_string());
+        bf.clear();
+        assert_eq!(bf.byte_len(), bf.dirty_len());
+        bf.snapshot_delta();
+
+        crdt::merge(&mut bf, &other).unwrap();
+        bf |= &other;
+        bf &= &other;
+        let mut snapshot = filter();
+        snapshot.apply_snapshot_delta(&bf.snapshot_delta()).unwrap();
+        assert_eq!(bf.bits, snapshot.bits);
+        assert_eq!(bf.byte_len(), snapshot.dirty_len());
+    }
+
+    #[test]
+    fn invalid_chunks() {
+        let mut bf = filter();
+        let mut delta = SnapshotDelta {
+            num_inserted: 1,
+            chunks: vec![Chunk {
+                offset: bf.byte_len() - 1,
+                bytes: vec![1, 1],
+            }],
+        };
+        assert_eq!(Err(Error::InvalidChunk), bf.apply_snapshot_delta(&delta));
+        delta.chunks[0].offset = usize::MAX;
+        assert_eq!(Err(Error::InvalidChunk), bf.apply_snapshot_delta(&delta));
+        assert!(bf.is_empty() && bf.bits.iter().all(|b| *b == 0));
+    }
+}
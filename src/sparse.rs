@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use roaring::RoaringTreemap;
+
+use crate::words::AlignedWords;
+use crate::{
+    class_num_hash_fns, filter_params, probes, tier_num_hash_fns, words, BloomBuilder, BloomFilter,
+    Error, FilterParams, HasherInstance,
+};
+
+impl<T: AsRef<[u8]>> BloomBuilder<T> {
+    /// Builds a bloom filter holding its set bits in a roaring bitmap rather than in a
+    /// bit array, see `RoaringBloomFilter`. This pays off for filters provisioned far
+    /// beyond what they end up holding: an empty filter takes a few bytes whatever its
+    /// capacity, and each inserted item adds a few bytes per bit it sets, where a bit
+    /// array costs a bit per bit of capacity upfront. Items probe the same bits as in
+    /// the filter returned by `build`, so the two convert into each other with `From`,
+    /// but `max_fp_rate` is ignored. Requires the `roaring` feature.
+    pub fn build_roaring(self) -> RoaringBloomFilter<T> {
+        let (num_hash_fns, size) = self.checked_sizing();
+        RoaringBloomFilter {
+            bits: RoaringTreemap::new(),
+            bit_len: size as u64 * 8,
+            capacity: self.capacity,
+            num_hash_fns,
+            hasher: self.hasher,
+            class_hash_fns: self.tiers.iter().map(|p| tier_num_hash_fns(*p)).collect(),
+            num_inserted: 0,
+            probe_hashers: self.probe_hashers,
+        }
+    }
+}
+
+/// A bloom filter holding its set bits in a compressed roaring bitmap, so that very
+/// large filters with few elements inserted use memory proportional to their set bits
+/// rather than to their capacity, such as filters sized for a worst case that is rarely
+/// reached. Built with `BloomBuilder::build_roaring`. Requires the `roaring` feature.
+///
+/// Queries are slower than those of a `BloomFilter`, and once a filter is more than a
+/// few percent full its bitmap takes about as much memory as a bit array, at which
+/// point it can be converted into a `BloomFilter` with `From`, setting the same bits.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomBuilder, BloomFilter, RoaringBloomFilter};
+///
+/// let mut bf: RoaringBloomFilter<&str> = BloomBuilder::new(100_000_000, 0.01).build_roaring();
+/// bf.insert("hello");
+/// assert!(bf.has("hello"));
+/// assert!(bf.memory_size() < 1024);
+///
+/// let dense = BloomFilter::from(&bf);
+/// assert!(dense.has("hello"));
+/// ```
+pub struct RoaringBloomFilter<T: AsRef<[u8]>> {
+    bits: RoaringTreemap,
+    bit_len: u64,
+    capacity: u64,
+    num_hash_fns: u32,
+    hasher: Arc<dyn HasherInstance<T>>,
+    class_hash_fns: Vec<u32>,
+    num_inserted: usize,
+    probe_hashers: Vec<Arc<dyn HasherInstance<T>>>,
+}
+
+impl<T: AsRef<[u8]>> RoaringBloomFilter<T> {
+    /// Insert an element into the bloom filter. Returns true if the element was
+    /// probably not in the filter before, as in `BloomFilter::insert`.
+    pub fn insert(&mut self, elem: T) -> bool {
+        let num_hash_fns = self.num_hash_fns;
+        self.insert_with_probes(&elem, num_hash_fns)
+    }
+    /// Insert an element into the bloom filter with the number of hash functions of a
+    /// tier, as in `BloomFilter::insert_with_class`. Fails with `Error::UnknownClass` if the class
+    /// was not configured via `BloomBuilder::tier`.
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self.insert_with_probes(&elem, num_hash_fns))
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.probes(&elem, self.num_hash_fns)
+            .all(|idx| self.bits.contains(idx))
+    }
+    /// Checks if the bloom filter contains an element inserted with a class, as in
+    /// `BloomFilter::has_with_class`.
+    pub fn has_with_class(&self, elem: T, class: usize) -> Result<bool, Error> {
+        let num_hash_fns = class_num_hash_fns(&self.class_hash_fns, class)?;
+        Ok(self
+            .probes(&elem, num_hash_fns)
+            .all(|idx| self.bits.contains(idx)))
+    }
+    /// Returns the number of times an element has been inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.num_inserted
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_inserted == 0
+    }
+    /// Returns the number of bits set in the bloom filter.
+    pub fn count_ones(&self) -> u64 {
+        self.bits.len()
+    }
+    /// Returns the number of bytes the roaring bitmap occupies when serialized, which
+    /// closely tracks the memory it uses.
+    pub fn memory_size(&self) -> usize {
+        self.bits.serialized_size()
+    }
+    /// Returns the structural parameters of the bloom filter, identical to those of a
+    /// `BloomFilter` built with the same builder.
+    pub fn params(&self) -> FilterParams {
+        filter_params(
+            self.capacity,
+            self.bit_len,
+            self.num_hash_fns,
+            &self.class_hash_fns,
+            &self.hasher,
+            &self.probe_hashers,
+        )
+    }
+    fn insert_with_probes(&mut self, elem: &T, num_hash_fns: u32) -> bool {
+        self.num_inserted += 1;
+        let mut changed = false;
+        for idx in probes(
+            &*self.hasher,
+            &self.probe_hashers,
+            elem,
+            num_hash_fns,
+            self.bit_len,
+        ) {
+            changed |= self.bits.insert(idx);
+        }
+        changed
+    }
+    fn probes<'a>(&'a self, elem: &'a T, num_hash_fns: u32) -> impl Iterator<Item = u64> + 'a {
+        probes(
+            &*self.hasher,
+            &self.probe_hashers,
+            elem,
+            num_hash_fns,
+            self.bit_len,
+        )
+    }
+}
+
+/// Converts a roaring bloom filter into a bloom filter holding its bits in a bit array,
+/// with the same parameters, hashers and set bits.
+impl<T: AsRef<[u8]>> From<&RoaringBloomFilter<T>> for BloomFilter<T> {
+    fn from(bf: &RoaringBloomFilter<T>) -> BloomFilter<T> {
+        let byte_len = (bf.bit_len / 8) as usize;
+        let mut bits = AlignedWords::zeroed(words::word_len(byte_len));
+        for idx in bf.bits.iter() {
+            bits[(idx / 64) as usize] |= 1 << (idx % 64);
+        }
+        BloomFilter {
+            bits,
+            byte_len,
+            capacity: bf.capacity,
+            num_hash_fns: bf.num_hash_fns,
+            hasher: bf.hasher.clone(),
+            class_hash_fns: bf.class_hash_fns.clone(),
+            num_inserted: bf.num_inserted,
+            max_fp_rate: None,
+            probe_hashers: bf.probe_hashers.clone(),
+            dirty: vec![],
+        }
+    }
+}
+
+/// Converts a bloom filter into a roaring bloom filter with the same parameters,
+/// hashers and set bits.
+impl<T: AsRef<[u8]>> From<&BloomFilter<T>> for RoaringBloomFilter<T> {
+    fn from(bf: &BloomFilter<T>) -> RoaringBloomFilter<T> {
+        let set_bits = bf.bits.iter().enumerate().flat_map(|(i, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i as u64 * 64 + bit)
+        });
+        RoaringBloomFilter {
+            // Set bits are produced in increasing order.
+            bits: RoaringTreemap::from_sorted_iter(set_bits).unwrap(),
+            bit_len: bf.bit_len() as u64,
+            capacity: bf.capacity,
+            num_hash_fns: bf.num_hash_fns,
+            hasher: bf.hasher.clone(),
+            class_hash_fns: bf.class_hash_fns.clone(),
+            num_inserted: bf.num_inserted,
+            probe_hashers: bf.probe_hashers.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_matches_dense, Backend};
+    use crate::KeyedHasher;
+
+    impl Backend for RoaringBloomFilter<String> {
+        fn insert(&mut self, elem: String) -> bool {
+            Self::insert(self, elem)
+        }
+        fn insert_with_class(&mut self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::insert_with_class(self, elem, class)
+        }
+        fn has(&self, elem: String) -> bool {
+            Self::has(self, elem)
+        }
+        fn has_with_class(&self, elem: String, class: usize) -> Result<bool, Error> {
+            Self::has_with_class(self, elem, class)
+        }
+        fn to_filter(&self) -> BloomFilter<String> {
+            BloomFilter::from(self)
+        }
+    }
+
+    #[test]
+    fn matches_dense_filter() {
+        let builder = || BloomBuilder::new(10_000, 0.01).seed(7).tier(0.001);
+        assert_matches_dense(builder().build(), builder().build_roaring(), 100);
+    }
+
+    #[test]
+    fn converts_set_bits() {
+        let mut dense: BloomFilter<String> = BloomBuilder::new(10_000, 0.01).seed(7).build();
+        for i in 0..100 {
+            dense.insert(format!("{}", i));
+        }
+        let sparse = RoaringBloomFilter::from(&dense);
+        assert_eq!(dense.params(), sparse.params());
+        assert_eq!(dense.count_ones() as u64, sparse.count_ones());
+        assert_eq!(dense.len(), sparse.len());
+        let back = BloomFilter::from(&sparse);
+        assert_eq!(dense.bits, back.bits);
+        assert_eq!(dense.len(), back.len());
+    }
+
+    #[test]
+    fn sparse_memory() {
+        let mut bf: RoaringBloomFilter<String> =
+            BloomBuilder::new(400_000_000, 0.01).build_roaring();
+        assert!(bf.is_empty());
+        for i in 0..1_000 {
+            bf.insert(format!("{}", i));
+        }
+        // A bit array would take over 400 MiB.
+        assert!(bf.params().bit_len > 3_500_000_000);
+        assert!(bf.memory_size() < 1_000 * 7 * 16);
+        assert!((0..1_000).all(|i| bf.has(format!("{}", i))));
+    }
+
+    #[test]
+    fn independent_hashers() {
+        let builder = || {
+            BloomBuilder::new(1_000, 0.01).hashers(
+                (0..3)
+                    .map(|seed| Box::new(KeyedHasher::new(seed)) as Box<dyn HasherInstance<&str>>)
+                    .collect(),
+            )
+        };
+        let mut dense = builder().build();
+        let mut sparse = builder().build_roaring();
+        dense.insert("foo");
+        sparse.insert("foo");
+        assert_eq!(dense.bits, BloomFilter::from(&sparse).bits);
+        assert_eq!(dense.params(), sparse.params());
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt;
+
+/// A snapshot of a bloom filter's parameters and how full it is, suitable
+/// for logging or emitting as metrics. See `BloomFilter::stats`. Implements
+/// `Serialize` and `Deserialize` with the `serde` feature, to be emitted as structured
+/// logs.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BloomStats {
+    /// The number of items the filter was sized for.
+    pub capacity: u64,
+    /// The number of hash functions used for each item.
+    pub num_hash_fns: u32,
+    /// The number of bits in the filter.
+    pub bit_len: usize,
+    /// The number of set bits in the filter.
+    pub set_bits: usize,
+    /// The fraction of the filter's bits that are set.
+    pub fill_ratio: f64,
+    /// The estimated number of distinct items inserted into the filter.
+    pub estimated_count: f64,
+    /// The estimated false positive rate given how full the filter is.
+    pub estimated_fp_rate: f64,
+}
+
+/// Displays the stats as comma-separated key=value pairs.
+impl fmt::Display for BloomStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "capacity={}, num_hash_fns={}, bit_len={}, set_bits={}, fill_ratio={}, estimated_count={}, estimated_fp_rate={}",
+            self.capacity,
+            self.num_hash_fns,
+            self.bit_len,
+            self.set_bits,
+            self.fill_ratio,
+            self.estimated_count,
+            self.estimated_fp_rate,
+        )
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::format::invalid;
+use crate::hasher::builtin_hasher;
+use crate::{BloomFilter, Error};
+
+/// An embedded key-value store that a `PersistentBloomStore` keeps its filters in,
+/// each encoded as by `BloomFilter::to_bytes` under its name.
+///
+/// Implemented for `sled::Tree` with the `sled` feature. Other stores, such as
+/// RocksDB, can be used by implementing this trait over their own write batches.
+pub trait StoreBackend {
+    /// Returns the value stored under a key, if any.
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+    /// Applies a batch of writes atomically, storing each value under its key, or
+    /// removing the key if the value is None.
+    fn write_batch(&self, batch: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> io::Result<()>;
+    /// Returns once all writes applied so far are durably stored.
+    fn flush(&self) -> io::Result<()>;
+}
+
+#[cfg(feature = "sled")]
+impl StoreBackend for sled::Tree {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(sled::Tree::get(self, key)?.map(|value| value.to_vec()))
+    }
+    fn write_batch(&self, batch: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> io::Result<()> {
+        let mut writes = sled::Batch::default();
+        for (key, value) in batch {
+            match value {
+                Some(value) => writes.insert(key, value),
+                None => writes.remove(key),
+            }
+        }
+        Ok(self.apply_batch(writes)?)
+    }
+    fn flush(&self) -> io::Result<()> {
+        sled::Tree::flush(self)?;
+        Ok(())
+    }
+}
+
+/// A filter loaded from the backend, along with whether it changed since it was last
+/// written back, and when it was last used.
+struct Loaded<T: AsRef<[u8]>> {
+    filter: BloomFilter<T>,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// Keeps many named bloom filters durable in an embedded key-value store, such as one
+/// filter per tenant, loading each into memory on first use and writing changed
+/// filters back in a single atomic batch on `flush`.
+///
+/// The number of filters kept in memory can be bounded with `max_loaded`, evicting the
+/// least recently used filter once it is reached, and changes can be flushed
+/// periodically with `flush_interval`. Changes that have not been flushed are lost if
+/// the process exits, so `flush` should be called before shutting down. Only filters
+/// built with one of the package's hashers can be stored.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter, PersistentBloomStore, StoreBackend};
+/// # use std::{cell::RefCell, collections::HashMap, io};
+/// # #[derive(Default)]
+/// # struct Memory(RefCell<HashMap<Vec<u8>, Vec<u8>>>);
+/// # impl StoreBackend for Memory {
+/// #     fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+/// #         Ok(self.0.borrow().get(key).cloned())
+/// #     }
+/// #     fn write_batch(&self, batch: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> io::Result<()> {
+/// #         for (key, value) in batch {
+/// #             match value {
+/// #                 Some(value) => self.0.borrow_mut().insert(key, value),
+/// #                 None => self.0.borrow_mut().remove(&key),
+/// #             };
+/// #         }
+/// #         Ok(())
+/// #     }
+/// #     fn flush(&self) -> io::Result<()> {
+/// #         Ok(())
+/// #     }
+/// # }
+///
+/// // Any backend works, such as a `sled::Tree` with the `sled` feature.
+/// let backend = Memory::default();
+/// let mut store = PersistentBloomStore::new(backend).max_loaded(10_000);
+/// store.replace("tenant-1", BloomFilter::new(1000, 0.01)).unwrap();
+/// store.insert("tenant-1", "hello").unwrap();
+/// store.flush().unwrap();
+///
+/// let mut store = PersistentBloomStore::new(store.into_backend());
+/// assert!(store.has("tenant-1", "hello").unwrap());
+/// assert!(!store.has("tenant-2", "hello").unwrap());
+/// ```
+pub struct PersistentBloomStore<T: AsRef<[u8]>, B: StoreBackend> {
+    backend: B,
+    loaded: HashMap<String, Loaded<T>>,
+    max_loaded: usize,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    // Incremented on every access, to find the least recently used filter.
+    clock: u64,
+}
+
+impl<T: AsRef<[u8]>, B: StoreBackend> PersistentBloomStore<T, B> {
+    /// Creates a store keeping filters in a backend, without loading any of them.
+    pub fn new(backend: B) -> PersistentBloomStore<T, B> {
+        PersistentBloomStore {
+            backend,
+            loaded: HashMap::new(),
+            max_loaded: usize::MAX,
+            flush_interval: None,
+            last_flush: Instant::now(),
+            clock: 0,
+        }
+    }
+    /// Sets the maximum number of filters kept in memory. Loading a filter once it is
+    /// reached evicts the least recently used one, writing it back first if it changed.
+    /// Panics if the maximum is zero.
+    pub fn max_loaded(mut self, max_loaded: usize) -> PersistentBloomStore<T, B> {
+        assert!(max_loaded > 0, "at least one filter must fit in memory");
+        self.max_loaded = max_loaded;
+        self
+    }
+    /// Flushes changed filters automatically on the first insert made once an interval
+    /// has passed since the last flush.
+    pub fn flush_interval(mut self, interval: Duration) -> PersistentBloomStore<T, B> {
+        self.flush_interval = Some(interval);
+        self
+    }
+    /// Insert an element into a named filter, loading it if needed. Returns true if the
+    /// element was probably not in the filter before, as in `BloomFilter::insert`. Fails
+    /// with an error of kind `NotFound` if no filter is stored under the name.
+    pub fn insert(&mut self, name: &str, elem: T) -> io::Result<bool> {
+        let loaded = self
+            .load(name)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "filter not found"))?;
+        loaded.dirty = true;
+        let changed = loaded.filter.insert(elem);
+        if let Some(interval) = self.flush_interval {
+            if self.last_flush.elapsed() >= interval {
+                self.flush()?;
+            }
+        }
+        Ok(changed)
+    }
+    /// Checks if a named filter contains an element, loading it if needed. Returns
+    /// false if no filter is stored under the name.
+    pub fn has(&mut self, name: &str, elem: T) -> io::Result<bool> {
+        Ok(self
+            .load(name)?
+            .is_some_and(|loaded| loaded.filter.has(elem)))
+    }
+    /// Returns a named filter, loading it if needed, or None if no filter is stored
+    /// under the name.
+    pub fn get(&mut self, name: &str) -> io::Result<Option<&BloomFilter<T>>> {
+        Ok(self.load(name)?.map(|loaded| &loaded.filter))
+    }
+    /// Stores a filter under a name, replacing any filter stored under it. The filter
+    /// is written to the backend immediately in a single write, so the previous filter
+    /// is durably replaced as a whole once this returns, such as when rotating a
+    /// tenant's filter for a fresh one. Fails with `Error::UnknownHasher` if the filter
+    /// uses a custom hasher or independent hashers, as it could not be restored.
+    pub fn replace(&mut self, name: &str, filter: BloomFilter<T>) -> io::Result<()> {
+        let params = filter.params();
+        if builtin_hasher::<T>(params.hasher_id, params.seed).is_none() {
+            return Err(invalid(Error::UnknownHasher(params.hasher_id)));
+        }
+        self.backend
+            .write_batch(vec![(name.into(), Some(filter.to_bytes()))])?;
+        self.backend.flush()?;
+        self.loaded.remove(name);
+        self.make_room()?;
+        self.clock += 1;
+        self.loaded.insert(
+            name.to_string(),
+            Loaded {
+                filter,
+                dirty: false,
+                last_used: self.clock,
+            },
+        );
+        Ok(())
+    }
+    /// Removes a named filter from memory and from the backend. Returns true if a filter
+    /// was stored under the name.
+    pub fn remove(&mut self, name: &str) -> io::Result<bool> {
+        let existed =
+            self.loaded.remove(name).is_some() || self.backend.get(name.as_bytes())?.is_some();
+        self.backend.write_batch(vec![(name.into(), None)])?;
+        self.backend.flush()?;
+        Ok(existed)
+    }
+    /// Writes every filter that changed since it was loaded or last flushed back to
+    /// the backend in a single atomic batch, returning once they are durably stored.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let batch: Vec<_> = self
+            .loaded
+            .iter()
+            .filter(|(_, loaded)| loaded.dirty)
+            .map(|(name, loaded)| (name.as_bytes().to_vec(), Some(loaded.filter.to_bytes())))
+            .collect();
+        if !batch.is_empty() {
+            self.backend.write_batch(batch)?;
+            self.backend.flush()?;
+        }
+        self.loaded
+            .values_mut()
+            .for_each(|loaded| loaded.dirty = false);
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+    /// Returns the number of filters currently loaded in memory.
+    pub fn num_loaded(&self) -> usize {
+        self.loaded.len()
+    }
+    /// Returns the backend, dropping every filter loaded in memory, including changes
+    /// that were not flushed.
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
+    fn load(&mut self, name: &str) -> io::Result<Option<&mut Loaded<T>>> {
+        self.clock += 1;
+        if !self.loaded.contains_key(name) {
+            let Some(bytes) = self.backend.get(name.as_bytes())? else {
+                return Ok(None);
+            };
+            let filter = BloomFilter::from_bytes(&bytes).map_err(invalid)?;
+            self.make_room()?;
+            self.loaded.insert(
+                name.to_string(),
+                Loaded {
+                    filter,
+                    dirty: false,
+                    last_used: 0,
+                },
+            );
+        }
+        let loaded = self.loaded.get_mut(name).unwrap();
+        loaded.last_used = self.clock;
+        Ok(Some(loaded))
+    }
+    /// Evicts the least recently used filter if the maximum number of filters is
+    /// loaded, writing it back first if it changed.
+    fn make_room(&mut self) -> io::Result<()> {
+        if self.loaded.len() < self.max_loaded {
+            return Ok(());
+        }
+        let Some(name) = self
+            .loaded
+            .iter()
+            .min_by_key(|(_, loaded)| loaded.last_used)
+            .map(|(name, _)| name.clone())
+        else {
+            return Ok(());
+        };
+        // The filter stays loaded until it is written, so a failed write loses nothing.
+        if let Some(evicted) = self.loaded.get_mut(&name).filter(|loaded| loaded.dirty) {
+            let bytes = evicted.filter.to_bytes();
+            self.backend
+                .write_batch(vec![(name.clone().into_bytes(), Some(bytes))])?;
+            self.backend.flush()?;
+            evicted.dirty = false;
+        }
+        self.loaded.remove(&name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::BloomBuilder;
+
+    /// A backend keeping values in memory, counting the batches written to it, whose
+    /// writes fail while `failing` is set.
+    #[derive(Default)]
+    struct Memory {
+        values: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+        batches: Cell<usize>,
+        failing: Cell<bool>,
+    }
+
+    impl StoreBackend for Memory {
+        fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.values.borrow().get(key).cloned())
+        }
+        fn write_batch(&self, batch: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> io::Result<()> {
+            if self.failing.get() {
+                return Err(io::Error::other("write failed"));
+            }
+            let mut values = self.values.borrow_mut();
+            for (key, value) in batch {
+                match value {
+                    Some(value) => values.insert(key, value),
+                    None => values.remove(&key),
+                };
+            }
+            self.batches.set(self.batches.get() + 1);
+            Ok(())
+        }
+        fn flush(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn filter() -> BloomFilter<String> {
+        BloomBuilder::new(100, 0.01).build()
+    }
+
+    #[test]
+    fn loads_on_demand() {
+        let mut store = PersistentBloomStore::new(Memory::default());
+        for tenant in 0..10 {
+            store.replace(&format!("{}", tenant), filter()).unwrap();
+            store
+                .insert(&format!("{}", tenant), format!("item-{}", tenant))
+                .unwrap();
+        }
+        store.flush().unwrap();
+        let backend = store.into_backend();
+        assert_eq!(10, backend.values.borrow().len());
+        let batches = backend.batches.get();
+
+        let mut store: PersistentBloomStore<String, _> = PersistentBloomStore::new(backend);
+        assert_eq!(0, store.num_loaded());
+        assert!(store.has("3", "item-3".to_string()).unwrap());
+        assert!(!store.has("3", "item-4".to_string()).unwrap());
+        assert_eq!(1, store.num_loaded());
+        assert_eq!(1, store.get("3").unwrap().unwrap().len());
+        assert!(store.get("missing").unwrap().is_none());
+        let err = store.insert("missing", "foo".to_string()).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+
+        // Flushing without changes writes nothing.
+        store.flush().unwrap();
+        assert_eq!(batches, store.into_backend().batches.get());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut store = PersistentBloomStore::new(Memory::default()).max_loaded(2);
+        for tenant in ["a", "b", "c"] {
+            store.replace(tenant, filter()).unwrap();
+        }
+        assert_eq!(2, store.num_loaded());
+        store.insert("b", "foo".to_string()).unwrap();
+        store.insert("c", "bar".to_string()).unwrap();
+        // Loading a evicts b, writing back its insert.
+        assert!(!store.has("a", "foo".to_string()).unwrap());
+        assert!(!store.loaded.contains_key("b"));
+        // Loading b again evicts c, writing back its insert too.
+        assert!(store.has("b", "foo".to_string()).unwrap());
+        assert_eq!(2, store.num_loaded());
+        store.insert("a", "baz".to_string()).unwrap();
+
+        // Changes to a were neither flushed nor evicted, so they are lost.
+        let mut store: PersistentBloomStore<String, _> =
+            PersistentBloomStore::new(store.into_backend());
+        assert!(store.has("b", "foo".to_string()).unwrap());
+        assert!(store.has("c", "bar".to_string()).unwrap());
+        assert!(!store.has("a", "baz".to_string()).unwrap());
+    }
+
+    #[test]
+    fn failed_eviction_keeps_filter() {
+        let mut store = PersistentBloomStore::new(Memory::default()).max_loaded(1);
+        store.replace("b", filter()).unwrap();
+        store.replace("a", filter()).unwrap();
+        store.insert("a", "foo".to_string()).unwrap();
+        store.backend.failing.set(true);
+        assert!(store.has("b", "foo".to_string()).is_err());
+        assert!(store.loaded["a"].dirty);
+        assert!(!store.loaded.contains_key("b"));
+
+        store.backend.failing.set(false);
+        store.flush().unwrap();
+        let mut store: PersistentBloomStore<String, _> =
+            PersistentBloomStore::new(store.into_backend());
+        assert!(store.has("a", "foo".to_string()).unwrap());
+    }
+
+    #[test]
+    fn periodic_flush() {
+        let mut store = PersistentBloomStore::new(Memory::default()).flush_interval(Duration::ZERO);
+        store.replace("a", filter()).unwrap();
+        store.insert("a", "foo".to_string()).unwrap();
+        let mut store: PersistentBloomStore<String, _> =
+            PersistentBloomStore::new(store.into_backend());
+        assert!(store.has("a", "foo".to_string()).unwrap());
+    }
+
+    #[test]
+    fn replace_and_remove() {
+        let mut store = PersistentBloomStore::new(Memory::default());
+        store.replace("a", filter()).unwrap();
+        store.insert("a", "foo".to_string()).unwrap();
+        // Replacing discards unflushed changes to the previous filter.
+        store.replace("a", filter()).unwrap();
+        assert!(!store.has("a", "foo".to_string()).unwrap());
+
+        assert!(store.remove("a").unwrap());
+        assert!(!store.remove("a").unwrap());
+        assert!(store.get("a").unwrap().is_none());
+        assert!(store.into_backend().values.borrow().is_empty());
+
+        let mut store = PersistentBloomStore::new(Memory::default());
+        let custom = BloomBuilder::new(100, 0.01).hash_fn(|item: &String| item.len() as u64);
+        let err = store.replace("a", custom.build()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_backend() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut store = PersistentBloomStore::new(db.open_tree("filters").unwrap());
+        store.replace("a", filter()).unwrap();
+        store.replace("b", filter()).unwrap();
+        store.insert("a", "foo".to_string()).unwrap();
+        store.flush().unwrap();
+        store.remove("b").unwrap();
+
+        let mut store: PersistentBloomStore<String, _> =
+            PersistentBloomStore::new(db.open_tree("filters").unwrap());
+        assert!(store.has("a", "foo".to_string()).unwrap());
+        assert!(store.get("b").unwrap().is_none());
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{BloomFilter, Error};
+
+/// The operations every kind of bloom filter shares with `BloomFilter`, so that each of
+/// them can be checked against it with `assert_matches_dense`.
+pub(crate) trait Backend {
+    fn insert(&mut self, elem: String) -> bool;
+    fn insert_with_class(&mut self, elem: String, class: usize) -> Result<bool, Error>;
+    fn has(&self, elem: String) -> bool;
+    fn has_with_class(&self, elem: String, class: usize) -> Result<bool, Error>;
+    fn to_filter(&self) -> BloomFilter<String>;
+}
+
+/// Checks that a filter behaves exactly like an empty `BloomFilter` with the same
+/// parameters and hasher: inserting a number of elements, and one element per tier,
+/// returns the same results and sets the same bits, and every element, inserted or
+/// not, is reported as a member by both or by neither.
+pub(crate) fn assert_matches_dense<B: Backend>(
+    mut dense: BloomFilter<String>,
+    mut bf: B,
+    n: usize,
+) {
+    let copy = bf.to_filter();
+    assert_eq!(dense.params(), copy.params());
+    assert_eq!(dense.bits, copy.bits);
+    for i in 0..n {
+        assert_eq!(dense.insert(format!("{}", i)), bf.insert(format!("{}", i)));
+    }
+    for class in 0..dense.params().tier_hash_fns.len() {
+        let elem = format!("class-{}", class);
+        assert_eq!(
+            dense.insert_with_class(elem.clone(), class),
+            bf.insert_with_class(elem.clone(), class)
+        );
+        assert_eq!(Ok(true), bf.has_with_class(elem, class));
+    }
+    let class = dense.params().tier_hash_fns.len();
+    assert_eq!(
+        Err(Error::UnknownClass(class)),
+        bf.insert_with_class("foo".to_string(), class)
+    );
+    for i in 0..2 * n {
+        assert_eq!(dense.has(format!("{}", i)), bf.has(format!("{}", i)));
+    }
+    let copy = bf.to_filter();
+    assert_eq!(dense.bits, copy.bits);
+    assert_eq!(dense.len(), copy.len());
+}
@@ -0,0 +1,444 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::format::{crc32_update, invalid};
+use crate::hasher::builtin_hasher;
+use crate::{BloomFilter, Error};
+
+/// The size of a log record: the two halves of an element's hash and its number of
+/// probes, followed by a CRC-32 checksum of the three.
+const RECORD_LEN: usize = 24;
+
+/// A bloom filter persisted to a directory through a write-ahead log, so that inserts
+/// are durable as soon as they are synced, without rewriting the whole filter each time.
+/// A crash loses at most the inserts made since the last `sync`, rather than everything
+/// since the filter was last saved.
+///
+/// The directory holds a snapshot of the filter, encoded as by `BloomFilter::write_to`,
+/// and a log to which every insert appends the hash of its element. Opening the filter
+/// loads the snapshot and replays the log on top of it, ignoring a partially written
+/// record at its end. Once the log grows larger than the snapshot, it is compacted into
+/// a new snapshot, and can also be compacted at any time with `compact`.
+///
+/// Only filters built with one of the package's hashers can be persisted, as they are
+/// restored from their snapshot.
+///
+/// ## Example
+/// ```
+/// use flowerbloom::{BloomFilter, WalBloomFilter};
+///
+/// let dir = std::env::temp_dir().join("flowerbloom-wal-doc");
+/// let bf: BloomFilter<&str> = BloomFilter::new(1000, 0.01);
+/// let mut wal = WalBloomFilter::create(&dir, bf).unwrap();
+/// wal.insert("hello").unwrap();
+/// wal.sync().unwrap();
+/// drop(wal);
+///
+/// let wal: WalBloomFilter<&str> = WalBloomFilter::open(&dir).unwrap();
+/// assert!(wal.has("hello"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct WalBloomFilter<T: AsRef<[u8]>> {
+    filter: BloomFilter<T>,
+    dir: PathBuf,
+    // Snapshots and logs are numbered by generation, so that a snapshot is never
+    // combined with a log that was already compacted into it.
+    generation: u64,
+    log: BufWriter<File>,
+    log_len: u64,
+}
+
+impl<T: AsRef<[u8]>> WalBloomFilter<T> {
+    /// Persists a bloom filter to a directory, creating it if needed, and replacing any
+    /// filter previously persisted there. Fails with `Error::UnknownHasher` if the
+    /// filter uses a custom hasher or independent hashers, as it could not be restored.
+    pub fn create<P: AsRef<Path>>(dir: P, filter: BloomFilter<T>) -> io::Result<WalBloomFilter<T>> {
+        let params = filter.params();
+        if builtin_hasher::<T>(params.hasher_id, params.seed).is_none() {
+            return Err(invalid(Error::UnknownHasher(params.hasher_id)));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let generation = generations(&dir)?.into_iter().max().map_or(0, |g| g + 1);
+        write_snapshot(&dir, generation, &filter)?;
+        let log = create_log(&dir, generation)?;
+        remove_before(&dir, generation)?;
+        Ok(WalBloomFilter {
+            filter,
+            dir,
+            generation,
+            log: BufWriter::new(log),
+            log_len: 0,
+        })
+    }
+    /// Opens a filter persisted to a directory by `create`, replaying every insert
+    /// logged since its last compaction. A record left incomplete or corrupted by a
+    /// crash, or probing a number of hash functions the snapshot's parameters do not
+    /// allow, ends the log, and is truncated away.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<WalBloomFilter<T>> {
+        let dir = dir.as_ref().to_path_buf();
+        let generation = generations(&dir)?
+            .into_iter()
+            .max()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no snapshot found"))?;
+        let snapshot = File::open(snapshot_path(&dir, generation))?;
+        let mut filter = BloomFilter::read_from(io::BufReader::new(snapshot))?;
+        // A crash during compaction can leave a snapshot without its log.
+        let mut log = match OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(log_path(&dir, generation))
+        {
+            Ok(log) => log,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => create_log(&dir, generation)?,
+            Err(err) => return Err(err),
+        };
+        let mut records = vec![];
+        log.read_to_end(&mut records)?;
+        let mut log_len = 0;
+        for record in records.chunks_exact(RECORD_LEN) {
+            let Some((hashes, num_hash_fns)) = decode_record(record, &filter) else {
+                break;
+            };
+            filter.insert_hashed(hashes, num_hash_fns);
+            log_len += RECORD_LEN as u64;
+        }
+        if log_len != records.len() as u64 {
+            log.set_len(log_len)?;
+            log.sync_data()?;
+        }
+        remove_before(&dir, generation)?;
+        Ok(WalBloomFilter {
+            filter,
+            dir,
+            generation,
+            log: BufWriter::new(log),
+            log_len,
+        })
+    }
+    /// Insert an element into the bloom filter, appending it to the log. Returns true
+    /// if the element was probably not in the filter before, as in `BloomFilter::insert`.
+    /// The insert is only durable once `sync` is called.
+    pub fn insert(&mut self, elem: T) -> io::Result<bool> {
+        let num_hash_fns = self.filter.num_hash_fns;
+        self.insert_with_probes(&elem, num_hash_fns)
+    }
+    /// Insert an element into the bloom filter with the number of hash functions of a
+    /// tier, appending it to the log, as in `BloomFilter::insert_with_class`. Fails with
+    /// an `InvalidInput` error if the class was not configured as a tier.
+    pub fn insert_with_class(&mut self, elem: T, class: usize) -> io::Result<bool> {
+        let num_hash_fns = self
+            .filter
+            .class_num_hash_fns(class)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        self.insert_with_probes(&elem, num_hash_fns)
+    }
+    /// Checks if the bloom filter contains a specified element. Like any bloom filter,
+    /// it can produce false positives, but will never produce false negatives.
+    pub fn has(&self, elem: T) -> bool {
+        self.filter.has(elem)
+    }
+    /// Writes all logged inserts to disk, returning once they are durably stored.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.log.flush()?;
+        self.log.get_ref().sync_data()
+    }
+    /// Writes the filter to a new snapshot and starts an empty log, so that the filter
+    /// can be opened without replaying its inserts. The snapshot is written to a
+    /// temporary file and renamed into place, so a crash leaves either the previous
+    /// snapshot and its log, or the new snapshot.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.sync()?;
+        let generation = self.generation + 1;
+        write_snapshot(&self.dir, generation, &self.filter)?;
+        self.log = BufWriter::new(create_log(&self.dir, generation)?);
+        self.generation = generation;
+        self.log_len = 0;
+        remove_before(&self.dir, generation)
+    }
+    /// Returns the in-memory bloom filter, including inserts not yet synced.
+    pub fn filter(&self) -> &BloomFilter<T> {
+        &self.filter
+    }
+    /// Returns the size in bytes of the log since the last compaction.
+    pub fn log_len(&self) -> u64 {
+        self.log_len
+    }
+    /// Returns the number of elements inserted into the bloom filter.
+    pub fn len(&self) -> usize {
+        self.filter.len()
+    }
+    /// Returns true if no elements have been inserted into the bloom filter.
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty()
+    }
+    fn insert_with_probes(&mut self, elem: &T, num_hash_fns: u32) -> io::Result<bool> {
+        let hashes = self.filter.hash(elem);
+        self.log.write_all(&encode_record(hashes, num_hash_fns))?;
+        self.log_len += RECORD_LEN as u64;
+        let changed = self.filter.insert_hashed(hashes, num_hash_fns);
+        if self.log_len > self.filter.byte_len() as u64 {
+            self.compact()?;
+        }
+        Ok(changed)
+    }
+}
+
+fn encode_record((h1, h2): (u64, u64), num_hash_fns: u32) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    record[..8].copy_from_slice(&h1.to_be_bytes());
+    record[8..16].copy_from_slice(&h2.to_be_bytes());
+    record[16..20].copy_from_slice(&num_hash_fns.to_be_bytes());
+    let crc = crc32_update(0, &record[..20]);
+    record[20..].copy_from_slice(&crc.to_be_bytes());
+    record
+}
+
+/// Decodes a log record, returning `None` if its checksum does not match or if its
+/// number of probes is neither the filter's nor one of its tiers', as replaying it could
+/// otherwise probe the filter billions of times.
+fn decode_record<T: AsRef<[u8]>>(
+    record: &[u8],
+    filter: &BloomFilter<T>,
+) -> Option<((u64, u64), u32)> {
+    let crc = u32::from_be_bytes(record[20..24].try_into().unwrap());
+    if crc32_update(0, &record[..20]) != crc {
+        return None;
+    }
+    let h1 = u64::from_be_bytes(record[..8].try_into().unwrap());
+    let h2 = u64::from_be_bytes(record[8..16].try_into().unwrap());
+    let num_hash_fns = u32::from_be_bytes(record[16..20].try_into().unwrap());
+    if num_hash_fns != filter.num_hash_fns && !filter.class_hash_fns.contains(&num_hash_fns) {
+        return None;
+    }
+    Some(((h1, h2), num_hash_fns))
+}
+
+fn snapshot_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("{:016x}.snapshot", generation))
+}
+
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("{:016x}.log", generation))
+}
+
+/// Returns the generations of the snapshots in a directory.
+fn generations(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut generations = vec![];
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(generation) = name.strip_suffix(".snapshot") {
+            if let Ok(generation) = u64::from_str_radix(generation, 16) {
+                generations.push(generation);
+            }
+        }
+    }
+    Ok(generations)
+}
+
+fn write_snapshot<T: AsRef<[u8]>>(
+    dir: &Path,
+    generation: u64,
+    filter: &BloomFilter<T>,
+) -> io::Result<()> {
+    let tmp = dir.join(format!("{:016x}.snapshot.tmp", generation));
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    filter.write_to(&mut w)?;
+    w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    fs::rename(&tmp, snapshot_path(dir, generation))?;
+    sync_dir(dir)
+}
+
+fn create_log(dir: &Path, generation: u64) -> io::Result<File> {
+    let log = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .truncate(false)
+        .open(log_path(dir, generation))?;
+    sync_dir(dir)?;
+    Ok(log)
+}
+
+/// Removes the snapshots and logs of generations older than a given one, along with
+/// temporary files left by interrupted compactions.
+fn remove_before(dir: &Path, generation: u64) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let stale = match name.split_once('.') {
+            Some((_, "snapshot.tmp")) => true,
+            Some((g, "snapshot" | "log")) => {
+                u64::from_str_radix(g, 16).is_ok_and(|g| g < generation)
+            }
+            _ => false,
+        };
+        if stale {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes the creation and renaming of files in a directory durable.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomBuilder;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("flowerbloom-wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn replays_log() {
+        let dir = temp_dir("replay");
+        let bf: BloomFilter<String> = BloomFilter::new(1_000, 0.01);
+        let mut wal = WalBloomFilter::create(&dir, bf).unwrap();
+        assert!(wal.is_empty());
+        for i in 0..10 {
+            assert!(wal.insert(format!("{}", i)).unwrap());
+        }
+        wal.sync().unwrap();
+        assert_eq!(10 * RECORD_LEN as u64, wal.log_len());
+        let bits = wal.filter().bits.clone();
+        drop(wal);
+
+        let wal: WalBloomFilter<String> = WalBloomFilter::open(&dir).unwrap();
+        assert_eq!(10, wal.len());
+        assert_eq!(bits, wal.filter().bits);
+        assert!((0..10).all(|i| wal.has(format!("{}", i))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncates_torn_records() {
+        let dir = temp_dir("torn");
+        let bf: BloomFilter<&str> = BloomFilter::new(1_000, 0.01);
+        let mut wal = WalBloomFilter::create(&dir, bf).unwrap();
+        wal.insert("foo").unwrap();
+        wal.insert("bar").unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        // Corrupt the second record and leave half of a third one behind it.
+        let path = log_path(&dir, 0);
+        let mut log = fs::read(&path).unwrap();
+        log[RECORD_LEN + 3] ^= 1;
+        log.extend_from_slice(&[0; RECORD_LEN / 2]);
+        fs::write(&path, &log).unwrap();
+
+        let mut wal: WalBloomFilter<&str> = WalBloomFilter::open(&dir).unwrap();
+        assert_eq!(1, wal.len());
+        assert!(wal.has("foo"));
+        assert_eq!(RECORD_LEN as u64, fs::metadata(&path).unwrap().len());
+        wal.insert("baz").unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        let wal: WalBloomFilter<&str> = WalBloomFilter::open(&dir).unwrap();
+        assert_eq!(2, wal.len());
+        assert!(wal.has("foo") && wal.has("baz"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncates_invalid_probes() {
+        let dir = temp_dir("probes");
+        let bf: BloomFilter<&str> = BloomBuilder::new(1_000, 0.01).tier(0.001).build();
+        let mut wal = WalBloomFilter::create(&dir, bf).unwrap();
+        wal.insert("foo").unwrap();
+        wal.insert_with_class("bar", 0).unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        // A record with a valid checksum but a number of probes the filter never uses.
+        let path = log_path(&dir, 0);
+        let mut log = fs::read(&path).unwrap();
+        log.extend_from_slice(&encode_record((1, 2), u32::MAX));
+        fs::write(&path, &log).unwrap();
+
+        let wal: WalBloomFilter<&str> = WalBloomFilter::open(&dir).unwrap();
+        assert_eq!(2, wal.len());
+        assert!(wal.has("foo") && wal.has("bar"));
+        assert_eq!(2 * RECORD_LEN as u64, fs::metadata(&path).unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compaction() {
+        let dir = temp_dir("compact");
+        let bf: BloomFilter<String> = BloomBuilder::new(100, 0.01).tier(0.001).build();
+        let mut wal = WalBloomFilter::create(&dir, bf).unwrap();
+        wal.insert_with_class("foo".to_string(), 0).unwrap();
+        wal.compact().unwrap();
+        assert_eq!(0, wal.log_len());
+        assert!(!snapshot_path(&dir, 0).exists() && !log_path(&dir, 0).exists());
+
+        // The log is compacted automatically once it outgrows the snapshot.
+        let snapshot_len = wal.filter().byte_len() as u64;
+        for i in 0..100 {
+            wal.insert(format!("{}", i)).unwrap();
+            assert!(wal.log_len() <= snapshot_len);
+        }
+        assert!(wal.generation > 1);
+        wal.sync().unwrap();
+        let bits = wal.filter().bits.clone();
+        drop(wal);
+
+        let wal: WalBloomFilter<String> = WalBloomFilter::open(&dir).unwrap();
+        assert_eq!(101, wal.len());
+        assert_eq!(bits, wal.filter().bits);
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_compaction() {
+        let dir = temp_dir("interrupted");
+        let bf: BloomFilter<&str> = BloomFilter::new(1_000, 0.01);
+        let mut wal = WalBloomFilter::create(&dir, bf).unwrap();
+        wal.insert("foo").unwrap();
+        wal.sync().unwrap();
+        // A crash after the new snapshot is renamed into place, but before its log is
+        // created, must not replay the previous log on top of it.
+        write_snapshot(&dir, 1, wal.filter()).unwrap();
+        fs::write(dir.join("0000000000000002.snapshot.tmp"), b"partial").unwrap();
+        drop(wal);
+
+        let wal: WalBloomFilter<&str> = WalBloomFilter::open(&dir).unwrap();
+        assert_eq!(1, wal.len());
+        assert!(wal.has("foo"));
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn custom_hashers() {
+        let dir = temp_dir("custom");
+        let bf: BloomFilter<&str> = BloomBuilder::new(100, 0.01)
+            .hashers(vec![
+                Box::new(crate::KeyedHasher::new(1)) as Box<dyn crate::HasherInstance<&str>>,
+                Box::new(crate::KeyedHasher::new(2)),
+            ])
+            .build();
+        let err = WalBloomFilter::create(&dir, bf).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,163 @@
+//! Compact encodings of bloom filters with postcard and bincode, for sending filters
+//! between embedded devices and servers that both speak one of those formats.
+//!
+//! Both encodings serialize the same fields in a fixed order: the filter's parameters,
+//! that is its capacity, bit length, number of hash functions, number of hash functions
+//! of each tier, hasher id and seed, followed by the number of inserted elements, the
+//! maximum false positive rate, and finally the bits. Integers are variable-length,
+//! options are a tag byte followed by their value, floats are 8 little-endian bytes,
+//! and the bits are their length followed by the raw bytes.
+//!
+//! The configuration of each format is pinned rather than left to serde defaults:
+//! postcard uses its only encoding, and bincode uses `bincode::config::standard()`,
+//! which is little-endian with variable-length integers. With these configurations both
+//! formats produce the same bytes for typical filters. Beyond its bits, a filter costs
+//! at most 80 bytes plus 5 bytes per tier in either format, around 25 bytes for a
+//! filter with a seeded hasher and no tiers, and around 10 bytes for a deterministic one.
+use crate::serialize::Repr;
+use crate::{BloomFilter, Error};
+
+impl<T: AsRef<[u8]>> BloomFilter<T> {
+    /// Encodes the bloom filter with postcard, see the `wire` module for its layout.
+    /// Requires the `postcard` feature.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let restored: BloomFilter<&str> = BloomFilter::from_postcard(&bf.to_postcard()).unwrap();
+    /// assert!(restored.has("hello"));
+    /// ```
+    #[cfg(feature = "postcard")]
+    pub fn to_postcard(&self) -> Vec<u8> {
+        match postcard::to_allocvec(&Repr::new(self)) {
+            Ok(bytes) => bytes,
+            // Serializing to a vector never fails.
+            Err(_) => unreachable!(),
+        }
+    }
+    /// Decodes a bloom filter encoded with `to_postcard`, restoring it with the hasher
+    /// identified in the encoding. Fails with `Error::InvalidEncoding` if the input is
+    /// malformed or has trailing bytes, and with `Error::UnknownHasher` if the filter
+    /// was not built with one of the package's hashers.
+    #[cfg(feature = "postcard")]
+    pub fn from_postcard(bytes: &[u8]) -> Result<BloomFilter<T>, Error> {
+        match postcard::take_from_bytes::<Repr<Vec<u8>>>(bytes) {
+            Ok((repr, [])) => repr.into_filter(),
+            _ => Err(Error::InvalidEncoding),
+        }
+    }
+    /// Encodes the bloom filter with bincode's standard configuration, see the `wire`
+    /// module for its layout. Requires the `bincode` feature.
+    ///
+    /// ## Example
+    /// ```
+    /// use flowerbloom::{BloomBuilder, BloomFilter};
+    ///
+    /// let mut bf: BloomFilter<&str> = BloomBuilder::new(1000, 0.01).build();
+    /// bf.insert("hello");
+    ///
+    /// let restored: BloomFilter<&str> = BloomFilter::from_bincode(&bf.to_bincode()).unwrap();
+    /// assert!(restored.has("hello"));
+    /// ```
+    #[cfg(feature = "bincode")]
+    pub fn to_bincode(&self) -> Vec<u8> {
+        match bincode::serde::encode_to_vec(Repr::new(self), bincode::config::standard()) {
+            Ok(bytes) => bytes,
+            // Serializing to a vector never fails.
+            Err(_) => unreachable!(),
+        }
+    }
+    /// Decodes a bloom filter encoded with `to_bincode`, restoring it with the hasher
+    /// identified in the encoding. Fails with `Error::InvalidEncoding` if the input is
+    /// malformed or has trailing bytes, and with `Error::UnknownHasher` if the filter
+    /// was not built with one of the package's hashers.
+    #[cfg(feature = "bincode")]
+    pub fn from_bincode(bytes: &[u8]) -> Result<BloomFilter<T>, Error> {
+        let config = bincode::config::standard();
+        match bincode::serde::decode_from_slice::<Repr<Vec<u8>>, _>(bytes, config) {
+            Ok((repr, len)) if len == bytes.len() => repr.into_filter(),
+            _ => Err(Error::InvalidEncoding),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BloomBuilder, BloomFilter, Error, HmacHasher};
+
+    // Capacity 10, 96 bits, 7 hash functions, no tiers, hasher id 1 without a seed,
+    // 1 inserted element, no maximum false positive rate, and 12 bytes of bits.
+    const ENCODED: [u8; 21] = [
+        10, 96, 7, 0, 1, 0, 1, 0, 12, 0, 0, 0, 38, 0, 0, 128, 8, 0, 0, 0, 17,
+    ];
+
+    fn filter() -> BloomFilter<&'static str> {
+        let mut bf = BloomBuilder::new(10, 0.01).deterministic().build();
+        bf.insert("foo");
+        bf
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard() {
+        let bf = filter();
+        let bytes = bf.to_postcard();
+        assert_eq!(&ENCODED[..], &bytes[..]);
+        let restored: BloomFilter<&str> = BloomFilter::from_postcard(&bytes).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert_eq!(bf.len(), restored.len());
+        assert!(restored.has("foo"));
+
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            BloomFilter::<&str>::from_postcard(&bytes[..bytes.len() - 1]).map(|_| ())
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            BloomFilter::<&str>::from_postcard(&trailing).map(|_| ())
+        );
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode() {
+        let bf = filter();
+        let bytes = bf.to_bincode();
+        assert_eq!(&ENCODED[..], &bytes[..]);
+        let restored: BloomFilter<&str> = BloomFilter::from_bincode(&bytes).unwrap();
+        assert_eq!(bf.bits, restored.bits);
+        assert_eq!(bf.params(), restored.params());
+        assert!(restored.has("foo"));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Err(Error::InvalidEncoding),
+            BloomFilter::<&str>::from_bincode(&trailing).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn unknown_hasher() {
+        let bf: BloomFilter<&str> = BloomBuilder::new(10, 0.01)
+            .hasher_instance(HmacHasher::new(b"secret"))
+            .build();
+        #[cfg(feature = "postcard")]
+        assert!(matches!(
+            BloomFilter::<&str>::from_postcard(&bf.to_postcard()),
+            Err(Error::UnknownHasher(10))
+        ));
+        #[cfg(feature = "bincode")]
+        assert!(matches!(
+            BloomFilter::<&str>::from_bincode(&bf.to_bincode()),
+            Err(Error::UnknownHasher(10))
+        ));
+    }
+}
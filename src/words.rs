@@ -0,0 +1,230 @@
+//! Conversions between the words a filter's bits are stored in and the bytes they are
+//! encoded as. Bit `i` of a filter is bit `i % 64` of word `i / 64`, and bit `i % 8` of
+//! byte `i / 8` once encoded, so the bytes of each word are laid out in little-endian
+//! order. Filters whose number of bytes is not a multiple of 8 pad their last word with
+//! zero bytes, which are never encoded.
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicU64;
+
+/// The number of words filling a 64-byte cache line.
+pub(crate) const LINE_WORDS: usize = 8;
+
+/// A cache line of words, aligned to its size.
+#[derive(Clone, Default)]
+#[repr(C, align(64))]
+struct Line<W>([W; LINE_WORDS]);
+
+/// The 64-bit words filters can be stored in.
+pub(crate) trait Word: Default {}
+
+impl Word for u64 {}
+impl Word for AtomicU64 {}
+
+/// Words stored in cache lines, so that the first word and every 8th word after it
+/// start a 64-byte cache line, and filters shared between threads never share a line
+/// with other data. The words past the end of the last line are zero.
+pub(crate) struct AlignedWords<W: Word = u64> {
+    lines: Vec<Line<W>>,
+    len: usize,
+}
+
+impl<W: Word> AlignedWords<W> {
+    /// Creates a number of words, each computed from its index.
+    pub(crate) fn from_fn(len: usize, mut f: impl FnMut(usize) -> W) -> AlignedWords<W> {
+        let lines = (0..len.div_ceil(LINE_WORDS))
+            .map(|line| {
+                Line(std::array::from_fn(|i| match line * LINE_WORDS + i {
+                    idx if idx < len => f(idx),
+                    _ => W::default(),
+                }))
+            })
+            .collect();
+        AlignedWords { lines, len }
+    }
+    /// Creates a number of zero words.
+    pub(crate) fn zeroed(len: usize) -> AlignedWords<W> {
+        AlignedWords::from_fn(len, |_| W::default())
+    }
+}
+
+impl AlignedWords {
+    /// Copies words into aligned storage.
+    pub(crate) fn from_slice(words: &[u64]) -> AlignedWords {
+        AlignedWords::from_fn(words.len(), |i| words[i])
+    }
+    /// Packs bytes into aligned words, padding the last word with zero bytes.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> AlignedWords {
+        AlignedWords::from_fn(word_len(bytes.len()), |i| {
+            let chunk = &bytes[i * 8..(i * 8 + 8).min(bytes.len())];
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        })
+    }
+}
+
+impl FromIterator<u64> for AlignedWords {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> AlignedWords {
+        let mut words = AlignedWords {
+            lines: vec![],
+            len: 0,
+        };
+        for word in iter {
+            if words.len % LINE_WORDS == 0 {
+                words.lines.push(Line::default());
+            }
+            words.lines[words.len / LINE_WORDS].0[words.len % LINE_WORDS] = word;
+            words.len += 1;
+        }
+        words
+    }
+}
+
+impl<W: Word> Deref for AlignedWords<W> {
+    type Target = [W];
+
+    fn deref(&self) -> &[W] {
+        // SAFETY: words are 8 bytes, so lines of 8 words hold no padding, and the
+        // lines are laid out back to back holding at least `len` words.
+        unsafe { std::slice::from_raw_parts(self.lines.as_ptr() as *const W, self.len) }
+    }
+}
+
+impl<W: Word> DerefMut for AlignedWords<W> {
+    fn deref_mut(&mut self) -> &mut [W] {
+        // SAFETY: as for `deref`.
+        unsafe { std::slice::from_raw_parts_mut(self.lines.as_mut_ptr() as *mut W, self.len) }
+    }
+}
+
+impl Clone for AlignedWords {
+    fn clone(&self) -> AlignedWords {
+        AlignedWords {
+            lines: self.lines.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl PartialEq for AlignedWords {
+    fn eq(&self, other: &AlignedWords) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for AlignedWords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// Returns the number of words holding a number of bytes.
+pub(crate) fn word_len(byte_len: usize) -> usize {
+    byte_len.div_ceil(8)
+}
+
+/// Packs bytes into words, padding the last word with zero bytes.
+pub(crate) fn from_bytes(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks(8)
+        .map(|chunk| {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        })
+        .collect()
+}
+
+/// Unpacks the first bytes of words, dropping the padding of the last word.
+pub(crate) fn to_bytes(words: &[u64], byte_len: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    bytes.truncate(byte_len);
+    bytes
+}
+
+/// Returns a range of the bytes of words.
+pub(crate) fn bytes_in(words: &[u64], start: usize, end: usize) -> Vec<u8> {
+    (start..end).map(|i| byte(words, i)).collect()
+}
+
+/// Returns the byte at an offset of words.
+pub(crate) fn byte(words: &[u64], i: usize) -> u8 {
+    (words[i / 8] >> (8 * (i % 8))) as u8
+}
+
+/// Overwrites the bytes of words at an offset.
+pub(crate) fn copy_from_bytes(words: &mut [u64], offset: usize, bytes: &[u8]) {
+    for (i, b) in bytes.iter().enumerate() {
+        let i = offset + i;
+        let shift = 8 * (i % 8);
+        words[i / 8] = (words[i / 8] & !(0xff << shift)) | ((*b as u64) << shift);
+    }
+}
+
+/// Splits the first bytes of words into batches of up to 8 KiB, so that large filters
+/// can be streamed without building a copy of all of their bytes.
+pub(crate) fn byte_batches(words: &[u64], byte_len: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+    words.chunks(1024).enumerate().map(move |(i, batch)| {
+        let mut bytes: Vec<u8> = batch.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bytes.truncate(byte_len - (i * 8 * 1024).min(byte_len));
+        bytes
+    })
+}
+
+/// Streams the first bytes of words to a writer, a batch at a time.
+pub(crate) fn write_bytes<W: Write>(w: &mut W, words: &[u64], byte_len: usize) -> io::Result<()> {
+    for batch in byte_batches(words, byte_len) {
+        w.write_all(&batch)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let bytes: Vec<u8> = (1..=13).collect();
+        let words = from_bytes(&bytes);
+        assert_eq!(vec![0x0807060504030201, 0x0d0c0b0a09], words);
+        assert_eq!(word_len(bytes.len()), words.len());
+        assert_eq!(bytes, to_bytes(&words, bytes.len()));
+        assert_eq!(vec![8, 9, 10], bytes_in(&words, 7, 10));
+
+        let mut streamed = vec![];
+        write_bytes(&mut streamed, &words, bytes.len()).unwrap();
+        assert_eq!(bytes, streamed);
+        let large = vec![u64::MAX; 2_000];
+        let batches: Vec<usize> = byte_batches(&large, 15_999).map(|b| b.len()).collect();
+        assert_eq!(vec![8_192, 7_807], batches);
+
+        let mut words = words;
+        copy_from_bytes(&mut words, 6, &[0xff, 0xee, 0xdd]);
+        assert_eq!(0xff, byte(&words, 6));
+        assert_eq!(vec![0xee, 0xdd, 0x0a], bytes_in(&words, 7, 10));
+    }
+
+    #[test]
+    fn aligned() {
+        for len in [0, 1, 8, 13, 1_000] {
+            let words: Vec<u64> = (0..len as u64).collect();
+            let aligned = AlignedWords::from_slice(&words);
+            assert_eq!(0, aligned.as_ptr() as usize % 64);
+            assert_eq!(&words[..], &aligned[..]);
+            assert_eq!(aligned, aligned.clone());
+            let bytes = to_bytes(&words, len * 8);
+            assert_eq!(aligned, AlignedWords::from_bytes(&bytes));
+        }
+        let bytes: Vec<u8> = (1..=13).collect();
+        assert_eq!(
+            &from_bytes(&bytes)[..],
+            &AlignedWords::from_bytes(&bytes)[..]
+        );
+        let atomic: AlignedWords<AtomicU64> = AlignedWords::zeroed(9);
+        assert_eq!(9, atomic.len());
+        assert_eq!(0, atomic.as_ptr() as usize % 64);
+    }
+}